@@ -0,0 +1,54 @@
+use std::time::Duration;
+
+use naia_socket_shared::Random;
+
+/// Determines how long the Client Socket waits before each attempt to
+/// reconnect to the Server. The delay grows exponentially with each failed
+/// attempt, & is randomized so that many clients which lost their connection
+/// at the same moment don't all retry at once
+#[derive(Debug, Clone)]
+pub struct BackoffConfig {
+    /// The delay before the first attempt
+    pub initial_delay: Duration,
+    /// The longest the delay is allowed to grow to
+    pub max_delay: Duration,
+    /// The factor the delay is multiplied by after each failed attempt
+    pub multiplier: f32,
+    /// The fraction of the delay by which it may be randomly lengthened or
+    /// shortened, between 0.0 & 1.0
+    pub jitter: f32,
+    /// The number of attempts to make before giving up, or `None` to keep
+    /// trying forever
+    pub max_attempts: Option<u32>,
+}
+
+impl BackoffConfig {
+    /// Gets the delay to wait before making the given attempt, counting from 1
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let max_secs = self.max_delay.as_secs_f32();
+        let secs =
+            (self.initial_delay.as_secs_f32() * self.multiplier.powi(exponent)).min(max_secs);
+
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        let jittered = if jitter > 0.0 {
+            secs * Random::gen_range_f32(1.0 - jitter, 1.0 + jitter)
+        } else {
+            secs
+        };
+
+        Duration::from_secs_f32(jittered.max(0.0))
+    }
+}
+
+impl Default for BackoffConfig {
+    fn default() -> Self {
+        BackoffConfig {
+            initial_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            multiplier: 2.0,
+            jitter: 0.25,
+            max_attempts: None,
+        }
+    }
+}
@@ -0,0 +1,54 @@
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{ClientSocketTrait, NaiaClientSocketError, SocketEvent};
+
+// How long to sleep between looking for events
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Wraps a Client Socket in a receive which blocks the calling thread until
+/// an event arrives, for simple tools & tests. The socket is polled while
+/// waiting, so its timers keep running
+#[derive(Debug)]
+pub struct BlockingSocket {
+    socket: Box<dyn ClientSocketTrait>,
+}
+
+impl BlockingSocket {
+    /// Wraps the given Client Socket
+    pub fn new(socket: Box<dyn ClientSocketTrait>) -> Self {
+        BlockingSocket { socket }
+    }
+
+    /// Blocks until the next event is received, or the timeout runs out, in
+    /// which case `None` is returned
+    pub fn receive_timeout(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<Option<SocketEvent>, NaiaClientSocketError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(event) = self.socket.receive()? {
+                return Ok(Some(event));
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(None);
+            }
+            thread::sleep(POLL_INTERVAL.min(deadline - now));
+        }
+    }
+
+    /// Gets a mutable reference to the socket, to send through or manage the
+    /// connection with
+    pub fn socket_mut(&mut self) -> &mut dyn ClientSocketTrait {
+        self.socket.as_mut()
+    }
+
+    /// Takes the socket back out of the BlockingSocket
+    pub fn into_inner(self) -> Box<dyn ClientSocketTrait> {
+        self.socket
+    }
+}
@@ -0,0 +1,81 @@
+use std::collections::{HashMap, VecDeque};
+
+use naia_socket_shared::Ref;
+
+use crate::{Packet, SocketEvent};
+
+/// Hands the packets received on chosen channels to ChannelReceivers of their
+/// own, so that each kind of traffic can be handled apart from the rest of
+/// the socket's events. Every event received from the socket should be passed
+/// through `route`
+#[derive(Debug, Default)]
+pub struct ChannelRouter {
+    receivers: HashMap<u8, (Ref<VecDeque<Packet>>, usize)>,
+}
+
+impl ChannelRouter {
+    /// Create a new ChannelRouter, which hands every event back until
+    /// receivers are added
+    pub fn new() -> Self {
+        ChannelRouter {
+            receivers: HashMap::new(),
+        }
+    }
+
+    /// Gets a receiver for the packets arriving on the given channel, which
+    /// holds up to `queue_size` of them. Replaces any receiver the channel
+    /// already had
+    pub fn receiver(&mut self, channel: u8, queue_size: usize) -> ChannelReceiver {
+        let queue = Ref::new(VecDeque::new());
+        self.receivers.insert(channel, (queue.clone(), queue_size));
+        ChannelReceiver { channel, queue }
+    }
+
+    /// Takes an event received from the socket, handing it back unless it is
+    /// a packet on a channel with a receiver. Packets which arrive while their
+    /// receiver is full are dropped
+    pub fn route(&mut self, event: SocketEvent) -> Option<SocketEvent> {
+        let packet = match event {
+            SocketEvent::Packet(packet) => packet,
+            event => return Some(event),
+        };
+        let (queue, queue_size) = match packet
+            .channel()
+            .and_then(|channel| self.receivers.get(&channel))
+        {
+            Some(receiver) => receiver,
+            None => return Some(SocketEvent::Packet(packet)),
+        };
+
+        let mut queue = queue.borrow_mut();
+        if queue.len() < *queue_size {
+            queue.push_back(packet);
+        } else {
+            log::warn!(
+                "Dropped a packet on channel {}, as its receiver is full",
+                packet.channel().unwrap()
+            );
+        }
+        None
+    }
+}
+
+/// Receives the packets arriving on a single channel, as handed over by a
+/// ChannelRouter
+#[derive(Debug)]
+pub struct ChannelReceiver {
+    channel: u8,
+    queue: Ref<VecDeque<Packet>>,
+}
+
+impl ChannelReceiver {
+    /// Gets the id of the channel packets are received on
+    pub fn channel(&self) -> u8 {
+        self.channel
+    }
+
+    /// Gets the next packet on the channel, if one has arrived
+    pub fn receive(&mut self) -> Option<Packet> {
+        self.queue.borrow_mut().pop_front()
+    }
+}
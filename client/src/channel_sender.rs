@@ -0,0 +1,27 @@
+use crate::{MessageSender, NaiaClientSocketError, Packet};
+
+/// Sends packets to the Server on a single channel, so that each kind of
+/// traffic can have a sender of its own
+#[derive(Clone, Debug)]
+pub struct ChannelSender {
+    sender: MessageSender,
+    channel: u8,
+}
+
+impl ChannelSender {
+    /// Create a new ChannelSender, which moves every packet sent through it
+    /// onto the given channel
+    pub fn new(sender: MessageSender, channel: u8) -> Self {
+        ChannelSender { sender, channel }
+    }
+
+    /// Gets the id of the channel packets are sent on
+    pub fn channel(&self) -> u8 {
+        self.channel
+    }
+
+    /// Send a Packet to the Server on the channel. See `MessageSender::send`
+    pub fn send(&mut self, packet: Packet) -> Result<(), NaiaClientSocketError> {
+        self.sender.send(packet.with_channel(self.channel))
+    }
+}
@@ -1,8 +1,11 @@
-use std::fmt::Debug;
+use std::{fmt::Debug, net::SocketAddr, time::Duration};
 
-use naia_socket_shared::LinkConditionerConfig;
+use naia_socket_shared::{ConditionerHandle, ConditionerStats, LinkConditionerConfig};
 
-use super::{error::NaiaClientSocketError, packet::Packet};
+use super::{
+    connection_state::ConnectionState, error::NaiaClientSocketError, middleware::SocketMiddleware,
+    socket_event::SocketEvent,
+};
 use crate::MessageSender;
 
 cfg_if! {
@@ -16,14 +19,55 @@ cfg_if! {
 }
 /// Defines the functionality of a Naia Client Socket
 pub trait ClientSocketTrait: ClientSocketBaseTrait {
-    /// Receive a new packet from the socket, or a tick event
-    fn receive(&mut self) -> Result<Option<Packet>, NaiaClientSocketError>;
+    /// Receive the next event from the socket, such as an incoming packet or
+    /// a change in the state of the connection. Never blocks, returning
+    /// `None` if no event is ready, so it can be called from a game loop
+    /// without an async executor
+    fn receive(&mut self) -> Result<Option<SocketEvent>, NaiaClientSocketError>;
     /// Gets a MessageSender you can use to send messages through the Server
     /// Socket
     fn get_sender(&mut self) -> MessageSender;
-    /// Wraps the current socket in a LinkConditioner
+    /// Gets the current state of the connection with the Server
+    fn state(&self) -> ConnectionState;
+    /// Closes the connection with the Server. The socket won't try to
+    /// reconnect afterwards
+    fn disconnect(&mut self);
+    /// Moves the connection over to the Server at the given address, such as
+    /// after a HostMigration event. Native sockets handshake with the new
+    /// Server over the same socket, browsers set up a new peer connection
+    fn switch_server(&mut self, server_address: SocketAddr) -> Result<(), NaiaClientSocketError>;
+    /// Simulates the given network conditions on received packets, by
+    /// stacking a Conditioner on the socket as middleware
     fn with_link_conditioner(
         self: Box<Self>,
         config: &LinkConditionerConfig,
     ) -> Box<dyn ClientSocketTrait>;
+    /// Stacks a layer of middleware on the socket, further from it than any
+    /// added before
+    fn with_middleware(
+        self: Box<Self>,
+        middleware: Box<dyn SocketMiddleware>,
+    ) -> Box<dyn ClientSocketTrait>;
+    /// Gets a handle onto the conditions simulated on received packets, which
+    /// can change them while the socket is live, if the socket has a link
+    /// conditioner
+    fn conditioner_handle(&self) -> Option<ConditionerHandle>;
+    /// Gets the counters describing what has been done to received packets,
+    /// if the socket has a link conditioner
+    fn conditioner_stats(&self) -> Option<ConditionerStats>;
+    /// Gets the smoothed round trip time to the Server, measured by pinging
+    /// it while `SocketConfig::ping_interval` is set. Only known on the
+    /// native client, once the Server has replied to a ping
+    fn rtt(&self) -> Option<Duration>;
+    /// Gets how much the round trip time to the Server varies from one ping
+    /// to the next. Only known on the native client, like `rtt`
+    fn jitter(&self) -> Option<Duration>;
+    /// Estimates the current time on the Server's system clock, as time since
+    /// the Unix epoch, from the times it reported in replies to pings, so
+    /// that packets stamped with it can be interpolated. Only known on the
+    /// native client, like `rtt`
+    fn server_time_estimate(&self) -> Option<Duration>;
+    /// Gets the local address the socket is bound to, as chosen by
+    /// `SocketConfig::port_search`. Only known on the native client
+    fn local_address(&self) -> Option<SocketAddr>;
 }
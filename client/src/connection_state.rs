@@ -0,0 +1,16 @@
+/// The state of the Client Socket's connection with the Server
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ConnectionState {
+    /// Trying to establish a connection with the Server
+    Connecting,
+    /// Connected to the Server
+    Connected,
+    /// Closing the connection, at the application's request
+    Disconnecting,
+    /// Not connected to the Server. If `auto_reconnect` is enabled, the socket
+    /// may be waiting to try again
+    Disconnected,
+    /// The socket has stopped trying to connect to the Server, & won't try
+    /// again
+    Failed,
+}
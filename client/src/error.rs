@@ -1,22 +1,145 @@
-use std::{error::Error, fmt};
+use std::{error::Error, fmt, io};
+
+use naia_socket_shared::HandshakeError;
 
 /// An Error type specifically related to the Naia Client Socket
-/// This is under construction and needs to be cleaned up
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum NaiaClientSocketError {
-    /// A simple error message
-    Message(String),
-    /// A wrapped error from another library/codebase
-    Wrapped(Box<dyn Error + Send>),
+    /// The underlying socket failed
+    Io(io::Error),
+    /// The WebRTC session with the Server couldn't be set up
+    Signaling(String),
+    /// The data channel with the Server, or the ICE connection it runs over,
+    /// failed for the reason given, & a Disconnection event follows unless
+    /// the connection was still being set up. Only returned by the browser
+    /// clients
+    DataChannel(String),
+    /// The Server didn't answer the request for a WebRTC session within
+    /// `WebRtcTimeouts::signaling`. Only returned by the browser clients
+    SignalingTimeout,
+    /// The ICE connection to the Server wasn't made within
+    /// `WebRtcTimeouts::ice`. Only returned by the browser clients
+    IceTimeout,
+    /// The data channel didn't open within `WebRtcTimeouts::channel_open` of
+    /// the ICE connection being made. Only returned by the browser clients
+    ChannelOpenTimeout,
+    /// The Server refused the connection
+    Handshake(HandshakeError),
+    /// The socket gave up reconnecting to the Server, after the most attempts
+    /// its BackoffConfig allows
+    ReconnectFailed {
+        /// The number of attempts made
+        attempts: u32,
+    },
+    /// A Packet was sent on a channel which isn't one of the connection's
+    /// channels
+    UnknownChannel(u8),
+    /// A Packet's payload was larger than `SocketConfig::max_payload_size`,
+    /// or than the most it can be split into fragments for
+    PayloadTooLarge {
+        /// The size of the payload
+        size: usize,
+        /// The most a payload can be
+        max_payload_size: usize,
+    },
+    /// A Packet sent as text didn't hold valid UTF-8
+    InvalidText,
 }
 
 impl fmt::Display for NaiaClientSocketError {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         match self {
-            NaiaClientSocketError::Message(msg) => write!(f, "Naia Client Socket Error: {}", msg),
-            NaiaClientSocketError::Wrapped(boxed_err) => fmt::Display::fmt(boxed_err.as_ref(), f),
+            NaiaClientSocketError::Io(err) => write!(f, "Naia Client Socket Error: {}", err),
+            NaiaClientSocketError::Signaling(message) => {
+                write!(f, "Naia Client Socket Error: signaling failed: {}", message)
+            }
+            NaiaClientSocketError::DataChannel(reason) => {
+                write!(
+                    f,
+                    "Naia Client Socket Error: data channel failed: {}",
+                    reason
+                )
+            }
+            NaiaClientSocketError::SignalingTimeout => {
+                write!(f, "Naia Client Socket Error: signaling timed out")
+            }
+            NaiaClientSocketError::IceTimeout => {
+                write!(f, "Naia Client Socket Error: ICE connection timed out")
+            }
+            NaiaClientSocketError::ChannelOpenTimeout => {
+                write!(
+                    f,
+                    "Naia Client Socket Error: data channel timed out opening"
+                )
+            }
+            NaiaClientSocketError::Handshake(err) => {
+                write!(f, "Naia Client Socket Error: refused by server: {}", err)
+            }
+            NaiaClientSocketError::ReconnectFailed { attempts } => write!(
+                f,
+                "Naia Client Socket Error: gave up reconnecting after {} attempts",
+                attempts
+            ),
+            NaiaClientSocketError::UnknownChannel(channel) => write!(
+                f,
+                "Naia Client Socket Error: channel {} isn't one of the connection's channels",
+                channel
+            ),
+            NaiaClientSocketError::PayloadTooLarge {
+                size,
+                max_payload_size,
+            } => write!(
+                f,
+                "Naia Client Socket Error: payload of {} bytes is larger than the most, {}",
+                size, max_payload_size
+            ),
+            NaiaClientSocketError::InvalidText => {
+                write!(
+                    f,
+                    "Naia Client Socket Error: could not send the packet as text"
+                )
+            }
+        }
+    }
+}
+
+impl NaiaClientSocketError {
+    // Refuses a payload larger than the most which can be sent
+    pub(crate) fn check_payload_size(
+        size: usize,
+        max_payload_size: Option<usize>,
+    ) -> Result<(), Self> {
+        match max_payload_size {
+            Some(max_payload_size) if size > max_payload_size => {
+                Err(NaiaClientSocketError::PayloadTooLarge {
+                    size,
+                    max_payload_size,
+                })
+            }
+            _ => Ok(()),
         }
     }
 }
 
-impl Error for NaiaClientSocketError {}
+impl Error for NaiaClientSocketError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            NaiaClientSocketError::Io(err) => Some(err),
+            NaiaClientSocketError::Handshake(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for NaiaClientSocketError {
+    fn from(err: io::Error) -> Self {
+        NaiaClientSocketError::Io(err)
+    }
+}
+
+impl From<HandshakeError> for NaiaClientSocketError {
+    fn from(err: HandshakeError) -> Self {
+        NaiaClientSocketError::Handshake(err)
+    }
+}
@@ -1,69 +1,255 @@
-use std::{collections::VecDeque, net::SocketAddr};
+use std::{collections::VecDeque, net::SocketAddr, time::Duration};
 
 use super::shared::{
-    naia_connect, naia_resend_dropped_messages, JsObject, ERROR_QUEUE, MESSAGE_QUEUE,
+    naia_connect, naia_disconnect, naia_resend_dropped_messages, JsObject, ERROR_QUEUE,
+    EVENT_QUEUE, SETUP_PHASE,
 };
 
 use crate::{
-    error::NaiaClientSocketError, link_conditioner::LinkConditioner, ClientSocketTrait,
-    MessageSender, Packet,
+    error::NaiaClientSocketError,
+    link_conditioner::Conditioner,
+    middleware::{MiddlewareChain, SocketMiddleware},
+    packet_tap::PacketTap,
+    setup_timer::{SetupPhase, SetupTimer},
+    state_machine::StateMachine,
+    ClientSocketTrait, ConnectionState, MessageSender, SocketConfig, SocketEvent,
 };
 
-use naia_socket_shared::LinkConditionerConfig;
+use naia_socket_shared::{
+    ConditionerHandle, ConditionerStats, LinkConditionerConfig, PacketDirection,
+};
 
 /// A client-side socket which communicates with an underlying unordered &
 /// unreliable protocol
 #[derive(Debug)]
 pub struct ClientSocket {
     address: SocketAddr,
+    config: SocketConfig,
     message_sender: MessageSender,
+    state_machine: StateMachine,
+    setup_timer: SetupTimer,
+    packet_tap: PacketTap,
+    middleware: MiddlewareChain,
+    // a clone of the link conditioner stacked on the socket, if there is one
+    conditioner: Option<Conditioner>,
 }
 
 impl ClientSocket {
     /// Returns a new ClientSocket, connected to the given socket address
-    pub fn connect(server_socket_address: SocketAddr) -> Box<dyn ClientSocketTrait> {
+    pub fn connect(
+        server_socket_address: SocketAddr,
+        config: SocketConfig,
+    ) -> Box<dyn ClientSocketTrait> {
+        // browsers don't say which address they talk to the Server from
+        let packet_tap = PacketTap::new(
+            &config,
+            SocketAddr::from(([0, 0, 0, 0], 0)),
+            server_socket_address,
+        );
+        let middleware = MiddlewareChain::new();
+        let mut socket = ClientSocket {
+            address: server_socket_address,
+            state_machine: StateMachine::new(&config),
+            setup_timer: SetupTimer::new(&config.webrtc_timeouts),
+            message_sender: MessageSender::new(packet_tap.clone(), middleware.clone())
+                .with_max_payload_size(config.max_payload_size),
+            config,
+            packet_tap,
+            middleware,
+            conditioner: None,
+        };
+        socket.start_connecting();
+
+        Box::new(socket)
+    }
+
+    // Runs signaling, replacing any previous peer connection
+    fn start_connecting(&mut self) {
+        let connect_token_hex = self.config.connect_token_hex().unwrap_or_default();
+        self.setup_timer = SetupTimer::new(&self.config.webrtc_timeouts);
         unsafe {
-            MESSAGE_QUEUE = Some(VecDeque::new());
+            EVENT_QUEUE = Some(VecDeque::new());
             ERROR_QUEUE = Some(VecDeque::new());
-            naia_connect(JsObject::string(server_socket_address.to_string().as_str()));
+            SETUP_PHASE = SetupPhase::Signaling;
+            naia_connect(
+                JsObject::string(self.address.to_string().as_str()),
+                JsObject::string(connect_token_hex.as_str()),
+            );
         }
-
-        Box::new(ClientSocket {
-            address: server_socket_address,
-            message_sender: MessageSender::new(),
-        })
     }
-}
 
-impl ClientSocketTrait for ClientSocket {
-    fn receive(&mut self) -> Result<Option<Packet>, NaiaClientSocketError> {
+    // Gets the next event, before it is dumped
+    fn receive_event(&mut self) -> Result<Option<SocketEvent>, NaiaClientSocketError> {
+        if let Some(event) = self.state_machine.pop_event() {
+            return Ok(Some(event));
+        }
+
+        if self.state_machine.poll_connect_timeout()? {
+            unsafe {
+                naia_disconnect();
+            }
+            return Ok(self.state_machine.pop_event());
+        }
+
+        if self.state_machine.poll_reconnect() {
+            self.start_connecting();
+            return Ok(self.state_machine.pop_event());
+        }
+
+        if self.state_machine.state() == ConnectionState::Connecting {
+            if let Some(err) = self.setup_timer.poll(unsafe { SETUP_PHASE }) {
+                unsafe {
+                    naia_disconnect();
+                }
+                self.state_machine.attempt_failed()?;
+                return Err(err);
+            }
+        }
+
         unsafe {
             naia_resend_dropped_messages();
 
-            if let Some(msg_queue) = &mut MESSAGE_QUEUE {
-                if let Some(message) = msg_queue.pop_front() {
-                    return Ok(Some(Packet::new_raw(message)));
+            // errors come first, as the Disconnection caused by one comes after it
+            while let Some(error) = ERROR_QUEUE.as_mut().and_then(|queue| queue.pop_front()) {
+                match (error, self.state_machine.state()) {
+                    // the channel can err as it is closed on purpose
+                    (NaiaClientSocketError::DataChannel(_), ConnectionState::Disconnecting) => {}
+                    (error, ConnectionState::Connecting) => {
+                        // signaling or the channel failed, so this attempt failed
+                        self.state_machine.attempt_failed()?;
+                        return Err(error);
+                    }
+                    (error, _) => return Err(error),
                 }
             }
 
-            if let Some(error_queue) = &mut ERROR_QUEUE {
-                if let Some(error) = error_queue.pop_front() {
-                    return Err(NaiaClientSocketError::Message(error));
+            while let Some(event) = EVENT_QUEUE.as_mut().and_then(|queue| queue.pop_front()) {
+                match event {
+                    SocketEvent::Connection => {
+                        self.state_machine.connected();
+                    }
+                    SocketEvent::Kicked(reason) => {
+                        if self.state_machine.state() == ConnectionState::Connected {
+                            self.state_machine.kicked(reason);
+                            naia_disconnect();
+                        }
+                    }
+                    SocketEvent::Disconnection => match self.state_machine.state() {
+                        ConnectionState::Connected => self.state_machine.connection_lost(),
+                        ConnectionState::Disconnecting => self.state_machine.disconnected(),
+                        // the data channel never opened, so this attempt failed
+                        ConnectionState::Connecting => self.state_machine.attempt_failed()?,
+                        _ => {}
+                    },
+                    event => {
+                        return Ok(Some(event));
+                    }
+                }
+                if let Some(event) = self.state_machine.pop_event() {
+                    return Ok(Some(event));
                 }
             }
         };
 
         Ok(None)
     }
+}
+
+impl ClientSocketTrait for ClientSocket {
+    fn receive(&mut self) -> Result<Option<SocketEvent>, NaiaClientSocketError> {
+        loop {
+            match self.receive_event() {
+                Ok(Some(SocketEvent::Packet(packet))) => {
+                    let size = packet.payload().len();
+                    if self.config.max_payload_size.is_some_and(|max| size > max) {
+                        return Ok(Some(SocketEvent::OversizedPacket { size }));
+                    }
+                    self.packet_tap
+                        .tap(PacketDirection::Incoming, packet.payload());
+                    if let Some(packet) = self.middleware.inbound(packet) {
+                        return Ok(Some(SocketEvent::Packet(packet)));
+                    }
+                }
+                Ok(None) => {
+                    return Ok(self.middleware.release_inbound().map(SocketEvent::Packet));
+                }
+                event => return event,
+            }
+        }
+    }
+
+    fn state(&self) -> ConnectionState {
+        self.state_machine.state()
+    }
+
+    fn disconnect(&mut self) {
+        match self.state_machine.state() {
+            ConnectionState::Connected | ConnectionState::Connecting => {
+                // finishes once the data channel's close event arrives
+                self.state_machine.disconnecting();
+                unsafe {
+                    naia_disconnect();
+                }
+            }
+            ConnectionState::Disconnected => {
+                // stop any reconnection attempt from being made
+                self.state_machine.disconnected();
+            }
+            _ => {}
+        }
+    }
 
     fn get_sender(&mut self) -> MessageSender {
         return self.message_sender.clone();
     }
 
+    fn switch_server(&mut self, server_address: SocketAddr) -> Result<(), NaiaClientSocketError> {
+        self.address = server_address;
+        self.packet_tap.set_server_address(server_address);
+        self.state_machine.switching_server();
+        self.start_connecting();
+        Ok(())
+    }
+
     fn with_link_conditioner(
-        self: Box<Self>,
+        mut self: Box<Self>,
         config: &LinkConditionerConfig,
     ) -> Box<dyn ClientSocketTrait> {
-        Box::new(LinkConditioner::new(config, self))
+        let conditioner = Conditioner::new(ConditionerHandle::new(config));
+        self.middleware.push(Box::new(conditioner.clone()));
+        self.conditioner = Some(conditioner);
+        self
+    }
+
+    fn with_middleware(
+        self: Box<Self>,
+        middleware: Box<dyn SocketMiddleware>,
+    ) -> Box<dyn ClientSocketTrait> {
+        self.middleware.push(middleware);
+        self
+    }
+
+    fn conditioner_handle(&self) -> Option<ConditionerHandle> {
+        self.conditioner.as_ref().map(Conditioner::handle)
+    }
+
+    fn conditioner_stats(&self) -> Option<ConditionerStats> {
+        self.conditioner.as_ref().map(Conditioner::stats)
+    }
+
+    fn rtt(&self) -> Option<Duration> {
+        None
+    }
+
+    fn jitter(&self) -> Option<Duration> {
+        None
+    }
+
+    fn server_time_estimate(&self) -> Option<Duration> {
+        None
+    }
+
+    fn local_address(&self) -> Option<SocketAddr> {
+        None
     }
 }
@@ -1,29 +1,66 @@
-use std::error::Error;
+use std::str;
 
-use super::shared::{naia_create_u8_array, naia_send};
-use crate::Packet;
+use super::shared::{naia_create_string, naia_create_u8_array, naia_send};
+use crate::{
+    error::NaiaClientSocketError, middleware::MiddlewareChain, packet_tap::PacketTap, MessageMode,
+    Packet,
+};
+use naia_socket_shared::PacketDirection;
 
 /// Handles sending messages to the Server for a given Client Socket
 #[derive(Clone, Debug)]
-pub struct MessageSender {}
+pub struct MessageSender {
+    packet_tap: PacketTap,
+    middleware: MiddlewareChain,
+    max_payload_size: Option<usize>,
+}
 
 impl MessageSender {
-    /// Create a new MessageSender, if supplied with the RtcDataChannel and a
-    /// reference to a list of dropped messages
-    pub fn new() -> MessageSender {
-        MessageSender {}
+    /// Create a new MessageSender, if supplied with where to tap packets to &
+    /// the middleware stacked on the socket
+    pub fn new(packet_tap: PacketTap, middleware: MiddlewareChain) -> MessageSender {
+        MessageSender {
+            packet_tap,
+            middleware,
+            max_payload_size: None,
+        }
+    }
+
+    // Refuses packets with a payload larger than the given size from then on
+    pub(crate) fn with_max_payload_size(mut self, max_payload_size: Option<usize>) -> Self {
+        self.max_payload_size = max_payload_size;
+        self
     }
 
     /// Send a Packet to the Server
-    pub fn send(&mut self, packet: Packet) -> Result<(), Box<dyn Error + Send>> {
+    pub fn send(&mut self, packet: Packet) -> Result<(), NaiaClientSocketError> {
+        NaiaClientSocketError::check_payload_size(packet.payload().len(), self.max_payload_size)?;
+        let packet = match self.middleware.outbound(packet) {
+            Some(packet) => packet,
+            None => return Ok(()),
+        };
+        if packet.mode() == MessageMode::Text && str::from_utf8(packet.payload()).is_err() {
+            return Err(NaiaClientSocketError::InvalidText);
+        }
+        self.packet_tap
+            .tap(PacketDirection::Outgoing, packet.payload());
         unsafe {
             let payload: &[u8] = packet.payload();
             let ptr = payload.as_ptr();
             let len = payload.len();
-            let js_obj = naia_create_u8_array(ptr as _, len as _);
+            let js_obj = match packet.mode() {
+                MessageMode::Binary => naia_create_u8_array(ptr as _, len as _),
+                MessageMode::Text => naia_create_string(ptr as _, len as _),
+            };
             naia_send(js_obj);
         }
 
         Ok(())
     }
+
+    /// Packets are sent straight away rather than coalesced, so there is
+    /// nothing to flush
+    pub fn flush(&mut self) -> Result<(), NaiaClientSocketError> {
+        Ok(())
+    }
 }
@@ -1,13 +1,13 @@
 const naia_socket = {
     channel: null,
-    encoder: new TextEncoder(),
-    decoder: new TextDecoder("utf-8"),
+    peer: null,
     dropped_outgoing_messages: [],
     js_objects: {},
     unique_js_id: 0,
 
     plugin: function (importObject) {
-        importObject.env.naia_connect = function (address) { naia_socket.connect(address); };
+        importObject.env.naia_connect = function (address, connect_token) { naia_socket.connect(address, connect_token); };
+        importObject.env.naia_disconnect = function () { naia_socket.disconnect(); };
         importObject.env.naia_send = function (message) { naia_socket.send(message); };
         importObject.env.naia_resend_dropped_messages = function() { naia_socket.resend_dropped_messages(); };
         importObject.env.naia_create_string = function (buf, max_len) { return naia_socket.js_create_string(buf, max_len); };
@@ -19,35 +19,102 @@ const naia_socket = {
         importObject.env.naia_free_object = function (js_object) { naia_socket.js_free_object(js_object); };
         importObject.env.naia_random = function () { return Math.random(); };
         importObject.env.naia_now = function () { return Date.now(); };
+        importObject.env.naia_performance_now = function () { return performance.now(); };
     },
 
-    connect: function (server_socket_address) {
+    connect: function (server_socket_address, connect_token) {
         let _this = this;
         let server_socket_address_string = naia_socket.get_js_object(server_socket_address);
+        let connect_token_string = naia_socket.get_js_object(connect_token);
         let ADDRESS = "http://" + server_socket_address_string + "/new_rtc_session";
+        if (connect_token_string.length > 0) {
+            ADDRESS += "?connect_token=" + connect_token_string;
+        }
+
+        // when reconnecting, tear down the previous peer connection first
+        if (this.channel) {
+            this.channel.onclose = null;
+            this.channel.close();
+        }
+        if (this.peer) {
+            this.peer.close();
+        }
 
         let peer = new RTCPeerConnection({
             iceServers: [{
                 urls: ["stun:stun.l.google.com:19302"]
             }]
         });
+        this.peer = peer;
 
-        this.channel = peer.createDataChannel("webudp", {
+        let channel = peer.createDataChannel("webudp", {
             ordered: false,
             maxRetransmits: 0
         });
+        this.channel = channel;
 
-        this.channel.binaryType = "arraybuffer";
+        // errors from a peer connection which has since been replaced are ignored
+        let error = function(desc, err) {
+            if (_this.channel === channel) {
+                _this.error(desc, err);
+            }
+        };
 
-        this.channel.onopen = function() {
-            _this.channel.onmessage = function(evt) {
+        channel.binaryType = "arraybuffer";
+
+        channel.onopen = function() {
+            channel.onmessage = function(evt) {
+                // text is either a message about the connection itself, or a packet sent as text
+                if (typeof evt.data === "string") {
+                    wasm_exports.control(naia_socket.js_object(evt.data));
+                    return;
+                }
                 let array = new Uint8Array(evt.data);
                 wasm_exports.receive(naia_socket.js_object(array));
             };
+            wasm_exports.connected();
         };
 
-        this.channel.onerror = function(evt) {
-            _this.error("data channel error", evt.message);
+        // whichever of the channel closing & the ICE connection failing comes
+        // first is the one reported
+        let closed = false;
+        let close = function(reason) {
+            if (closed || _this.channel !== channel) {
+                return;
+            }
+            closed = true;
+            if (reason) {
+                wasm_exports.channel_error(naia_socket.js_object(reason));
+            }
+            wasm_exports.disconnected();
+        };
+
+        channel.onclose = function() {
+            close(null);
+        };
+
+        channel.onerror = function(evt) {
+            if (closed || _this.channel !== channel) {
+                return;
+            }
+            // an RTCErrorEvent holds an RTCError in browsers which have them
+            let reason = (evt.error && evt.error.message) || evt.message || "unknown error";
+            wasm_exports.channel_error(naia_socket.js_object(reason));
+        };
+
+        // the channel only notices the connection is gone once SCTP gives up
+        // on it, long after ICE has
+        peer.oniceconnectionstatechange = function() {
+            if (_this.channel !== channel) {
+                return;
+            }
+            if (peer.iceConnectionState === "connected" || peer.iceConnectionState === "completed") {
+                wasm_exports.ice_connected();
+            } else if (peer.iceConnectionState === "failed") {
+                close("the ICE connection failed");
+            } else if (peer.iceConnectionState === "closed") {
+                close(null);
+            }
         };
 
         peer.onicecandidate = function(evt) {
@@ -65,30 +132,42 @@ const naia_socket = {
             request.open("POST", ADDRESS);
             request.onload = function() {
                 if (request.status === 200) {
+                    if (_this.channel === channel) {
+                        wasm_exports.signaled();
+                    }
                     let response = JSON.parse(request.responseText);
                     peer.setRemoteDescription(new RTCSessionDescription(response.answer)).then(function() {
                         let candidate = new RTCIceCandidate(response.candidate);
                         peer.addIceCandidate(candidate).then(function() {
                             //console.log("add ice candidate success");
                         }).catch(function(err) {
-                            _this.error("error during 'addIceCandidate'", err);
+                            error("error during 'addIceCandidate'", err);
                         });
                     }).catch(function(err) {
-                        _this.error("error during 'setRemoteDescription'", err);
+                        error("error during 'setRemoteDescription'", err);
                     });
                 } else {
-                    _this.error("error sending POST /new_rtc_session request", { response_status: request.status });
+                    error("error sending POST /new_rtc_session request", { response_status: request.status });
                 }
             };
             request.onerror = function(err) {
-                _this.error("error sending POST /new_rtc_session request", err);
+                error("error sending POST /new_rtc_session request", err);
             };
             request.send(peer.localDescription.sdp);
         }).catch(function(err) {
-            _this.error("error during 'createOffer'", err);
+            error("error during 'createOffer'", err);
         });
     },
 
+    disconnect: function () {
+        if (this.channel) {
+            this.channel.close();
+        }
+        if (this.peer) {
+            this.peer.close();
+        }
+    },
+
     error: function (desc, err) {
         err['naia_desc'] = desc;
         wasm_exports.error(this.js_object(JSON.stringify(err)));
@@ -104,25 +183,12 @@ const naia_socket = {
             let temp_array = this.dropped_outgoing_messages;
             this.dropped_outgoing_messages = [];
             for (let i = 0; i < temp_array.length; i+=1) {
-                this.send_u8_array(Uint8Array.from(temp_array[i]));
+                let message = temp_array[i];
+                this.send_u8_array(typeof message === "string" ? message : Uint8Array.from(message));
             }
         }
     },
 
-    send_str: function (str) {
-        if (this.channel) {
-            try {
-                this.channel.send(this.encoder.encode(str));
-            }
-            catch(err) {
-                this.dropped_outgoing_messages.push(str);
-            }
-        }
-        else {
-            this.dropped_outgoing_messages.push(str);
-        }
-    },
-
     js_create_string: function (buf, max_len) {
         let string = UTF8ToString(buf, max_len);
         return this.js_object(string);
@@ -149,11 +215,11 @@ const naia_socket = {
                 this.channel.send(str);
             }
             catch(err) {
-                this.dropped_outgoing_messages.push(Array.from(str));
+                this.dropped_outgoing_messages.push(typeof str === "string" ? str : Array.from(str));
             }
         }
         else {
-            this.dropped_outgoing_messages.push(Array.from(str));
+            this.dropped_outgoing_messages.push(typeof str === "string" ? str : Array.from(str));
         }
     },
 
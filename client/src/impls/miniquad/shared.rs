@@ -1,10 +1,18 @@
 use std::collections::VecDeque;
 
-pub static mut MESSAGE_QUEUE: Option<VecDeque<Box<[u8]>>> = None;
-pub static mut ERROR_QUEUE: Option<VecDeque<String>> = None;
+use naia_socket_shared::ControlMessage;
+
+use crate::{
+    error::NaiaClientSocketError, setup_timer::SetupPhase, MessageMode, Packet, SocketEvent,
+};
+
+pub static mut EVENT_QUEUE: Option<VecDeque<SocketEvent>> = None;
+pub static mut ERROR_QUEUE: Option<VecDeque<NaiaClientSocketError>> = None;
+pub static mut SETUP_PHASE: SetupPhase = SetupPhase::Signaling;
 
 extern "C" {
-    pub fn naia_connect(server_socket_address: JsObject);
+    pub fn naia_connect(server_socket_address: JsObject, connect_token: JsObject);
+    pub fn naia_disconnect();
     pub fn naia_send(message: JsObject);
     pub fn naia_resend_dropped_messages();
     pub fn naia_free_object(js_object: JsObjectWeak);
@@ -73,8 +81,63 @@ pub extern "C" fn receive(message: JsObject) {
     message.to_u8_array(&mut message_string);
 
     unsafe {
-        if let Some(msg_queue) = &mut MESSAGE_QUEUE {
-            msg_queue.push_back(message_string.into_boxed_slice());
+        if let Some(event_queue) = &mut EVENT_QUEUE {
+            event_queue.push_back(SocketEvent::Packet(Packet::new_raw(
+                message_string.into_boxed_slice(),
+            )));
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn control(message: JsObject) {
+    let mut message_string = String::new();
+
+    message.to_string(&mut message_string);
+
+    let event = match ControlMessage::decode(&message_string) {
+        Some(ControlMessage::Kicked(reason)) => SocketEvent::Kicked(Packet::new(reason)),
+        Some(ControlMessage::HostMigration(address)) => SocketEvent::HostMigration(address),
+        None => SocketEvent::Packet(
+            Packet::new(message_string.into_bytes()).with_mode(MessageMode::Text),
+        ),
+    };
+
+    unsafe {
+        if let Some(event_queue) = &mut EVENT_QUEUE {
+            event_queue.push_back(event);
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn connected() {
+    unsafe {
+        if let Some(event_queue) = &mut EVENT_QUEUE {
+            event_queue.push_back(SocketEvent::Connection);
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn signaled() {
+    unsafe {
+        SETUP_PHASE = SetupPhase::Ice;
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn ice_connected() {
+    unsafe {
+        SETUP_PHASE = SetupPhase::ChannelOpen;
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn disconnected() {
+    unsafe {
+        if let Some(event_queue) = &mut EVENT_QUEUE {
+            event_queue.push_back(SocketEvent::Disconnection);
         }
     }
 }
@@ -87,7 +150,20 @@ pub extern "C" fn error(error: JsObject) {
 
     unsafe {
         if let Some(error_queue) = &mut ERROR_QUEUE {
-            error_queue.push_back(error_string);
+            error_queue.push_back(NaiaClientSocketError::Signaling(error_string));
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn channel_error(reason: JsObject) {
+    let mut reason_string = String::new();
+
+    reason.to_string(&mut reason_string);
+
+    unsafe {
+        if let Some(error_queue) = &mut ERROR_QUEUE {
+            error_queue.push_back(NaiaClientSocketError::DataChannel(reason_string));
         }
     }
 }
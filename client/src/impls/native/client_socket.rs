@@ -1,74 +1,743 @@
 extern crate log;
 
+use bytes::{Bytes, BytesMut};
 use std::{
-    io::ErrorKind,
-    net::{SocketAddr, UdpSocket},
+    collections::VecDeque,
+    convert::TryInto,
+    io::{Error as IoError, ErrorKind},
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket},
+    time::Duration,
 };
 
-use naia_socket_shared::{find_available_port, find_my_ip_address, LinkConditionerConfig, Ref};
+use naia_socket_shared::{
+    acknowledgement::{AckTracker, ACK_HEADER_SIZE},
+    address_for_socket, bind_udp, canonical_address, coalescing, compression,
+    encryption::{self, KeyExchange, Protection, SessionKeys},
+    find_my_ip_address,
+    fragmentation::Reassembler,
+    handshake,
+    ping::{self, RttEstimator},
+    reliability::{self, ReliableChannels, RELIABLE_HEADER_SIZE},
+    sequence::ReceivedWindow,
+    set_traffic_class, ChannelMode, ConditionerHandle, ConditionerStats, Delivery, HandshakeError,
+    LinkConditionerConfig, PacketDirection, PacketType, Ref, Timer,
+};
 
-use crate::{link_conditioner::LinkConditioner, ClientSocketTrait, MessageSender};
+use crate::{
+    link_conditioner::Conditioner,
+    middleware::{MiddlewareChain, SocketMiddleware},
+    packet_tap::PacketTap,
+    state_machine::StateMachine,
+    ClientSocketTrait, ConnectionState, MessageSender, SocketConfig, SocketEvent,
+};
 
 use crate::{error::NaiaClientSocketError, Packet};
 
+use super::message_sender::SharedConnection;
+
+const CONNECT_REQUEST_INTERVAL: Duration = Duration::from_millis(500);
+const RECEIVE_BUFFER_SIZE: usize = 1472;
+
 /// A client-side socket which communicates with an underlying unordered &
 /// unreliable protocol
 #[derive(Debug)]
 pub struct ClientSocket {
-    address: SocketAddr,
     socket: Ref<UdpSocket>,
-    receive_buffer: Vec<u8>,
+    receive_buffer: BytesMut,
     message_sender: MessageSender,
+    connection_token: Ref<Option<u64>>,
+    resumption_token: Option<u128>,
+    // the host the Server last told us to move to, so that the announcement is
+    // only reported once
+    host_migration: Option<SocketAddr>,
+    // packets which arrived coalesced, waiting to be received one by one
+    received_packets: VecDeque<Packet>,
+    reassembler: Option<Reassembler>,
+    reliable: Ref<Option<ReliableChannels>>,
+    acks: Ref<Option<AckTracker>>,
+    // whether packets' payloads are compressed, as agreed on while connecting
+    compression: Ref<bool>,
+    // kept for as long as the Server is, so that whichever of our challenge
+    // responses it answers, its keys match ours
+    key_exchange: Option<KeyExchange>,
+    session_keys: Ref<Option<SessionKeys>>,
+    // the sequence numbers of the sealed packets received, as each must only
+    // be opened once
+    received_window: ReceivedWindow<u64>,
+    rtt: Option<RttEstimator>,
+    connect_timer: Timer,
+    // rings once the Server has been quiet for the idle timeout
+    idle_timer: Option<Timer>,
+    state_machine: StateMachine,
+    packet_tap: PacketTap,
+    middleware: MiddlewareChain,
+    // a clone of the link conditioner stacked on the socket, if there is one
+    conditioner: Option<Conditioner>,
+    config: SocketConfig,
 }
 
 impl ClientSocket {
     /// Returns a new ClientSocket, connected to the given socket address
-    pub fn connect(server_socket_address: SocketAddr) -> Box<dyn ClientSocketTrait> {
-        let client_ip_address = find_my_ip_address().expect("cannot find current ip address");
-        let free_socket = find_available_port(&client_ip_address).expect("no available ports");
-        let client_socket_address = format!("{}:{}", client_ip_address, free_socket);
+    pub fn connect(
+        server_socket_address: SocketAddr,
+        config: SocketConfig,
+    ) -> Box<dyn ClientSocketTrait> {
+        if config.connect_payload.as_ref().map_or(0, Vec::len) > handshake::MAX_CONNECT_PAYLOAD_SIZE
+        {
+            log::warn!("Connect payload is too large, the Server will ignore the handshake");
+        }
+
+        let server_socket_address = canonical_address(server_socket_address);
+        let mut port_search = config.port_search.clone();
+        if port_search.ip.is_none() {
+            // a socket only reaches a Server of its own address family, unless
+            // it is a dual-stack one
+            port_search.ip = Some(match find_my_ip_address() {
+                Some(ip) if ip.is_ipv4() == server_socket_address.is_ipv4() => ip,
+                _ if server_socket_address.is_ipv4() => Ipv4Addr::UNSPECIFIED.into(),
+                _ => Ipv6Addr::UNSPECIFIED.into(),
+            });
+        }
+        let client_socket_address = port_search.find().expect("no available ports");
 
-        let socket = Ref::new(UdpSocket::bind(client_socket_address).unwrap());
+        let socket = Ref::new(bind_udp(client_socket_address).unwrap());
+        // connecting the socket means the OS filters out traffic from anyone but
+        // the Server, & reports when the Server is unreachable
+        let ipv6 = client_socket_address.is_ipv6();
+        socket
+            .borrow()
+            .connect(address_for_socket(server_socket_address, ipv6))
+            .expect("can't connect socket to server address!");
         socket
             .borrow()
             .set_nonblocking(true)
             .expect("can't set socket to non-blocking!");
+        if config.dscp.is_some() || config.ecn {
+            let dscp = config.dscp.unwrap_or(0);
+            if let Err(err) = set_traffic_class(&socket.borrow(), dscp, config.ecn) {
+                log::warn!("Can't mark outgoing packets: {}", err);
+            }
+        }
+
+        let connection_token = Ref::new(None);
+        let reliable = Ref::new(None);
+        let acks = Ref::new(None);
+        let compression = Ref::new(false);
+        let session_keys = Ref::new(None);
+        let local_address = socket
+            .borrow()
+            .local_addr()
+            .unwrap_or_else(|_| SocketAddr::from(([0, 0, 0, 0], 0)));
+        let packet_tap = PacketTap::new(&config, local_address, server_socket_address);
+        let middleware = MiddlewareChain::new();
+
+        let message_sender = MessageSender::new(
+            socket.clone(),
+            SharedConnection {
+                token: connection_token.clone(),
+                reliable: reliable.clone(),
+                acks: acks.clone(),
+                compression: compression.clone(),
+                session_keys: session_keys.clone(),
+            },
+            packet_tap.clone(),
+            middleware.clone(),
+            &config,
+        );
 
-        let message_sender = MessageSender::new(server_socket_address, socket.clone());
+        let mut connect_timer = Timer::new(CONNECT_REQUEST_INTERVAL);
+        connect_timer.ring_manual();
 
         Box::new(ClientSocket {
-            address: server_socket_address,
             socket,
-            receive_buffer: vec![0; 1472],
+            receive_buffer: BytesMut::with_capacity(RECEIVE_BUFFER_SIZE),
             message_sender,
+            connection_token,
+            resumption_token: None,
+            host_migration: None,
+            received_packets: VecDeque::new(),
+            reassembler: config.fragmentation.clone().map(Reassembler::new),
+            reliable,
+            acks,
+            compression,
+            key_exchange: config.protection().map(|_| KeyExchange::new()),
+            session_keys,
+            received_window: ReceivedWindow::new(),
+            rtt: config.ping_interval.map(RttEstimator::new),
+            connect_timer,
+            idle_timer: config.idle_timeout.map(Timer::new),
+            state_machine: StateMachine::new(&config),
+            packet_tap,
+            middleware,
+            conditioner: None,
+            config,
         })
     }
-}
 
-impl ClientSocketTrait for ClientSocket {
-    fn receive(&mut self) -> Result<Option<Packet>, NaiaClientSocketError> {
-        let buffer: &mut [u8] = self.receive_buffer.as_mut();
-        match self
-            .socket
-            .borrow()
-            .recv_from(buffer)
-            .map(move |(recv_len, address)| (&buffer[..recv_len], address))
+    fn is_handshaking(&self) -> bool {
+        self.state_machine.state() == ConnectionState::Connecting
+    }
+
+    fn send_connect_request(&mut self) -> Result<(), IoError> {
+        self.connect_timer.reset();
+
+        let mut request = Vec::with_capacity(handshake::CONNECT_REQUEST_SIZE);
+        request.push(PacketType::ClientConnectRequest.to_byte());
+        handshake::write_header(&mut request);
+        request.resize(handshake::CONNECT_REQUEST_SIZE, 0);
+
+        trace_event!(DEBUG, "connect request sent");
+        self.send_handshake_packet(&request)
+    }
+
+    fn send_challenge_response(&mut self, cookie: &[u8]) -> Result<(), IoError> {
+        let connect_token = self.config.connect_token.as_deref().unwrap_or(&[]);
+        let connect_payload = self.config.connect_payload.as_deref().unwrap_or(&[]);
+
+        let mut response = Vec::with_capacity(
+            2 + handshake::HANDSHAKE_HEADER_SIZE
+                + cookie.len()
+                + 2
+                + connect_token.len()
+                + 2
+                + connect_payload.len()
+                + encryption::PUBLIC_KEY_SIZE
+                + handshake::RESUMPTION_TOKEN_SIZE,
+        );
+        response.push(PacketType::ClientChallengeResponse.to_byte());
+        handshake::write_header(&mut response);
+        let mut features = 0;
+        if self.config.compression.is_some() {
+            features |= handshake::FEATURE_COMPRESSION;
+        }
+        if let Some(protection) = self.config.protection() {
+            features |= protection.feature();
+            if self.config.server_public_key.is_some() {
+                features |= handshake::FEATURE_PINNED_KEY;
+            }
+        }
+        response.push(features);
+        response.extend_from_slice(cookie);
+        handshake::write_connect_token(&mut response, connect_token);
+        handshake::write_connect_payload(&mut response, connect_payload);
+        if let Some(key_exchange) = &self.key_exchange {
+            response.extend_from_slice(key_exchange.public_key());
+        }
+        // lets the Server hand back our previous connection, if it still has it
+        if let Some(resumption_token) = self.resumption_token {
+            response.extend_from_slice(&resumption_token.to_be_bytes());
+        }
+
+        trace_event!(DEBUG, "challenge response sent");
+        self.send_handshake_packet(&response)
+    }
+
+    fn send_handshake_packet(&self, packet: &[u8]) -> Result<(), IoError> {
+        match self.socket.borrow().send(packet) {
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock => Ok(()),
+            result => result.map(|_| ()),
+        }
+    }
+
+    fn accept_connection(&mut self, token: u64, features: u8, session_keys: Option<SessionKeys>) {
+        *self.connection_token.borrow_mut() = Some(token);
+        *self.session_keys.borrow_mut() = session_keys;
+        self.received_window = ReceivedWindow::new();
+        *self.compression.borrow_mut() =
+            features & handshake::FEATURE_COMPRESSION != 0 && self.config.compression.is_some();
+        // the Server starts every connection's reliable channels afresh
+        *self.reliable.borrow_mut() = self.config.reliability.as_ref().map(ReliableChannels::new);
+        *self.acks.borrow_mut() = self.config.acknowledgement.clone().map(AckTracker::new);
+        // round trips to a previous Server say nothing about this one
+        self.rtt = self.config.ping_interval.map(RttEstimator::new);
+        if let Some(idle_timer) = &mut self.idle_timer {
+            idle_timer.reset();
+        }
+        self.state_machine.connected();
+        self.message_sender.send_unsent();
+    }
+
+    // Reads the Server's connect response, returning the connection token, the
+    // features agreed to, the resumption token, & the keys packets are sealed
+    // with if protecting them. Returns `None` if it is malformed, or the Server
+    // didn't agree to the protection asked for
+    fn read_connect_response(
+        &self,
+        response: &[u8],
+    ) -> Option<(u64, u8, Option<u128>, Option<SessionKeys>)> {
+        let token = u64::from_be_bytes(response.get(1..9)?.try_into().unwrap());
+        // the optional features the Server has agreed to
+        let features = *response.get(9)?;
+
+        let (key_exchange, protection) = match (&self.key_exchange, self.config.protection()) {
+            (Some(key_exchange), Some(protection)) => (key_exchange, protection),
+            _ => {
+                let resumption_token = response
+                    .get(10..10 + handshake::RESUMPTION_TOKEN_SIZE)
+                    .map(|bytes| u128::from_be_bytes(bytes.try_into().unwrap()));
+                return Some((token, features, resumption_token, None));
+            }
+        };
+        if Protection::from_features(features) != Some(protection) {
+            log::warn!(
+                "The Server didn't agree to {:?} packet protection, so it won't be connected to",
+                protection
+            );
+            return None;
+        }
+        let public_key_end = 10 + encryption::PUBLIC_KEY_SIZE;
+        let server_public_key = response.get(10..public_key_end)?.try_into().unwrap();
+        let session_keys = key_exchange.client_keys(
+            server_public_key,
+            self.config.server_public_key.as_ref(),
+            protection,
+        )?;
+        let resumption_token = match session_keys.open(
+            0,
+            &response[..public_key_end],
+            &response[public_key_end..],
+        ) {
+            Some(resumption_token) => resumption_token,
+            None => {
+                if self.config.server_public_key.is_some() {
+                    log::warn!("A connect response didn't come from the Server whose key is pinned, ignoring it");
+                }
+                return None;
+            }
+        };
+        let resumption_token = u128::from_be_bytes(resumption_token.try_into().ok()?);
+        Some((token, features, Some(resumption_token), Some(session_keys)))
+    }
+
+    // Opens a sealed packet received from the Server, if the connection is
+    // protected, returning it as it was before it was sealed. Returns `None`
+    // if it wasn't sealed with the connection's keys, or was opened before
+    fn open(&mut self, packet: Bytes) -> Option<Bytes> {
+        let session_keys = self.session_keys.borrow();
+        let session_keys = match session_keys.as_ref() {
+            Some(session_keys) => session_keys,
+            None => return Some(packet),
+        };
+        match packet.first().copied().and_then(PacketType::from_byte) {
+            Some(packet_type) if packet_type.is_sealed() => {}
+            _ => return Some(packet),
+        }
+
+        let sequence = u64::from_be_bytes(packet.get(1..9)?.try_into().unwrap());
+        let body = session_keys.open(sequence, &packet[..9], &packet[9..])?;
+        if !self.received_window.check(sequence).is_accepted() {
+            return None;
+        }
+        Some(Bytes::from([&packet[..1], &body].concat()))
+    }
+
+    // Strips the number & ack from the front of an unreliable packet received
+    // from the Server, dropping it if it has been received before, &
+    // decompresses it
+    fn receive_acked(&mut self, payload: Bytes) -> Option<Packet> {
+        let new = match self.acks.borrow_mut().as_mut() {
+            Some(acks) => acks.read_header(&payload),
+            None => return self.decompress(payload).map(Packet::from_bytes),
+        };
+        if !new {
+            return None;
+        }
+        self.decompress(payload.slice(ACK_HEADER_SIZE..))
+            .map(Packet::from_bytes)
+    }
+
+    // Decompresses the payload of a packet received from the Server, if
+    // compression was agreed on, returning `None` if it is malformed
+    fn decompress(&self, payload: Bytes) -> Option<Bytes> {
+        let config = match &self.config.compression {
+            Some(config) if *self.compression.borrow() => config,
+            _ => return Some(payload),
+        };
+        match payload.first().copied() {
+            Some(compression::STORED) => Some(payload.slice(1..)),
+            Some(compression::DEFLATED) => {
+                compression::decompress(&payload[1..], config.max_decompressed_size)
+                    .map(Bytes::from)
+            }
+            _ => None,
+        }
+    }
+
+    // Gets an event for the next packet sent to the Server whose delivery is
+    // now known
+    fn poll_delivery(&mut self) -> Option<SocketEvent> {
+        let delivery = self.acks.borrow_mut().as_mut()?.poll_delivery()?;
+        Some(match delivery {
+            Delivery::Acked(ack_id) => SocketEvent::PacketAcked(ack_id),
+            Delivery::Lost(ack_id) => SocketEvent::PacketLost(ack_id),
+        })
+    }
+
+    // The OS has told us the Server can't be reached, either because it has gone
+    // away or because it was never there
+    fn connection_refused(&mut self) -> Result<Option<SocketEvent>, NaiaClientSocketError> {
+        match self.state_machine.state() {
+            ConnectionState::Connected => {
+                *self.connection_token.borrow_mut() = None;
+                self.state_machine.connection_lost();
+            }
+            // without auto_reconnect, keep sending connect requests until the
+            // Server appears
+            ConnectionState::Connecting if self.config.auto_reconnect.is_some() => {
+                self.state_machine.attempt_failed()?;
+            }
+            _ => {}
+        }
+        Ok(self.state_machine.pop_event())
+    }
+
+    fn handle_io_error(
+        &mut self,
+        error: IoError,
+    ) -> Result<Option<SocketEvent>, NaiaClientSocketError> {
+        if error.kind() == ErrorKind::ConnectionRefused {
+            return self.connection_refused();
+        }
+        Err(NaiaClientSocketError::Io(error))
+    }
+
+    // Gets the next event, before it is dumped
+    fn receive_event(&mut self) -> Result<Option<SocketEvent>, NaiaClientSocketError> {
+        if let Some(event) = self.state_machine.pop_event() {
+            return Ok(Some(event));
+        }
+
+        if self.state_machine.poll_connect_timeout()? {
+            // stops sending connect requests, as the state is no longer Connecting
+            return Ok(self.state_machine.pop_event());
+        }
+
+        if self.state_machine.poll_reconnect() {
+            self.connect_timer.ring_manual();
+            return Ok(self.state_machine.pop_event());
+        }
+
+        if self.state_machine.state() == ConnectionState::Connected
+            && self.idle_timer.as_ref().is_some_and(Timer::ringing)
         {
-            Ok((payload, address)) => {
-                if address == self.address {
-                    return Ok(Some(Packet::new(payload.to_vec())));
-                } else {
-                    return Err(NaiaClientSocketError::Message(
-                        "Unknown sender.".to_string(),
-                    ));
+            // the Server has gone quiet, as if it had gone away
+            *self.connection_token.borrow_mut() = None;
+            self.state_machine.connection_lost();
+            return Ok(self.state_machine.pop_event());
+        }
+
+        if self.is_handshaking() && self.connect_timer.ringing() {
+            if let Err(err) = self.send_connect_request() {
+                return self.handle_io_error(err);
+            }
+        }
+
+        if let Err(err) = self.message_sender.flush_if_due() {
+            log::info!("Can't send coalesced packets: {:?}", err);
+        }
+
+        if self.state_machine.state() == ConnectionState::Connected {
+            if let Some(rtt) = self.rtt.as_mut().filter(|rtt| rtt.ping_due()) {
+                let ping = rtt.write_ping();
+                if let Err(err) = self.message_sender.send_ping(PacketType::Ping, &ping) {
+                    log::info!("Can't send ping: {:?}", err);
                 }
             }
-            Err(ref e) if e.kind() == ErrorKind::WouldBlock => {
-                //just didn't receive anything this time
-                return Ok(None);
+        }
+
+        if let Some(packet) = self.received_packets.pop_front() {
+            return Ok(Some(SocketEvent::Packet(packet)));
+        }
+
+        if let Some(event) = self.poll_delivery() {
+            return Ok(Some(event));
+        }
+
+        loop {
+            // Once every packet split off the buffer has been dropped, this
+            // reuses its allocation rather than making a new one
+            self.receive_buffer.resize(RECEIVE_BUFFER_SIZE, 0);
+            let received = self.socket.borrow().recv(&mut self.receive_buffer[..]);
+            match received {
+                Ok(recv_len) => {
+                    trace_event!(TRACE, bytes = recv_len, "datagram received");
+                    let payload = self.receive_buffer.split_to(recv_len).freeze();
+                    let payload = match self.open(payload) {
+                        Some(payload) => payload,
+                        None => continue,
+                    };
+                    if let Some(idle_timer) = &mut self.idle_timer {
+                        idle_timer.reset();
+                    }
+                    match payload.first().copied().and_then(PacketType::from_byte) {
+                        Some(PacketType::ServerChallenge)
+                            if self.is_handshaking()
+                                && payload.len() == 1 + handshake::CHALLENGE_COOKIE_SIZE =>
+                        {
+                            let cookie = payload[1..].to_vec();
+                            if let Err(err) = self.send_challenge_response(&cookie) {
+                                return self.handle_io_error(err);
+                            }
+                        }
+                        Some(PacketType::ServerConnectResponse) => {
+                            if !self.is_handshaking() {
+                                continue;
+                            }
+                            if let Some((token, features, resumption_token, session_keys)) =
+                                self.read_connect_response(&payload)
+                            {
+                                self.resumption_token = resumption_token;
+                                self.accept_connection(token, features, session_keys);
+                                return Ok(self.state_machine.pop_event());
+                            }
+                        }
+                        Some(PacketType::ServerQueuePosition)
+                            if self.is_handshaking() && payload.len() == 5 =>
+                        {
+                            let position = u32::from_be_bytes(payload[1..5].try_into().unwrap());
+                            self.state_machine.queued(position);
+                            if let Some(event) = self.state_machine.pop_event() {
+                                return Ok(Some(event));
+                            }
+                        }
+                        Some(PacketType::ServerFull) if self.is_handshaking() => {
+                            self.state_machine.attempt_failed()?;
+                            return Err(HandshakeError::ServerFull.into());
+                        }
+                        Some(PacketType::ServerDuplicateConnection) if self.is_handshaking() => {
+                            self.state_machine.attempt_failed()?;
+                            return Err(HandshakeError::DuplicateConnection.into());
+                        }
+                        Some(PacketType::ServerNotAccepting) if self.is_handshaking() => {
+                            self.state_machine.attempt_failed()?;
+                            return Err(HandshakeError::NotAccepting.into());
+                        }
+                        Some(PacketType::ServerVersionMismatch) => {
+                            if !self.is_handshaking() {
+                                continue;
+                            }
+                            if let Some(server_version) = handshake::read_header(&payload[1..]) {
+                                // stop trying to connect, this will never succeed
+                                self.state_machine.failed();
+                                return Err(HandshakeError::VersionMismatch {
+                                    client_version: handshake::PROTOCOL_VERSION,
+                                    server_version,
+                                }
+                                .into());
+                            }
+                        }
+                        Some(PacketType::ServerDisconnect) => {
+                            let connection_token = *self.connection_token.borrow();
+                            // the token shows it really came from the Server
+                            if payload.len() >= 9
+                                && connection_token
+                                    == Some(u64::from_be_bytes(payload[1..9].try_into().unwrap()))
+                            {
+                                let reason = Packet::from_bytes(payload.slice(9..));
+                                *self.connection_token.borrow_mut() = None;
+                                self.resumption_token = None;
+                                self.state_machine.kicked(reason);
+                                if let Some(event) = self.state_machine.pop_event() {
+                                    return Ok(Some(event));
+                                }
+                            }
+                        }
+                        Some(PacketType::ServerHostMigration) => {
+                            let connection_token = *self.connection_token.borrow();
+                            if payload.len() < 9
+                                || connection_token
+                                    != Some(u64::from_be_bytes(payload[1..9].try_into().unwrap()))
+                            {
+                                continue;
+                            }
+                            let new_address = std::str::from_utf8(&payload[9..])
+                                .ok()
+                                .and_then(|address| address.parse::<SocketAddr>().ok())
+                                .map(canonical_address);
+                            if let Some(new_address) = new_address {
+                                if self.host_migration != Some(new_address) {
+                                    self.host_migration = Some(new_address);
+                                    return Ok(Some(SocketEvent::HostMigration(new_address)));
+                                }
+                            }
+                        }
+                        Some(PacketType::ServerMtuProbe) => {
+                            let connection_token = *self.connection_token.borrow();
+                            if payload.len() < 9
+                                || connection_token
+                                    != Some(u64::from_be_bytes(payload[1..9].try_into().unwrap()))
+                            {
+                                continue;
+                            }
+                            // tells the Server this size gets through
+                            if let Err(err) = self.message_sender.send_mtu_probe_ack(recv_len) {
+                                log::info!("Can't acknowledge MTU probe: {:?}", err);
+                            }
+                        }
+                        Some(PacketType::Data) => {
+                            if self.state_machine.state() != ConnectionState::Connected {
+                                continue;
+                            }
+                            if let Some(packet) = self.receive_acked(payload.slice(1..)) {
+                                return Ok(Some(SocketEvent::Packet(packet)));
+                            }
+                        }
+                        Some(PacketType::Coalesced) => {
+                            if self.state_machine.state() != ConnectionState::Connected {
+                                continue;
+                            }
+                            let subframes = payload.slice(1..);
+                            for subframe in
+                                coalescing::read_subframes(&subframes).unwrap_or_default()
+                            {
+                                if let Some(packet) = self.receive_acked(subframes.slice(subframe))
+                                {
+                                    self.received_packets.push_back(packet);
+                                }
+                            }
+                            if let Some(packet) = self.received_packets.pop_front() {
+                                return Ok(Some(SocketEvent::Packet(packet)));
+                            }
+                        }
+                        Some(PacketType::Reliable) => {
+                            if self.state_machine.state() != ConnectionState::Connected {
+                                continue;
+                            }
+                            let (channel, id, message) =
+                                match reliability::read_header(&payload[1..]) {
+                                    Some(header) => header,
+                                    None => continue,
+                                };
+                            let delivered = self
+                                .reliable
+                                .borrow_mut()
+                                .as_mut()
+                                .and_then(|reliable| reliable.receive(channel, id, message));
+                            let delivered = match delivered {
+                                Some(delivered) => delivered,
+                                None => continue,
+                            };
+                            if let Err(err) = self
+                                .message_sender
+                                .send_reliable_ack(&payload[1..1 + RELIABLE_HEADER_SIZE])
+                            {
+                                log::info!("Can't acknowledge reliable message: {:?}", err);
+                            }
+                            for payload in delivered {
+                                if let Some(payload) = self.decompress(Bytes::from(payload)) {
+                                    self.received_packets.push_back(
+                                        Packet::from_bytes(payload)
+                                            .with_channel(channel)
+                                            .with_reliable(),
+                                    );
+                                }
+                            }
+                            if let Some(packet) = self.received_packets.pop_front() {
+                                return Ok(Some(SocketEvent::Packet(packet)));
+                            }
+                        }
+                        Some(PacketType::ReliableAck) => {
+                            let (channel, id, _) = match reliability::read_header(&payload[1..]) {
+                                Some(header) => header,
+                                None => continue,
+                            };
+                            if let Some(reliable) = self.reliable.borrow_mut().as_mut() {
+                                reliable.acked(channel, id);
+                            }
+                        }
+                        Some(PacketType::ChannelData) => {
+                            if self.state_machine.state() != ConnectionState::Connected
+                                || payload.len() < 2
+                            {
+                                continue;
+                            }
+                            let channel = payload[1];
+                            let mode = self
+                                .reliable
+                                .borrow()
+                                .as_ref()
+                                .and_then(|reliable| reliable.mode(channel));
+                            if mode != Some(ChannelMode::Unreliable) {
+                                continue;
+                            }
+                            if let Some(payload) = self.decompress(payload.slice(2..)) {
+                                let packet = Packet::from_bytes(payload).with_channel(channel);
+                                return Ok(Some(SocketEvent::Packet(packet)));
+                            }
+                        }
+                        Some(PacketType::Ack) => {
+                            if let Some(acks) = self.acks.borrow_mut().as_mut() {
+                                acks.read_ack(&payload[1..]);
+                            }
+                        }
+                        Some(PacketType::Ping) => {
+                            if self.state_machine.state() != ConnectionState::Connected {
+                                continue;
+                            }
+                            let pong = match ping::write_pong(&payload[1..]) {
+                                Some(pong) => pong,
+                                None => continue,
+                            };
+                            if let Err(err) = self.message_sender.send_ping(PacketType::Pong, &pong)
+                            {
+                                log::info!("Can't answer ping: {:?}", err);
+                            }
+                        }
+                        Some(PacketType::Pong) => {
+                            if let Some(rtt) = self.rtt.as_mut() {
+                                rtt.read_pong(&payload[1..]);
+                            }
+                        }
+                        Some(PacketType::Fragment) => {
+                            if self.state_machine.state() != ConnectionState::Connected {
+                                continue;
+                            }
+                            let reassembled = self
+                                .reassembler
+                                .as_mut()
+                                .and_then(|reassembler| reassembler.receive(&payload[1..]));
+                            if let Some(packet) = reassembled.and_then(|reassembled| {
+                                self.receive_acked(Bytes::from(reassembled))
+                            }) {
+                                return Ok(Some(SocketEvent::Packet(packet)));
+                            }
+                        }
+                        _ => {
+                            // not a packet we understand, discard it
+                        }
+                    }
+                }
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => {
+                    //just didn't receive anything this time
+                    return Ok(self.poll_delivery());
+                }
+                Err(e) => {
+                    return self.handle_io_error(e);
+                }
             }
-            Err(e) => {
-                return Err(NaiaClientSocketError::Wrapped(Box::new(e)));
+        }
+    }
+}
+
+impl ClientSocketTrait for ClientSocket {
+    fn receive(&mut self) -> Result<Option<SocketEvent>, NaiaClientSocketError> {
+        loop {
+            match self.receive_event() {
+                Ok(Some(SocketEvent::Packet(packet))) => {
+                    let size = packet.payload().len();
+                    if self.config.max_payload_size.is_some_and(|max| size > max) {
+                        return Ok(Some(SocketEvent::OversizedPacket { size }));
+                    }
+                    self.packet_tap
+                        .tap(PacketDirection::Incoming, packet.payload());
+                    if let Some(packet) = self.middleware.inbound(packet) {
+                        return Ok(Some(SocketEvent::Packet(packet)));
+                    }
+                }
+                Ok(None) => {
+                    return Ok(self.middleware.release_inbound().map(SocketEvent::Packet));
+                }
+                event => return event,
             }
         }
     }
@@ -77,10 +746,82 @@ impl ClientSocketTrait for ClientSocket {
         return self.message_sender.clone();
     }
 
+    fn state(&self) -> ConnectionState {
+        self.state_machine.state()
+    }
+
+    fn disconnect(&mut self) {
+        // anything sent before disconnecting goes out first
+        if let Err(err) = self.message_sender.flush() {
+            log::info!("Can't send coalesced packets: {:?}", err);
+        }
+        if let Err(err) = self.message_sender.send_disconnect() {
+            log::info!("Can't tell the Server we're disconnecting: {:?}", err);
+        }
+        *self.connection_token.borrow_mut() = None;
+        self.resumption_token = None;
+        self.state_machine.disconnected();
+    }
+
+    fn switch_server(&mut self, server_address: SocketAddr) -> Result<(), NaiaClientSocketError> {
+        let server_address = canonical_address(server_address);
+        let ipv6 = self.socket.borrow().local_addr()?.is_ipv6();
+        self.socket
+            .borrow()
+            .connect(address_for_socket(server_address, ipv6))?;
+
+        self.packet_tap.set_server_address(server_address);
+        // the new Server knows nothing of our previous connection
+        *self.connection_token.borrow_mut() = None;
+        self.resumption_token = None;
+        self.host_migration = None;
+        if self.key_exchange.is_some() {
+            self.key_exchange = Some(KeyExchange::new());
+        }
+        self.state_machine.switching_server();
+        self.connect_timer.ring_manual();
+        Ok(())
+    }
+
     fn with_link_conditioner(
-        self: Box<Self>,
+        mut self: Box<Self>,
         config: &LinkConditionerConfig,
     ) -> Box<dyn ClientSocketTrait> {
-        Box::new(LinkConditioner::new(config, self))
+        let conditioner = Conditioner::new(ConditionerHandle::new(config));
+        self.middleware.push(Box::new(conditioner.clone()));
+        self.conditioner = Some(conditioner);
+        self
+    }
+
+    fn with_middleware(
+        self: Box<Self>,
+        middleware: Box<dyn SocketMiddleware>,
+    ) -> Box<dyn ClientSocketTrait> {
+        self.middleware.push(middleware);
+        self
+    }
+
+    fn conditioner_handle(&self) -> Option<ConditionerHandle> {
+        self.conditioner.as_ref().map(Conditioner::handle)
+    }
+
+    fn conditioner_stats(&self) -> Option<ConditionerStats> {
+        self.conditioner.as_ref().map(Conditioner::stats)
+    }
+
+    fn rtt(&self) -> Option<Duration> {
+        self.rtt.as_ref().and_then(RttEstimator::rtt)
+    }
+
+    fn jitter(&self) -> Option<Duration> {
+        self.rtt.as_ref().and_then(RttEstimator::jitter)
+    }
+
+    fn server_time_estimate(&self) -> Option<Duration> {
+        self.rtt.as_ref().and_then(RttEstimator::remote_time)
+    }
+
+    fn local_address(&self) -> Option<SocketAddr> {
+        self.socket.borrow().local_addr().ok()
     }
 }
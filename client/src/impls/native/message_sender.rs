@@ -1,32 +1,429 @@
-use std::net::{SocketAddr, UdpSocket};
+use std::{
+    collections::VecDeque,
+    net::UdpSocket,
+    time::{Duration, Instant},
+};
 
-use crate::Packet;
-use naia_socket_shared::Ref;
-use std::error::Error;
+use crate::{
+    error::NaiaClientSocketError, middleware::MiddlewareChain, packet_tap::PacketTap, Packet,
+    SocketConfig,
+};
+use naia_socket_shared::{
+    acknowledgement::AckTracker,
+    clock,
+    coalescing::{self, SUBFRAME_HEADER_SIZE},
+    compression,
+    encryption::{self, SessionKeys},
+    fragmentation::{self, FRAGMENT_HEADER_SIZE, MAX_FRAGMENTS},
+    reliability::ReliableChannels,
+    ChannelMode, CompressionConfig, FragmentationConfig, PacketDirection, PacketType, Priority,
+    Ref,
+};
+
+// Client Data packets are preceded by the packet type, the connection token &
+// the packet's sequence number
+const DATA_HEADER_SIZE: usize = 17;
+// Packets which close the connection are sent several times, as they may be
+// the last the Server hears from us
+const CONTROL_REDUNDANCY: usize = 3;
 
 /// Handles sending messages to the Server for a given Client Socket
 #[derive(Clone, Debug)]
 pub struct MessageSender {
-    address: SocketAddr,
     socket: Ref<UdpSocket>,
+    connection_token: Ref<Option<u64>>,
+    next_sequence: Ref<u64>,
+    next_fragment_id: Ref<u16>,
+    // packets sent before the connection was accepted, & when they were sent
+    unsent_outgoing_messages: Ref<VecDeque<(Packet, Instant)>>,
+    expired_packets: Ref<u64>,
+    coalesced: Ref<Option<CoalescedDatagram>>,
+    reliable: Ref<Option<ReliableChannels>>,
+    acks: Ref<Option<AckTracker>>,
+    compression: Ref<bool>,
+    compression_config: Option<CompressionConfig>,
+    session_keys: Ref<Option<SessionKeys>>,
+    mtu: usize,
+    max_payload_size: Option<usize>,
+    fragmentation: Option<FragmentationConfig>,
+    coalesce_interval: Option<Duration>,
+    packet_tap: PacketTap,
+    middleware: MiddlewareChain,
+}
+
+// The state of the connection a Client Socket shares with its MessageSender
+#[derive(Clone, Debug)]
+pub(crate) struct SharedConnection {
+    pub(crate) token: Ref<Option<u64>>,
+    pub(crate) reliable: Ref<Option<ReliableChannels>>,
+    pub(crate) acks: Ref<Option<AckTracker>>,
+    pub(crate) compression: Ref<bool>,
+    pub(crate) session_keys: Ref<Option<SessionKeys>>,
+}
+
+// Small packets packed together while coalescing, waiting to be sent
+#[derive(Debug)]
+struct CoalescedDatagram {
+    subframes: Vec<u8>,
+    packets: usize,
+    started: Instant,
 }
 
 impl MessageSender {
-    /// Create a new MessageSender, if supplied with the Server's address & a
-    /// reference back to the parent Socket
-    pub fn new(address: SocketAddr, socket: Ref<UdpSocket>) -> MessageSender {
-        MessageSender { address, socket }
+    /// Create a new MessageSender, if supplied with a reference back to the
+    /// parent Socket (which must be connected to the Server), the state of the
+    /// connection it shares with the Socket (the connection token the Server
+    /// has assigned once it has, the state of the reliable channels & of the
+    /// acknowledgements, whether compression has been agreed on & the keys
+    /// packets are sealed with if encrypting), where to tap packets to, the
+    /// middleware stacked on the socket & its config
+    pub(crate) fn new(
+        socket: Ref<UdpSocket>,
+        connection: SharedConnection,
+        packet_tap: PacketTap,
+        middleware: MiddlewareChain,
+        config: &SocketConfig,
+    ) -> MessageSender {
+        let SharedConnection {
+            token: connection_token,
+            reliable,
+            acks,
+            compression,
+            session_keys,
+        } = connection;
+        // sealed packets gain a tag, which must fit too
+        let mtu = if config.protection().is_some() {
+            config.mtu.saturating_sub(encryption::TAG_SIZE)
+        } else {
+            config.mtu
+        };
+        MessageSender {
+            socket,
+            connection_token,
+            next_sequence: Ref::new(0),
+            next_fragment_id: Ref::new(0),
+            unsent_outgoing_messages: Ref::new(VecDeque::new()),
+            expired_packets: Ref::new(0),
+            coalesced: Ref::new(None),
+            reliable,
+            acks,
+            compression,
+            compression_config: config.compression.clone(),
+            session_keys,
+            mtu,
+            max_payload_size: config.max_payload_size,
+            fragmentation: config.fragmentation.clone(),
+            coalesce_interval: config.coalesce_interval,
+            packet_tap,
+            middleware,
+        }
+    }
+
+    /// Send a Packet to the Server. Packets sent before the connection has
+    /// been accepted are held back until it is, & then sent most urgent first.
+    /// If coalescing, small packets which aren't High priority are held back
+    /// until they are flushed
+    pub fn send(&mut self, packet: Packet) -> Result<(), NaiaClientSocketError> {
+        NaiaClientSocketError::check_payload_size(packet.payload().len(), self.max_payload_size)?;
+        let packet = match self.middleware.outbound(packet) {
+            Some(packet) => packet,
+            None => return Ok(()),
+        };
+        self.packet_tap
+            .tap(PacketDirection::Outgoing, packet.payload());
+        self.send_packet(packet)
+    }
+
+    // Sends a Packet which has already been tapped
+    fn send_packet(&mut self, packet: Packet) -> Result<(), NaiaClientSocketError> {
+        let token = match *self.connection_token.borrow() {
+            Some(token) => token,
+            None => {
+                self.unsent_outgoing_messages
+                    .borrow_mut()
+                    .push_back((packet, clock::now()));
+                return Ok(());
+            }
+        };
+
+        let compressed_payload;
+        let payload = match &self.compression_config {
+            Some(config) if *self.compression.borrow() => {
+                compressed_payload = compression::compress(packet.payload(), config);
+                &compressed_payload[..]
+            }
+            _ => packet.payload(),
+        };
+        if let Some(channel) = packet.channel() {
+            let mode = self
+                .reliable
+                .borrow()
+                .as_ref()
+                .and_then(|reliable| reliable.mode(channel));
+            if mode == Some(ChannelMode::Unreliable) {
+                return self.send_datagram(PacketType::ChannelData, token, &[&[channel], payload]);
+            }
+            let queued = match self.reliable.borrow_mut().as_mut() {
+                Some(reliable) => reliable.send(channel, payload.to_vec()),
+                None => false,
+            };
+            if !queued {
+                return Err(NaiaClientSocketError::UnknownChannel(channel));
+            }
+            return self.send_reliable(token);
+        }
+
+        // the packet's number & an ack go in front of its payload
+        let header = self
+            .acks
+            .borrow_mut()
+            .as_mut()
+            .map(|acks| acks.write_header(packet.ack_id()));
+        let acked_payload;
+        let payload = match header {
+            Some(header) => {
+                acked_payload = [&header[..], payload].concat();
+                &acked_payload[..]
+            }
+            None => payload,
+        };
+
+        // urgent packets aren't held back, & so overtake those being coalesced
+        if self.coalesce_interval.is_some() && packet.priority() != Priority::High {
+            let subframe_len = SUBFRAME_HEADER_SIZE + payload.len();
+            if DATA_HEADER_SIZE + subframe_len <= self.mtu {
+                let full = self.coalesced.borrow().as_ref().is_some_and(|coalesced| {
+                    DATA_HEADER_SIZE + coalesced.subframes.len() + subframe_len > self.mtu
+                });
+                if full {
+                    self.flush()?;
+                }
+                let mut coalesced = self.coalesced.borrow_mut();
+                let coalesced = coalesced.get_or_insert_with(|| CoalescedDatagram {
+                    subframes: Vec::new(),
+                    packets: 0,
+                    started: clock::now(),
+                });
+                coalescing::write_subframe(&mut coalesced.subframes, payload);
+                coalesced.packets += 1;
+                return Ok(());
+            }
+            // keeps packets in the order they were sent
+            self.flush()?;
+        }
+        let config = match &self.fragmentation {
+            Some(config) if DATA_HEADER_SIZE + payload.len() > self.mtu => config,
+            _ => return self.send_datagram(PacketType::Data, token, &[payload]),
+        };
+
+        let id = {
+            let mut next_fragment_id = self.next_fragment_id.borrow_mut();
+            let id = *next_fragment_id;
+            *next_fragment_id = next_fragment_id.wrapping_add(1);
+            id
+        };
+        let chunk_size = self
+            .mtu
+            .saturating_sub(DATA_HEADER_SIZE + FRAGMENT_HEADER_SIZE);
+        let fragments = match fragmentation::split_into_fragments(payload, id, chunk_size) {
+            Some(fragments) if payload.len() <= config.max_packet_size => fragments,
+            _ => {
+                return Err(NaiaClientSocketError::PayloadTooLarge {
+                    size: payload.len(),
+                    max_payload_size: config.max_packet_size.min(MAX_FRAGMENTS * chunk_size),
+                });
+            }
+        };
+        for (header, data) in fragments {
+            self.send_datagram(PacketType::Fragment, token, &[&header, data])?;
+        }
+        return Ok(());
+    }
+
+    /// Gets the number of packets which were dropped because their time to
+    /// live ran out while they were held back, waiting for the connection to
+    /// be accepted
+    pub fn expired_packets(&self) -> u64 {
+        *self.expired_packets.borrow()
+    }
+
+    // Sends the packets held back until the connection was accepted, most
+    // urgent first, dropping those which have waited too long
+    pub(crate) fn send_unsent(&mut self) {
+        let mut unsent_packets: Vec<(Packet, Instant)> = self
+            .unsent_outgoing_messages
+            .borrow_mut()
+            .drain(..)
+            .collect();
+        unsent_packets.sort_by_key(|(packet, _)| packet.priority());
+        for (packet, queued) in unsent_packets {
+            // packets on reliable channels are never given up on
+            let reliable = packet.channel().is_some_and(|channel| {
+                self.reliable
+                    .borrow()
+                    .as_ref()
+                    .and_then(|reliable| reliable.mode(channel))
+                    != Some(ChannelMode::Unreliable)
+            });
+            let expired = packet
+                .ttl()
+                .is_some_and(|ttl| clock::elapsed(queued) >= ttl);
+            if expired && !reliable {
+                *self.expired_packets.borrow_mut() += 1;
+                continue;
+            }
+            self.send_packet(packet)
+                .unwrap_or_else(|err| log::info!("Can't send queued packet: {:?}", err));
+        }
     }
 
-    /// Send a Packet to the Server
-    pub fn send(&mut self, packet: Packet) -> Result<(), Box<dyn Error + Send>> {
+    /// Sends any small packets held back to be coalesced. This is done
+    /// automatically once they have waited for the coalesce interval, while
+    /// the socket is being received from
+    pub fn flush(&mut self) -> Result<(), NaiaClientSocketError> {
+        let coalesced = match self.coalesced.borrow_mut().take() {
+            Some(coalesced) => coalesced,
+            None => return Ok(()),
+        };
+        // the connection is gone, & the packets with it
+        let token = match *self.connection_token.borrow() {
+            Some(token) => token,
+            None => return Ok(()),
+        };
+
+        if coalesced.packets == 1 {
+            let payload = &coalesced.subframes[SUBFRAME_HEADER_SIZE..];
+            return self.send_datagram(PacketType::Data, token, &[payload]);
+        }
+        return self.send_datagram(PacketType::Coalesced, token, &[&coalesced.subframes]);
+    }
+
+    // Flushes the coalesced packets if they have waited long enough, & sends
+    // the reliable messages & the ack which are due
+    pub(crate) fn flush_if_due(&mut self) -> Result<(), NaiaClientSocketError> {
+        if let Some(interval) = self.coalesce_interval {
+            let due = self
+                .coalesced
+                .borrow()
+                .as_ref()
+                .is_some_and(|coalesced| clock::elapsed(coalesced.started) >= interval);
+            if due {
+                self.flush()?;
+            }
+        }
+
+        let token = match *self.connection_token.borrow() {
+            Some(token) => token,
+            None => return Ok(()),
+        };
+        self.send_reliable(token)?;
+
+        let ack = match self.acks.borrow_mut().as_mut() {
+            Some(acks) if acks.ack_due() => acks.write_ack(),
+            _ => return Ok(()),
+        };
+        return self.send_datagram(PacketType::Ack, token, &[&ack]);
+    }
+
+    // Acknowledges a reliable message, given its channel & message id header
+    pub(crate) fn send_reliable_ack(&mut self, header: &[u8]) -> Result<(), NaiaClientSocketError> {
+        let token = match *self.connection_token.borrow() {
+            Some(token) => token,
+            None => return Ok(()),
+        };
+        return self.send_datagram(PacketType::ReliableAck, token, &[header]);
+    }
+
+    // Sends a Ping, or a Pong answering one, carrying the given timestamp
+    pub(crate) fn send_ping(
+        &mut self,
+        packet_type: PacketType,
+        timestamp: &[u8],
+    ) -> Result<(), NaiaClientSocketError> {
+        let token = match *self.connection_token.borrow() {
+            Some(token) => token,
+            None => return Ok(()),
+        };
+        return self.send_datagram(packet_type, token, &[timestamp]);
+    }
+
+    // Tells the Server the connection is being closed
+    pub(crate) fn send_disconnect(&mut self) -> Result<(), NaiaClientSocketError> {
+        let token = match *self.connection_token.borrow() {
+            Some(token) => token,
+            None => return Ok(()),
+        };
+        for _ in 0..CONTROL_REDUNDANCY {
+            self.send_datagram(PacketType::ClientDisconnect, token, &[])?;
+        }
+        return Ok(());
+    }
+
+    // Tells the Server a probe of the given size, in bytes of UDP payload, got
+    // through
+    pub(crate) fn send_mtu_probe_ack(&mut self, size: usize) -> Result<(), NaiaClientSocketError> {
+        let token = match *self.connection_token.borrow() {
+            Some(token) => token,
+            None => return Ok(()),
+        };
+        let size = (size as u16).to_be_bytes();
+        return self.send_datagram(PacketType::ClientMtuProbeAck, token, &[&size]);
+    }
+
+    // Sends every reliable message which is due, for the first time or again
+    fn send_reliable(&mut self, token: u64) -> Result<(), NaiaClientSocketError> {
+        let outgoing = match self.reliable.borrow_mut().as_mut() {
+            Some(reliable) => reliable.outgoing(),
+            None => return Ok(()),
+        };
+        for message in outgoing {
+            let header = [message.channel];
+            let id = message.id.to_be_bytes();
+            self.send_datagram(
+                PacketType::Reliable,
+                token,
+                &[&header, &id, &message.payload],
+            )?;
+        }
+        return Ok(());
+    }
+
+    // Sends a single datagram, each of which gets its own sequence number
+    fn send_datagram(
+        &mut self,
+        packet_type: PacketType,
+        token: u64,
+        parts: &[&[u8]],
+    ) -> Result<(), NaiaClientSocketError> {
+        let sequence = {
+            let mut next_sequence = self.next_sequence.borrow_mut();
+            let sequence = *next_sequence;
+            *next_sequence = next_sequence.wrapping_add(1);
+            sequence
+        };
+
+        let parts_len: usize = parts.iter().map(|part| part.len()).sum();
+        let mut message = Vec::with_capacity(DATA_HEADER_SIZE + parts_len);
+        message.push(packet_type.to_byte());
+        message.extend_from_slice(&token.to_be_bytes());
+        message.extend_from_slice(&sequence.to_be_bytes());
+        match self.session_keys.borrow().as_ref() {
+            Some(keys) => {
+                let ciphertext = keys.seal(sequence, &message, &parts.concat());
+                message.extend_from_slice(&ciphertext);
+            }
+            None => {
+                for part in parts {
+                    message.extend_from_slice(part);
+                }
+            }
+        }
+
         //send it
-        if let Err(err) = self
-            .socket
-            .borrow()
-            .send_to(&packet.payload(), self.address)
-        {
-            return Err(Box::new(err));
+        trace_event!(TRACE, bytes = message.len(), sequence, "datagram sent");
+        if let Err(err) = self.socket.borrow().send(&message) {
+            trace_event!(WARN, error = %err, "send failed");
+            return Err(NaiaClientSocketError::Io(err));
         } else {
             return Ok(());
         }
@@ -1,14 +1,23 @@
 extern crate log;
 use log::info;
 
-use std::{collections::VecDeque, net::SocketAddr};
+use std::{collections::VecDeque, net::SocketAddr, time::Duration};
 
 use crate::{
-    error::NaiaClientSocketError, link_conditioner::LinkConditioner, ClientSocketTrait,
-    MessageSender, Packet,
+    error::NaiaClientSocketError,
+    link_conditioner::Conditioner,
+    middleware::{MiddlewareChain, SocketMiddleware},
+    packet_tap::PacketTap,
+    setup_timer::{SetupPhase, SetupTimer},
+    state_machine::StateMachine,
+    ClientSocketTrait, ConnectionState, MessageSender, Packet, SocketConfig, SocketEvent,
 };
 
-use naia_socket_shared::{LinkConditionerConfig, Ref};
+use naia_socket_shared::{
+    ConditionerHandle, ConditionerStats, LinkConditionerConfig, PacketDirection, Ref,
+};
+
+use web_sys::{RtcDataChannel, RtcPeerConnection};
 
 use super::webrtc_internal::webrtc_initialize;
 
@@ -17,40 +26,98 @@ use super::webrtc_internal::webrtc_initialize;
 #[derive(Debug)]
 pub struct ClientSocket {
     address: SocketAddr,
-    message_queue: Ref<VecDeque<Result<Option<Packet>, NaiaClientSocketError>>>,
+    config: SocketConfig,
+    peer: RtcPeerConnection,
+    data_channel: Ref<RtcDataChannel>,
+    message_queue: Ref<VecDeque<Result<SocketEvent, NaiaClientSocketError>>>,
     message_sender: MessageSender,
     dropped_outgoing_messages: Ref<VecDeque<Packet>>,
+    state_machine: StateMachine,
+    // the step of setting up the WebRTC session the browser has reached
+    setup_phase: Ref<SetupPhase>,
+    setup_timer: SetupTimer,
+    packet_tap: PacketTap,
+    middleware: MiddlewareChain,
+    // a clone of the link conditioner stacked on the socket, if there is one
+    conditioner: Option<Conditioner>,
 }
 
 impl ClientSocket {
     /// Returns a new ClientSocket, connected to the given socket address
-    pub fn connect(server_socket_address: SocketAddr) -> Box<dyn ClientSocketTrait> {
+    pub fn connect(
+        server_socket_address: SocketAddr,
+        config: SocketConfig,
+    ) -> Box<dyn ClientSocketTrait> {
         let message_queue = Ref::new(VecDeque::new());
-        let data_channel = webrtc_initialize(server_socket_address, message_queue.clone());
+        let setup_phase = Ref::new(SetupPhase::Signaling);
+        let (peer, data_channel) = webrtc_initialize(
+            server_socket_address,
+            config.connect_token_hex(),
+            message_queue.clone(),
+            setup_phase.clone(),
+        );
+        let data_channel = Ref::new(data_channel);
 
         let dropped_outgoing_messages = Ref::new(VecDeque::new());
 
-        let message_sender =
-            MessageSender::new(data_channel.clone(), dropped_outgoing_messages.clone());
+        // browsers don't say which address they talk to the Server from
+        let packet_tap = PacketTap::new(
+            &config,
+            SocketAddr::from(([0, 0, 0, 0], 0)),
+            server_socket_address,
+        );
+        let middleware = MiddlewareChain::new();
+        let message_sender = MessageSender::new(
+            data_channel.clone(),
+            dropped_outgoing_messages.clone(),
+            packet_tap.clone(),
+            middleware.clone(),
+        )
+        .with_max_payload_size(config.max_payload_size);
 
         Box::new(ClientSocket {
             address: server_socket_address,
+            state_machine: StateMachine::new(&config),
+            setup_phase,
+            setup_timer: SetupTimer::new(&config.webrtc_timeouts),
+            config,
+            peer,
+            data_channel,
             message_queue,
             message_sender,
             dropped_outgoing_messages,
+            packet_tap,
+            middleware,
+            conditioner: None,
         })
     }
-}
 
-#[allow(unsafe_code)]
-#[cfg(feature = "multithread")]
-unsafe impl Send for ClientSocket {}
-#[allow(unsafe_code)]
-#[cfg(feature = "multithread")]
-unsafe impl Sync for ClientSocket {}
+    fn close_peer(&mut self) {
+        self.data_channel.borrow().close();
+        self.peer.close();
+    }
 
-impl ClientSocketTrait for ClientSocket {
-    fn receive(&mut self) -> Result<Option<Packet>, NaiaClientSocketError> {
+    // Tears down the current peer connection & runs signaling again
+    fn reconnect(&mut self) {
+        self.close_peer();
+
+        // events still to come from the old peer connection go to the old queue,
+        // & are never seen
+        self.message_queue = Ref::new(VecDeque::new());
+        self.setup_phase = Ref::new(SetupPhase::Signaling);
+        self.setup_timer = SetupTimer::new(&self.config.webrtc_timeouts);
+        let (peer, data_channel) = webrtc_initialize(
+            self.address,
+            self.config.connect_token_hex(),
+            self.message_queue.clone(),
+            self.setup_phase.clone(),
+        );
+        self.peer = peer;
+        *self.data_channel.borrow_mut() = data_channel;
+    }
+
+    // Gets the next event, before it is dumped
+    fn receive_event(&mut self) -> Result<Option<SocketEvent>, NaiaClientSocketError> {
         if !self.dropped_outgoing_messages.borrow().is_empty() {
             if let Some(dropped_packets) = {
                 let mut dom = self.dropped_outgoing_messages.borrow_mut();
@@ -59,7 +126,7 @@ impl ClientSocketTrait for ClientSocket {
             } {
                 for dropped_packet in dropped_packets {
                     self.message_sender
-                        .send(dropped_packet)
+                        .resend(dropped_packet)
                         .unwrap_or_else(|err| {
                             info!("Can't send dropped packet. Original Error: {:?}", err)
                         });
@@ -67,38 +134,180 @@ impl ClientSocketTrait for ClientSocket {
             }
         }
 
-        loop {
-            if self.message_queue.borrow().is_empty() {
-                return Ok(None);
+        if let Some(event) = self.state_machine.pop_event() {
+            return Ok(Some(event));
+        }
+
+        if self.state_machine.poll_connect_timeout()? {
+            self.close_peer();
+            return Ok(self.state_machine.pop_event());
+        }
+
+        if self.state_machine.poll_reconnect() {
+            self.reconnect();
+            return Ok(self.state_machine.pop_event());
+        }
+
+        if self.state_machine.state() == ConnectionState::Connecting {
+            let phase = *self.setup_phase.borrow();
+            if let Some(err) = self.setup_timer.poll(phase) {
+                self.close_peer();
+                self.state_machine.attempt_failed()?;
+                return Err(err);
             }
+        }
 
-            match self
-                .message_queue
-                .borrow_mut()
-                .pop_front()
-                .expect("message queue shouldn't be empty!")
-            {
-                Ok(Some(packet)) => {
-                    return Ok(Some(packet));
+        loop {
+            let next = self.message_queue.borrow_mut().pop_front();
+            match next {
+                None => {
+                    return Ok(None);
+                }
+                Some(Ok(SocketEvent::Connection)) => {
+                    self.state_machine.connected();
+                    return Ok(self.state_machine.pop_event());
+                }
+                Some(Ok(SocketEvent::Kicked(reason))) => {
+                    if self.state_machine.state() == ConnectionState::Connected {
+                        self.state_machine.kicked(reason);
+                        self.close_peer();
+                    }
+                    if let Some(event) = self.state_machine.pop_event() {
+                        return Ok(Some(event));
+                    }
+                }
+                Some(Ok(SocketEvent::Disconnection)) => {
+                    match self.state_machine.state() {
+                        ConnectionState::Connected => self.state_machine.connection_lost(),
+                        ConnectionState::Disconnecting => self.state_machine.disconnected(),
+                        // the data channel never opened, so this attempt failed
+                        ConnectionState::Connecting => self.state_machine.attempt_failed()?,
+                        _ => {}
+                    }
+                    if let Some(event) = self.state_machine.pop_event() {
+                        return Ok(Some(event));
+                    }
                 }
-                Ok(inner) => {
-                    return Ok(inner);
+                Some(Ok(event)) => {
+                    return Ok(Some(event));
                 }
-                Err(err) => {
+                Some(Err(NaiaClientSocketError::DataChannel(_)))
+                    if self.state_machine.state() == ConnectionState::Disconnecting =>
+                {
+                    // the channel can err as it is closed on purpose
+                }
+                Some(Err(err)) => {
+                    if self.state_machine.state() == ConnectionState::Connecting {
+                        // signaling or the channel failed, so this attempt failed
+                        self.state_machine.attempt_failed()?;
+                    }
                     return Err(err);
                 }
             }
         }
     }
+}
+
+#[allow(unsafe_code)]
+#[cfg(feature = "multithread")]
+unsafe impl Send for ClientSocket {}
+#[allow(unsafe_code)]
+#[cfg(feature = "multithread")]
+unsafe impl Sync for ClientSocket {}
+
+impl ClientSocketTrait for ClientSocket {
+    fn receive(&mut self) -> Result<Option<SocketEvent>, NaiaClientSocketError> {
+        loop {
+            match self.receive_event() {
+                Ok(Some(SocketEvent::Packet(packet))) => {
+                    let size = packet.payload().len();
+                    if self.config.max_payload_size.is_some_and(|max| size > max) {
+                        return Ok(Some(SocketEvent::OversizedPacket { size }));
+                    }
+                    self.packet_tap
+                        .tap(PacketDirection::Incoming, packet.payload());
+                    if let Some(packet) = self.middleware.inbound(packet) {
+                        return Ok(Some(SocketEvent::Packet(packet)));
+                    }
+                }
+                Ok(None) => {
+                    return Ok(self.middleware.release_inbound().map(SocketEvent::Packet));
+                }
+                event => return event,
+            }
+        }
+    }
+
+    fn state(&self) -> ConnectionState {
+        self.state_machine.state()
+    }
+
+    fn disconnect(&mut self) {
+        match self.state_machine.state() {
+            ConnectionState::Connected | ConnectionState::Connecting => {
+                // finishes once the data channel's close event arrives
+                self.state_machine.disconnecting();
+                self.data_channel.borrow().close();
+            }
+            ConnectionState::Disconnected => {
+                // stop any reconnection attempt from being made
+                self.state_machine.disconnected();
+            }
+            _ => {}
+        }
+    }
 
     fn get_sender(&mut self) -> MessageSender {
         return self.message_sender.clone();
     }
 
+    fn switch_server(&mut self, server_address: SocketAddr) -> Result<(), NaiaClientSocketError> {
+        self.address = server_address;
+        self.packet_tap.set_server_address(server_address);
+        self.state_machine.switching_server();
+        self.reconnect();
+        Ok(())
+    }
+
     fn with_link_conditioner(
-        self: Box<Self>,
+        mut self: Box<Self>,
         config: &LinkConditionerConfig,
     ) -> Box<dyn ClientSocketTrait> {
-        Box::new(LinkConditioner::new(config, self))
+        let conditioner = Conditioner::new(ConditionerHandle::new(config));
+        self.middleware.push(Box::new(conditioner.clone()));
+        self.conditioner = Some(conditioner);
+        self
+    }
+
+    fn with_middleware(
+        self: Box<Self>,
+        middleware: Box<dyn SocketMiddleware>,
+    ) -> Box<dyn ClientSocketTrait> {
+        self.middleware.push(middleware);
+        self
+    }
+
+    fn conditioner_handle(&self) -> Option<ConditionerHandle> {
+        self.conditioner.as_ref().map(Conditioner::handle)
+    }
+
+    fn conditioner_stats(&self) -> Option<ConditionerStats> {
+        self.conditioner.as_ref().map(Conditioner::stats)
+    }
+
+    fn rtt(&self) -> Option<Duration> {
+        None
+    }
+
+    fn jitter(&self) -> Option<Duration> {
+        None
+    }
+
+    fn server_time_estimate(&self) -> Option<Duration> {
+        None
+    }
+
+    fn local_address(&self) -> Option<SocketAddr> {
+        None
     }
 }
@@ -1,37 +1,87 @@
-use std::collections::VecDeque;
+use std::{collections::VecDeque, str};
 
-use crate::Packet;
-use naia_socket_shared::Ref;
-use std::error::Error;
+use crate::{
+    error::NaiaClientSocketError, middleware::MiddlewareChain, packet_tap::PacketTap, MessageMode,
+    Packet,
+};
+use naia_socket_shared::{PacketDirection, Ref};
 use web_sys::RtcDataChannel;
 
 /// Handles sending messages to the Server for a given Client Socket
 #[derive(Clone, Debug)]
 pub struct MessageSender {
-    data_channel: RtcDataChannel,
+    data_channel: Ref<RtcDataChannel>,
     dropped_outgoing_messages: Ref<VecDeque<Packet>>,
+    packet_tap: PacketTap,
+    middleware: MiddlewareChain,
+    max_payload_size: Option<usize>,
 }
 
 impl MessageSender {
-    /// Create a new MessageSender, if supplied with the RtcDataChannel and a
-    /// reference to a list of dropped messages
+    /// Create a new MessageSender, if supplied with the RtcDataChannel, a
+    /// reference to a list of dropped messages, where to tap packets to & the
+    /// middleware stacked on the socket. The RtcDataChannel is replaced by the socket
+    /// whenever it reconnects
     pub fn new(
-        data_channel: RtcDataChannel,
+        data_channel: Ref<RtcDataChannel>,
         dropped_outgoing_messages: Ref<VecDeque<Packet>>,
+        packet_tap: PacketTap,
+        middleware: MiddlewareChain,
     ) -> MessageSender {
         MessageSender {
             data_channel,
             dropped_outgoing_messages,
+            packet_tap,
+            middleware,
+            max_payload_size: None,
         }
     }
 
+    // Refuses packets with a payload larger than the given size from then on
+    pub(crate) fn with_max_payload_size(mut self, max_payload_size: Option<usize>) -> Self {
+        self.max_payload_size = max_payload_size;
+        self
+    }
+
     /// Send a Packet to the Server
-    pub fn send(&mut self, packet: Packet) -> Result<(), Box<dyn Error + Send>> {
-        if let Err(_) = self.data_channel.send_with_u8_array(&packet.payload()) {
+    pub fn send(&mut self, packet: Packet) -> Result<(), NaiaClientSocketError> {
+        NaiaClientSocketError::check_payload_size(packet.payload().len(), self.max_payload_size)?;
+        let packet = match self.middleware.outbound(packet) {
+            Some(packet) => packet,
+            None => return Ok(()),
+        };
+        if packet.mode() == MessageMode::Text && str::from_utf8(packet.payload()).is_err() {
+            return Err(NaiaClientSocketError::InvalidText);
+        }
+        self.packet_tap
+            .tap(PacketDirection::Outgoing, packet.payload());
+        self.resend(packet)
+    }
+
+    // Sends a Packet which has already been tapped
+    pub(crate) fn resend(&mut self, packet: Packet) -> Result<(), NaiaClientSocketError> {
+        let sent = match packet.mode() {
+            MessageMode::Binary => self
+                .data_channel
+                .borrow()
+                .send_with_u8_array(&packet.payload()),
+            // checked to be UTF-8 when it was first sent
+            MessageMode::Text => self
+                .data_channel
+                .borrow()
+                .send_with_str(str::from_utf8(packet.payload()).unwrap_or_default()),
+        };
+        if let Err(_) = sent {
             self.dropped_outgoing_messages
                 .borrow_mut()
                 .push_back(packet);
         }
         Ok(())
     }
+
+    /// Packets are sent straight away rather than coalesced, so there is
+    /// nothing to flush
+    pub fn flush(&mut self) -> Result<(), NaiaClientSocketError> {
+        Ok(())
+    }
 }
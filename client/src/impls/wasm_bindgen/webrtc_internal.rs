@@ -3,15 +3,18 @@ use log::info;
 
 use std::{collections::VecDeque, net::SocketAddr};
 
-use crate::{error::NaiaClientSocketError, Packet};
+use crate::{
+    error::NaiaClientSocketError, setup_timer::SetupPhase, MessageMode, Packet, SocketEvent,
+};
 
-use naia_socket_shared::Ref;
+use naia_socket_shared::{ControlMessage, Ref};
 
 use wasm_bindgen::{prelude::*, JsCast, JsValue};
 use web_sys::{
-    ErrorEvent, MessageEvent, ProgressEvent, RtcConfiguration, RtcDataChannel, RtcDataChannelInit,
-    RtcDataChannelType, RtcIceCandidate, RtcIceCandidateInit, RtcPeerConnection, RtcSdpType,
-    RtcSessionDescription, RtcSessionDescriptionInit, XmlHttpRequest,
+    MessageEvent, ProgressEvent, RtcConfiguration, RtcDataChannel, RtcDataChannelInit,
+    RtcDataChannelType, RtcIceCandidate, RtcIceCandidateInit, RtcIceConnectionState,
+    RtcPeerConnection, RtcSdpType, RtcSessionDescription, RtcSessionDescriptionInit,
+    XmlHttpRequest,
 };
 
 #[derive(Deserialize, Debug, Clone)]
@@ -45,9 +48,15 @@ pub struct IceServerConfig {
 #[allow(unused_must_use)]
 pub fn webrtc_initialize(
     socket_address: SocketAddr,
-    msg_queue: Ref<VecDeque<Result<Option<Packet>, NaiaClientSocketError>>>,
-) -> RtcDataChannel {
-    let server_url_str = format!("http://{}/new_rtc_session", socket_address);
+    connect_token_hex: Option<String>,
+    msg_queue: Ref<VecDeque<Result<SocketEvent, NaiaClientSocketError>>>,
+    setup_phase: Ref<SetupPhase>,
+) -> (RtcPeerConnection, RtcDataChannel) {
+    let mut server_url_str = format!("http://{}/new_rtc_session", socket_address);
+    if let Some(connect_token_hex) = connect_token_hex {
+        server_url_str.push_str("?connect_token=");
+        server_url_str.push_str(&connect_token_hex);
+    }
 
     let mut peer_config: RtcConfiguration = RtcConfiguration::new();
     let ice_server_config = IceServerConfig {
@@ -70,6 +79,10 @@ pub fn webrtc_initialize(
     let cloned_channel = channel.clone();
     let msg_queue_clone = msg_queue.clone();
     let channel_onopen_func: Box<dyn FnMut(JsValue)> = Box::new(move |_| {
+        msg_queue_clone
+            .borrow_mut()
+            .push_back(Ok(SocketEvent::Connection));
+
         let msg_queue_clone_2 = msg_queue_clone.clone();
         let channel_onmsg_func: Box<dyn FnMut(MessageEvent)> =
             Box::new(move |evt: MessageEvent| {
@@ -79,7 +92,22 @@ pub fn webrtc_initialize(
                     uarray.copy_to(&mut body[..]);
                     msg_queue_clone_2
                         .borrow_mut()
-                        .push_back(Ok(Some(Packet::new(body))));
+                        .push_back(Ok(SocketEvent::Packet(Packet::new(body))));
+                } else if let Some(text) = evt.data().as_string() {
+                    // text is either a message about the connection itself, or a Packet
+                    // sent as text
+                    let event = match ControlMessage::decode(&text) {
+                        Some(ControlMessage::Kicked(reason)) => {
+                            SocketEvent::Kicked(Packet::new(reason))
+                        }
+                        Some(ControlMessage::HostMigration(address)) => {
+                            SocketEvent::HostMigration(address)
+                        }
+                        None => SocketEvent::Packet(
+                            Packet::new(text.into_bytes()).with_mode(MessageMode::Text),
+                        ),
+                    };
+                    msg_queue_clone_2.borrow_mut().push_back(Ok(event));
                 }
             });
         let channel_onmsg_closure = Closure::wrap(channel_onmsg_func);
@@ -91,19 +119,70 @@ pub fn webrtc_initialize(
     channel.set_onopen(Some(channel_onopen_closure.as_ref().unchecked_ref()));
     channel_onopen_closure.forget();
 
-    let onerror_func: Box<dyn FnMut(ErrorEvent)> = Box::new(move |e: ErrorEvent| {
-        info!("data channel error event: {:?}", e);
+    // whichever of the channel closing & the ICE connection failing comes
+    // first is the one reported
+    let closed = Ref::new(false);
+
+    let msg_queue_clone = msg_queue.clone();
+    let closed_clone = closed.clone();
+    let channel_onclose_func: Box<dyn FnMut(JsValue)> = Box::new(move |_| {
+        report_closed(&closed_clone, &msg_queue_clone, None);
+    });
+    let channel_onclose_closure = Closure::wrap(channel_onclose_func);
+    channel.set_onclose(Some(channel_onclose_closure.as_ref().unchecked_ref()));
+    channel_onclose_closure.forget();
+
+    let msg_queue_clone = msg_queue.clone();
+    let closed_clone = closed.clone();
+    let onerror_func: Box<dyn FnMut(JsValue)> = Box::new(move |e: JsValue| {
+        let reason = error_reason(&e);
+        info!("data channel error event: {}", reason);
+        if !*closed_clone.borrow() {
+            msg_queue_clone
+                .borrow_mut()
+                .push_back(Err(NaiaClientSocketError::DataChannel(reason)));
+        }
     });
     let onerror_callback = Closure::wrap(onerror_func);
     channel.set_onerror(Some(onerror_callback.as_ref().unchecked_ref()));
     onerror_callback.forget();
 
+    // the channel only notices the connection is gone once SCTP gives up on
+    // it, long after ICE has
+    let peer_clone = peer.clone();
+    let msg_queue_clone = msg_queue.clone();
+    let setup_phase_clone = setup_phase.clone();
+    let ice_state_func: Box<dyn FnMut(JsValue)> = Box::new(move |_| {
+        match peer_clone.ice_connection_state() {
+            RtcIceConnectionState::Connected | RtcIceConnectionState::Completed => {
+                *setup_phase_clone.borrow_mut() = SetupPhase::ChannelOpen;
+            }
+            RtcIceConnectionState::Failed => report_closed(
+                &closed,
+                &msg_queue_clone,
+                Some("the ICE connection failed".to_string()),
+            ),
+            RtcIceConnectionState::Closed => report_closed(&closed, &msg_queue_clone, None),
+            RtcIceConnectionState::Disconnected => {
+                // this can recover by itself, & fails if it doesn't
+                info!("ICE connection interrupted");
+            }
+            _ => {}
+        }
+    });
+    let ice_state_callback = Closure::wrap(ice_state_func);
+    peer.set_oniceconnectionstatechange(Some(ice_state_callback.as_ref().unchecked_ref()));
+    ice_state_callback.forget();
+
     let peer_clone = peer.clone();
     let server_url_msg = Ref::new(server_url_str);
+    let msg_queue_clone = msg_queue.clone();
     let peer_offer_func: Box<dyn FnMut(JsValue)> = Box::new(move |e: JsValue| {
         let session_description = e.dyn_into::<RtcSessionDescription>().unwrap();
         let peer_clone_2 = peer_clone.clone();
         let server_url_msg_clone = server_url_msg.clone();
+        let msg_queue_clone_2 = msg_queue_clone.clone();
+        let setup_phase_clone = setup_phase.clone();
         let peer_desc_func: Box<dyn FnMut(JsValue)> = Box::new(move |_: JsValue| {
             let request = XmlHttpRequest::new().expect("can't create new XmlHttpRequest");
 
@@ -118,8 +197,19 @@ pub fn webrtc_initialize(
 
             let request_2 = request.clone();
             let peer_clone_3 = peer_clone_2.clone();
+            let msg_queue_clone_3 = msg_queue_clone_2.clone();
+            let setup_phase_clone_2 = setup_phase_clone.clone();
             let request_func: Box<dyn FnMut(ProgressEvent)> = Box::new(move |_: ProgressEvent| {
-                if request_2.status().unwrap() == 200 {
+                let status = request_2.status().unwrap();
+                if status != 200 {
+                    msg_queue_clone_3.borrow_mut().push_back(Err(
+                        NaiaClientSocketError::Signaling(format!(
+                            "error sending POST /new_rtc_session request, status {}",
+                            status
+                        )),
+                    ));
+                } else {
+                    *setup_phase_clone_2.borrow_mut() = SetupPhase::Ice;
                     let response_string = request_2.response_text().unwrap().unwrap();
                     let response_js_value = js_sys::JSON::parse(response_string.as_str()).unwrap();
                     let session_response: JsSessionResponse =
@@ -188,6 +278,19 @@ pub fn webrtc_initialize(
             request.set_onload(Some(request_callback.as_ref().unchecked_ref()));
             request_callback.forget();
 
+            let msg_queue_clone_3 = msg_queue_clone_2.clone();
+            let request_error_func: Box<dyn FnMut(ProgressEvent)> =
+                Box::new(move |_: ProgressEvent| {
+                    msg_queue_clone_3.borrow_mut().push_back(Err(
+                        NaiaClientSocketError::Signaling(
+                            "error sending POST /new_rtc_session request".to_string(),
+                        ),
+                    ));
+                });
+            let request_error_callback = Closure::wrap(request_error_func);
+            request.set_onerror(Some(request_error_callback.as_ref().unchecked_ref()));
+            request_error_callback.forget();
+
             request
                 .send_with_opt_str(Some(
                     peer_clone_2.local_description().unwrap().sdp().as_str(),
@@ -218,5 +321,42 @@ pub fn webrtc_initialize(
     peer_offer_callback.forget();
     peer_error_callback.forget();
 
-    return channel;
+    return (peer, channel);
+}
+
+// Reports the data channel as closed, after the reason it failed if there is
+// one, unless it has already been reported
+fn report_closed(
+    closed: &Ref<bool>,
+    msg_queue: &Ref<VecDeque<Result<SocketEvent, NaiaClientSocketError>>>,
+    reason: Option<String>,
+) {
+    let mut closed = closed.borrow_mut();
+    if *closed {
+        return;
+    }
+    *closed = true;
+    let mut msg_queue = msg_queue.borrow_mut();
+    if let Some(reason) = reason {
+        msg_queue.push_back(Err(NaiaClientSocketError::DataChannel(reason)));
+    }
+    msg_queue.push_back(Ok(SocketEvent::Disconnection));
+}
+
+// Gets what went wrong from a data channel's error event, which is an
+// RTCErrorEvent holding an RTCError in browsers which have them, & a plain
+// ErrorEvent elsewhere
+fn error_reason(event: &JsValue) -> String {
+    let message = |value: &JsValue| {
+        js_sys::Reflect::get(value, &JsValue::from_str("message"))
+            .ok()
+            .and_then(|message| message.as_string())
+            .filter(|message| !message.is_empty())
+    };
+    js_sys::Reflect::get(event, &JsValue::from_str("error"))
+        .ok()
+        .filter(|error| error.is_object())
+        .and_then(|error| message(&error))
+        .or_else(|| message(event))
+        .unwrap_or_else(|| "unknown error".to_string())
 }
@@ -13,6 +13,9 @@
 #[macro_use]
 extern crate cfg_if;
 
+#[macro_use]
+mod trace_event;
+
 cfg_if! {
     if #[cfg(all(target_arch = "wasm32", feature = "wbindgen"))] {
         #[macro_use]
@@ -22,16 +25,51 @@ cfg_if! {
     }
 }
 
-pub use naia_socket_shared::LinkConditionerConfig;
+pub use naia_socket_shared::{
+    AckConfig, BurstLossConfig, ChannelMode, CompressionConfig, ConditionerHandle,
+    ConditionerStats, ConditionerTrace, FragmentationConfig, HandshakeError, JitterDistribution,
+    LatencySpikeConfig, LinkConditionerConfig, MessageMode, MiddlewareAction, PacketCapture,
+    PacketDirection, PacketDump, PortSearch, PortStrategy, Priority, ProfileError,
+    ReliabilityConfig,
+};
 
+mod backoff_config;
+mod channel_router;
+mod channel_sender;
 mod client_socket;
+mod connection_state;
 mod error;
 mod impls;
 mod link_conditioner;
+mod middleware;
 mod packet;
+mod packet_tap;
+mod reconnector;
+mod socket_config;
+mod socket_event;
+mod state_machine;
+mod webrtc_timeouts;
+
+#[cfg(not(target_arch = "wasm32"))]
+mod blocking_socket;
+#[cfg(not(target_arch = "wasm32"))]
+pub use blocking_socket::BlockingSocket;
+#[cfg(target_arch = "wasm32")]
+mod setup_timer;
 
+pub use backoff_config::BackoffConfig;
+pub use channel_router::{ChannelReceiver, ChannelRouter};
+pub use channel_sender::ChannelSender;
 pub use client_socket::ClientSocketTrait;
+pub use connection_state::ConnectionState;
 pub use error::NaiaClientSocketError;
 pub use impls::{ClientSocket, MessageSender};
-pub use naia_socket_shared::find_my_ip_address;
+pub use link_conditioner::Conditioner;
+pub use middleware::SocketMiddleware;
+pub use naia_socket_shared::{
+    clock::VirtualClock, find_my_ip_address, handshake::MAX_CONNECT_PAYLOAD_SIZE,
+};
 pub use packet::Packet;
+pub use socket_config::SocketConfig;
+pub use socket_event::SocketEvent;
+pub use webrtc_timeouts::WebRtcTimeouts;
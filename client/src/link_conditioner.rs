@@ -1,75 +1,98 @@
-use naia_socket_shared::{link_condition_logic, LinkConditionerConfig, TimeQueue};
+use std::fmt;
 
-use crate::MessageSender;
+use bytes::Bytes;
 
-use super::{client_socket::ClientSocketTrait, error::NaiaClientSocketError, packet::Packet};
+use naia_socket_shared::{
+    link_condition_logic::{self, ConditionedPacket, LinkState},
+    ConditionerHandle, ConditionerStats, MiddlewareAction, Ref, TimeQueue,
+};
 
-#[derive(Debug)]
-pub struct LinkConditioner {
-    config: LinkConditionerConfig,
-    inner_socket: Box<dyn ClientSocketTrait>,
+use super::{middleware::SocketMiddleware, packet::Packet};
+
+/// A layer of middleware simulating network conditions on the packets
+/// received through it, as `ClientSocketTrait::with_link_conditioner` adds.
+/// Clones share the same link & counters, so a clone can be kept to read the
+/// counters from once the Conditioner has been added to a socket
+#[derive(Clone)]
+pub struct Conditioner {
+    handle: ConditionerHandle,
+    link: Ref<Link>,
+}
+
+struct Link {
+    link_state: LinkState,
+    stats: ConditionerStats,
     time_queue: TimeQueue<Packet>,
 }
 
-impl LinkConditioner {
-    pub fn new(config: &LinkConditionerConfig, socket: Box<dyn ClientSocketTrait>) -> Self {
-        LinkConditioner {
-            config: config.clone(),
-            inner_socket: socket,
-            time_queue: TimeQueue::new(),
+impl Conditioner {
+    /// Create a new Conditioner, simulating the conditions the handle is set
+    /// to, whatever they are changed to
+    pub fn new(handle: ConditionerHandle) -> Self {
+        Conditioner {
+            handle,
+            link: Ref::new(Link {
+                link_state: LinkState::new(),
+                stats: ConditionerStats::new(),
+                time_queue: TimeQueue::new(),
+            }),
         }
     }
-}
 
-impl ClientSocketTrait for LinkConditioner {
-    fn receive(&mut self) -> Result<Option<Packet>, NaiaClientSocketError> {
-        loop {
-            match self.inner_socket.receive() {
-                Ok(event) => match event {
-                    None => {
-                        break;
-                    }
-                    Some(packet) => {
-                        self.process_packet(packet);
-                    }
-                },
-                Err(error) => {
-                    return Err(error);
-                }
-            }
-        }
+    /// Gets a handle onto the conditions simulated, which can change them
+    /// while the socket is live
+    pub fn handle(&self) -> ConditionerHandle {
+        self.handle.clone()
+    }
 
-        if self.has_packet() {
-            return Ok(Some(self.get_packet()));
-        } else {
-            return Ok(None);
-        }
+    /// Gets the counters describing what has been done to received packets
+    pub fn stats(&self) -> ConditionerStats {
+        let link = self.link.borrow();
+        link.stats.with_queue_depth(link.time_queue.len())
     }
+}
 
-    fn get_sender(&mut self) -> MessageSender {
-        self.inner_socket.get_sender()
+impl SocketMiddleware for Conditioner {
+    fn on_inbound(&mut self, packet: &Packet) -> MiddlewareAction {
+        // packets on reliable channels have already made it through, &
+        // conditioning them would break the channel's guarantees
+        if packet.is_reliable() {
+            return MiddlewareAction::Keep;
+        }
+        let config = match self.handle.config() {
+            Some(config) => config,
+            None => return MiddlewareAction::Keep,
+        };
+        let link = &mut *self.link.borrow_mut();
+        link_condition_logic::process_packet(
+            &config,
+            &mut link.link_state,
+            &mut link.stats,
+            &mut link.time_queue,
+            packet.clone(),
+        );
+        MiddlewareAction::Drop
     }
 
-    fn with_link_conditioner(
-        self: Box<Self>,
-        config: &LinkConditionerConfig,
-    ) -> Box<dyn ClientSocketTrait> {
-        // Absolutely do not recommend decorating a socket with multiple link
-        // conditioners... why would you do this??
-        Box::new(LinkConditioner::new(config, self))
+    fn release_inbound(&mut self) -> Option<Packet> {
+        self.link.borrow_mut().time_queue.pop_item()
     }
 }
 
-impl LinkConditioner {
-    fn process_packet(&mut self, packet: Packet) {
-        link_condition_logic::process_packet(&self.config, &mut self.time_queue, packet);
+impl fmt::Debug for Conditioner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Conditioner")
+            .field("config", &self.handle.config())
+            .finish()
     }
+}
 
-    fn has_packet(&self) -> bool {
-        self.time_queue.has_item()
+impl ConditionedPacket for Packet {
+    fn payload(&self) -> &[u8] {
+        Packet::payload(self)
     }
 
-    fn get_packet(&mut self) -> Packet {
-        self.time_queue.pop_item().unwrap()
+    fn with_payload(self, payload: Vec<u8>) -> Self {
+        Packet::with_payload(self, Bytes::from(payload))
     }
 }
@@ -0,0 +1,130 @@
+use std::fmt;
+
+use naia_socket_shared::{MiddlewareAction, Ref};
+
+use crate::Packet;
+
+cfg_if! {
+    if #[cfg(feature = "multithread")] {
+        /// What every SocketMiddleware must be, so that sockets stay Send
+        pub trait SocketMiddlewareBaseTrait: Send {}
+        impl < T > SocketMiddlewareBaseTrait for T where T: Send {}
+    } else {
+        /// What every SocketMiddleware must be
+        pub trait SocketMiddlewareBaseTrait {}
+        impl < T > SocketMiddlewareBaseTrait for T {}
+    }
+}
+
+/// A layer which the packets a Client Socket receives & sends pass through,
+/// to be logged, filtered or transformed on their way. Layers are stacked
+/// with `ClientSocketTrait::with_middleware`, each added further from the
+/// socket than the last, so received packets pass through them in the order
+/// they were added & packets being sent in the reverse order. Packets can
+/// only be held back on their way in, as nothing polls the MessageSenders to
+/// release them. The link conditioner is such a layer
+pub trait SocketMiddleware: SocketMiddlewareBaseTrait {
+    /// Decides what happens to a packet received from the Server, before the
+    /// layers added after this one see it
+    fn on_inbound(&mut self, _packet: &Packet) -> MiddlewareAction {
+        MiddlewareAction::Keep
+    }
+
+    /// Decides what happens to a packet being sent to the Server, before the
+    /// layers added before this one see it
+    fn on_outbound(&mut self, _packet: &Packet) -> MiddlewareAction {
+        MiddlewareAction::Keep
+    }
+
+    /// Hands back a received packet this layer held back, once it is due to
+    /// carry on to the layers added after this one
+    fn release_inbound(&mut self) -> Option<Packet> {
+        None
+    }
+}
+
+/// The layers of middleware stacked on a Client Socket, shared between it &
+/// its MessageSenders
+#[derive(Clone)]
+pub struct MiddlewareChain {
+    layers: Ref<Vec<Box<dyn SocketMiddleware>>>,
+}
+
+impl MiddlewareChain {
+    /// Create a new MiddlewareChain, with no layers
+    pub fn new() -> Self {
+        MiddlewareChain {
+            layers: Ref::new(Vec::new()),
+        }
+    }
+
+    /// Adds a layer, further from the socket than those already added
+    pub fn push(&self, middleware: Box<dyn SocketMiddleware>) {
+        self.layers.borrow_mut().push(middleware);
+    }
+
+    /// Passes a received packet through every layer, handing it back if none
+    /// of them dropped it
+    pub fn inbound(&self, packet: Packet) -> Option<Packet> {
+        self.inbound_from(packet, 0)
+    }
+
+    /// Passes a packet being sent through every layer, handing it back if
+    /// none of them dropped it
+    pub fn outbound(&self, mut packet: Packet) -> Option<Packet> {
+        let mut layers = self.layers.borrow_mut();
+        for layer in layers.iter_mut().rev() {
+            match layer.on_outbound(&packet) {
+                MiddlewareAction::Keep => {}
+                MiddlewareAction::Drop => return None,
+                MiddlewareAction::Rewrite(payload) => packet = packet.with_payload(payload),
+            }
+        }
+        Some(packet)
+    }
+
+    /// Gets the next received packet a layer has released, once it has passed
+    /// through the layers after it
+    pub fn release_inbound(&self) -> Option<Packet> {
+        let count = self.layers.borrow().len();
+        for index in 0..count {
+            loop {
+                let released = self.layers.borrow_mut()[index].release_inbound();
+                let packet = match released {
+                    Some(packet) => packet,
+                    None => break,
+                };
+                if let Some(packet) = self.inbound_from(packet, index + 1) {
+                    return Some(packet);
+                }
+            }
+        }
+        None
+    }
+
+    fn inbound_from(&self, mut packet: Packet, from: usize) -> Option<Packet> {
+        let mut layers = self.layers.borrow_mut();
+        for layer in layers[from..].iter_mut() {
+            match layer.on_inbound(&packet) {
+                MiddlewareAction::Keep => {}
+                MiddlewareAction::Drop => return None,
+                MiddlewareAction::Rewrite(payload) => packet = packet.with_payload(payload),
+            }
+        }
+        Some(packet)
+    }
+}
+
+impl Default for MiddlewareChain {
+    fn default() -> Self {
+        MiddlewareChain::new()
+    }
+}
+
+impl fmt::Debug for MiddlewareChain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MiddlewareChain")
+            .field("layers", &self.layers.borrow().len())
+            .finish()
+    }
+}
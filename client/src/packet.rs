@@ -1,32 +1,193 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+
+use naia_socket_shared::{MessageMode, Payload, Priority};
+
 /// A Packet that can be sent to the Server
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq)]
 pub struct Packet {
-    /// The raw payload of the packet
-    payload: Box<[u8]>,
+    /// The raw payload of the packet, stored inline if it is small, or in a
+    /// buffer which may be shared with other packets
+    payload: Payload,
+    /// The channel the packet is sent on, if any
+    channel: Option<u8>,
+    /// Whether the packet arrived on a reliable channel
+    reliable: bool,
+    /// The id the packet's delivery is reported under, if any
+    ack_id: Option<u64>,
+    /// How urgently the packet should be sent
+    priority: Priority,
+    /// Whether the packet goes over WebRTC as binary or text
+    mode: MessageMode,
+    /// How long the packet may wait to be sent before it is dropped, if at all
+    ttl: Option<Duration>,
 }
 
 impl Packet {
-    /// Create a packet from a Vec payload, converts data to a slice on the heap
+    /// Create a packet from a Vec payload, taking ownership of its buffer
     pub fn new(payload: Vec<u8>) -> Packet {
         Packet {
-            payload: payload.into_boxed_slice(),
+            payload: Payload::from_bytes(Bytes::from(payload)),
+            channel: None,
+            reliable: false,
+            ack_id: None,
+            priority: Priority::Normal,
+            mode: MessageMode::Binary,
+            ttl: None,
         }
     }
 
     /// Create a packet from an existing boxed slice of bytes
     pub fn new_raw(payload: Box<[u8]>) -> Packet {
-        Packet { payload }
+        Packet {
+            payload: Payload::from_bytes(Bytes::from(payload)),
+            channel: None,
+            reliable: false,
+            ack_id: None,
+            priority: Priority::Normal,
+            mode: MessageMode::Binary,
+            ttl: None,
+        }
+    }
+
+    /// Create a packet holding a copy of the given payload. Payloads of up to
+    /// `INLINE_PAYLOAD_SIZE` bytes are stored inline, without allocating
+    pub fn from_slice(payload: &[u8]) -> Packet {
+        Packet {
+            payload: Payload::from_slice(payload),
+            channel: None,
+            reliable: false,
+            ack_id: None,
+            priority: Priority::Normal,
+            mode: MessageMode::Binary,
+            ttl: None,
+        }
+    }
+
+    /// Create a packet sharing an existing buffer, without copying it
+    pub fn from_bytes(payload: Bytes) -> Packet {
+        Packet {
+            payload: Payload::from_bytes(payload),
+            channel: None,
+            reliable: false,
+            ack_id: None,
+            priority: Priority::Normal,
+            mode: MessageMode::Binary,
+            ttl: None,
+        }
     }
 
     /// Create an empty packet
     pub fn empty() -> Packet {
         Packet {
-            payload: Box::new([]),
+            payload: Payload::default(),
+            channel: None,
+            reliable: false,
+            ack_id: None,
+            priority: Priority::Normal,
+            mode: MessageMode::Binary,
+            ttl: None,
         }
     }
 
+    /// Moves the packet onto the channel with the given id, which must be one
+    /// of those in `SocketConfig::reliability`. Received packets keep the
+    /// channel they arrived on, & can be told apart with a ChannelRouter. Only
+    /// the native client has channels
+    pub fn with_channel(mut self, channel: u8) -> Packet {
+        self.channel = Some(channel);
+        self
+    }
+
+    /// Gets the channel the packet is sent on, or `None` if it is sent off
+    /// any channel
+    pub fn channel(&self) -> Option<u8> {
+        self.channel
+    }
+
+    // Replaces the packet's payload, keeping everything else about it
+    pub(crate) fn with_payload(mut self, payload: Bytes) -> Packet {
+        self.payload = Payload::from_bytes(payload);
+        self
+    }
+
+    // Marks a received packet as having arrived on a reliable channel
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    pub(crate) fn with_reliable(mut self) -> Packet {
+        self.reliable = true;
+        self
+    }
+
+    // Gets whether a received packet arrived on a reliable channel
+    pub(crate) fn is_reliable(&self) -> bool {
+        self.reliable
+    }
+
+    /// Has the packet's delivery reported back under the given id, as a
+    /// PacketAcked or PacketLost event, once it is known. Requires
+    /// `SocketConfig::acknowledgement`, which only the native client has.
+    /// Ignored for packets on a reliable channel
+    pub fn with_ack_id(mut self, ack_id: u64) -> Packet {
+        self.ack_id = Some(ack_id);
+        self
+    }
+
+    /// Gets the id the packet's delivery is reported under, if any
+    pub fn ack_id(&self) -> Option<u64> {
+        self.ack_id
+    }
+
+    /// Sets how urgently the packet should be sent, relative to the others
+    /// waiting to be sent. Packets are Normal priority unless set
+    pub fn with_priority(mut self, priority: Priority) -> Packet {
+        self.priority = priority;
+        self
+    }
+
+    /// Gets how urgently the packet should be sent
+    pub fn priority(&self) -> Priority {
+        self.priority
+    }
+
+    /// Sets whether the packet goes over WebRTC as a binary message or a text
+    /// one. Packets are Binary unless set, & text must be valid UTF-8. Only
+    /// the browser clients tell the two apart
+    pub fn with_mode(mut self, mode: MessageMode) -> Packet {
+        self.mode = mode;
+        self
+    }
+
+    /// Gets whether the packet goes over WebRTC as binary or text, or came
+    /// over it as which
+    pub fn mode(&self) -> MessageMode {
+        self.mode
+    }
+
+    /// Has the packet dropped if it is still waiting to be sent once the given
+    /// time has passed. Stale state is often worse than none. Only the native
+    /// client holds packets back, until its connection is accepted, & counts
+    /// those dropped in `MessageSender::expired_packets`. Ignored for packets
+    /// on a reliable channel
+    pub fn with_ttl(mut self, ttl: Duration) -> Packet {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    // Gets how long the packet may wait to be sent
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    pub(crate) fn ttl(&self) -> Option<Duration> {
+        self.ttl
+    }
+
     /// Get at the underlying byte payload of the packet
     pub fn payload(&self) -> &[u8] {
-        &self.payload
+        self.payload.as_slice()
+    }
+
+    /// Takes the payload of the packet, which can be cloned & sliced without
+    /// copying it
+    pub fn into_payload(self) -> Bytes {
+        self.payload.into_bytes()
     }
 }
@@ -0,0 +1,52 @@
+use std::net::SocketAddr;
+
+use naia_socket_shared::{PacketCapture, PacketDirection, PacketDump, Ref};
+
+use crate::SocketConfig;
+
+/// Hands the packets exchanged with the application to the PacketDump &
+/// PacketCapture configured, if there are any. Clones share the Server's
+/// address, so that switching Servers is seen by all of them
+#[derive(Debug, Clone)]
+pub struct PacketTap {
+    dump: Option<PacketDump>,
+    capture: Option<PacketCapture>,
+    local_address: SocketAddr,
+    server_address: Ref<SocketAddr>,
+}
+
+impl PacketTap {
+    /// Create a new PacketTap, for a socket at the given address talking to
+    /// the Server at the other
+    pub fn new(
+        config: &SocketConfig,
+        local_address: SocketAddr,
+        server_address: SocketAddr,
+    ) -> Self {
+        PacketTap {
+            dump: config.packet_dump.clone(),
+            capture: config.packet_capture.clone(),
+            local_address,
+            server_address: Ref::new(server_address),
+        }
+    }
+
+    /// Points the packets tapped at the Server which is now being talked to
+    pub fn set_server_address(&self, server_address: SocketAddr) {
+        *self.server_address.borrow_mut() = server_address;
+    }
+
+    /// Dumps & captures a packet exchanged with the Server
+    pub fn tap(&self, direction: PacketDirection, payload: &[u8]) {
+        if self.dump.is_none() && self.capture.is_none() {
+            return;
+        }
+        let server_address = *self.server_address.borrow();
+        if let Some(dump) = &self.dump {
+            dump.dump(direction, &server_address, payload);
+        }
+        if let Some(capture) = &self.capture {
+            capture.capture(direction, &self.local_address, &server_address, payload);
+        }
+    }
+}
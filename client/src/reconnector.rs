@@ -0,0 +1,69 @@
+use naia_socket_shared::Timer;
+
+use super::{backoff_config::BackoffConfig, error::NaiaClientSocketError};
+
+/// Keeps track of when a Client Socket should next try to reconnect to the
+/// Server, shared by each of the Client Socket implementations
+#[derive(Debug)]
+pub struct Reconnector {
+    config: Option<BackoffConfig>,
+    // the number of attempts made since the socket was last connected
+    attempt: u32,
+    next_attempt: Option<Timer>,
+}
+
+impl Reconnector {
+    pub fn new(config: Option<BackoffConfig>) -> Self {
+        Reconnector {
+            config,
+            attempt: 0,
+            next_attempt: None,
+        }
+    }
+
+    /// Call when the connection has been lost or a connection attempt has
+    /// failed. Schedules the next attempt, returning an error if there will be
+    /// no further attempts
+    pub fn connection_failed(&mut self) -> Result<(), NaiaClientSocketError> {
+        let config = match &self.config {
+            Some(config) => config,
+            None => return Ok(()),
+        };
+
+        if let Some(max_attempts) = config.max_attempts {
+            if self.attempt >= max_attempts {
+                self.next_attempt = None;
+                return Err(NaiaClientSocketError::ReconnectFailed {
+                    attempts: self.attempt,
+                });
+            }
+        }
+        self.next_attempt = Some(Timer::new(config.delay(self.attempt + 1)));
+        Ok(())
+    }
+
+    /// Returns the number of the attempt which should be started now, if it
+    /// is time for one
+    pub fn poll(&mut self) -> Option<u32> {
+        if !self.next_attempt.as_ref()?.ringing() {
+            return None;
+        }
+        self.next_attempt = None;
+        self.attempt += 1;
+        Some(self.attempt)
+    }
+
+    /// Stops any further attempts from being made
+    pub fn cancel(&mut self) {
+        self.next_attempt = None;
+    }
+
+    /// Call when a connection has been established. Returns whether this
+    /// was a reconnection
+    pub fn connected(&mut self) -> bool {
+        let reconnected = self.attempt > 0;
+        self.attempt = 0;
+        self.next_attempt = None;
+        reconnected
+    }
+}
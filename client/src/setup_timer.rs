@@ -0,0 +1,57 @@
+use naia_socket_shared::Timer;
+
+use crate::{error::NaiaClientSocketError, WebRtcTimeouts};
+
+/// The steps of setting up a browser client's WebRTC session, in order
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum SetupPhase {
+    /// Creating the offer & waiting for the Server's answer to it
+    Signaling,
+    /// Making the ICE connection to the Server
+    Ice,
+    /// Opening the data channel over the ICE connection
+    ChannelOpen,
+}
+
+/// Times each step of setting up a connection attempt's WebRTC session, as
+/// the browser reports reaching it, against its WebRtcTimeouts
+#[derive(Debug)]
+pub struct SetupTimer {
+    timeouts: WebRtcTimeouts,
+    phase: SetupPhase,
+    timer: Timer,
+}
+
+impl SetupTimer {
+    /// Create a new SetupTimer, for an attempt which has just started
+    /// signaling
+    pub fn new(timeouts: &WebRtcTimeouts) -> Self {
+        SetupTimer {
+            timeouts: timeouts.clone(),
+            phase: SetupPhase::Signaling,
+            timer: Timer::new(timeouts.signaling),
+        }
+    }
+
+    /// Moves on to the step the session has reached, restarting the timer if
+    /// it is a new one, & gets the error to fail the attempt with if the step
+    /// has taken too long
+    pub fn poll(&mut self, phase: SetupPhase) -> Option<NaiaClientSocketError> {
+        if phase != self.phase {
+            self.phase = phase;
+            self.timer = Timer::new(match phase {
+                SetupPhase::Signaling => self.timeouts.signaling,
+                SetupPhase::Ice => self.timeouts.ice,
+                SetupPhase::ChannelOpen => self.timeouts.channel_open,
+            });
+        }
+        if !self.timer.ringing() {
+            return None;
+        }
+        Some(match phase {
+            SetupPhase::Signaling => NaiaClientSocketError::SignalingTimeout,
+            SetupPhase::Ice => NaiaClientSocketError::IceTimeout,
+            SetupPhase::ChannelOpen => NaiaClientSocketError::ChannelOpenTimeout,
+        })
+    }
+}
@@ -0,0 +1,170 @@
+use std::time::Duration;
+
+use naia_socket_shared::{
+    AckConfig, CompressionConfig, FragmentationConfig, PacketCapture, PacketDump, PortSearch,
+    ReliabilityConfig,
+};
+
+use crate::{BackoffConfig, WebRtcTimeouts};
+
+/// Contains settings which determine how the Client Socket behaves
+#[derive(Debug, Clone)]
+pub struct SocketConfig {
+    /// An encoded connect token to present to the Server, for Servers which
+    /// require one. These are minted by a backend service, & this socket
+    /// treats them as opaque bytes
+    pub connect_token: Option<Vec<u8>>,
+    /// An application payload to attach to the handshake, such as a lobby id,
+    /// which the Server receives along with the connection. At most
+    /// `MAX_CONNECT_PAYLOAD_SIZE` bytes long, & only sent by the native client
+    pub connect_payload: Option<Vec<u8>>,
+    /// If set, the socket tries to connect again after losing its connection
+    /// or failing to connect, waiting longer between each attempt
+    pub auto_reconnect: Option<BackoffConfig>,
+    /// How long a connection attempt may take, from the first request sent to
+    /// the Server until the connection is ready to use, before it is given up
+    /// on. If `None`, the socket waits indefinitely
+    pub connect_timeout: Option<Duration>,
+    /// If set, a connection the Server hasn't been heard from on in this long
+    /// is treated as lost, as if the Server had gone away, & connected again
+    /// if `auto_reconnect` is set. Servers ping every second by default, which
+    /// keeps quiet connections open. Only applies to the native client
+    pub idle_timeout: Option<Duration>,
+    /// How long each step of setting up the WebRTC session may take, see
+    /// `WebRtcTimeouts`. Only applies to the browser clients
+    pub webrtc_timeouts: WebRtcTimeouts,
+    /// Where & how to look for the local port the socket binds to. The one
+    /// chosen is given by `ClientSocketTrait::local_address`. If its `ip`
+    /// isn't set, the host's address is used if it is of the same family as
+    /// the Server's, & the unspecified one otherwise, which for IPv6 is
+    /// dual-stack. Only applies to the native client, browsers choose their
+    /// own
+    pub port_search: PortSearch,
+    /// If set, every packet the socket sends is marked with this DSCP value,
+    /// so that routers which honor it can prioritize the traffic. For example,
+    /// 46 is Expedited Forwarding. Only applies to the native client, browsers
+    /// don't allow it
+    pub dscp: Option<u8>,
+    /// If set, packets the socket sends are marked as ECN-capable, so that
+    /// congested routers can mark them rather than drop them, & the Server
+    /// can count how often that happens. Only applies to the native client
+    pub ecn: bool,
+    /// The largest datagram, in bytes of UDP payload, assumed to reach the
+    /// Server without IP fragmentation. Only applies to the native client
+    pub mtu: usize,
+    /// If set, packets the application sends with a payload larger than this
+    /// many bytes are refused with a PayloadTooLarge error, & packets received
+    /// with one are dropped & reported with OversizedPacket events
+    pub max_payload_size: Option<usize>,
+    /// If set, packets too large for a single datagram are split into
+    /// fragments, & fragments received from the Server are put back together.
+    /// The Server must enable it too. Only applies to the native client
+    pub fragmentation: Option<FragmentationConfig>,
+    /// If set, small packets are packed together into one datagram, which is
+    /// sent once it is full, once this long has passed since the first of
+    /// them was sent, or on `MessageSender::flush`. Only applies to the native
+    /// client
+    pub coalesce_interval: Option<Duration>,
+    /// If set, the connection gets the channels described, reliable or not,
+    /// which packets are moved onto with `Packet::with_channel`. The Server
+    /// must have the same channels. Only applies to the native client
+    pub reliability: Option<ReliabilityConfig>,
+    /// If set, unreliable packets are numbered & acknowledged, & those sent
+    /// with `Packet::with_ack_id` are reported back as PacketAcked or
+    /// PacketLost events. The Server must have acknowledgements enabled too.
+    /// Only applies to the native client
+    pub acknowledgement: Option<AckConfig>,
+    /// If set, the payloads of packets are compressed, as long as the Server
+    /// has compression enabled too. This is agreed on while connecting. Only
+    /// applies to the native client
+    pub compression: Option<CompressionConfig>,
+    /// If set, every packet exchanged with the Server after the handshake is
+    /// encrypted & authenticated, under keys agreed on while connecting, & a
+    /// Server which doesn't agree to it is never connected to. Unless
+    /// `server_public_key` is pinned, the Server's identity isn't verified, so
+    /// this guards against eavesdropping & tampering but not against a man in
+    /// the middle. The Server must have
+    /// encryption enabled too. Only applies to the native client, as the
+    /// browser's connections are always encrypted by DTLS
+    pub encryption: bool,
+    /// If set, & `encryption` isn't, every packet exchanged with the Server
+    /// after the handshake carries an HMAC-SHA256 tag which authenticates it,
+    /// but isn't encrypted, which is cheaper. Packets which fail
+    /// authentication are dropped. The Server must have authentication
+    /// enabled too. Only applies to the native client
+    pub authentication: bool,
+    /// If set, the public key of the Server's identity, which is mixed into
+    /// the connection's keys so that only the Server holding its secret can
+    /// complete the handshake, & a man in the middle can't stand in for it.
+    /// Only takes effect along with `encryption` or `authentication`, & only
+    /// applies to the native client
+    pub server_public_key: Option<[u8; 32]>,
+    /// If set, the Server is pinged this often once connected, & the round
+    /// trip time & jitter measured from its replies are reported by
+    /// `ClientSocketTrait::rtt` & `ClientSocketTrait::jitter`. Pings from the
+    /// Server are answered either way. Only applies to the native client
+    pub ping_interval: Option<Duration>,
+    /// If set, every packet the application sends or receives is handed to
+    /// this to be logged while it is enabled. See `PacketDump`
+    pub packet_dump: Option<PacketDump>,
+    /// If set, every packet the application sends or receives is written to
+    /// this capture, to be inspected offline in Wireshark. See
+    /// `PacketCapture`
+    pub packet_capture: Option<PacketCapture>,
+}
+
+impl Default for SocketConfig {
+    fn default() -> Self {
+        SocketConfig {
+            connect_token: None,
+            connect_payload: None,
+            auto_reconnect: None,
+            connect_timeout: Some(Duration::from_secs(10)),
+            idle_timeout: Some(Duration::from_secs(10)),
+            webrtc_timeouts: WebRtcTimeouts::default(),
+            port_search: PortSearch::default(),
+            dscp: None,
+            ecn: false,
+            mtu: 1200,
+            max_payload_size: None,
+            fragmentation: None,
+            coalesce_interval: None,
+            reliability: None,
+            acknowledgement: None,
+            compression: None,
+            encryption: false,
+            authentication: false,
+            server_public_key: None,
+            ping_interval: Some(Duration::from_secs(1)),
+            packet_dump: None,
+            packet_capture: None,
+        }
+    }
+}
+
+impl SocketConfig {
+    /// Browsers pass the connect token to the signaling server hex-encoded,
+    /// as part of the session url
+    #[cfg(target_arch = "wasm32")]
+    pub(crate) fn connect_token_hex(&self) -> Option<String> {
+        self.connect_token
+            .as_deref()
+            .map(naia_socket_shared::hex::encode)
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl SocketConfig {
+    // Gets how packets must be protected, if at all
+    pub(crate) fn protection(&self) -> Option<naia_socket_shared::encryption::Protection> {
+        use naia_socket_shared::encryption::Protection;
+
+        if self.encryption {
+            return Some(Protection::Encrypted);
+        }
+        if self.authentication {
+            return Some(Protection::Authenticated);
+        }
+        None
+    }
+}
@@ -0,0 +1,59 @@
+use std::net::SocketAddr;
+
+use super::{connection_state::ConnectionState, packet::Packet};
+
+/// An Event that can be emitted by the Client Socket
+#[derive(Debug)]
+pub enum SocketEvent {
+    /// The connection with the Server has been established
+    Connection,
+    /// The connection with the Server has been lost
+    Disconnection,
+    /// A Packet has been received from the Server
+    Packet(Packet),
+    /// A packet sent with the given ack id has been received by the Server.
+    /// Only emitted by the native client, when `SocketConfig::acknowledgement`
+    /// is set
+    PacketAcked(u64),
+    /// A packet sent with the given ack id hasn't been acknowledged by the
+    /// Server in time, & is assumed lost. Only emitted by the native client,
+    /// when `SocketConfig::acknowledgement` is set
+    PacketLost(u64),
+    /// The Server has closed the connection, giving the contained reason. The
+    /// socket won't try to reconnect afterwards. A Server which closes the
+    /// connection without a reason causes a Disconnection event instead
+    Kicked(Packet),
+    /// The Server has asked the socket to move over to the host at the given
+    /// address. Call `switch_server` to follow it
+    HostMigration(SocketAddr),
+    /// The socket is making another attempt to connect to the Server, after
+    /// a lost connection or a failed connection attempt. Only emitted when
+    /// `auto_reconnect` is enabled
+    Reconnecting {
+        /// The number of attempts made since the socket was last connected,
+        /// including this one
+        attempt: u32,
+    },
+    /// The connection with the Server has been re-established, after having
+    /// been lost or having failed
+    Reconnected,
+    /// A connection attempt took longer than the configured `connect_timeout`
+    /// & was abandoned
+    ConnectTimeout,
+    /// The Server is at capacity, & the socket is waiting in its queue to be
+    /// admitted. Emitted whenever the socket's position in the queue changes.
+    /// Only emitted by the native client
+    Queued {
+        /// The socket's position in the queue, starting from 1
+        position: u32,
+    },
+    /// A Packet received from the Server had a payload larger than
+    /// `SocketConfig::max_payload_size`, & was dropped
+    OversizedPacket {
+        /// The size of the Packet's payload
+        size: usize,
+    },
+    /// The state of the connection has changed to the given one. Emitted
+    /// before any other events caused by the same change
+    StateChanged(ConnectionState),
+}
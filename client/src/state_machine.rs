@@ -0,0 +1,188 @@
+use std::{collections::VecDeque, time::Duration};
+
+use naia_socket_shared::Timer;
+
+use super::{
+    connection_state::ConnectionState, error::NaiaClientSocketError, packet::Packet,
+    reconnector::Reconnector, socket_config::SocketConfig, socket_event::SocketEvent,
+};
+
+/// Tracks the state of a Client Socket's connection & the events to emit as
+/// it changes, shared by each of the Client Socket implementations
+#[derive(Debug)]
+pub struct StateMachine {
+    state: ConnectionState,
+    events: VecDeque<SocketEvent>,
+    reconnector: Reconnector,
+    auto_reconnect: bool,
+    connect_timeout: Option<Duration>,
+    // restarted whenever a connection attempt begins
+    connect_timer: Option<Timer>,
+    queue_position: Option<u32>,
+}
+
+impl StateMachine {
+    /// Create a new StateMachine, for a socket which has just started
+    /// connecting
+    pub fn new(config: &SocketConfig) -> Self {
+        StateMachine {
+            state: ConnectionState::Connecting,
+            events: VecDeque::new(),
+            auto_reconnect: config.auto_reconnect.is_some(),
+            reconnector: Reconnector::new(config.auto_reconnect.clone()),
+            connect_timeout: config.connect_timeout,
+            connect_timer: config.connect_timeout.map(Timer::new),
+            queue_position: None,
+        }
+    }
+
+    /// Gets the current state of the connection
+    pub fn state(&self) -> ConnectionState {
+        self.state
+    }
+
+    /// Gets the next event waiting to be emitted
+    pub fn pop_event(&mut self) -> Option<SocketEvent> {
+        self.events.pop_front()
+    }
+
+    /// Returns true if it is time to make another connection attempt, in which
+    /// case the socket must start connecting again
+    pub fn poll_reconnect(&mut self) -> bool {
+        match self.reconnector.poll() {
+            Some(attempt) => {
+                self.transition(ConnectionState::Connecting);
+                self.events.push_back(SocketEvent::Reconnecting { attempt });
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns true if the current connection attempt has taken too long, in
+    /// which case the socket must abandon it. Returns an error if there will be
+    /// no further attempts
+    pub fn poll_connect_timeout(&mut self) -> Result<bool, NaiaClientSocketError> {
+        if self.state != ConnectionState::Connecting {
+            return Ok(false);
+        }
+        match &self.connect_timer {
+            Some(timer) if timer.ringing() => {}
+            _ => return Ok(false),
+        }
+
+        let result = self.attempt_failed();
+        self.events.push_back(SocketEvent::ConnectTimeout);
+        result.map(|_| true)
+    }
+
+    /// Call when the Server has told the socket its position in the queue of
+    /// clients waiting to be admitted
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    pub fn queued(&mut self, position: u32) {
+        // waiting in the queue isn't a stalled attempt, so the timeout starts over
+        self.connect_timer = self.connect_timeout.map(Timer::new);
+        if self.queue_position != Some(position) {
+            self.queue_position = Some(position);
+            self.events.push_back(SocketEvent::Queued { position });
+        }
+    }
+
+    /// Call when the socket starts connecting to a different Server, dropping
+    /// any connection it had
+    pub fn switching_server(&mut self) {
+        self.reconnector.cancel();
+        self.transition(ConnectionState::Connecting);
+        self.connect_timer = self.connect_timeout.map(Timer::new);
+    }
+
+    /// Call when a connection with the Server has been established
+    pub fn connected(&mut self) {
+        self.transition(ConnectionState::Connected);
+        if self.reconnector.connected() {
+            self.events.push_back(SocketEvent::Reconnected);
+        } else {
+            self.events.push_back(SocketEvent::Connection);
+        }
+    }
+
+    /// Call when an established connection has been lost
+    pub fn connection_lost(&mut self) {
+        match self.reconnector.connection_failed() {
+            Ok(()) => self.transition(ConnectionState::Disconnected),
+            Err(_) => self.transition(ConnectionState::Failed),
+        }
+        self.events.push_back(SocketEvent::Disconnection);
+    }
+
+    /// Call when the Server has closed the connection, giving the reason in
+    /// the payload of the given packet
+    pub fn kicked(&mut self, reason: Packet) {
+        trace_event!(INFO, reason_bytes = reason.payload().len(), "kicked");
+        self.reconnector.cancel();
+        self.transition(ConnectionState::Disconnected);
+        if reason.payload().is_empty() {
+            self.events.push_back(SocketEvent::Disconnection);
+        } else {
+            self.events.push_back(SocketEvent::Kicked(reason));
+        }
+    }
+
+    /// Call when a connection attempt has failed. Returns an error if there
+    /// will be no further attempts
+    pub fn attempt_failed(&mut self) -> Result<(), NaiaClientSocketError> {
+        if !self.auto_reconnect {
+            self.transition(ConnectionState::Failed);
+            return Ok(());
+        }
+        match self.reconnector.connection_failed() {
+            Ok(()) => {
+                self.transition(ConnectionState::Disconnected);
+                Ok(())
+            }
+            Err(err) => {
+                self.transition(ConnectionState::Failed);
+                Err(err)
+            }
+        }
+    }
+
+    /// Call when the socket can never connect to the Server
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    pub fn failed(&mut self) {
+        self.reconnector.cancel();
+        self.transition(ConnectionState::Failed);
+    }
+
+    /// Call when the application asks for the connection to be closed. The
+    /// socket won't try to reconnect afterwards
+    #[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
+    pub fn disconnecting(&mut self) {
+        self.reconnector.cancel();
+        self.transition(ConnectionState::Disconnecting);
+    }
+
+    /// Call when a connection closed at the application's request has
+    /// finished closing
+    pub fn disconnected(&mut self) {
+        let was_connected = self.state == ConnectionState::Connected
+            || self.state == ConnectionState::Disconnecting;
+        self.reconnector.cancel();
+        self.transition(ConnectionState::Disconnected);
+        if was_connected {
+            self.events.push_back(SocketEvent::Disconnection);
+        }
+    }
+
+    fn transition(&mut self, state: ConnectionState) {
+        if self.state != state {
+            self.queue_position = None;
+            if state == ConnectionState::Connecting {
+                self.connect_timer = self.connect_timeout.map(Timer::new);
+            }
+            trace_event!(INFO, from = ?self.state, to = ?state, "connection state changed");
+            self.state = state;
+            self.events.push_back(SocketEvent::StateChanged(state));
+        }
+    }
+}
@@ -0,0 +1,29 @@
+use std::time::Duration;
+
+/// How long each step of setting up the browser's WebRTC session with the
+/// Server may take before the connection attempt is given up on, so that
+/// none of them can hang it forever. Each step's timer starts once the step
+/// before it is done. These apply as well as `SocketConfig::connect_timeout`,
+/// whichever is reached first
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct WebRtcTimeouts {
+    /// From creating the offer until the Server's answer to it arrives, after
+    /// which the attempt fails with a SignalingTimeout error
+    pub signaling: Duration,
+    /// From the Server's answer arriving until the ICE connection to it is
+    /// made, after which the attempt fails with an IceTimeout error
+    pub ice: Duration,
+    /// From the ICE connection being made until the data channel opens over
+    /// it, after which the attempt fails with a ChannelOpenTimeout error
+    pub channel_open: Duration,
+}
+
+impl Default for WebRtcTimeouts {
+    fn default() -> Self {
+        WebRtcTimeouts {
+            signaling: Duration::from_secs(10),
+            ice: Duration::from_secs(10),
+            channel_open: Duration::from_secs(5),
+        }
+    }
+}
@@ -0,0 +1,83 @@
+//! The native client's connect requests & timeout, run through seconds of
+//! virtual time against a Server which never answers
+
+use std::{
+    net::UdpSocket,
+    time::{Duration, Instant},
+};
+
+use naia_client_socket::{
+    ClientSocket, ClientSocketTrait, ConnectionState, SocketConfig, SocketEvent, VirtualClock,
+};
+
+const STEP: Duration = Duration::from_millis(100);
+
+// A Server which takes in connect requests but never answers them
+fn silent_server() -> UdpSocket {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket
+        .set_read_timeout(Some(Duration::from_millis(10)))
+        .unwrap();
+    socket
+}
+
+fn count_datagrams(socket: &UdpSocket) -> usize {
+    let mut buffer = [0; 2048];
+    let mut count = 0;
+    while socket.recv(&mut buffer).is_ok() {
+        count += 1;
+    }
+    count
+}
+
+fn drain(socket: &mut Box<dyn ClientSocketTrait>, events: &mut Vec<SocketEvent>) {
+    while let Some(event) = socket.receive().unwrap() {
+        events.push(event);
+    }
+}
+
+#[test]
+fn connect_requests_are_resent_every_half_second() {
+    let clock = VirtualClock::install();
+    let server = silent_server();
+    let mut socket = ClientSocket::connect(server.local_addr().unwrap(), SocketConfig::default());
+
+    let mut events = Vec::new();
+    clock.run_for(Duration::from_secs(2), STEP, || {
+        drain(&mut socket, &mut events);
+        true
+    });
+    assert_eq!(count_datagrams(&server), 4);
+    assert_eq!(socket.state(), ConnectionState::Connecting);
+}
+
+#[test]
+fn connect_timeout_passes_in_virtual_time() {
+    let wall = Instant::now();
+    let clock = VirtualClock::install();
+    let server = silent_server();
+    let config = SocketConfig {
+        connect_timeout: Some(Duration::from_secs(10)),
+        ..SocketConfig::default()
+    };
+    let mut socket = ClientSocket::connect(server.local_addr().unwrap(), config);
+
+    let mut events = Vec::new();
+    clock.run_for(Duration::from_secs(10), STEP, || {
+        drain(&mut socket, &mut events);
+        true
+    });
+    assert!(!events
+        .iter()
+        .any(|event| matches!(event, SocketEvent::ConnectTimeout)));
+
+    clock.run_for(STEP, STEP, || {
+        drain(&mut socket, &mut events);
+        true
+    });
+    assert!(events
+        .iter()
+        .any(|event| matches!(event, SocketEvent::ConnectTimeout)));
+    assert_eq!(socket.state(), ConnectionState::Failed);
+    assert!(wall.elapsed() < Duration::from_secs(5));
+}
@@ -2,7 +2,8 @@ use miniquad::info;
 use std::net::SocketAddr;
 
 use naia_client_socket::{
-    ClientSocket, ClientSocketTrait, LinkConditionerConfig, MessageSender, Packet,
+    ClientSocket, ClientSocketTrait, LinkConditionerConfig, MessageSender, Packet, SocketConfig,
+    SocketEvent,
 };
 
 const PING_MSG: &str = "ping";
@@ -34,8 +35,9 @@ impl App {
             .expect("couldn't parse input IP address");
         let server_socket_address = SocketAddr::new(server_ip_address, SERVER_PORT);
 
-        let mut client_socket = ClientSocket::connect(server_socket_address)
-            .with_link_conditioner(&LinkConditionerConfig::good_condition());
+        let mut client_socket =
+            ClientSocket::connect(server_socket_address, SocketConfig::default())
+                .with_link_conditioner(&LinkConditionerConfig::good_condition());
         let mut message_sender = client_socket.get_sender();
 
         message_sender
@@ -55,7 +57,49 @@ impl App {
         loop {
             match self.client_socket.receive() {
                 Ok(event) => match event {
-                    Some(packet) => {
+                    Some(SocketEvent::Connection) => {
+                        info!("Client connected");
+                    }
+                    Some(SocketEvent::Disconnection) => {
+                        info!("Client disconnected");
+                    }
+                    Some(SocketEvent::Reconnecting { attempt }) => {
+                        info!("Client reconnecting (attempt {})", attempt);
+                    }
+                    Some(SocketEvent::Reconnected) => {
+                        info!("Client reconnected");
+                    }
+                    Some(SocketEvent::Kicked(reason)) => {
+                        info!(
+                            "Client kicked: {}",
+                            String::from_utf8_lossy(reason.payload())
+                        );
+                    }
+                    Some(SocketEvent::HostMigration(address)) => {
+                        info!("Client following host migration to {}", address);
+                        if let Err(err) = self.client_socket.switch_server(address) {
+                            info!("Client can't switch server: {}", err);
+                        }
+                    }
+                    Some(SocketEvent::ConnectTimeout) => {
+                        info!("Client connection attempt timed out");
+                    }
+                    Some(SocketEvent::Queued { position }) => {
+                        info!("Client waiting to be admitted, position {}", position);
+                    }
+                    Some(SocketEvent::PacketAcked(ack_id)) => {
+                        info!("Client packet {} was delivered", ack_id);
+                    }
+                    Some(SocketEvent::PacketLost(ack_id)) => {
+                        info!("Client packet {} was lost", ack_id);
+                    }
+                    Some(SocketEvent::OversizedPacket { size }) => {
+                        info!("Client dropped a packet of {} bytes", size);
+                    }
+                    Some(SocketEvent::StateChanged(state)) => {
+                        info!("Client connection state: {:?}", state);
+                    }
+                    Some(SocketEvent::Packet(packet)) => {
                         let message = String::from_utf8_lossy(packet.payload());
                         info!("Client recv: {}", message);
 
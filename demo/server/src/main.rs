@@ -3,7 +3,9 @@ extern crate log;
 
 use std::net::{IpAddr, SocketAddr};
 
-use naia_server_socket::{LinkConditionerConfig, Packet, ServerSocket};
+use naia_server_socket::{
+    LinkConditionerConfig, Packet, ServerSocket, ServerSocketEvent, SocketConfig,
+};
 use simple_logger;
 use smol::io;
 
@@ -34,15 +36,112 @@ fn main() -> io::Result<()> {
             .expect("couldn't parse input IP address");
         let current_socket_address = SocketAddr::new(server_ip_address, port);
 
-        let mut server_socket = ServerSocket::listen(current_socket_address)
-            .await
-            .with_link_conditioner(&LinkConditionerConfig::good_condition());
+        let mut server_socket =
+            ServerSocket::listen(current_socket_address, SocketConfig::default())
+                .await
+                .expect("could not listen")
+                .with_link_conditioner(&LinkConditionerConfig::good_condition());
 
         let mut sender = server_socket.get_sender();
 
         loop {
             match server_socket.receive().await {
-                Ok(packet) => {
+                Ok(ServerSocketEvent::Connection(connection_id, address)) => {
+                    info!("Server connection <- {} (id {})", address, connection_id);
+                }
+                Ok(ServerSocketEvent::Disconnection(connection_id, address, stats)) => {
+                    info!(
+                        "Server disconnection <- {} (id {}, {} packets received)",
+                        address, connection_id, stats.packets_received
+                    );
+                }
+                Ok(ServerSocketEvent::Reconnection(connection_id, address)) => {
+                    info!("Server reconnection <- {} (id {})", address, connection_id);
+                }
+                Ok(ServerSocketEvent::AddressChanged {
+                    connection_id,
+                    old_address,
+                    new_address,
+                }) => {
+                    info!(
+                        "Server address change <- {} is now {} (id {})",
+                        old_address, new_address, connection_id
+                    );
+                }
+                Ok(ServerSocketEvent::Queued { address, position }) => {
+                    info!("Server queued {} at position {}", address, position);
+                }
+                Ok(ServerSocketEvent::LeftQueue { address }) => {
+                    info!("Server queue left by {}", address);
+                }
+                Ok(ServerSocketEvent::VersionMismatch {
+                    address,
+                    client_version,
+                }) => {
+                    info!(
+                        "Server refused {}: client speaks protocol version {}",
+                        address, client_version
+                    );
+                }
+                Ok(ServerSocketEvent::PacketAcked(connection_id, ack_id)) => {
+                    info!(
+                        "Server packet {} to {} was delivered",
+                        ack_id, connection_id
+                    );
+                }
+                Ok(ServerSocketEvent::PacketLost(connection_id, ack_id)) => {
+                    info!("Server packet {} to {} was lost", ack_id, connection_id);
+                }
+                Ok(ServerSocketEvent::QueueOverflow { direction, dropped }) => {
+                    info!("Server {:?} queue dropped {} packets", direction, dropped);
+                }
+                Ok(ServerSocketEvent::SlowConsumer {
+                    connection_id,
+                    queued,
+                }) => {
+                    info!(
+                        "Server client {} is slow, {} datagrams queued",
+                        connection_id, queued
+                    );
+                }
+                Ok(ServerSocketEvent::ConsumerCaughtUp { connection_id }) => {
+                    info!("Server client {} caught up", connection_id);
+                }
+                Ok(ServerSocketEvent::Closed) => {
+                    info!("Server socket closed");
+                    break;
+                }
+                Ok(ServerSocketEvent::QualityChanged {
+                    connection_id,
+                    quality,
+                }) => {
+                    info!("Server client {} quality is {:?}", connection_id, quality);
+                }
+                Ok(ServerSocketEvent::BackpressureDrop {
+                    connection_id,
+                    dropped,
+                }) => {
+                    info!(
+                        "Server dropped {} datagrams to backed up client {}",
+                        dropped, connection_id
+                    );
+                }
+                Ok(ServerSocketEvent::OversizedPacket {
+                    connection_id,
+                    size,
+                }) => {
+                    info!(
+                        "Server dropped a packet of {} bytes from client {}",
+                        size, connection_id
+                    );
+                }
+                Ok(ServerSocketEvent::Error(error)) => {
+                    info!("Server error: {}", error);
+                }
+                Ok(ServerSocketEvent::PublicAddressChanged(address)) => {
+                    info!("Server public address is now {}", address);
+                }
+                Ok(ServerSocketEvent::Packet(packet)) => {
                     let address = packet.address();
                     let message = String::from_utf8_lossy(packet.payload());
                     info!("Server recv <- {}: {}", address, message);
@@ -56,10 +155,16 @@ fn main() -> io::Result<()> {
                             .expect("send error");
                     }
                 }
+                Ok(_) => {}
                 Err(error) => {
                     info!("Server Error: {}", error);
+                    if error.is_fatal() {
+                        break;
+                    }
                 }
             }
         }
+
+        Ok(())
     })
 }
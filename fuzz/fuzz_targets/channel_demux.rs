@@ -0,0 +1,64 @@
+//! Feeds arbitrary channel traffic to a connection's ReliableChannels, which
+//! hand each message to the channel named in its header. The input is a run of
+//! packets, each preceded by its length as one byte & starting with its
+//! packet type, as they arrive
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use naia_socket_shared::reliability::{self, ReliableChannels};
+use naia_socket_shared::{ChannelMode, PacketType, ReliabilityConfig};
+
+fuzz_target!(|data: &[u8]| {
+    let config = ReliabilityConfig {
+        channels: vec![
+            ChannelMode::Unreliable,
+            ChannelMode::ReliableUnordered,
+            ChannelMode::ReliableOrdered,
+        ],
+        max_in_flight: 16,
+        ..ReliabilityConfig::default()
+    };
+    let mut channels = ReliableChannels::new(&config);
+
+    let mut rest = data;
+    while let Some((len, tail)) = rest.split_first() {
+        let len = usize::from(*len).min(tail.len());
+        let (packet, tail) = tail.split_at(len);
+        rest = tail;
+
+        let (packet_type, body) = match packet.split_first() {
+            Some((packet_type, body)) => (PacketType::from_byte(*packet_type), body),
+            None => continue,
+        };
+        match packet_type {
+            Some(PacketType::Reliable) => {
+                let (channel, id, message) = match reliability::read_header(body) {
+                    Some(header) => header,
+                    None => continue,
+                };
+                let delivered = channels.receive(channel, id, message);
+                match channels.mode(channel) {
+                    Some(ChannelMode::ReliableOrdered) | Some(ChannelMode::ReliableUnordered) => {}
+                    // only reliable channels keep track of what they receive
+                    _ => assert!(delivered.is_none()),
+                }
+                for delivered in delivered.unwrap_or_default() {
+                    assert!(delivered.len() <= data.len());
+                }
+            }
+            Some(PacketType::ReliableAck) => {
+                if let Some((channel, id, _)) = reliability::read_header(body) {
+                    channels.acked(channel, id);
+                }
+            }
+            Some(PacketType::ChannelData) => {
+                if let Some(channel) = body.first() {
+                    let _ = channels.mode(*channel);
+                }
+            }
+            _ => {}
+        }
+    }
+});
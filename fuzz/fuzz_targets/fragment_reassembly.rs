@@ -0,0 +1,50 @@
+//! Feeds arbitrary fragments to a connection's Reassembler. The input is a run
+//! of fragments, each preceded by its length as one byte. The input is then
+//! split into fragments itself, which must be put back together into it
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use naia_socket_shared::fragmentation::{self, Reassembler, FRAGMENT_HEADER_SIZE};
+use naia_socket_shared::FragmentationConfig;
+
+fuzz_target!(|data: &[u8]| {
+    let config = FragmentationConfig {
+        max_packet_size: 4096,
+        ..FragmentationConfig::default()
+    };
+    let max_packet_size = config.max_packet_size;
+    let mut reassembler = Reassembler::new(config.clone());
+
+    let mut rest = data;
+    while let Some((len, tail)) = rest.split_first() {
+        let len = usize::from(*len).min(tail.len());
+        let (fragment, tail) = tail.split_at(len);
+        rest = tail;
+
+        if let Some(packet) = reassembler.receive(fragment) {
+            assert!(packet.len() <= max_packet_size);
+            // every byte of it came from a fragment, less the header
+            assert!(fragment.len() >= FRAGMENT_HEADER_SIZE);
+            assert!(packet.len() <= data.len());
+        }
+    }
+
+    // a packet split into fragments comes back whole, whichever order its
+    // fragments arrive in
+    let payload = &data[..data.len().min(max_packet_size)];
+    let chunk_size = usize::from(data.first().copied().unwrap_or(1));
+    if let Some(fragments) = fragmentation::split_into_fragments(payload, 0, chunk_size) {
+        let mut reassembler = Reassembler::new(config);
+        let count = fragments.len();
+        for (i, (header, fragment)) in fragments.into_iter().rev().enumerate() {
+            let reassembled = reassembler.receive(&[&header[..], fragment].concat());
+            if i + 1 < count {
+                assert_eq!(reassembled, None);
+            } else {
+                assert_eq!(reassembled.as_deref(), Some(payload));
+            }
+        }
+    }
+});
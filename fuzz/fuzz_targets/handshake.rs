@@ -0,0 +1,41 @@
+//! Feeds arbitrary datagrams to the UDP Server's handshake parsing, which
+//! anyone who can reach its port can send to
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use naia_socket_shared::{encryption, handshake, PacketType};
+
+fuzz_target!(|data: &[u8]| {
+    let body = match data.split_first() {
+        Some((packet_type, body)) => {
+            if let Some(packet_type) = PacketType::from_byte(*packet_type) {
+                assert_eq!(
+                    PacketType::from_byte(packet_type.to_byte()),
+                    Some(packet_type)
+                );
+            }
+            body
+        }
+        None => return,
+    };
+
+    if handshake::read_header(body).is_none() {
+        return;
+    }
+
+    // a challenge response's header is followed by its feature flags, its
+    // cookie, & then its body
+    let cookie_end = handshake::HANDSHAKE_HEADER_SIZE + 1 + handshake::CHALLENGE_COOKIE_SIZE;
+    let rest = match body.get(cookie_end..) {
+        Some(rest) => rest,
+        None => return,
+    };
+    for public_key_size in [None, Some(encryption::PUBLIC_KEY_SIZE)].iter() {
+        if let Some(response) = handshake::read_challenge_response_body(rest, *public_key_size) {
+            assert!(response.connect_payload.len() <= handshake::MAX_CONNECT_PAYLOAD_SIZE);
+            assert_eq!(response.public_key.map(<[u8]>::len), *public_key_size);
+        }
+    }
+});
@@ -0,0 +1,24 @@
+use std::time::Duration;
+
+/// Settings for spotting clients which can't keep up with what they are
+/// sent. A connection's outgoing queue is made of the datagrams its pacer is
+/// holding back, so this needs `SocketConfig::pacing` or
+/// `SocketConfig::congestion_control`
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct BackpressureConfig {
+    /// The number of datagrams a connection's outgoing queue can hold before
+    /// it is considered backed up
+    pub high_water_mark: usize,
+    /// How long a connection's outgoing queue has to stay backed up before
+    /// the client is reported as a slow consumer
+    pub threshold: Duration,
+}
+
+impl Default for BackpressureConfig {
+    fn default() -> Self {
+        BackpressureConfig {
+            high_water_mark: 32,
+            threshold: Duration::from_secs(1),
+        }
+    }
+}
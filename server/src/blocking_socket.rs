@@ -0,0 +1,75 @@
+use std::{
+    fmt, thread,
+    time::{Duration, Instant},
+};
+
+use crate::{MessageSender, NaiaServerSocketError, Packet, ServerSocketEvent, ServerSocketTrait};
+
+// How long to sleep between looking for events
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Wraps a Server Socket in calls which block the calling thread until they
+/// are done, for simple tools & tests which would rather not set up an async
+/// runtime. The socket's loop only runs while one of them is blocked, so
+/// packets sent are only sent once the next receive is called
+pub struct BlockingSocket {
+    socket: Box<dyn ServerSocketTrait>,
+    sender: MessageSender,
+}
+
+impl BlockingSocket {
+    /// Wraps the given Server Socket
+    pub fn new(mut socket: Box<dyn ServerSocketTrait>) -> Self {
+        let sender = socket.get_sender();
+        BlockingSocket { socket, sender }
+    }
+
+    /// Blocks until the next event is received
+    pub fn receive(&mut self) -> Result<ServerSocketEvent, NaiaServerSocketError> {
+        async_io::block_on(self.socket.receive())
+    }
+
+    /// Blocks until the next event is received, or the timeout runs out, in
+    /// which case `None` is returned. Nothing the socket has already read is
+    /// lost when it does
+    pub fn receive_timeout(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<Option<ServerSocketEvent>, NaiaServerSocketError> {
+        // polled, rather than raced against a timer, which would cancel a
+        // receive part of the way through what it had read
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(event) = self.socket.try_receive()? {
+                return Ok(Some(event));
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(None);
+            }
+            thread::sleep(POLL_INTERVAL.min(deadline - now));
+        }
+    }
+
+    /// Queues a Packet to be sent to a client, blocking while the outgoing
+    /// queue is full
+    pub fn send(&mut self, packet: Packet) -> Result<(), NaiaServerSocketError> {
+        async_io::block_on(self.sender.send(packet))
+    }
+
+    /// Gets a mutable reference to the socket
+    pub fn socket_mut(&mut self) -> &mut dyn ServerSocketTrait {
+        self.socket.as_mut()
+    }
+
+    /// Takes the socket back out of the BlockingSocket
+    pub fn into_inner(self) -> Box<dyn ServerSocketTrait> {
+        self.socket
+    }
+}
+
+impl fmt::Debug for BlockingSocket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BlockingSocket").finish()
+    }
+}
@@ -0,0 +1,146 @@
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use bytes::Bytes;
+
+/// Settings for the pool of buffers which received packets are stored in, so
+/// that a busy Server Socket isn't constantly allocating & freeing them
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct BufferPoolConfig {
+    /// The size of each buffer, in bytes. Packets larger than this get a
+    /// buffer of their own, which is freed rather than reused
+    pub buffer_size: usize,
+    /// The most buffers kept around for reuse. A buffer released while this
+    /// many are already waiting is freed
+    pub max_buffers: usize,
+}
+
+impl Default for BufferPoolConfig {
+    fn default() -> Self {
+        BufferPoolConfig {
+            buffer_size: 1472,
+            max_buffers: 1024,
+        }
+    }
+}
+
+/// Counters describing how well the buffer pool fits the traffic it sees
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct BufferPoolStats {
+    /// The number of packets stored in a buffer reused from the pool
+    pub reused: u64,
+    /// The number of packets which needed a new buffer, because none were
+    /// free. If this keeps growing, `max_buffers` is too small
+    pub allocated: u64,
+    /// The number of packets too large for a pool buffer. If this keeps
+    /// growing, `buffer_size` is too small
+    pub oversized: u64,
+    /// The number of buffers currently waiting to be reused
+    pub free_buffers: usize,
+}
+
+/// Hands out buffers for received packets, which return to the pool once
+/// every Packet sharing them has been dropped
+#[derive(Clone)]
+pub struct BufferPool {
+    shared: Arc<PoolShared>,
+}
+
+struct PoolShared {
+    config: BufferPoolConfig,
+    free: Mutex<Vec<Vec<u8>>>,
+    reused: AtomicU64,
+    allocated: AtomicU64,
+    oversized: AtomicU64,
+}
+
+impl BufferPool {
+    /// Create a new, empty BufferPool
+    pub fn new(config: BufferPoolConfig) -> Self {
+        BufferPool {
+            shared: Arc::new(PoolShared {
+                config,
+                free: Mutex::new(Vec::new()),
+                reused: AtomicU64::new(0),
+                allocated: AtomicU64::new(0),
+                oversized: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// Copies the given data into a buffer from the pool
+    pub fn copy_from(&self, data: &[u8]) -> Bytes {
+        let shared = &self.shared;
+        if data.len() > shared.config.buffer_size {
+            shared.oversized.fetch_add(1, Ordering::Relaxed);
+            return Bytes::copy_from_slice(data);
+        }
+
+        let reused_buffer = shared.free.lock().unwrap().pop();
+        let mut buffer = match reused_buffer {
+            Some(buffer) => {
+                shared.reused.fetch_add(1, Ordering::Relaxed);
+                buffer
+            }
+            None => {
+                shared.allocated.fetch_add(1, Ordering::Relaxed);
+                Vec::with_capacity(shared.config.buffer_size)
+            }
+        };
+        buffer.extend_from_slice(data);
+
+        Bytes::from_owner(PooledBuffer {
+            buffer,
+            pool: shared.clone(),
+        })
+    }
+
+    /// Gets the pool's counters
+    pub fn stats(&self) -> BufferPoolStats {
+        let shared = &self.shared;
+        BufferPoolStats {
+            reused: shared.reused.load(Ordering::Relaxed),
+            allocated: shared.allocated.load(Ordering::Relaxed),
+            oversized: shared.oversized.load(Ordering::Relaxed),
+            free_buffers: shared.free.lock().unwrap().len(),
+        }
+    }
+}
+
+impl fmt::Debug for BufferPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BufferPool")
+            .field("config", &self.shared.config)
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
+
+// Owns a buffer on behalf of the Packets sharing it, & puts it back in the
+// pool when the last of them is dropped
+struct PooledBuffer {
+    buffer: Vec<u8>,
+    pool: Arc<PoolShared>,
+}
+
+impl AsRef<[u8]> for PooledBuffer {
+    fn as_ref(&self) -> &[u8] {
+        &self.buffer
+    }
+}
+
+impl Drop for PooledBuffer {
+    fn drop(&mut self) {
+        let mut buffer = std::mem::take(&mut self.buffer);
+        buffer.clear();
+        let mut free = self.pool.free.lock().unwrap();
+        if free.len() < self.pool.config.max_buffers {
+            free.push(buffer);
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use std::{
+    collections::HashMap,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_channel::mpsc;
+use futures_util::{stream::Stream, StreamExt};
+use log::warn;
+
+use crate::{Packet, ServerSocketEvent};
+
+/// Hands the packets received on chosen channels to ChannelReceivers of their
+/// own, so that each kind of traffic can be handled apart from the rest of
+/// the socket's events. Every event received from the socket should be passed
+/// through `route`
+#[derive(Debug, Default)]
+pub struct ChannelRouter {
+    receivers: HashMap<u8, mpsc::Sender<Packet>>,
+}
+
+impl ChannelRouter {
+    /// Create a new ChannelRouter, which hands every event back until
+    /// receivers are added
+    pub fn new() -> Self {
+        ChannelRouter {
+            receivers: HashMap::new(),
+        }
+    }
+
+    /// Gets a receiver for the packets arriving on the given channel, which
+    /// holds up to `queue_size` of them. Replaces any receiver the channel
+    /// already had
+    pub fn receiver(&mut self, channel: u8, queue_size: usize) -> ChannelReceiver {
+        let (sender, receiver) = mpsc::channel(queue_size);
+        self.receivers.insert(channel, sender);
+        ChannelReceiver {
+            channel,
+            internal: receiver,
+        }
+    }
+
+    /// Takes an event received from the socket, handing it back unless it is
+    /// a packet on a channel with a receiver. Packets which arrive while their
+    /// receiver is full are dropped, & once a receiver is dropped, its
+    /// channel's packets are handed back again
+    pub fn route(&mut self, event: ServerSocketEvent) -> Option<ServerSocketEvent> {
+        let packet = match event {
+            ServerSocketEvent::Packet(packet) => packet,
+            event => return Some(event),
+        };
+        let channel = match packet.channel() {
+            Some(channel) if self.receivers.contains_key(&channel) => channel,
+            _ => return Some(ServerSocketEvent::Packet(packet)),
+        };
+
+        let receiver = self.receivers.get_mut(&channel).unwrap();
+        match receiver.try_send(packet) {
+            Ok(()) => None,
+            Err(error) if error.is_full() => {
+                warn!(
+                    "Dropped a packet on channel {}, as its receiver is full",
+                    channel
+                );
+                None
+            }
+            Err(error) => {
+                self.receivers.remove(&channel);
+                Some(ServerSocketEvent::Packet(error.into_inner()))
+            }
+        }
+    }
+}
+
+/// Receives the packets arriving on a single channel, as handed over by a
+/// ChannelRouter
+#[derive(Debug)]
+pub struct ChannelReceiver {
+    channel: u8,
+    internal: mpsc::Receiver<Packet>,
+}
+
+impl ChannelReceiver {
+    /// Gets the id of the channel packets are received on
+    pub fn channel(&self) -> u8 {
+        self.channel
+    }
+
+    /// Waits for the next packet on the channel, returning `None` once the
+    /// ChannelRouter has been dropped or has replaced this receiver
+    pub async fn receive(&mut self) -> Option<Packet> {
+        self.internal.next().await
+    }
+
+    /// Gets the next packet on the channel without waiting, if one has
+    /// arrived
+    pub fn try_receive(&mut self) -> Option<Packet> {
+        self.internal.try_recv().ok()
+    }
+}
+
+impl Stream for ChannelReceiver {
+    type Item = Packet;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Packet>> {
+        Pin::new(&mut self.internal).poll_next(cx)
+    }
+}
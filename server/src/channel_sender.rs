@@ -0,0 +1,34 @@
+use crate::{MessageSender, NaiaServerSocketError, Packet, TrySendError};
+
+/// Sends packets to clients on a single channel, so that each kind of traffic
+/// can have a sender of its own
+#[derive(Debug)]
+pub struct ChannelSender {
+    sender: MessageSender,
+    channel: u8,
+}
+
+impl ChannelSender {
+    /// Create a new ChannelSender, which moves every packet sent through it
+    /// onto the given channel
+    pub fn new(sender: MessageSender, channel: u8) -> Self {
+        ChannelSender { sender, channel }
+    }
+
+    /// Gets the id of the channel packets are sent on
+    pub fn channel(&self) -> u8 {
+        self.channel
+    }
+
+    /// Send a Packet to a client on the channel, waiting for room if the
+    /// Server Socket's outgoing queue is full. See `MessageSender::send`
+    pub async fn send(&mut self, packet: Packet) -> Result<(), NaiaServerSocketError> {
+        self.sender.send(packet.with_channel(self.channel)).await
+    }
+
+    /// Queue a Packet to be sent to a client on the channel without waiting,
+    /// handing the Packet back if the Server Socket's outgoing queue is full
+    pub fn try_send(&mut self, packet: Packet) -> Result<(), TrySendError> {
+        self.sender.try_send(packet.with_channel(self.channel))
+    }
+}
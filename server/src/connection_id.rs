@@ -0,0 +1,19 @@
+use std::fmt;
+
+/// Identifies a connection to the Server Socket. Assigned by the server when a
+/// client connects, and stays the same for the lifetime of that connection
+/// even if the client's address changes
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub struct ConnectionId(u64);
+
+impl ConnectionId {
+    pub(crate) fn new(id: u64) -> Self {
+        ConnectionId(id)
+    }
+}
+
+impl fmt::Display for ConnectionId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
@@ -0,0 +1,320 @@
+use std::{any::Any, collections::HashMap, fmt, net::SocketAddr, time::Duration};
+
+use naia_socket_shared::{canonical_address, clock, ConnectToken};
+
+use super::{
+    connection_id::ConnectionId, connection_stats::ConnectionStats, socket_metrics::MetricsTracker,
+};
+
+/// An application-defined value which can be attached to a connection
+pub type UserData = Box<dyn Any + Send + Sync>;
+
+/// Keeps track of every client that has connected to the Server Socket, along
+/// with any data the application has attached to them. Clients are known by
+/// their IPv4 address rather than an IPv4-mapped one, however they reached
+/// the Server
+#[derive(Debug, Default)]
+pub struct ConnectionManager {
+    next_id: u64,
+    connections: HashMap<ConnectionId, Connection>,
+    addresses: HashMap<SocketAddr, ConnectionId>,
+    metrics: MetricsTracker,
+}
+
+impl ConnectionManager {
+    /// Create a new, empty ConnectionManager
+    pub fn new() -> Self {
+        ConnectionManager {
+            next_id: 0,
+            connections: HashMap::new(),
+            addresses: HashMap::new(),
+            metrics: MetricsTracker::default(),
+        }
+    }
+
+    /// Registers a new connection coming from the given address, and returns
+    /// the ConnectionId assigned to it
+    pub fn add_connection(&mut self, address: &SocketAddr) -> ConnectionId {
+        let address = canonical_address(*address);
+        let connection_id = ConnectionId::new(self.next_id);
+        self.next_id += 1;
+
+        let connection = Connection::new(connection_id, address);
+        trace_event!(parent: &connection.span, INFO, "connected");
+        self.connections.insert(connection_id, connection);
+        self.addresses.insert(address, connection_id);
+        self.metrics.handshake();
+
+        connection_id
+    }
+
+    /// Stops tracking the given connection, returning the address it was last
+    /// known by & its final counters
+    pub fn remove_connection(
+        &mut self,
+        connection_id: &ConnectionId,
+    ) -> Option<(SocketAddr, ConnectionStats)> {
+        let connection = self.connections.remove(connection_id)?;
+        self.addresses.remove(&connection.address);
+        connection.trace_disconnected();
+        Some((connection.address, connection.stats))
+    }
+
+    /// Stops tracking every connection, returning the ConnectionIds, the
+    /// addresses they were last known by & their final counters
+    pub fn remove_all_connections(&mut self) -> Vec<(ConnectionId, SocketAddr, ConnectionStats)> {
+        self.addresses.clear();
+        self.connections
+            .drain()
+            .map(|(connection_id, connection)| {
+                connection.trace_disconnected();
+                (connection_id, connection.address, connection.stats)
+            })
+            .collect()
+    }
+
+    /// Moves the given connection over to a new address, returning the address
+    /// it was previously known by. Only the UDP transport is able to migrate
+    /// connections
+    #[cfg_attr(feature = "use-webrtc", allow(dead_code))]
+    pub fn change_address(
+        &mut self,
+        connection_id: &ConnectionId,
+        new_address: &SocketAddr,
+    ) -> Option<SocketAddr> {
+        let new_address = canonical_address(*new_address);
+        let connection = self.connections.get_mut(connection_id)?;
+        let old_address = connection.address;
+        connection.address = new_address;
+        trace_event!(
+            parent: &connection.span,
+            INFO,
+            %old_address,
+            %new_address,
+            "migrated"
+        );
+
+        self.addresses.remove(&old_address);
+        self.addresses.insert(new_address, *connection_id);
+
+        Some(old_address)
+    }
+
+    /// Gets every connection being tracked, with the address it is known by
+    pub fn connections(&self) -> Vec<(ConnectionId, SocketAddr)> {
+        self.connections
+            .iter()
+            .map(|(connection_id, connection)| (*connection_id, connection.address))
+            .collect()
+    }
+
+    /// Gets the number of connections being tracked
+    pub fn connection_count(&self) -> usize {
+        self.connections.len()
+    }
+
+    /// Gets the ConnectionId of the connection with the given address
+    pub fn connection_id(&self, address: &SocketAddr) -> Option<ConnectionId> {
+        self.addresses.get(&canonical_address(*address)).copied()
+    }
+
+    /// Gets the connection whose connect token was minted for the given client
+    #[cfg_attr(feature = "use-webrtc", allow(dead_code))]
+    pub fn connection_with_client_id(&self, client_id: u64) -> Option<ConnectionId> {
+        self.connections
+            .iter()
+            .find_map(
+                |(connection_id, connection)| match &connection.connect_token {
+                    Some(connect_token) if connect_token.client_id == client_id => {
+                        Some(*connection_id)
+                    }
+                    _ => None,
+                },
+            )
+    }
+
+    /// Gets the address the given connection is currently known by
+    pub fn address(&self, connection_id: &ConnectionId) -> Option<SocketAddr> {
+        self.connections
+            .get(connection_id)
+            .map(|connection| connection.address)
+    }
+
+    /// Records the connect token the given connection presented when it was
+    /// accepted
+    #[cfg_attr(feature = "use-webrtc", allow(dead_code))]
+    pub fn set_connect_token(&mut self, connection_id: &ConnectionId, connect_token: ConnectToken) {
+        if let Some(connection) = self.connections.get_mut(connection_id) {
+            connection.connect_token = Some(connect_token);
+        }
+    }
+
+    /// Gets the connect token the given connection presented when it was
+    /// accepted
+    pub fn connect_token(&self, connection_id: &ConnectionId) -> Option<&ConnectToken> {
+        self.connections
+            .get(connection_id)
+            .and_then(|connection| connection.connect_token.as_ref())
+    }
+
+    /// Stores the application payload the given connection attached to its
+    /// handshake
+    #[cfg_attr(feature = "use-webrtc", allow(dead_code))]
+    pub fn set_connect_payload(&mut self, connection_id: &ConnectionId, connect_payload: Vec<u8>) {
+        if let Some(connection) = self.connections.get_mut(connection_id) {
+            connection.connect_payload = connect_payload;
+        }
+    }
+
+    /// Gets the application payload the given connection attached to its
+    /// handshake
+    pub fn connect_payload(&self, connection_id: &ConnectionId) -> Option<&[u8]> {
+        self.connections
+            .get(connection_id)
+            .map(|connection| connection.connect_payload.as_slice())
+    }
+
+    /// Gets the counters kept for the given connection
+    pub fn stats(&self, connection_id: &ConnectionId) -> Option<ConnectionStats> {
+        self.connections
+            .get(connection_id)
+            .map(|connection| connection.stats)
+    }
+
+    /// Gets the round trip times of every connection where they're known
+    pub fn rtts(&self) -> Vec<Duration> {
+        self.connections
+            .values()
+            .filter_map(|connection| connection.stats.rtt)
+            .collect()
+    }
+
+    /// Gets a mutable reference to the counters kept for the given connection
+    pub fn stats_mut(&mut self, connection_id: &ConnectionId) -> Option<&mut ConnectionStats> {
+        self.connections
+            .get_mut(connection_id)
+            .map(|connection| &mut connection.stats)
+    }
+
+    /// Counts a packet of the given size received from the given connection
+    pub fn record_received(&mut self, connection_id: &ConnectionId, bytes: usize) {
+        self.metrics.received(bytes);
+        if let Some(connection) = self.connections.get_mut(connection_id) {
+            trace_event!(parent: &connection.span, TRACE, bytes, "packet received");
+            let stats = &mut connection.stats;
+            stats.packets_received += 1;
+            stats.bytes_received += bytes as u64;
+            stats.last_received = Some(clock::now());
+        }
+    }
+
+    /// Counts a packet of the given size sent to the given connection
+    pub fn record_sent(&mut self, connection_id: &ConnectionId, bytes: usize) {
+        self.metrics.sent(bytes);
+        if let Some(connection) = self.connections.get_mut(connection_id) {
+            trace_event!(parent: &connection.span, TRACE, bytes, "packet sent");
+            let stats = &mut connection.stats;
+            stats.packets_sent += 1;
+            stats.bytes_sent += bytes as u64;
+        }
+    }
+
+    /// Gets the running totals kept for the socket as a whole
+    pub fn metrics(&self) -> &MetricsTracker {
+        &self.metrics
+    }
+
+    /// Gets a mutable reference to the running totals kept for the socket as
+    /// a whole
+    pub fn metrics_mut(&mut self) -> &mut MetricsTracker {
+        &mut self.metrics
+    }
+
+    /// Attaches a value to the given connection, replacing and returning the
+    /// previous one. Does nothing if there is no such connection
+    pub fn set_user_data(
+        &mut self,
+        connection_id: &ConnectionId,
+        data: UserData,
+    ) -> Option<UserData> {
+        self.connections
+            .get_mut(connection_id)?
+            .user_data
+            .replace(data)
+    }
+
+    /// Gets a reference to the value attached to the given connection
+    pub fn user_data(&self, connection_id: &ConnectionId) -> Option<&(dyn Any + Send + Sync)> {
+        self.connections
+            .get(connection_id)
+            .and_then(|connection| connection.user_data.as_deref())
+    }
+
+    /// Gets a mutable reference to the value attached to the given connection
+    pub fn user_data_mut(
+        &mut self,
+        connection_id: &ConnectionId,
+    ) -> Option<&mut (dyn Any + Send + Sync)> {
+        self.connections
+            .get_mut(connection_id)
+            .and_then(|connection| connection.user_data.as_deref_mut())
+    }
+
+    /// Removes the value attached to the given connection, leaving the
+    /// connection itself in place
+    pub fn take_user_data(&mut self, connection_id: &ConnectionId) -> Option<UserData> {
+        self.connections
+            .get_mut(connection_id)
+            .and_then(|connection| connection.user_data.take())
+    }
+}
+
+struct Connection {
+    address: SocketAddr,
+    connect_token: Option<ConnectToken>,
+    connect_payload: Vec<u8>,
+    stats: ConnectionStats,
+    user_data: Option<UserData>,
+    // every event about the connection belongs to this, so it can be followed
+    // from the handshake to the disconnection
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+}
+
+impl Connection {
+    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+    fn new(connection_id: ConnectionId, address: SocketAddr) -> Self {
+        Connection {
+            address,
+            connect_token: None,
+            connect_payload: Vec::new(),
+            stats: ConnectionStats::default(),
+            user_data: None,
+            #[cfg(feature = "tracing")]
+            span: tracing::info_span!("connection", id = %connection_id, %address),
+        }
+    }
+
+    fn trace_disconnected(&self) {
+        trace_event!(
+            parent: &self.span,
+            INFO,
+            packets_sent = self.stats.packets_sent,
+            packets_received = self.stats.packets_received,
+            rtt_ms = self.stats.rtt.map(|rtt| rtt.as_millis() as u64),
+            "disconnected"
+        );
+    }
+}
+
+impl fmt::Debug for Connection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Connection")
+            .field("address", &self.address)
+            .field("connect_token", &self.connect_token)
+            .field("connect_payload", &self.connect_payload)
+            .field("stats", &self.stats)
+            .field("has_user_data", &self.user_data.is_some())
+            .finish()
+    }
+}
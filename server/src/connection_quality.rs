@@ -0,0 +1,130 @@
+use std::time::Duration;
+
+use crate::ConnectionStats;
+
+// the weight given to the loss measured between each update
+#[cfg_attr(feature = "use-webrtc", allow(dead_code))]
+const LOSS_GAIN: f64 = 0.25;
+
+/// How good a connection is, judged from its round trip time, jitter & loss,
+/// as a single signal for when to show a bad connection icon or to send less
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum ConnectionQuality {
+    /// Nothing is worse than the Fair thresholds
+    Good,
+    /// Something is worse than the Fair thresholds, but nothing is worse than
+    /// the Poor ones
+    Fair,
+    /// Something is worse than the Poor thresholds
+    Poor,
+}
+
+/// The point past which each measure puts a connection at a quality level
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QualityThresholds {
+    /// The smoothed round trip time
+    pub rtt: Duration,
+    /// How much the round trip time varies
+    pub jitter: Duration,
+    /// The share of recent packets lost, between 0 & 1
+    pub loss: f64,
+}
+
+/// Settings for judging the quality of each connection. The round trip time
+/// is measured by `SocketConfig::ping_interval` or
+/// `SocketConfig::acknowledgement`, jitter by the former & loss by the
+/// latter, & measures which aren't known are left out
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QualityConfig {
+    /// Past any of these, a connection is Fair
+    pub fair: QualityThresholds,
+    /// Past any of these, a connection is Poor
+    pub poor: QualityThresholds,
+}
+
+impl Default for QualityConfig {
+    fn default() -> Self {
+        QualityConfig {
+            fair: QualityThresholds {
+                rtt: Duration::from_millis(150),
+                jitter: Duration::from_millis(30),
+                loss: 0.02,
+            },
+            poor: QualityThresholds {
+                rtt: Duration::from_millis(300),
+                jitter: Duration::from_millis(75),
+                loss: 0.1,
+            },
+        }
+    }
+}
+
+#[cfg_attr(feature = "use-webrtc", allow(dead_code))]
+impl QualityThresholds {
+    // Gets whether any of the measures known is past these thresholds
+    fn exceeded(&self, rtt: Option<Duration>, jitter: Option<Duration>, loss: Option<f64>) -> bool {
+        rtt.is_some_and(|rtt| rtt > self.rtt)
+            || jitter.is_some_and(|jitter| jitter > self.jitter)
+            || loss.is_some_and(|loss| loss > self.loss)
+    }
+}
+
+/// Judges a single connection's quality from its stats as they change,
+/// keeping the loss of recent packets rather than since the connection began
+#[derive(Debug)]
+#[cfg_attr(feature = "use-webrtc", allow(dead_code))]
+pub struct QualityTracker {
+    config: QualityConfig,
+    quality: Option<ConnectionQuality>,
+    loss: Option<f64>,
+    acked: u64,
+    lost: u64,
+}
+
+#[cfg_attr(feature = "use-webrtc", allow(dead_code))]
+impl QualityTracker {
+    /// Create a new QualityTracker, which judges nothing until something is
+    /// measured
+    pub fn new(config: QualityConfig) -> Self {
+        QualityTracker {
+            config,
+            quality: None,
+            loss: None,
+            acked: 0,
+            lost: 0,
+        }
+    }
+
+    /// Judges the connection again from its latest stats, returning its
+    /// quality if that has changed
+    pub fn update(&mut self, stats: &ConnectionStats) -> Option<ConnectionQuality> {
+        let acked = stats.acked_packets.saturating_sub(self.acked);
+        let lost = stats.lost_packets.saturating_sub(self.lost);
+        if acked + lost > 0 {
+            let sample = lost as f64 / (acked + lost) as f64;
+            self.loss = Some(match self.loss {
+                Some(loss) => loss * (1.0 - LOSS_GAIN) + sample * LOSS_GAIN,
+                None => sample,
+            });
+            self.acked = stats.acked_packets;
+            self.lost = stats.lost_packets;
+        }
+
+        let (rtt, jitter, loss) = (stats.rtt, stats.jitter, self.loss);
+        if rtt.is_none() && jitter.is_none() && loss.is_none() {
+            return None;
+        }
+        let quality = if self.config.poor.exceeded(rtt, jitter, loss) {
+            ConnectionQuality::Poor
+        } else if self.config.fair.exceeded(rtt, jitter, loss) {
+            ConnectionQuality::Fair
+        } else {
+            ConnectionQuality::Good
+        };
+        if self.quality == Some(quality) {
+            return None;
+        }
+        self.quality = Some(quality);
+        Some(quality)
+    }
+}
@@ -0,0 +1,80 @@
+use std::time::{Duration, Instant};
+
+use crate::ConnectionQuality;
+
+/// Counters kept by the Server Socket for each connection
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct ConnectionStats {
+    /// The number of packets sent to the connection. On the UDP transport
+    /// these are datagrams, after coalescing & fragmenting, & packets sent
+    /// through a split socket's SendHalf aren't counted
+    pub packets_sent: u64,
+    /// The number of bytes sent to the connection, in the same packets
+    pub bytes_sent: u64,
+    /// The number of packets received from the connection, handshake packets
+    /// aside
+    pub packets_received: u64,
+    /// The number of bytes received from the connection, in the same packets
+    pub bytes_received: u64,
+    /// The moment a packet was last received from the connection
+    pub last_received: Option<Instant>,
+    /// The smoothed round trip time to the connection, measured by pinging it
+    /// while `SocketConfig::ping_interval` is set, or else from its
+    /// acknowledgements while `SocketConfig::acknowledgement` is. Only known
+    /// on the UDP transport
+    pub rtt: Option<Duration>,
+    /// How much the round trip time to the connection varies from one ping
+    /// to the next. Only known on the UDP transport while
+    /// `SocketConfig::ping_interval` is set
+    pub jitter: Option<Duration>,
+    /// The number of packets followed by acknowledgements which were
+    /// received. Only counted when `SocketConfig::acknowledgement` is set
+    pub acked_packets: u64,
+    /// The number of packets followed by acknowledgements which were lost.
+    /// Only counted when `SocketConfig::acknowledgement` is set
+    pub lost_packets: u64,
+    /// The number of packets dropped because one with the same sequence number
+    /// had already been received
+    pub replayed_packets: u64,
+    /// The number of packets dropped because they were too old to tell
+    /// whether they had already been received
+    pub stale_packets: u64,
+    /// The number of packets which arrived after a packet sent later than
+    /// them. They are still delivered. Only counted when
+    /// `SocketConfig::replay_protection` is set
+    pub out_of_order_packets: u64,
+    /// The number of packets which arrived marked by a router as having
+    /// experienced congestion. Only counted when `SocketConfig::ecn` is set
+    pub congestion_experienced: u64,
+    /// The number of packets to the connection which were dropped because
+    /// their time to live ran out before they could be sent
+    pub expired_packets: u64,
+    /// The number of packets dropped because they failed authentication,
+    /// having been tampered with or sent by someone other than the client.
+    /// Only counted when `SocketConfig::encryption` or
+    /// `SocketConfig::authentication` is set
+    pub forged_packets: u64,
+    /// The number of datagrams to the connection which were dropped because
+    /// they would have waited longer than `PacingConfig::max_delay` for their
+    /// turn. Only counted when `SocketConfig::pacing` is set
+    pub paced_out_datagrams: u64,
+    /// The number of bytes per second the connection is estimated to be able
+    /// to take. Only known when `SocketConfig::congestion_control` is set
+    pub estimated_bandwidth: Option<u64>,
+    /// How good the connection is, judged from the measures above. Only known
+    /// on the UDP transport while `SocketConfig::quality` is set, once
+    /// something has been measured
+    pub quality: Option<ConnectionQuality>,
+}
+
+impl ConnectionStats {
+    /// Gets the share of the packets followed by acknowledgements which were
+    /// lost, between 0 & 1, once the fate of any is known
+    pub fn loss_rate(&self) -> Option<f64> {
+        let known = self.acked_packets + self.lost_packets;
+        if known == 0 {
+            return None;
+        }
+        Some(self.lost_packets as f64 / known as f64)
+    }
+}
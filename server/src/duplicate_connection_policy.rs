@@ -0,0 +1,14 @@
+/// What the Server Socket does when a client connects using the identity of a
+/// client which is already connected, as given by the `client_id` of their
+/// connect tokens
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub enum DuplicateConnectionPolicy {
+    /// Both connections are kept
+    #[default]
+    Allow,
+    /// The new connection is refused
+    Reject,
+    /// The existing connection is closed, with a Disconnection event, & the
+    /// new one is accepted
+    Replace,
+}
@@ -1,22 +1,233 @@
-use std::{error::Error, fmt, net::SocketAddr};
+use std::{
+    error::Error,
+    fmt,
+    io::{self, ErrorKind},
+    net::SocketAddr,
+};
+
+use naia_socket_shared::HandshakeError;
+
+use crate::Packet;
 
 /// An Error type specifically related to the Naia Server Socket
-/// This is under construction and needs to be cleaned up
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum NaiaServerSocketError {
-    /// A wrapped error from another library/codebase
-    Wrapped(Box<dyn Error>),
-    /// An error indicating an inability to send to the given address
+    /// The underlying socket failed
+    Io(io::Error),
+    /// A datagram couldn't be sent to the given address
     SendError(SocketAddr),
+    /// A Packet was sent to an address with no connection, which has
+    /// probably gone away
+    ConnectionClosed {
+        /// The address the Packet was sent to
+        address: SocketAddr,
+    },
+    /// The Server Socket's outgoing queue is full
+    SendQueueFull,
+    /// A Packet to the given address was in Text mode, but wasn't valid
+    /// UTF-8 or began the way the WebRTC transport's own messages do, & wasn't
+    /// sent
+    InvalidText(SocketAddr),
+    /// A Packet's payload was larger than `SocketConfig::max_payload_size`
+    PayloadTooLarge {
+        /// The size of the payload
+        size: usize,
+        /// The most a payload can be
+        max_payload_size: usize,
+    },
+    /// The Server Socket has been dropped, or the thread receiving from it
+    /// has stopped
+    Closed,
+    /// The given address couldn't be listened at, as something else is
+    /// already listening there
+    AddrInUse(SocketAddr),
+    /// The given address couldn't be listened at, as this process isn't
+    /// allowed to, for example because the port is privileged
+    PermissionDenied(SocketAddr),
+    /// The certificate WebRTC sessions are secured with couldn't be
+    /// generated
+    CertError(String),
+    /// The public address couldn't be discovered with STUN
+    StunError(io::Error),
+    /// The request for a WebRTC session from the given address wasn't over
+    /// within `SocketConfig::signaling_timeout`, & was dropped
+    SignalingTimeout(SocketAddr),
+    /// The WebRTC session requested from the given address couldn't be
+    /// negotiated, for the reason given
+    Signaling(SocketAddr, String),
+    /// A client was refused, for the reason given. Reported at most once a
+    /// second for each address, which is logged
+    Handshake(HandshakeError),
+}
+
+impl NaiaServerSocketError {
+    // Describes a failure to listen at an address, picking out the reasons
+    // an application can do something about
+    pub(crate) fn from_bind_error(err: io::Error, address: SocketAddr) -> Self {
+        match err.kind() {
+            ErrorKind::AddrInUse => NaiaServerSocketError::AddrInUse(address),
+            ErrorKind::PermissionDenied => NaiaServerSocketError::PermissionDenied(address),
+            _ => NaiaServerSocketError::Io(err),
+        }
+    }
+
+    /// Gets whether the socket is unusable after this error, & should be
+    /// dropped or rebound. Other errors concern a single send or datagram, &
+    /// the socket can carry on receiving
+    pub fn is_fatal(&self) -> bool {
+        match self {
+            NaiaServerSocketError::Io(err) => !is_transient(err),
+            NaiaServerSocketError::SendError(_)
+            | NaiaServerSocketError::ConnectionClosed { .. }
+            | NaiaServerSocketError::SendQueueFull
+            | NaiaServerSocketError::InvalidText(_)
+            | NaiaServerSocketError::PayloadTooLarge { .. }
+            | NaiaServerSocketError::SignalingTimeout(_)
+            | NaiaServerSocketError::Signaling(..)
+            | NaiaServerSocketError::Handshake(_) => false,
+            NaiaServerSocketError::Closed
+            | NaiaServerSocketError::AddrInUse(_)
+            | NaiaServerSocketError::PermissionDenied(_)
+            | NaiaServerSocketError::CertError(_)
+            | NaiaServerSocketError::StunError(_) => true,
+        }
+    }
+}
+
+// Gets whether an IO error passes, so that trying again can succeed, such as
+// an interrupted call or an ICMP report about a single peer
+pub(crate) fn is_transient(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        ErrorKind::Interrupted
+            | ErrorKind::WouldBlock
+            | ErrorKind::TimedOut
+            | ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionRefused
+            | ErrorKind::ConnectionAborted
+            | ErrorKind::HostUnreachable
+            | ErrorKind::NetworkUnreachable
+    )
 }
 
 impl fmt::Display for NaiaServerSocketError {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         match self {
-            NaiaServerSocketError::Wrapped(boxed_err) => fmt::Display::fmt(boxed_err.as_ref(), f),
-            NaiaServerSocketError::SendError(addr) => fmt::Display::fmt(&addr, f),
+            NaiaServerSocketError::Io(err) => fmt::Display::fmt(err, f),
+            NaiaServerSocketError::SendError(addr) => write!(f, "could not send to {}", addr),
+            NaiaServerSocketError::ConnectionClosed { address } => {
+                write!(f, "no connection to {}", address)
+            }
+            NaiaServerSocketError::SendQueueFull => write!(f, "outgoing queue is full"),
+            NaiaServerSocketError::InvalidText(addr) => {
+                write!(f, "could not send the packet to {} as text", addr)
+            }
+            NaiaServerSocketError::PayloadTooLarge {
+                size,
+                max_payload_size,
+            } => write!(
+                f,
+                "payload of {} bytes is larger than the most, {}",
+                size, max_payload_size
+            ),
+            NaiaServerSocketError::Closed => write!(f, "server socket has been closed"),
+            NaiaServerSocketError::AddrInUse(addr) => write!(f, "address {} is in use", addr),
+            NaiaServerSocketError::PermissionDenied(addr) => {
+                write!(f, "not allowed to listen at {}", addr)
+            }
+            NaiaServerSocketError::CertError(message) => {
+                write!(f, "could not generate certificate: {}", message)
+            }
+            NaiaServerSocketError::StunError(err) => {
+                write!(f, "could not discover the public address: {}", err)
+            }
+            NaiaServerSocketError::SignalingTimeout(addr) => {
+                write!(f, "session request from {} timed out", addr)
+            }
+            NaiaServerSocketError::Signaling(addr, reason) => {
+                write!(f, "session request from {} failed: {}", addr, reason)
+            }
+            NaiaServerSocketError::Handshake(err) => write!(f, "refused a client: {}", err),
+        }
+    }
+}
+
+impl Error for NaiaServerSocketError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            NaiaServerSocketError::Io(err) | NaiaServerSocketError::StunError(err) => Some(err),
+            NaiaServerSocketError::Handshake(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for NaiaServerSocketError {
+    fn from(err: io::Error) -> Self {
+        NaiaServerSocketError::Io(err)
+    }
+}
+
+impl From<TrySendError> for NaiaServerSocketError {
+    fn from(err: TrySendError) -> Self {
+        match err {
+            TrySendError::Full(_) => NaiaServerSocketError::SendQueueFull,
+            TrySendError::Disconnected(_) => NaiaServerSocketError::Closed,
+            TrySendError::TooLarge {
+                packet,
+                max_payload_size,
+            } => NaiaServerSocketError::PayloadTooLarge {
+                size: packet.payload().len(),
+                max_payload_size,
+            },
+        }
+    }
+}
+
+/// The reasons `MessageSender::try_send` can fail, each of which hands back the
+/// Packet which wasn't sent, boxed so that the error stays small
+#[derive(Debug)]
+pub enum TrySendError {
+    /// The Server Socket's outgoing queue is full
+    Full(Box<Packet>),
+    /// The Server Socket has been dropped
+    Disconnected(Box<Packet>),
+    /// The Packet's payload is larger than `SocketConfig::max_payload_size`
+    TooLarge {
+        /// The Packet which wasn't sent
+        packet: Box<Packet>,
+        /// The most a payload can be
+        max_payload_size: usize,
+    },
+}
+
+impl TrySendError {
+    /// Takes back the Packet which wasn't sent
+    pub fn into_packet(self) -> Packet {
+        match self {
+            TrySendError::Full(packet) | TrySendError::Disconnected(packet) => *packet,
+            TrySendError::TooLarge { packet, .. } => *packet,
+        }
+    }
+}
+
+impl fmt::Display for TrySendError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            TrySendError::Full(_) => write!(f, "outgoing queue is full"),
+            TrySendError::Disconnected(_) => write!(f, "server socket has been dropped"),
+            TrySendError::TooLarge {
+                packet,
+                max_payload_size,
+            } => write!(
+                f,
+                "payload of {} bytes is larger than the most, {}",
+                packet.payload().len(),
+                max_payload_size
+            ),
         }
     }
 }
 
-impl Error for NaiaServerSocketError {}
+impl Error for TrySendError {}
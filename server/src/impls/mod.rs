@@ -1,11 +1,15 @@
 cfg_if! {
     if #[cfg(feature = "use-udp")] {
         mod udp;
-        pub use self::udp::server_socket::ServerSocket;
+        pub use self::udp::{send_half::SendHalf, server_socket::ServerSocket};
     }
     else if #[cfg(feature = "use-webrtc")] {
         mod webrtc;
-        pub use self::webrtc::server_socket::ServerSocket;
+        pub use self::webrtc::{
+            packet_ref::{PacketRef, ReceivedRef},
+            send_half::SendHalf,
+            server_socket::ServerSocket,
+        };
     }
     else {
     }
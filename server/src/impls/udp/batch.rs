@@ -0,0 +1,414 @@
+use async_io::Async;
+use std::{
+    io,
+    net::{SocketAddr, UdpSocket},
+};
+
+use naia_socket_shared::canonical_address;
+
+/// Buffers for receiving several datagrams with a single system call
+#[derive(Debug)]
+pub struct ReceiveBatch {
+    buffers: Vec<Vec<u8>>,
+    // space for the ancillary data which carries each datagram's ECN bits
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    controls: Vec<ControlBuffer>,
+    received: Vec<ReceivedDatagram>,
+}
+
+// 64 bytes, aligned for the control message headers laid out in it
+type ControlBuffer = [u64; 8];
+
+/// Where a received datagram was put, & what is known about it
+#[derive(Debug)]
+pub struct ReceivedDatagram {
+    buffer: usize,
+    len: usize,
+    address: SocketAddr,
+    congestion_experienced: bool,
+}
+
+impl ReceiveBatch {
+    /// Create a new ReceiveBatch, able to receive up to `batch_size` datagrams
+    /// of up to `buffer_size` bytes each at once
+    pub fn new(batch_size: usize, buffer_size: usize) -> Self {
+        let batch_size = batch_size.max(1);
+        ReceiveBatch {
+            buffers: vec![vec![0; buffer_size]; batch_size],
+            controls: vec![[0; 8]; batch_size],
+            received: Vec::with_capacity(batch_size),
+        }
+    }
+
+    /// Waits for at least one datagram, then receives as many as are waiting,
+    /// up to the batch size. Where the platform can't receive several at once,
+    /// only one is received
+    pub async fn receive(&mut self, socket: &Async<UdpSocket>) -> io::Result<()> {
+        let buffers = &mut self.buffers;
+        let received = &mut self.received;
+        received.clear();
+
+        cfg_if! {
+            if #[cfg(target_os = "linux")] {
+                let controls = &mut self.controls;
+                socket
+                    .read_with(|socket| mmsg::receive(socket, buffers, controls, received))
+                    .await
+            } else {
+                let (len, address) = socket.recv_from(&mut buffers[0]).await?;
+                received.push(ReceivedDatagram {
+                    buffer: 0,
+                    len,
+                    address,
+                    congestion_experienced: false,
+                });
+                Ok(())
+            }
+        }
+    }
+
+    /// Receives as many datagrams as are waiting, up to the batch size,
+    /// without waiting for any, returning whether there were some
+    pub fn try_receive(&mut self, socket: &Async<UdpSocket>) -> io::Result<bool> {
+        let buffers = &mut self.buffers;
+        let received = &mut self.received;
+        received.clear();
+
+        let result = {
+            cfg_if! {
+                if #[cfg(target_os = "linux")] {
+                    mmsg::receive(socket.get_ref(), buffers, &mut self.controls, received)
+                } else {
+                    socket.get_ref().recv_from(&mut buffers[0]).map(|(len, address)| {
+                        received.push(ReceivedDatagram {
+                            buffer: 0,
+                            len,
+                            address,
+                            congestion_experienced: false,
+                        });
+                    })
+                }
+            }
+        };
+        match result {
+            Ok(()) => Ok(true),
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Gets the number of datagrams received by the last call to `receive`
+    pub fn len(&self) -> usize {
+        self.received.len()
+    }
+
+    /// Gets a datagram received by the last call to `receive`, along with the
+    /// address it came from, as an IPv4 one if it was IPv4-mapped, & whether
+    /// a router marked it as having experienced congestion
+    pub fn get(&self, index: usize) -> (&[u8], SocketAddr, bool) {
+        let datagram = &self.received[index];
+        (
+            &self.buffers[datagram.buffer][..datagram.len],
+            canonical_address(datagram.address),
+            datagram.congestion_experienced,
+        )
+    }
+}
+
+/// Asks the OS to report the traffic class of each datagram received on the
+/// socket, so that ECN marks can be read. Only possible on Linux, elsewhere
+/// this does nothing
+pub fn receive_traffic_class(socket: &UdpSocket) -> io::Result<()> {
+    cfg_if! {
+        if #[cfg(target_os = "linux")] {
+            mmsg::receive_traffic_class(socket)
+        } else {
+            let _ = socket;
+            Ok(())
+        }
+    }
+}
+
+/// Sends each message to its address, using as few system calls as the
+/// platform allows. A message which can't be sent doesn't stop the rest, but
+/// the address of the first one which failed is returned. Peers with an IPv4
+/// address are sent to at IPv4-mapped ones if the socket is an IPv6 one
+pub async fn send_batch(
+    socket: &Async<UdpSocket>,
+    ipv6: bool,
+    messages: &[(Vec<u8>, SocketAddr)],
+) -> Result<(), (SocketAddr, io::Error)> {
+    let mut failed = None;
+
+    cfg_if! {
+        if #[cfg(target_os = "linux")] {
+            let mut sent = 0;
+            while sent < messages.len() {
+                match socket.write_with(|socket| mmsg::send(socket, ipv6, &messages[sent..])).await {
+                    Ok(count) => sent += count,
+                    Err(err) => {
+                        // sendmmsg only reports an error for the first message, skip it
+                        failed = failed.or(Some((messages[sent].1, err)));
+                        sent += 1;
+                    }
+                }
+            }
+        } else {
+            for (message, address) in messages {
+                if let Err(err) = socket.send_to(message, naia_socket_shared::address_for_socket(*address, ipv6)).await {
+                    failed = failed.or(Some((*address, err)));
+                }
+            }
+        }
+    }
+
+    match failed {
+        Some(failed) => Err(failed),
+        None => Ok(()),
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod mmsg {
+    use std::{
+        io, mem,
+        net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV6, UdpSocket},
+        os::unix::io::AsRawFd,
+        ptr,
+    };
+
+    use naia_socket_shared::address_for_socket;
+
+    use super::{ControlBuffer, ReceivedDatagram};
+
+    // The kernel won't take more than this many messages in one call
+    const MAX_BATCH: usize = 1024;
+    // The ECN codepoint routers set on packets instead of dropping them
+    const ECN_CONGESTION_EXPERIENCED: u8 = 0b11;
+
+    /// Sends as many of the messages as the socket will take with a single
+    /// sendmmsg call, returning how many were sent
+    pub fn send(
+        socket: &UdpSocket,
+        ipv6: bool,
+        messages: &[(Vec<u8>, SocketAddr)],
+    ) -> io::Result<usize> {
+        let messages = &messages[..messages.len().min(MAX_BATCH)];
+
+        let mut addresses: Vec<(libc::sockaddr_storage, libc::socklen_t)> = messages
+            .iter()
+            .map(|(_, address)| to_sockaddr(&address_for_socket(*address, ipv6)))
+            .collect();
+        let mut iovecs: Vec<libc::iovec> = messages
+            .iter()
+            .map(|(message, _)| libc::iovec {
+                iov_base: message.as_ptr() as *mut libc::c_void,
+                iov_len: message.len(),
+            })
+            .collect();
+        let mut headers: Vec<libc::mmsghdr> = addresses
+            .iter_mut()
+            .zip(iovecs.iter_mut())
+            .map(|((address, address_len), iovec)| {
+                // zeroed, as the layout has private padding on some targets
+                let mut header: libc::mmsghdr = unsafe { mem::zeroed() };
+                header.msg_hdr.msg_name = ptr::addr_of_mut!(*address).cast();
+                header.msg_hdr.msg_namelen = *address_len;
+                header.msg_hdr.msg_iov = iovec;
+                header.msg_hdr.msg_iovlen = 1;
+                header
+            })
+            .collect();
+
+        let sent = unsafe {
+            libc::sendmmsg(
+                socket.as_raw_fd(),
+                headers.as_mut_ptr(),
+                headers.len() as libc::c_uint,
+                0,
+            )
+        };
+        if sent < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(sent as usize)
+    }
+
+    /// Receives as many datagrams as are waiting, up to one per buffer, with a
+    /// single recvmmsg call, adding where each went to `received`
+    pub fn receive(
+        socket: &UdpSocket,
+        buffers: &mut [Vec<u8>],
+        controls: &mut [ControlBuffer],
+        received: &mut Vec<ReceivedDatagram>,
+    ) -> io::Result<()> {
+        let batch_size = buffers.len().min(MAX_BATCH);
+        let buffers = &mut buffers[..batch_size];
+
+        let mut addresses: Vec<libc::sockaddr_storage> =
+            vec![unsafe { mem::zeroed() }; buffers.len()];
+        let mut iovecs: Vec<libc::iovec> = buffers
+            .iter_mut()
+            .map(|buffer| libc::iovec {
+                iov_base: buffer.as_mut_ptr().cast(),
+                iov_len: buffer.len(),
+            })
+            .collect();
+        let mut headers: Vec<libc::mmsghdr> = addresses
+            .iter_mut()
+            .zip(iovecs.iter_mut())
+            .zip(controls.iter_mut())
+            .map(|((address, iovec), control)| {
+                let mut header: libc::mmsghdr = unsafe { mem::zeroed() };
+                header.msg_hdr.msg_name = ptr::addr_of_mut!(*address).cast();
+                header.msg_hdr.msg_namelen = size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+                header.msg_hdr.msg_iov = iovec;
+                header.msg_hdr.msg_iovlen = 1;
+                header.msg_hdr.msg_control = control.as_mut_ptr().cast();
+                // the size of a ControlBuffer
+                header.msg_hdr.msg_controllen = 64;
+                header
+            })
+            .collect();
+
+        let count = unsafe {
+            libc::recvmmsg(
+                socket.as_raw_fd(),
+                headers.as_mut_ptr(),
+                headers.len() as libc::c_uint,
+                0,
+                ptr::null_mut(),
+            )
+        };
+        if count < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        for (index, (header, address)) in headers
+            .iter()
+            .zip(addresses.iter())
+            .take(count as usize)
+            .enumerate()
+        {
+            // datagrams from an address family we don't speak are dropped
+            if let Some(address) = from_sockaddr(address) {
+                received.push(ReceivedDatagram {
+                    buffer: index,
+                    len: header.msg_len as usize,
+                    address,
+                    congestion_experienced: ecn(&header.msg_hdr)
+                        == Some(ECN_CONGESTION_EXPERIENCED),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Asks the OS to attach the traffic class of each received datagram as
+    /// ancillary data
+    pub fn receive_traffic_class(socket: &UdpSocket) -> io::Result<()> {
+        match socket.local_addr()? {
+            SocketAddr::V4(_) => enable_option(socket, libc::IPPROTO_IP, libc::IP_RECVTOS),
+            SocketAddr::V6(_) => {
+                // IPv4 datagrams reaching a dual-stack socket carry their TOS
+                // only if asked for with the IPv4 option
+                let _ = enable_option(socket, libc::IPPROTO_IP, libc::IP_RECVTOS);
+                enable_option(socket, libc::IPPROTO_IPV6, libc::IPV6_RECVTCLASS)
+            }
+        }
+    }
+
+    fn enable_option(
+        socket: &UdpSocket,
+        level: libc::c_int,
+        option: libc::c_int,
+    ) -> io::Result<()> {
+        let enable: libc::c_int = 1;
+        let result = unsafe {
+            libc::setsockopt(
+                socket.as_raw_fd(),
+                level,
+                option,
+                ptr::addr_of!(enable).cast(),
+                size_of::<libc::c_int>() as libc::socklen_t,
+            )
+        };
+        if result != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    // Reads the ECN bits out of a received datagram's ancillary data
+    fn ecn(header: &libc::msghdr) -> Option<u8> {
+        let mut control = unsafe { libc::CMSG_FIRSTHDR(header) };
+        while !control.is_null() {
+            let (level, kind) = unsafe { ((*control).cmsg_level, (*control).cmsg_type) };
+            let data = unsafe { libc::CMSG_DATA(control) };
+            // IPv4 delivers the TOS as a byte, IPv6 the traffic class as an int
+            if level == libc::IPPROTO_IP && kind == libc::IP_TOS {
+                return Some(unsafe { *data } & 0b11);
+            }
+            if level == libc::IPPROTO_IPV6 && kind == libc::IPV6_TCLASS {
+                let traffic_class = unsafe { ptr::read_unaligned(data.cast::<libc::c_int>()) };
+                return Some(traffic_class as u8 & 0b11);
+            }
+            control = unsafe { libc::CMSG_NXTHDR(header, control) };
+        }
+        None
+    }
+
+    fn from_sockaddr(storage: &libc::sockaddr_storage) -> Option<SocketAddr> {
+        match storage.ss_family as libc::c_int {
+            libc::AF_INET => {
+                let sockaddr: libc::sockaddr_in =
+                    unsafe { ptr::read(ptr::addr_of!(*storage).cast()) };
+                let ip = Ipv4Addr::from(sockaddr.sin_addr.s_addr.to_ne_bytes());
+                Some(SocketAddr::new(ip.into(), u16::from_be(sockaddr.sin_port)))
+            }
+            libc::AF_INET6 => {
+                let sockaddr: libc::sockaddr_in6 =
+                    unsafe { ptr::read(ptr::addr_of!(*storage).cast()) };
+                Some(SocketAddr::V6(SocketAddrV6::new(
+                    Ipv6Addr::from(sockaddr.sin6_addr.s6_addr),
+                    u16::from_be(sockaddr.sin6_port),
+                    sockaddr.sin6_flowinfo,
+                    sockaddr.sin6_scope_id,
+                )))
+            }
+            _ => None,
+        }
+    }
+
+    fn to_sockaddr(address: &SocketAddr) -> (libc::sockaddr_storage, libc::socklen_t) {
+        let mut storage: libc::sockaddr_storage = unsafe { mem::zeroed() };
+        let len = match address {
+            SocketAddr::V4(address) => {
+                let sockaddr = libc::sockaddr_in {
+                    sin_family: libc::AF_INET as libc::sa_family_t,
+                    sin_port: address.port().to_be(),
+                    sin_addr: libc::in_addr {
+                        s_addr: u32::from_ne_bytes(address.ip().octets()),
+                    },
+                    sin_zero: [0; 8],
+                };
+                unsafe { ptr::write(ptr::addr_of_mut!(storage).cast(), sockaddr) };
+                size_of::<libc::sockaddr_in>()
+            }
+            SocketAddr::V6(address) => {
+                let sockaddr = libc::sockaddr_in6 {
+                    sin6_family: libc::AF_INET6 as libc::sa_family_t,
+                    sin6_port: address.port().to_be(),
+                    sin6_flowinfo: address.flowinfo(),
+                    sin6_addr: libc::in6_addr {
+                        s6_addr: address.ip().octets(),
+                    },
+                    sin6_scope_id: address.scope_id(),
+                };
+                unsafe { ptr::write(ptr::addr_of_mut!(storage).cast(), sockaddr) };
+                size_of::<libc::sockaddr_in6>()
+            }
+        };
+        (storage, len as libc::socklen_t)
+    }
+}
@@ -0,0 +1,121 @@
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+use naia_socket_shared::{
+    clock,
+    coalescing::{self, SUBFRAME_HEADER_SIZE},
+    PacketType,
+};
+
+/// Packs small packets sent to the same address into a single datagram,
+/// which is sent once it is full or has waited long enough
+#[derive(Debug)]
+pub struct Coalescer {
+    interval: Duration,
+    pending: HashMap<SocketAddr, PendingDatagram>,
+}
+
+#[derive(Debug)]
+struct PendingDatagram {
+    buffer: Vec<u8>,
+    packets: usize,
+    started: Instant,
+}
+
+impl Coalescer {
+    /// Create a new Coalescer, which holds packets back for at most the given
+    /// interval
+    pub fn new(interval: Duration) -> Self {
+        Coalescer {
+            interval,
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Adds a packet to the datagram being built for its address, moving that
+    /// datagram to `datagrams` first if the packet wouldn't fit within `mtu`.
+    /// Returns false if the packet is too large to share a datagram, in which
+    /// case the datagram built so far is moved to `datagrams` so that the
+    /// packet can be sent right after it
+    pub fn push(
+        &mut self,
+        payload: &[u8],
+        address: SocketAddr,
+        mtu: usize,
+        datagrams: &mut Vec<(Vec<u8>, SocketAddr)>,
+    ) -> bool {
+        if 1 + SUBFRAME_HEADER_SIZE + payload.len() > mtu {
+            self.flush_address(address, datagrams);
+            return false;
+        }
+
+        let full = self.pending.get(&address).is_some_and(|pending| {
+            pending.buffer.len() + SUBFRAME_HEADER_SIZE + payload.len() > mtu
+        });
+        if full {
+            self.flush_address(address, datagrams);
+        }
+
+        let pending = self
+            .pending
+            .entry(address)
+            .or_insert_with(|| PendingDatagram {
+                buffer: vec![PacketType::Coalesced.to_byte()],
+                packets: 0,
+                started: clock::now(),
+            });
+        coalescing::write_subframe(&mut pending.buffer, payload);
+        pending.packets += 1;
+        true
+    }
+
+    /// Gets the moment the oldest datagram being built is due to be sent
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.pending
+            .values()
+            .map(|pending| pending.started + self.interval)
+            .min()
+    }
+
+    /// Moves every datagram which has waited long enough to `datagrams`
+    pub fn flush_due(&mut self, datagrams: &mut Vec<(Vec<u8>, SocketAddr)>) {
+        let interval = self.interval;
+        let due: Vec<SocketAddr> = self
+            .pending
+            .iter()
+            .filter(|(_, pending)| clock::elapsed(pending.started) >= interval)
+            .map(|(address, _)| *address)
+            .collect();
+        for address in due {
+            self.flush_address(address, datagrams);
+        }
+    }
+
+    /// Moves every datagram being built to `datagrams`
+    pub fn flush_all(&mut self, datagrams: &mut Vec<(Vec<u8>, SocketAddr)>) {
+        for (address, pending) in self.pending.drain() {
+            datagrams.push((pending.into_datagram(), address));
+        }
+    }
+
+    fn flush_address(&mut self, address: SocketAddr, datagrams: &mut Vec<(Vec<u8>, SocketAddr)>) {
+        if let Some(pending) = self.pending.remove(&address) {
+            datagrams.push((pending.into_datagram(), address));
+        }
+    }
+}
+
+impl PendingDatagram {
+    // A lone packet is sent as an ordinary Data packet, without the subframe
+    // header
+    fn into_datagram(mut self) -> Vec<u8> {
+        if self.packets == 1 {
+            self.buffer.drain(1..1 + SUBFRAME_HEADER_SIZE);
+            self.buffer[0] = PacketType::Data.to_byte();
+        }
+        self.buffer
+    }
+}
@@ -0,0 +1,88 @@
+use std::{
+    convert::TryInto,
+    net::{IpAddr, SocketAddr},
+    time::{Duration, SystemTime},
+};
+
+use hmac::{Hmac, Mac, NewMac};
+use sha2::Sha256;
+
+use naia_socket_shared::{handshake::CHALLENGE_COOKIE_SIZE, Random};
+
+const COOKIE_LIFETIME: Duration = Duration::from_secs(10);
+const TIMESTAMP_SIZE: usize = 8;
+const SIGNATURE_SIZE: usize = CHALLENGE_COOKIE_SIZE - TIMESTAMP_SIZE;
+
+/// Issues & checks the cookies sent in a ServerChallenge. A cookie is a
+/// timestamp plus a signature over that timestamp & the client's address, so
+/// the server can tell a client really owns its address without storing
+/// anything until it answers
+#[derive(Debug)]
+pub struct CookieJar {
+    secret: [u8; 32],
+}
+
+impl CookieJar {
+    /// Creates a new CookieJar with a freshly generated secret
+    pub fn new() -> Self {
+        let mut secret = [0; 32];
+        for chunk in secret.chunks_mut(8) {
+            chunk.copy_from_slice(&Random::gen_u64().to_be_bytes());
+        }
+        CookieJar { secret }
+    }
+
+    /// Creates a cookie for the given address
+    pub fn bake(&self, address: &SocketAddr) -> [u8; CHALLENGE_COOKIE_SIZE] {
+        let timestamp = unix_time().to_be_bytes();
+
+        let mut cookie = [0; CHALLENGE_COOKIE_SIZE];
+        cookie[..TIMESTAMP_SIZE].copy_from_slice(&timestamp);
+        cookie[TIMESTAMP_SIZE..].copy_from_slice(&self.signature(&timestamp, address));
+        cookie
+    }
+
+    /// Returns whether the cookie was issued by this CookieJar, to the given
+    /// address, recently enough to still be accepted
+    pub fn check(&self, cookie: &[u8], address: &SocketAddr) -> bool {
+        if cookie.len() != CHALLENGE_COOKIE_SIZE {
+            return false;
+        }
+        let (timestamp, signature) = cookie.split_at(TIMESTAMP_SIZE);
+
+        let issued = u64::from_be_bytes(timestamp.try_into().unwrap());
+        if unix_time().saturating_sub(issued) > COOKIE_LIFETIME.as_secs() {
+            return false;
+        }
+
+        // compare without short-circuiting, so the signature can't be guessed
+        // byte by byte
+        self.signature(timestamp, address)
+            .iter()
+            .zip(signature)
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+    }
+
+    fn signature(&self, timestamp: &[u8], address: &SocketAddr) -> [u8; SIGNATURE_SIZE] {
+        let mut mac =
+            Hmac::<Sha256>::new_varkey(&self.secret).expect("HMAC can take a key of any size");
+        mac.update(timestamp);
+        match address.ip() {
+            IpAddr::V4(ip) => mac.update(&ip.octets()),
+            IpAddr::V6(ip) => mac.update(&ip.octets()),
+        }
+        mac.update(&address.port().to_be_bytes());
+
+        let mut signature = [0; SIGNATURE_SIZE];
+        signature.copy_from_slice(&mac.finalize().into_bytes()[..SIGNATURE_SIZE]);
+        signature
+    }
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .expect("timing error!")
+        .as_secs()
+}
@@ -0,0 +1,80 @@
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU16, Ordering},
+        Arc,
+    },
+};
+
+use log::warn;
+
+use naia_socket_shared::{
+    fragmentation::{self, FRAGMENT_HEADER_SIZE},
+    FragmentationConfig, PacketType,
+};
+
+use crate::Packet;
+
+/// Turns packets into the datagrams which carry them to a client, splitting
+/// those too large for a single datagram into fragments if fragmentation is
+/// enabled. Clones share the counter fragmented packets are numbered with
+#[derive(Debug, Clone)]
+pub struct Fragmenter {
+    config: Option<FragmentationConfig>,
+    next_id: Arc<AtomicU16>,
+}
+
+impl Fragmenter {
+    /// Create a new Fragmenter, which only splits packets if given a config
+    pub fn new(config: Option<FragmentationConfig>) -> Self {
+        Fragmenter {
+            config,
+            next_id: Arc::new(AtomicU16::new(0)),
+        }
+    }
+
+    /// Adds the datagrams carrying the given packet to `datagrams`, none of
+    /// them larger than `mtu` if it can be helped. Packets too large to send
+    /// are dropped
+    pub fn push_datagrams(
+        &self,
+        packet: &Packet,
+        mtu: usize,
+        datagrams: &mut Vec<(Vec<u8>, SocketAddr)>,
+    ) {
+        let payload = packet.payload();
+        let address = packet.address();
+
+        let config = match &self.config {
+            Some(config) if 1 + payload.len() > mtu => config,
+            _ => {
+                let mut message = Vec::with_capacity(1 + payload.len());
+                message.push(PacketType::Data.to_byte());
+                message.extend_from_slice(payload);
+                datagrams.push((message, address));
+                return;
+            }
+        };
+
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let chunk_size = mtu.saturating_sub(1 + FRAGMENT_HEADER_SIZE);
+        let fragments = match fragmentation::split_into_fragments(payload, id, chunk_size) {
+            Some(fragments) if payload.len() <= config.max_packet_size => fragments,
+            _ => {
+                warn!(
+                    "Dropped a packet of {} bytes to {}, which is too large to fragment",
+                    payload.len(),
+                    address
+                );
+                return;
+            }
+        };
+        for (header, data) in fragments {
+            let mut message = Vec::with_capacity(1 + FRAGMENT_HEADER_SIZE + data.len());
+            message.push(PacketType::Fragment.to_byte());
+            message.extend_from_slice(&header);
+            message.extend_from_slice(data);
+            datagrams.push((message, address));
+        }
+    }
+}
@@ -1 +1,11 @@
+mod batch;
+mod coalescer;
+mod cookie;
+mod fragmenter;
+mod mtu_probe;
+mod multicast;
+mod pacer;
+pub mod send_half;
 pub mod server_socket;
+mod shared_socket;
+mod waiting_room;
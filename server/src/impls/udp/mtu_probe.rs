@@ -0,0 +1,131 @@
+use std::{
+    io::Error as IoError,
+    net::UdpSocket,
+    time::{Duration, Instant},
+};
+
+use naia_socket_shared::clock;
+
+// Sizes of UDP payload to probe, in increasing order. 1472 & 1452 are the most
+// an IPv4 & IPv6 datagram can carry over a 1500 byte Ethernet MTU
+const PROBE_SIZES: [usize; 5] = [1280, 1350, 1400, 1452, 1472];
+// How long to wait for a probe to be acknowledged before trying again
+const PROBE_TIMEOUT: Duration = Duration::from_secs(1);
+// How many times a size is probed before concluding it doesn't get through
+const PROBE_ATTEMPTS: u32 = 3;
+
+/// Discovers the largest datagram which reaches a client without IP
+/// fragmentation, by sending it probes of increasing size
+#[derive(Debug)]
+pub struct MtuProbe {
+    mtu: usize,
+    next_size: Option<usize>,
+    attempts: u32,
+    last_sent: Option<Instant>,
+}
+
+impl MtuProbe {
+    /// Create a new MtuProbe, starting from the given MTU which is assumed to
+    /// get through. If not enabled, no probes are sent
+    pub fn new(mtu: usize, enabled: bool) -> Self {
+        let mut probe = MtuProbe {
+            mtu,
+            next_size: None,
+            attempts: 0,
+            last_sent: None,
+        };
+        if enabled {
+            probe.next_size = probe.size_above(mtu);
+        }
+        probe
+    }
+
+    /// Gets the largest datagram known to reach the client, in bytes of UDP
+    /// payload
+    pub fn mtu(&self) -> usize {
+        self.mtu
+    }
+
+    /// Gets the size of the next probe to send, if one is due
+    pub fn poll(&mut self) -> Option<usize> {
+        let size = self.next_size?;
+        if let Some(last_sent) = self.last_sent {
+            if clock::elapsed(last_sent) < PROBE_TIMEOUT {
+                return None;
+            }
+            if self.attempts >= PROBE_ATTEMPTS {
+                // this size doesn't get through, so neither will larger ones
+                self.next_size = None;
+                return None;
+            }
+        }
+        self.attempts += 1;
+        self.last_sent = Some(clock::now());
+        Some(size)
+    }
+
+    /// Notes that a probe of the given size reached the client
+    pub fn acked(&mut self, size: usize) {
+        if size <= self.mtu {
+            return;
+        }
+        self.mtu = size;
+        self.next_size = self.size_above(size);
+        self.attempts = 0;
+        self.last_sent = None;
+    }
+
+    fn size_above(&self, size: usize) -> Option<usize> {
+        PROBE_SIZES
+            .iter()
+            .copied()
+            .find(|probe_size| *probe_size > size)
+    }
+}
+
+/// Stops the OS from fragmenting datagrams sent from the socket, so that a
+/// probe only arrives if the path can carry it whole. Only possible on Linux,
+/// returns whether it was done
+pub fn disable_fragmentation(socket: &UdpSocket) -> Result<bool, IoError> {
+    cfg_if! {
+        if #[cfg(target_os = "linux")] {
+            use std::{net::SocketAddr, os::unix::io::AsRawFd, ptr};
+
+            let set_option = |level, option, value: libc::c_int| {
+                let result = unsafe {
+                    libc::setsockopt(
+                        socket.as_raw_fd(),
+                        level,
+                        option,
+                        ptr::addr_of!(value).cast(),
+                        size_of::<libc::c_int>() as libc::socklen_t,
+                    )
+                };
+                if result != 0 {
+                    return Err(IoError::last_os_error());
+                }
+                Ok(true)
+            };
+
+            match socket.local_addr()? {
+                SocketAddr::V4(_) => {
+                    set_option(libc::IPPROTO_IP, libc::IP_MTU_DISCOVER, libc::IP_PMTUDISC_PROBE)
+                }
+                SocketAddr::V6(_) => {
+                    // IPv4 datagrams sent from a dual-stack socket follow the
+                    // IPv4 option
+                    let _ =
+                        set_option(libc::IPPROTO_IP, libc::IP_MTU_DISCOVER, libc::IP_PMTUDISC_PROBE);
+                    set_option(
+                        libc::IPPROTO_IPV6,
+                        libc::IPV6_MTU_DISCOVER,
+                        libc::IPV6_PMTUDISC_PROBE,
+                    )
+                }
+            }
+        } else {
+            let _ = socket;
+            Ok(false)
+        }
+    }
+}
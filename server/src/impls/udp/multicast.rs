@@ -0,0 +1,53 @@
+use std::{
+    io::Error as IoError,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket},
+};
+
+use async_io::Async;
+use socket2::{Domain, Protocol, SockAddr, SockRef, Socket, Type};
+
+use crate::MulticastConfig;
+
+/// Binds a socket to the group's port & joins the group, for the datagrams
+/// sent to it. Other sockets on this host may have joined it too
+pub fn bind(config: &MulticastConfig) -> Result<Async<UdpSocket>, IoError> {
+    let socket = Socket::new(
+        Domain::for_address(config.group),
+        Type::DGRAM,
+        Some(Protocol::UDP),
+    )?;
+    socket.set_reuse_address(true)?;
+    // binding to the group's address keeps out the datagrams sent to the
+    // port some other way, which only works on unix
+    let address = if cfg!(unix) {
+        config.group
+    } else {
+        match config.group {
+            SocketAddr::V4(_) => SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), config.group.port()),
+            SocketAddr::V6(_) => SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), config.group.port()),
+        }
+    };
+    socket.bind(&SockAddr::from(address))?;
+    match config.group.ip() {
+        IpAddr::V4(group) => socket.join_multicast_v4(&group, &config.interface)?,
+        IpAddr::V6(group) => socket.join_multicast_v6(&group, 0)?,
+    }
+    Async::new(socket.into())
+}
+
+/// Sets how the datagrams the socket sends to the group go out
+pub fn set_send_options(socket: &UdpSocket, config: &MulticastConfig) -> Result<(), IoError> {
+    let socket_ref = SockRef::from(socket);
+    match config.group {
+        SocketAddr::V4(_) => {
+            socket_ref.set_multicast_if_v4(&config.interface)?;
+            socket_ref.set_multicast_ttl_v4(config.ttl)?;
+            socket_ref.set_multicast_loop_v4(config.loopback)?;
+        }
+        SocketAddr::V6(_) => {
+            socket_ref.set_multicast_hops_v6(config.ttl)?;
+            socket_ref.set_multicast_loop_v6(config.loopback)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,99 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use naia_socket_shared::clock;
+
+use crate::PacingConfig;
+
+/// A token bucket which holds back the datagrams sent to a single connection
+/// until they fit within its bandwidth budget
+#[derive(Debug)]
+pub struct Pacer {
+    bytes_per_second: f64,
+    max_bytes_per_second: f64,
+    burst: f64,
+    max_delay: f64,
+    // the most bytes which can wait, so that none waits longer than allowed
+    max_queued_bytes: usize,
+    tokens: f64,
+    last_refill: Instant,
+    queue: VecDeque<Vec<u8>>,
+    queued_bytes: usize,
+}
+
+impl Pacer {
+    /// Create a new Pacer, with a full bucket
+    pub fn new(config: &PacingConfig) -> Self {
+        let bytes_per_second = config.bytes_per_second.max(1) as f64;
+        Pacer {
+            bytes_per_second,
+            max_bytes_per_second: bytes_per_second,
+            burst: config.burst as f64,
+            max_delay: config.max_delay.as_secs_f64(),
+            max_queued_bytes: (bytes_per_second * config.max_delay.as_secs_f64()) as usize,
+            tokens: config.burst as f64,
+            last_refill: clock::now(),
+            queue: VecDeque::new(),
+            queued_bytes: 0,
+        }
+    }
+
+    /// Changes the rate datagrams are let through at, which is kept within the
+    /// rate the Pacer was configured with
+    pub fn set_rate(&mut self, bytes_per_second: usize) {
+        self.refill();
+        self.bytes_per_second = (bytes_per_second.max(1) as f64).min(self.max_bytes_per_second);
+        self.max_queued_bytes = (self.bytes_per_second * self.max_delay) as usize;
+    }
+
+    /// Queues a datagram to be sent once it fits within the budget. Returns
+    /// false if it was dropped instead, because it would have waited too long
+    pub fn push(&mut self, datagram: Vec<u8>) -> bool {
+        // a datagram which can go right away never waits
+        if !self.queue.is_empty() && self.queued_bytes + datagram.len() > self.max_queued_bytes {
+            return false;
+        }
+        self.queued_bytes += datagram.len();
+        self.queue.push_back(datagram);
+        true
+    }
+
+    /// Takes the next datagram, if it now fits within the budget
+    pub fn pop_ready(&mut self) -> Option<Vec<u8>> {
+        self.refill();
+        let len = self.queue.front()?.len() as f64;
+        // datagrams larger than the burst go out once the bucket is full, &
+        // leave it in debt
+        if self.tokens < len.min(self.burst) {
+            return None;
+        }
+        self.tokens -= len;
+        let datagram = self.queue.pop_front()?;
+        self.queued_bytes -= datagram.len();
+        Some(datagram)
+    }
+
+    /// Gets the number of datagrams waiting
+    pub fn queued(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Gets how long until the next datagram fits within the budget, if any
+    /// are waiting
+    pub fn next_ready_in(&self) -> Option<Duration> {
+        let len = self.queue.front()?.len() as f64;
+        let elapsed = clock::elapsed(self.last_refill).as_secs_f64();
+        let tokens = (self.tokens + elapsed * self.bytes_per_second).min(self.burst);
+        let missing = (len.min(self.burst) - tokens).max(0.0);
+        Some(Duration::from_secs_f64(missing / self.bytes_per_second))
+    }
+
+    fn refill(&mut self) {
+        let now = clock::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.bytes_per_second).min(self.burst);
+        self.last_refill = now;
+    }
+}
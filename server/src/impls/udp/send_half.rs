@@ -0,0 +1,92 @@
+use std::net::SocketAddr;
+
+use crate::{error::NaiaServerSocketError, MessageSender, Packet};
+
+use super::{fragmenter::Fragmenter, shared_socket::SharedSocket};
+
+/// The sending half of a split Server Socket, which sends packets straight to
+/// the network without going through the receive half's loop
+#[derive(Debug)]
+pub struct SendHalf {
+    // `None` when there's no socket to send through, & everything is queued
+    socket: Option<SharedSocket>,
+    fragmenter: Fragmenter,
+    mtu: usize,
+    // reliable packets, & all packets when they are acknowledged, compressed,
+    // protected or paced, are handed to the receive half, which keeps their
+    // state
+    reliable_sender: MessageSender,
+    queue_all: bool,
+    multicast_group: Option<SocketAddr>,
+}
+
+impl SendHalf {
+    pub(crate) fn new(
+        socket: SharedSocket,
+        fragmenter: Fragmenter,
+        mtu: usize,
+        reliable_sender: MessageSender,
+        queue_all: bool,
+        multicast_group: Option<SocketAddr>,
+    ) -> Self {
+        SendHalf {
+            socket: Some(socket),
+            fragmenter,
+            mtu,
+            reliable_sender,
+            queue_all,
+            multicast_group,
+        }
+    }
+
+    // Create a new SendHalf which queues every packet on the given sender,
+    // for sockets which aren't backed by a UdpSocket
+    pub(crate) fn queued(sender: MessageSender) -> Self {
+        SendHalf {
+            socket: None,
+            fragmenter: Fragmenter::new(None),
+            mtu: 0,
+            reliable_sender: sender,
+            queue_all: true,
+            multicast_group: None,
+        }
+    }
+
+    /// Send a Packet to a client. Packets which need fragmenting are split to
+    /// fit `SocketConfig::mtu`, as the MTU discovered for each connection is
+    /// only known to the receive half. Packets on a reliable channel, & all
+    /// packets when acknowledgements, compression, encryption, authentication,
+    /// pacing or congestion control are enabled, are queued for the receive
+    /// half to send, as it keeps the state they need. Other packets are sent
+    /// right away, so their priority has no effect
+    pub async fn send(&mut self, packet: Packet) -> Result<(), NaiaServerSocketError> {
+        self.reliable_sender.check_payload_size(&packet)?;
+        // packets to the multicast group go out as they are
+        if let Some(socket) = &self.socket {
+            if Some(packet.address()) == self.multicast_group {
+                let address = packet.address();
+                return match socket.send_to(packet.payload(), address).await {
+                    Ok(_) => Ok(()),
+                    Err(_) => Err(NaiaServerSocketError::SendError(address)),
+                };
+            }
+        }
+        let socket = match &self.socket {
+            Some(socket) if packet.channel().is_none() && !self.queue_all => socket,
+            _ => {
+                return self.reliable_sender.send(packet).await;
+            }
+        };
+
+        let mut messages = Vec::new();
+        self.fragmenter
+            .push_datagrams(&packet, self.mtu, &mut messages);
+
+        for (message, address) in messages {
+            if socket.send_to(&message, address).await.is_err() {
+                return Err(NaiaServerSocketError::SendError(address));
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,112 +1,2302 @@
-use async_io::Async;
+use async_io::{Async, Timer};
 use async_trait::async_trait;
+use bytes::Bytes;
 use futures_channel::mpsc;
-use futures_util::{pin_mut, select, FutureExt, StreamExt};
+use futures_util::{future, pin_mut, select, FutureExt, StreamExt};
+use log::{info, warn};
+use socket2::SockRef;
 use std::{
-    io::Error as IoError,
+    any::Any,
+    collections::{HashMap, VecDeque},
+    convert::TryInto,
+    io::{Error as IoError, ErrorKind},
     net::{SocketAddr, UdpSocket},
+    time::{Duration, Instant},
 };
 
-use naia_socket_shared::LinkConditionerConfig;
+use naia_socket_shared::{
+    acknowledgement::{AckTracker, ACK_HEADER_SIZE},
+    bind_udp, clock, coalescing, compression,
+    encryption::{self, KeyExchange, Protection, SessionKeys},
+    fragmentation::Reassembler,
+    handshake,
+    ping::{self, RttEstimator, PING_SIZE, PONG_SIZE},
+    reliability::{self, ReliableChannels, RELIABLE_HEADER_SIZE},
+    sequence::{ReceivedWindow, SequenceCheck},
+    set_traffic_class, ChannelMode, ConditionerHandle, ConditionerStats, ConnectToken, Delivery,
+    HandshakeError, LinkConditionerConfig, PacketDirection, PacketType, Priority, Random,
+};
+
+use crate::{
+    error::{self, NaiaServerSocketError},
+    DuplicateConnectionPolicy, PacingConfig, Packet, QueueDirection, RecvHalf, ServerSocketEvent,
+    ServerSocketTrait, SocketBufferSizes, SocketConfig, Transport,
+};
 
-use crate::{error::NaiaServerSocketError, Packet, ServerSocketTrait};
+use crate::{
+    buffer_pool::{BufferPool, BufferPoolStats},
+    connection_id::ConnectionId,
+    connection_manager::{ConnectionManager, UserData},
+    connection_quality::QualityTracker,
+    connection_stats::ConnectionStats,
+    link_conditioner::LinkConditioner,
+    message_sender::{MessageSender, SenderSource},
+    middleware::SocketMiddleware,
+    middleware_socket::MiddlewareSocket,
+    packet_tap::PacketTap,
+    port_mapping::{MappedProtocol, PortMapping},
+    send_queue::SendQueue,
+    socket_metrics::SocketMetrics,
+};
 
-use crate::{link_conditioner::LinkConditioner, message_sender::MessageSender};
+use super::{
+    batch::{self, ReceiveBatch},
+    coalescer::Coalescer,
+    cookie::CookieJar,
+    fragmenter::Fragmenter,
+    mtu_probe::{self, MtuProbe},
+    multicast,
+    pacer::Pacer,
+    send_half::SendHalf,
+    shared_socket::SharedSocket,
+    waiting_room::WaitingRoom,
+};
 
-const CLIENT_CHANNEL_SIZE: usize = 8;
+// Client Data packets are preceded by the packet type, the connection token &
+// the packet's sequence number
+const CLIENT_DATA_HEADER_SIZE: usize = 17;
+// Packets which close or move a connection are sent several times, as they may
+// be the last the client hears from us
+const CONTROL_REDUNDANCY: usize = 3;
+// Server Connect Responses contain the packet type, the connection token, the
+// optional features agreed to, the public key if encrypting, & the resumption
+// token, sealed if encrypting
+const CONNECT_RESPONSE_SIZE: usize =
+    10 + encryption::PUBLIC_KEY_SIZE + handshake::RESUMPTION_TOKEN_SIZE + encryption::TAG_SIZE;
+// Sealed packets sent to a client gain a sequence number & a tag
+const SEALED_OVERHEAD: usize = 8 + encryption::TAG_SIZE;
+// The most queued packets sent together in one batch
+const SEND_BATCH_SIZE: usize = 64;
+// Large enough for any UDP datagram
+const RECEIVE_BUFFER_SIZE: usize = 0x10000;
+// A refused client is reported at most once this often, as it may keep asking,
+// & if it uses another protocol version only answered that often too
+const REFUSAL_INTERVAL: Duration = Duration::from_secs(1);
+// The most addresses remembered as recently refused, beyond which others
+// aren't reported until some are forgotten
+const MAX_REFUSALS: usize = 1024;
 
 /// A socket server which communicates with clients using an underlying
 /// unordered & unreliable network protocol
 #[derive(Debug)]
 pub struct ServerSocket {
-    socket: Async<UdpSocket>,
-    to_client_sender: mpsc::Sender<Packet>,
+    socket: SharedSocket,
+    to_client_sender: SenderSource,
     to_client_receiver: mpsc::Receiver<Packet>,
-    receive_buffer: Vec<u8>,
+    // whether the queue packets are sent from has closed, as every
+    // MessageSender has been dropped, which shuts the socket down
+    closed: bool,
+    send_queue: SendQueue,
+    // packets the send queue has shed which haven't been reported yet
+    overflowed: usize,
+    receive_batch: ReceiveBatch,
+    multicast_socket: Option<Async<UdpSocket>>,
+    multicast_buffer: Vec<u8>,
+    buffer_pool: BufferPool,
+    fragmenter: Fragmenter,
+    coalescer: Option<Coalescer>,
+    connection_manager: ConnectionManager,
+    udp_connections: HashMap<ConnectionId, UdpConnection>,
+    connection_tokens: HashMap<u64, ConnectionId>,
+    resumption_tokens: HashMap<u128, ConnectionId>,
+    outstanding_events: VecDeque<ServerSocketEvent>,
+    cookie_jar: CookieJar,
+    waiting_room: WaitingRoom,
+    // when each refused client was last reported
+    refusals: HashMap<SocketAddr, Instant>,
+    accepting: bool,
+    packet_tap: PacketTap,
+    port_mapping: Option<PortMapping>,
+    config: SocketConfig,
 }
 
 impl ServerSocket {
-    /// Returns a new ServerSocket, listening at the given socket address
-    pub async fn listen(socket_address: SocketAddr) -> Box<dyn ServerSocketTrait> {
-        let socket = Async::new(UdpSocket::bind(&socket_address).unwrap()).unwrap();
+    /// Returns a new ServerSocket, listening at the given socket address. If
+    /// its port is 0, the OS assigns one, see `local_addr`
+    pub async fn listen(
+        socket_address: SocketAddr,
+        config: SocketConfig,
+    ) -> Result<Box<dyn ServerSocketTrait>, NaiaServerSocketError> {
+        let socket = ServerSocket::bind(socket_address, config).await?;
+        Ok(Box::new(socket))
+    }
+
+    /// Returns a new ServerSocket, listening at the given socket address,
+    /// without boxing it, so that calls to it are statically dispatched
+    pub async fn bind(
+        socket_address: SocketAddr,
+        config: SocketConfig,
+    ) -> Result<ServerSocket, NaiaServerSocketError> {
+        if config.health_check {
+            warn!("the UDP transport has no session server to answer health checks, ignoring it");
+        }
 
-        let (to_client_sender, to_client_receiver) = mpsc::channel(CLIENT_CHANNEL_SIZE);
+        let udp_socket = bind_socket(socket_address, &config)
+            .map_err(|err| NaiaServerSocketError::from_bind_error(err, socket_address))?;
+        let local_address = udp_socket.get_ref().local_addr().unwrap_or(socket_address);
+        let socket = SharedSocket::new(udp_socket);
+        let multicast_socket = match &config.multicast {
+            Some(multicast) => Some(
+                multicast::bind(multicast)
+                    .map_err(|err| NaiaServerSocketError::from_bind_error(err, multicast.group))?,
+            ),
+            None => None,
+        };
 
-        Box::new(ServerSocket {
+        let port_mapping = match &config.port_mapping {
+            Some(port_mapping) => {
+                PortMapping::request(
+                    port_mapping.clone(),
+                    vec![(MappedProtocol::Udp, local_address)],
+                )
+                .await
+            }
+            None => None,
+        };
+
+        let (to_client_sender, to_client_receiver) = mpsc::channel(config.send_queue_size);
+
+        Ok(ServerSocket {
             socket,
-            to_client_sender,
+            to_client_sender: SenderSource::new(to_client_sender),
             to_client_receiver,
-            receive_buffer: vec![0; 0x10000], /* Hopefully get rid of this one day.. next version
-                                               * of webrtc-unreliable should make that happen */
+            closed: false,
+            send_queue: SendQueue::new(config.send_queue_size, config.send_queue_policy),
+            overflowed: 0,
+            receive_batch: ReceiveBatch::new(config.receive_batch_size, RECEIVE_BUFFER_SIZE),
+            multicast_buffer: match multicast_socket {
+                Some(_) => vec![0; RECEIVE_BUFFER_SIZE],
+                None => Vec::new(),
+            },
+            multicast_socket,
+            buffer_pool: BufferPool::new(config.buffer_pool),
+            fragmenter: Fragmenter::new(config.fragmentation.clone()),
+            coalescer: config.coalesce_interval.map(Coalescer::new),
+            connection_manager: ConnectionManager::new(),
+            udp_connections: HashMap::new(),
+            connection_tokens: HashMap::new(),
+            resumption_tokens: HashMap::new(),
+            outstanding_events: VecDeque::new(),
+            cookie_jar: CookieJar::new(),
+            waiting_room: WaitingRoom::new(),
+            refusals: HashMap::new(),
+            accepting: true,
+            packet_tap: PacketTap::new(&config, local_address),
+            port_mapping,
+            config,
         })
     }
+
+    async fn process_datagram(
+        &mut self,
+        message: Bytes,
+        address: SocketAddr,
+        congestion_experienced: bool,
+        received_at: Instant,
+    ) -> Result<(), NaiaServerSocketError> {
+        let message_len = message.len();
+
+        match message.first().copied().and_then(PacketType::from_byte) {
+            Some(PacketType::ClientConnectRequest) => {
+                // stray traffic without the protocol magic is discarded
+                let client_version = match handshake::read_header(&message[1..]) {
+                    Some(client_version) => client_version,
+                    None => {
+                        self.discard(address, message_len);
+                        return Ok(());
+                    }
+                };
+
+                if client_version != handshake::PROTOCOL_VERSION {
+                    trace_event!(
+                        DEBUG,
+                        %address,
+                        client_version,
+                        "refused handshake: protocol version mismatch"
+                    );
+                    self.connection_manager
+                        .metrics_mut()
+                        .errors
+                        .refused_handshakes += 1;
+                    if !self.refusal_due(address) {
+                        return Ok(());
+                    }
+                    let mut response = Vec::with_capacity(1 + handshake::HANDSHAKE_HEADER_SIZE);
+                    response.push(PacketType::ServerVersionMismatch.to_byte());
+                    handshake::write_header(&mut response);
+                    self.send_to_unvalidated(&response, message_len, address)
+                        .await?;
+                    self.outstanding_events
+                        .push_back(ServerSocketEvent::VersionMismatch {
+                            address,
+                            client_version,
+                        });
+                    return Ok(());
+                }
+
+                // requests which aren't padded would let us send more than we received
+                if message_len < handshake::CONNECT_REQUEST_SIZE {
+                    return Ok(());
+                }
+
+                match self.connection_manager.connection_id(&address) {
+                    // the client didn't get our response, so send it again
+                    Some(connection_id) => {
+                        self.send_connect_response(&connection_id, address).await?;
+                    }
+                    // make the client prove it owns its address before keeping any state
+                    None => {
+                        let mut challenge =
+                            Vec::with_capacity(1 + handshake::CHALLENGE_COOKIE_SIZE);
+                        challenge.push(PacketType::ServerChallenge.to_byte());
+                        challenge.extend_from_slice(&self.cookie_jar.bake(&address));
+                        trace_event!(DEBUG, %address, "challenge sent");
+                        self.send_to_unvalidated(&challenge, message_len, address)
+                            .await?;
+                    }
+                }
+            }
+            Some(PacketType::ClientChallengeResponse) => {
+                if handshake::read_header(&message[1..]) != Some(handshake::PROTOCOL_VERSION) {
+                    self.discard(address, message_len);
+                    return Ok(());
+                }
+
+                if let Some(connection_id) = self.connection_manager.connection_id(&address) {
+                    // the client didn't get our response, so send it again
+                    return self.send_connect_response(&connection_id, address).await;
+                }
+
+                // the optional features the client wants come before the cookie
+                let features = match message.get(1 + handshake::HANDSHAKE_HEADER_SIZE) {
+                    Some(features) => *features,
+                    None => return Ok(()),
+                };
+                let cookie_start = 2 + handshake::HANDSHAKE_HEADER_SIZE;
+                let cookie_end = cookie_start + handshake::CHALLENGE_COOKIE_SIZE;
+                match message.get(cookie_start..cookie_end) {
+                    Some(cookie) if self.cookie_jar.check(cookie, &address) => {}
+                    _ => {
+                        self.discard(address, message_len);
+                        return Ok(());
+                    }
+                }
+
+                let client_protection = Protection::from_features(features);
+                let public_key_size = client_protection.map(|_| encryption::PUBLIC_KEY_SIZE);
+                let body = match handshake::read_challenge_response_body(
+                    &message[cookie_end..],
+                    public_key_size,
+                ) {
+                    Some(body) => body,
+                    None => return Ok(()),
+                };
+                let token_bytes = body.connect_token;
+                let connect_payload = body.connect_payload.to_vec();
+                let client_public_key: Option<[u8; encryption::PUBLIC_KEY_SIZE]> = body
+                    .public_key
+                    .map(|public_key| public_key.try_into().unwrap());
+                let resumption_token = body.resumption_token;
+
+                let connect_token = match &self.config.connect_token_key {
+                    Some(key) => match ConnectToken::decode(token_bytes, key) {
+                        Ok(connect_token) => Some(connect_token),
+                        Err(err) => {
+                            info!("Refused connection from {}: {}", address, err);
+                            trace_event!(DEBUG, %address, error = %err, "refused handshake: bad connect token");
+                            self.connection_manager
+                                .metrics_mut()
+                                .errors
+                                .refused_handshakes += 1;
+                            return Ok(());
+                        }
+                    },
+                    None => None,
+                };
+
+                // a client which wants its packets protected won't connect
+                // without it, so both sides must agree on how
+                let protection = self.config.protection();
+                if client_protection != protection {
+                    info!(
+                        "Refused connection from {}: it asked for {:?} packet protection, not {:?}",
+                        address, client_protection, protection
+                    );
+                    trace_event!(
+                        DEBUG,
+                        %address,
+                        "refused handshake: packet protection mismatch"
+                    );
+                    self.connection_manager
+                        .metrics_mut()
+                        .errors
+                        .refused_handshakes += 1;
+                    return Ok(());
+                }
+                // a client which pinned our identity can't be answered without it
+                let pinned = features & handshake::FEATURE_PINNED_KEY != 0 && protection.is_some();
+                if pinned && self.config.identity.is_none() {
+                    info!(
+                        "Refused connection from {}: it pinned a Server identity, but none is configured",
+                        address
+                    );
+                    trace_event!(
+                        DEBUG,
+                        %address,
+                        "refused handshake: no identity to pin"
+                    );
+                    self.connection_manager
+                        .metrics_mut()
+                        .errors
+                        .refused_handshakes += 1;
+                    return Ok(());
+                }
+                let identity = self.config.identity.as_ref().filter(|_| pinned);
+                let sealing = match (client_public_key, protection) {
+                    (Some(client_public_key), Some(protection)) => {
+                        let key_exchange = KeyExchange::new();
+                        match key_exchange.server_keys(&client_public_key, identity, protection) {
+                            Some(keys) => Some(Sealing::new(keys, *key_exchange.public_key())),
+                            None => return Ok(()),
+                        }
+                    }
+                    _ => None,
+                };
+
+                let resumed_connection = self.resumable_connection(resumption_token);
+                if resumed_connection.is_none() {
+                    if !self.accepting {
+                        trace_event!(DEBUG, %address, "refused handshake: not accepting");
+                        self.connection_manager
+                            .metrics_mut()
+                            .errors
+                            .refused_handshakes += 1;
+                        self.push_refusal(address, HandshakeError::NotAccepting);
+                        let response = [PacketType::ServerNotAccepting.to_byte()];
+                        return self.send_handshake_packet(&response, address).await;
+                    }
+
+                    let existing_connection = connect_token.as_ref().and_then(|connect_token| {
+                        self.connection_manager
+                            .connection_with_client_id(connect_token.client_id)
+                    });
+                    if let Some(existing_connection) = existing_connection {
+                        match self.config.duplicate_connection_policy {
+                            DuplicateConnectionPolicy::Allow => {}
+                            DuplicateConnectionPolicy::Reject => {
+                                info!("Refused duplicate connection from {}", address);
+                                trace_event!(
+                                    DEBUG,
+                                    %address,
+                                    "refused handshake: duplicate connection"
+                                );
+                                self.connection_manager
+                                    .metrics_mut()
+                                    .errors
+                                    .refused_handshakes += 1;
+                                self.push_refusal(address, HandshakeError::DuplicateConnection);
+                                let response = [PacketType::ServerDuplicateConnection.to_byte()];
+                                return self.send_handshake_packet(&response, address).await;
+                            }
+                            DuplicateConnectionPolicy::Replace => {
+                                ServerSocketTrait::disconnect(self, &existing_connection, None)
+                                    .await?;
+                            }
+                        }
+                    }
+
+                    match self.admission(address) {
+                        Admission::Admitted => {}
+                        Admission::Queued(position) => {
+                            let mut response = Vec::with_capacity(5);
+                            response.push(PacketType::ServerQueuePosition.to_byte());
+                            response.extend_from_slice(&(position as u32).to_be_bytes());
+                            return self.send_handshake_packet(&response, address).await;
+                        }
+                        Admission::Full => {
+                            self.push_refusal(address, HandshakeError::ServerFull);
+                            let response = [PacketType::ServerFull.to_byte()];
+                            return self.send_handshake_packet(&response, address).await;
+                        }
+                    }
+                }
+
+                let connection_id = match resumed_connection {
+                    Some(connection_id) => {
+                        // the client is picking up where it left off, under a new token
+                        if let Some(old) = self.udp_connections.remove(&connection_id) {
+                            self.connection_tokens.remove(&old.token);
+                            self.resumption_tokens.remove(&old.resumption_token);
+                        }
+                        self.connection_manager
+                            .change_address(&connection_id, &address);
+                        self.outstanding_events
+                            .push_back(ServerSocketEvent::Reconnection(connection_id, address));
+                        connection_id
+                    }
+                    None => {
+                        let connection_id = self.connection_manager.add_connection(&address);
+                        self.outstanding_events
+                            .push_back(ServerSocketEvent::Connection(connection_id, address));
+                        connection_id
+                    }
+                };
+                if let Some(connect_token) = connect_token {
+                    self.connection_manager
+                        .set_connect_token(&connection_id, connect_token);
+                }
+                self.connection_manager
+                    .set_connect_payload(&connection_id, connect_payload);
+
+                let pacer = self.new_pacer(self.config.acknowledgement.is_some());
+                let compression = features & handshake::FEATURE_COMPRESSION != 0
+                    && self.config.compression.is_some();
+                let udp_connection = UdpConnection::new(
+                    self.new_token(),
+                    self.new_resumption_token(),
+                    compression,
+                    sealing,
+                    pacer,
+                    &self.config,
+                );
+                self.connection_tokens
+                    .insert(udp_connection.token, connection_id);
+                self.resumption_tokens
+                    .insert(udp_connection.resumption_token, connection_id);
+                self.udp_connections.insert(connection_id, udp_connection);
+
+                self.send_connect_response(&connection_id, address).await?;
+            }
+            Some(
+                packet_type @ (PacketType::Data
+                | PacketType::Fragment
+                | PacketType::Coalesced
+                | PacketType::Reliable
+                | PacketType::ReliableAck
+                | PacketType::Ack
+                | PacketType::ChannelData
+                | PacketType::Ping
+                | PacketType::Pong
+                | PacketType::ClientDisconnect
+                | PacketType::ClientMtuProbeAck),
+            ) => {
+                if message.len() < CLIENT_DATA_HEADER_SIZE {
+                    self.discard(address, message_len);
+                    return Ok(());
+                }
+                let token = u64::from_be_bytes(message[1..9].try_into().unwrap());
+                let sequence =
+                    u64::from_be_bytes(message[9..CLIENT_DATA_HEADER_SIZE].try_into().unwrap());
+
+                // packets without a valid token are discarded, so only clients
+                // which completed a handshake are heard from, even once their
+                // address has changed
+                let connection_id = match self.connection_tokens.get(&token) {
+                    Some(connection_id) => *connection_id,
+                    None => {
+                        self.discard(address, message_len);
+                        return Ok(());
+                    }
+                };
+
+                let udp_connection = match self.udp_connections.get_mut(&connection_id) {
+                    Some(udp_connection) => udp_connection,
+                    None => return Ok(()),
+                };
+                let datagram_size = message.len();
+                // so are packets which weren't sealed with the connection's keys,
+                // before they can take up a place in the replay window
+                let message = match &udp_connection.sealing {
+                    Some(sealing) => {
+                        let header = &message[..CLIENT_DATA_HEADER_SIZE];
+                        let sealed = &message[CLIENT_DATA_HEADER_SIZE..];
+                        match sealing.keys.open(sequence, header, sealed) {
+                            Some(payload) => Bytes::from([header, &payload].concat()),
+                            None => {
+                                if let Some(stats) =
+                                    self.connection_manager.stats_mut(&connection_id)
+                                {
+                                    stats.forged_packets += 1;
+                                }
+                                trace_event!(WARN, %address, sequence, "forged packet");
+                                self.connection_manager.metrics_mut().errors.forged += 1;
+                                return Ok(());
+                            }
+                        }
+                    }
+                    None => message,
+                };
+                // checked before anything else is done with the packet, so that a
+                // replayed packet can't move the connection to the sender's address
+                if self.config.replay_protection {
+                    let check = udp_connection.replay_window.check(sequence);
+                    if let Some(stats) = self.connection_manager.stats_mut(&connection_id) {
+                        match check {
+                            SequenceCheck::New => {}
+                            SequenceCheck::OutOfOrder => stats.out_of_order_packets += 1,
+                            SequenceCheck::Duplicate => stats.replayed_packets += 1,
+                            SequenceCheck::Stale => stats.stale_packets += 1,
+                        }
+                    }
+                    if !check.is_accepted() {
+                        return Ok(());
+                    }
+                }
+                if packet_type == PacketType::ClientDisconnect {
+                    // the copies which follow it find no connection left
+                    self.close_connection(&connection_id, "client disconnected");
+                    return Ok(());
+                }
+                if packet_type == PacketType::ClientMtuProbeAck {
+                    match message[CLIENT_DATA_HEADER_SIZE..].try_into() {
+                        Ok(size) => udp_connection
+                            .mtu_probe
+                            .acked(u16::from_be_bytes(size) as usize),
+                        Err(_) => self.discard(address, message_len),
+                    }
+                    return Ok(());
+                }
+                udp_connection.last_received = clock::now();
+                self.connection_manager
+                    .record_received(&connection_id, datagram_size);
+                let mtu_probe = udp_connection.mtu_probe.poll();
+                if congestion_experienced {
+                    if let Some(stats) = self.connection_manager.stats_mut(&connection_id) {
+                        stats.congestion_experienced += 1;
+                    }
+                }
+
+                if self.connection_manager.address(&connection_id) != Some(address) {
+                    // the token is valid, so this is an existing client whose address has
+                    // changed, for example due to NAT rebinding
+                    if self.connection_manager.connection_id(&address).is_some() {
+                        // another connection already owns this address
+                        return Ok(());
+                    }
+                    if let Some(old_address) = self
+                        .connection_manager
+                        .change_address(&connection_id, &address)
+                    {
+                        self.outstanding_events
+                            .push_back(ServerSocketEvent::AddressChanged {
+                                connection_id,
+                                old_address,
+                                new_address: address,
+                            });
+                    }
+                }
+
+                let payload = message.slice(CLIENT_DATA_HEADER_SIZE..);
+                let packets = match packet_type {
+                    PacketType::Fragment => self
+                        .udp_connections
+                        .get_mut(&connection_id)
+                        .and_then(|udp_connection| udp_connection.reassembler.as_mut())
+                        .and_then(|reassembler| reassembler.receive(&payload))
+                        .map(|payload| vec![Packet::new(address, payload)])
+                        .unwrap_or_default(),
+                    PacketType::Coalesced => coalescing::read_subframes(&payload)
+                        .unwrap_or_default()
+                        .into_iter()
+                        .map(|subframe| Packet::from_bytes(address, payload.slice(subframe)))
+                        .collect(),
+                    PacketType::Reliable | PacketType::ReliableAck => {
+                        self.receive_reliable(packet_type, &connection_id, &payload, address)
+                            .await?
+                    }
+                    PacketType::ChannelData => {
+                        let unreliable = payload.first().copied().filter(|channel| {
+                            self.udp_connections
+                                .get(&connection_id)
+                                .and_then(|udp_connection| udp_connection.reliable.as_ref())
+                                .and_then(|reliable| reliable.mode(*channel))
+                                == Some(ChannelMode::Unreliable)
+                        });
+                        match unreliable {
+                            Some(channel) => {
+                                vec![Packet::from_bytes(address, payload.slice(1..))
+                                    .with_channel(channel)]
+                            }
+                            None => Vec::new(),
+                        }
+                    }
+                    PacketType::Ack => {
+                        if let Some(acks) = self
+                            .udp_connections
+                            .get_mut(&connection_id)
+                            .and_then(|udp_connection| udp_connection.acks.as_mut())
+                        {
+                            acks.read_ack(&payload);
+                        }
+                        Vec::new()
+                    }
+                    PacketType::Ping => {
+                        self.send_pong(&connection_id, &payload, address).await?;
+                        Vec::new()
+                    }
+                    PacketType::Pong => {
+                        self.receive_pong(&connection_id, &payload);
+                        Vec::new()
+                    }
+                    _ => vec![Packet::from_bytes(address, payload)],
+                };
+                let packets = self.receive_acked(&connection_id, packets);
+                let packets = self.receive_compressed(&connection_id, packets);
+                self.push_deliveries(&connection_id);
+                for packet in packets {
+                    let size = packet.payload().len();
+                    if self.config.max_payload_size.is_some_and(|max| size > max) {
+                        trace_event!(DEBUG, %address, bytes = size, "oversized packet");
+                        self.connection_manager.metrics_mut().errors.oversized += 1;
+                        self.outstanding_events
+                            .push_back(ServerSocketEvent::OversizedPacket {
+                                connection_id,
+                                size,
+                            });
+                        continue;
+                    }
+                    self.outstanding_events.push_back(ServerSocketEvent::Packet(
+                        packet.with_received(received_at, Transport::Udp),
+                    ));
+                }
+
+                // probes go out while the client is known to be sending to us
+                if let Some(size) = mtu_probe {
+                    self.send_mtu_probe(&connection_id, size, address).await;
+                }
+            }
+            _ => {
+                // not a packet we understand, discard it
+                self.discard(address, message_len);
+            }
+        }
+
+        Ok(())
+    }
+
+    // Counts a datagram which is discarded, as malformed if it came from a
+    // connected client, & otherwise as coming from an unknown source, such
+    // as a scan, which is dropped without a word
+    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+    fn discard(&mut self, address: SocketAddr, bytes: usize) {
+        let known = self.connection_manager.connection_id(&address).is_some();
+        let errors = &mut self.connection_manager.metrics_mut().errors;
+        if known {
+            trace_event!(DEBUG, %address, bytes, "malformed packet");
+            errors.malformed += 1;
+        } else {
+            trace_event!(TRACE, %address, bytes, "datagram from unknown source");
+            errors.unknown_source += 1;
+        }
+    }
+
+    async fn send_connect_response(
+        &self,
+        connection_id: &ConnectionId,
+        address: SocketAddr,
+    ) -> Result<(), NaiaServerSocketError> {
+        let udp_connection = &self.udp_connections[connection_id];
+
+        let mut response = Vec::with_capacity(CONNECT_RESPONSE_SIZE);
+        response.push(PacketType::ServerConnectResponse.to_byte());
+        response.extend_from_slice(&udp_connection.token.to_be_bytes());
+        let mut features = 0;
+        if udp_connection.compression {
+            features |= handshake::FEATURE_COMPRESSION;
+        }
+        if let Some(sealing) = &udp_connection.sealing {
+            features |= sealing.keys.protection().feature();
+        }
+        response.push(features);
+        let resumption_token = udp_connection.resumption_token.to_be_bytes();
+        match &udp_connection.sealing {
+            // the resumption token would let anyone who saw it take over the
+            // connection, so it is sealed under the sequence number no packet
+            // uses, & so encrypted if packets are
+            Some(sealing) => {
+                response.extend_from_slice(&sealing.public_key);
+                let sealed = sealing.keys.seal(0, &response, &resumption_token);
+                response.extend_from_slice(&sealed);
+            }
+            None => response.extend_from_slice(&resumption_token),
+        }
+        self.send_handshake_packet(&response, address).await
+    }
+
+    // Sends a reply to a client which has proven that it owns its address
+    async fn send_handshake_packet(
+        &self,
+        packet: &[u8],
+        address: SocketAddr,
+    ) -> Result<(), NaiaServerSocketError> {
+        if let Err(err) = self.socket.send_to(packet, address).await {
+            // an unreachable client has gone, & its handshake with it
+            if is_unreachable(&err) {
+                return Ok(());
+            }
+            return Err(NaiaServerSocketError::SendError(address));
+        }
+        Ok(())
+    }
+
+    // Adds the datagrams carrying the given packet, sized to fit its
+    // connection's MTU. If coalescing, small packets are held back until
+    // their datagram is flushed
+    fn push_datagrams(&mut self, packet: &Packet, datagrams: &mut Vec<(Vec<u8>, SocketAddr)>) {
+        // packets to the multicast group go out as they are
+        if let Some(multicast) = &self.config.multicast {
+            if packet.address() == multicast.group {
+                datagrams.push((packet.payload().to_vec(), packet.address()));
+                return;
+            }
+        }
+        let compression = self
+            .connection_manager
+            .connection_id(&packet.address())
+            .and_then(|connection_id| self.udp_connections.get(&connection_id))
+            .is_some_and(|udp_connection| udp_connection.compression);
+        let compressed_packet;
+        let packet = match &self.config.compression {
+            Some(config) if compression => {
+                let payload = compression::compress(packet.payload(), config);
+                compressed_packet = packet.clone().with_payload(Bytes::from(payload));
+                &compressed_packet
+            }
+            _ => packet,
+        };
+        let mtu = self
+            .connection_manager
+            .connection_id(&packet.address())
+            .and_then(|connection_id| self.udp_connections.get(&connection_id))
+            .map_or(self.config.mtu, |udp_connection| {
+                udp_connection.mtu_probe.mtu() - udp_connection.sealed_overhead()
+            });
+        let unreliable = match packet.channel() {
+            Some(channel) => {
+                self.connection_manager
+                    .connection_id(&packet.address())
+                    .and_then(|connection_id| self.udp_connections.get(&connection_id))
+                    .and_then(|udp_connection| udp_connection.reliable.as_ref())
+                    .and_then(|reliable| reliable.mode(channel))
+                    == Some(ChannelMode::Unreliable)
+            }
+            None => true,
+        };
+        // packets on reliable channels are never given up on
+        if unreliable && packet.is_expired() {
+            self.count_expired(packet);
+            return;
+        }
+
+        if let Some(channel) = packet.channel() {
+            let reliable = self
+                .connection_manager
+                .connection_id(&packet.address())
+                .and_then(|connection_id| self.udp_connections.get_mut(&connection_id))
+                .and_then(|udp_connection| udp_connection.reliable.as_mut());
+            let queued = match reliable {
+                Some(_) if unreliable => {
+                    let mut datagram = Vec::with_capacity(2 + packet.payload().len());
+                    datagram.push(PacketType::ChannelData.to_byte());
+                    datagram.push(channel);
+                    datagram.extend_from_slice(packet.payload());
+                    datagrams.push((datagram, packet.address()));
+                    true
+                }
+                Some(reliable) => reliable.send(channel, packet.payload().to_vec()),
+                None => false,
+            };
+            if !queued {
+                warn!(
+                    "Dropped a packet to {} on channel {}, which isn't one of its channels",
+                    packet.address(),
+                    channel
+                );
+            }
+            return;
+        }
+
+        // the packet's number & an ack go in front of its payload
+        let acks = self
+            .connection_manager
+            .connection_id(&packet.address())
+            .and_then(|connection_id| self.udp_connections.get_mut(&connection_id))
+            .and_then(|udp_connection| udp_connection.acks.as_mut());
+        let acked_packet;
+        let packet = match acks {
+            Some(acks) => {
+                let header = acks.write_header(packet.ack_id());
+                let mut payload = Vec::with_capacity(ACK_HEADER_SIZE + packet.payload().len());
+                payload.extend_from_slice(&header);
+                payload.extend_from_slice(packet.payload());
+                acked_packet = packet.clone().with_payload(Bytes::from(payload));
+                &acked_packet
+            }
+            None => packet,
+        };
+
+        if let Some(coalescer) = self
+            .coalescer
+            .as_mut()
+            .filter(|_| packet.priority() != Priority::High)
+        {
+            if coalescer.push(packet.payload(), packet.address(), mtu, datagrams) {
+                return;
+            }
+        }
+        self.fragmenter.push_datagrams(packet, mtu, datagrams);
+    }
+
+    // Takes the packets waiting in the MessageSenders' queue into the send
+    // queue, as far as there is room, & adds the datagrams carrying up to
+    // `limit` of the most urgent of them
+    fn push_queued_datagrams(&mut self, limit: usize, datagrams: &mut Vec<(Vec<u8>, SocketAddr)>) {
+        let mut sent = 0;
+        loop {
+            while self.send_queue.accepts_more() {
+                match self.to_client_receiver.try_recv() {
+                    Ok(packet) => self.queue_packet(packet),
+                    Err(_) => break,
+                }
+            }
+            if sent >= limit {
+                break;
+            }
+            match self.send_queue.pop() {
+                Some(packet) => self.push_datagrams(&packet, datagrams),
+                None => break,
+            }
+            sent += 1;
+        }
+    }
+
+    // Adds a packet taken from the MessageSenders to the send queue, copying
+    // broadcasts for every connection
+    fn queue_packet(&mut self, packet: Packet) {
+        if !packet.is_broadcast() {
+            self.tap_outgoing(&packet);
+            self.overflowed += self.send_queue.push(packet);
+            return;
+        }
+        for (_, address) in self.connection_manager.connections() {
+            let packet = packet.to_address(address);
+            self.tap_outgoing(&packet);
+            self.overflowed += self.send_queue.push(packet);
+        }
+    }
+
+    // Dumps & captures a packet about to be handed to the application
+    fn tap_incoming(&self, event: &ServerSocketEvent) {
+        if let ServerSocketEvent::Packet(packet) = event {
+            self.packet_tap.tap(
+                PacketDirection::Incoming,
+                &packet.address(),
+                packet.payload(),
+            );
+        }
+    }
+
+    // Dumps & captures a packet taken from the application to be sent
+    fn tap_outgoing(&self, packet: &Packet) {
+        self.packet_tap.tap(
+            PacketDirection::Outgoing,
+            &packet.address(),
+            packet.payload(),
+        );
+    }
+
+    // Gives the metrics exporter a fresh snapshot, if there is one
+    #[cfg(feature = "metrics")]
+    fn publish_metrics(&self) {
+        if let Some(exporter) = &self.config.metrics_exporter {
+            exporter.publish(self.metrics());
+        }
+    }
+
+    // Reports the metrics to the application's sink, if it is due a report
+    fn report_metrics(&self) {
+        if let Some(reporter) = self.config.metrics_reporter.as_ref().filter(|r| r.due()) {
+            reporter.report(self.metrics(), &self.connection_manager.rtts());
+        }
+    }
+
+    // Reports the clients whose outgoing queues have been backed up for too
+    // long, or have caught up, & the datagrams dropped for them
+    fn push_backpressure_events(&mut self) {
+        let config = match self.config.backpressure {
+            Some(config) => config,
+            None => return,
+        };
+        for (connection_id, udp_connection) in self.udp_connections.iter_mut() {
+            let queued = match &udp_connection.pacer {
+                Some(pacer) => pacer.queued(),
+                None => continue,
+            };
+            if udp_connection.paced_out > 0 {
+                self.outstanding_events
+                    .push_back(ServerSocketEvent::BackpressureDrop {
+                        connection_id: *connection_id,
+                        dropped: udp_connection.paced_out,
+                    });
+                udp_connection.paced_out = 0;
+            }
+
+            if queued <= config.high_water_mark {
+                udp_connection.backed_up_since = None;
+                if udp_connection.slow {
+                    udp_connection.slow = false;
+                    self.outstanding_events
+                        .push_back(ServerSocketEvent::ConsumerCaughtUp {
+                            connection_id: *connection_id,
+                        });
+                }
+                continue;
+            }
+            let backed_up_since = *udp_connection
+                .backed_up_since
+                .get_or_insert_with(clock::now);
+            if !udp_connection.slow && clock::elapsed(backed_up_since) >= config.threshold {
+                udp_connection.slow = true;
+                trace_event!(WARN, %connection_id, queued, "slow consumer");
+                self.outstanding_events
+                    .push_back(ServerSocketEvent::SlowConsumer {
+                        connection_id: *connection_id,
+                        queued,
+                    });
+            }
+        }
+    }
+
+    // Keeps receiving across an error which only concerned a single send or
+    // datagram, surfacing it as an event, & passes fatal ones on
+    fn push_transient(
+        &mut self,
+        result: Result<(), NaiaServerSocketError>,
+    ) -> Result<(), NaiaServerSocketError> {
+        match result {
+            Err(err) if !err.is_fatal() => {
+                self.outstanding_events
+                    .push_back(ServerSocketEvent::Error(err));
+                Ok(())
+            }
+            result => result,
+        }
+    }
+
+    // Reports the packets the send queue has shed since it was last reported
+    fn push_overflow_event(&mut self) {
+        if self.overflowed > 0 {
+            self.connection_manager.metrics_mut().errors.overflowed += self.overflowed as u64;
+            self.outstanding_events
+                .push_back(ServerSocketEvent::QueueOverflow {
+                    direction: QueueDirection::Outgoing,
+                    dropped: self.overflowed,
+                });
+            self.overflowed = 0;
+        }
+    }
+
+    fn count_expired(&mut self, packet: &Packet) {
+        if let Some(connection_id) = self.connection_manager.connection_id(&packet.address()) {
+            if let Some(stats) = self.connection_manager.stats_mut(&connection_id) {
+                stats.expired_packets += 1;
+            }
+        }
+    }
+
+    // Strips the numbers & acks from the front of the unreliable packets
+    // received from a client, dropping those which have been received before
+    fn receive_acked(&mut self, connection_id: &ConnectionId, packets: Vec<Packet>) -> Vec<Packet> {
+        let acks = match self
+            .udp_connections
+            .get_mut(connection_id)
+            .and_then(|udp_connection| udp_connection.acks.as_mut())
+        {
+            Some(acks) => acks,
+            None => return packets,
+        };
+        packets
+            .into_iter()
+            .filter_map(|packet| {
+                if packet.channel().is_some() {
+                    return Some(packet);
+                }
+                if !acks.read_header(packet.payload()) {
+                    return None;
+                }
+                let address = packet.address();
+                let payload = packet.into_payload().slice(ACK_HEADER_SIZE..);
+                Some(Packet::from_bytes(address, payload))
+            })
+            .collect()
+    }
+
+    // Decompresses the packets received from a client, if compression was
+    // agreed on, dropping those which are malformed
+    fn receive_compressed(
+        &self,
+        connection_id: &ConnectionId,
+        packets: Vec<Packet>,
+    ) -> Vec<Packet> {
+        let compression = self
+            .udp_connections
+            .get(connection_id)
+            .is_some_and(|udp_connection| udp_connection.compression);
+        let config = match &self.config.compression {
+            Some(config) if compression => config,
+            _ => return packets,
+        };
+        packets
+            .into_iter()
+            .filter_map(|packet| {
+                let payload = match packet.payload().first().copied() {
+                    Some(compression::STORED) => packet.clone().into_payload().slice(1..),
+                    Some(compression::DEFLATED) => Bytes::from(compression::decompress(
+                        &packet.payload()[1..],
+                        config.max_decompressed_size,
+                    )?),
+                    _ => return None,
+                };
+                Some(packet.with_payload(payload))
+            })
+            .collect()
+    }
+
+    // Queues an event for each packet sent to the client whose delivery is
+    // now known
+    fn push_deliveries(&mut self, connection_id: &ConnectionId) {
+        // pings measure the round trip time more directly, when they're sent
+        let pinged = self
+            .udp_connections
+            .get(connection_id)
+            .is_some_and(|udp_connection| udp_connection.rtt.is_some());
+        let acks = match self
+            .udp_connections
+            .get_mut(connection_id)
+            .and_then(|udp_connection| udp_connection.acks.as_mut())
+        {
+            Some(acks) => acks,
+            None => return,
+        };
+        while let Some(delivery) = acks.poll_delivery() {
+            self.outstanding_events.push_back(match delivery {
+                Delivery::Acked(ack_id) => ServerSocketEvent::PacketAcked(*connection_id, ack_id),
+                Delivery::Lost(ack_id) => ServerSocketEvent::PacketLost(*connection_id, ack_id),
+            });
+        }
+        if let Some(stats) = self.connection_manager.stats_mut(connection_id) {
+            if !pinged {
+                stats.rtt = acks.rtt();
+            }
+            stats.acked_packets = acks.acked_count();
+            stats.lost_packets = acks.lost_count();
+        }
+        let bandwidth = acks.congestion().map(|congestion| congestion.bandwidth());
+        self.update_quality(connection_id);
+
+        // the connection is paced to its bandwidth estimate
+        let bandwidth = match bandwidth {
+            Some(bandwidth) => bandwidth,
+            None => return,
+        };
+        if let Some(pacer) = self
+            .udp_connections
+            .get_mut(connection_id)
+            .and_then(|udp_connection| udp_connection.pacer.as_mut())
+        {
+            pacer.set_rate(bandwidth);
+        }
+        if let Some(stats) = self.connection_manager.stats_mut(connection_id) {
+            stats.estimated_bandwidth = Some(bandwidth as u64);
+        }
+    }
+
+    // Gets the pacer for a new connection, which is needed for pacing, & for
+    // congestion control if it has acks to go on
+    fn new_pacer(&self, acknowledged: bool) -> Option<Pacer> {
+        let congestion = self
+            .config
+            .congestion_control
+            .as_ref()
+            .filter(|_| acknowledged);
+        let mut pacer = match (&self.config.pacing, congestion) {
+            (Some(pacing), _) => Pacer::new(pacing),
+            (None, Some(congestion)) => Pacer::new(&PacingConfig {
+                bytes_per_second: congestion.max_bandwidth,
+                ..PacingConfig::default()
+            }),
+            (None, None) => return None,
+        };
+        if let Some(congestion) = congestion {
+            pacer.set_rate(congestion.initial_bandwidth);
+        }
+        Some(pacer)
+    }
+
+    // Adds an ack for every client which is owed one & has had no packet to
+    // carry it, & reports the packets which have gone unacknowledged too long
+    fn push_ack_datagrams(&mut self, datagrams: &mut Vec<(Vec<u8>, SocketAddr)>) {
+        let connection_ids: Vec<ConnectionId> = self
+            .udp_connections
+            .iter()
+            .filter(|(_, udp_connection)| udp_connection.acks.is_some())
+            .map(|(connection_id, _)| *connection_id)
+            .collect();
+        for connection_id in connection_ids {
+            self.push_deliveries(&connection_id);
+
+            let address = match self.connection_manager.address(&connection_id) {
+                Some(address) => address,
+                None => continue,
+            };
+            let acks = match self
+                .udp_connections
+                .get_mut(&connection_id)
+                .and_then(|udp_connection| udp_connection.acks.as_mut())
+            {
+                Some(acks) if acks.ack_due() => acks,
+                _ => continue,
+            };
+            let mut datagram = vec![PacketType::Ack.to_byte()];
+            datagram.extend_from_slice(&acks.write_ack());
+            datagrams.push((datagram, address));
+        }
+    }
+
+    // Answers a client's ping, echoing its timestamp along with our clock
+    async fn send_pong(
+        &mut self,
+        connection_id: &ConnectionId,
+        ping: &[u8],
+        address: SocketAddr,
+    ) -> Result<(), NaiaServerSocketError> {
+        let payload = match ping::write_pong(ping) {
+            Some(payload) => payload,
+            None => return Ok(()),
+        };
+        let mut pong = Vec::with_capacity(1 + PONG_SIZE);
+        pong.push(PacketType::Pong.to_byte());
+        pong.extend_from_slice(&payload);
+        if let Some(udp_connection) = self.udp_connections.get_mut(connection_id) {
+            pong = udp_connection.seal(pong);
+        }
+        self.send_handshake_packet(&pong, address).await
+    }
+
+    // Measures a round trip to a client from the reply to one of our pings
+    fn receive_pong(&mut self, connection_id: &ConnectionId, pong: &[u8]) {
+        let rtt = match self
+            .udp_connections
+            .get_mut(connection_id)
+            .and_then(|udp_connection| udp_connection.rtt.as_mut())
+        {
+            Some(rtt) => rtt,
+            None => return,
+        };
+        if !rtt.read_pong(pong) {
+            return;
+        }
+        if let Some(stats) = self.connection_manager.stats_mut(connection_id) {
+            stats.rtt = rtt.rtt();
+            stats.jitter = rtt.jitter();
+        }
+        self.update_quality(connection_id);
+    }
+
+    // Judges a connection's quality again after its stats have changed,
+    // reporting it if it changed
+    fn update_quality(&mut self, connection_id: &ConnectionId) {
+        let tracker = match self
+            .udp_connections
+            .get_mut(connection_id)
+            .and_then(|udp_connection| udp_connection.quality.as_mut())
+        {
+            Some(tracker) => tracker,
+            None => return,
+        };
+        let stats = match self.connection_manager.stats_mut(connection_id) {
+            Some(stats) => stats,
+            None => return,
+        };
+        if let Some(quality) = tracker.update(stats) {
+            stats.quality = Some(quality);
+            self.outstanding_events
+                .push_back(ServerSocketEvent::QualityChanged {
+                    connection_id: *connection_id,
+                    quality,
+                });
+        }
+    }
+
+    // Adds a ping for every client which is due one
+    fn push_ping_datagrams(&mut self, datagrams: &mut Vec<(Vec<u8>, SocketAddr)>) {
+        for (connection_id, udp_connection) in self.udp_connections.iter_mut() {
+            let rtt = match &mut udp_connection.rtt {
+                Some(rtt) if rtt.ping_due() => rtt,
+                _ => continue,
+            };
+            let address = match self.connection_manager.address(connection_id) {
+                Some(address) => address,
+                None => continue,
+            };
+            let mut datagram = Vec::with_capacity(1 + PING_SIZE);
+            datagram.push(PacketType::Ping.to_byte());
+            datagram.extend_from_slice(&rtt.write_ping());
+            datagrams.push((datagram, address));
+        }
+    }
+
+    // Handles a reliable message or ack from a client, acknowledging messages
+    // & returning those ready to be delivered
+    async fn receive_reliable(
+        &mut self,
+        packet_type: PacketType,
+        connection_id: &ConnectionId,
+        payload: &[u8],
+        address: SocketAddr,
+    ) -> Result<Vec<Packet>, NaiaServerSocketError> {
+        let reliable = self
+            .udp_connections
+            .get_mut(connection_id)
+            .and_then(|udp_connection| udp_connection.reliable.as_mut());
+        let (reliable, (channel, id, message)) = match (reliable, reliability::read_header(payload))
+        {
+            (Some(reliable), Some(header)) => (reliable, header),
+            _ => return Ok(Vec::new()),
+        };
+
+        if packet_type == PacketType::ReliableAck {
+            reliable.acked(channel, id);
+            return Ok(Vec::new());
+        }
+        let delivered = match reliable.receive(channel, id, message) {
+            Some(delivered) => delivered,
+            None => return Ok(Vec::new()),
+        };
+
+        let mut ack = Vec::with_capacity(1 + RELIABLE_HEADER_SIZE);
+        ack.push(PacketType::ReliableAck.to_byte());
+        ack.extend_from_slice(&payload[..RELIABLE_HEADER_SIZE]);
+        if let Some(udp_connection) = self.udp_connections.get_mut(connection_id) {
+            ack = udp_connection.seal(ack);
+        }
+        self.send_handshake_packet(&ack, address).await?;
+
+        Ok(delivered
+            .into_iter()
+            .map(|payload| {
+                Packet::new(address, payload)
+                    .with_channel(channel)
+                    .with_reliable()
+            })
+            .collect())
+    }
+
+    // Adds the datagrams carrying every reliable message which is due to be
+    // sent, for the first time or again
+    fn push_reliable_datagrams(&mut self, datagrams: &mut Vec<(Vec<u8>, SocketAddr)>) {
+        for (connection_id, udp_connection) in self.udp_connections.iter_mut() {
+            let reliable = match &mut udp_connection.reliable {
+                Some(reliable) => reliable,
+                None => continue,
+            };
+            let address = match self.connection_manager.address(connection_id) {
+                Some(address) => address,
+                None => continue,
+            };
+            for message in reliable.outgoing() {
+                let mut datagram =
+                    Vec::with_capacity(1 + RELIABLE_HEADER_SIZE + message.payload.len());
+                datagram.push(PacketType::Reliable.to_byte());
+                datagram.push(message.channel);
+                datagram.extend_from_slice(&message.id.to_be_bytes());
+                datagram.extend_from_slice(&message.payload);
+                datagrams.push((datagram, address));
+            }
+        }
+    }
+
+    // Gets the moment something held back is next due to be sent
+    fn next_send_deadline(&self) -> Option<Instant> {
+        // packets left over from the last batch go out right away
+        if !self.send_queue.is_empty() {
+            return Some(clock::now());
+        }
+        let coalesced = self.coalescer.as_ref().and_then(Coalescer::next_deadline);
+        let reliable = self
+            .udp_connections
+            .values()
+            .filter_map(|udp_connection| udp_connection.reliable.as_ref())
+            .filter_map(ReliableChannels::next_send_in);
+        let acks = self
+            .udp_connections
+            .values()
+            .filter_map(|udp_connection| udp_connection.acks.as_ref())
+            .filter_map(AckTracker::next_deadline_in);
+        let paced = self
+            .udp_connections
+            .values()
+            .filter_map(|udp_connection| udp_connection.pacer.as_ref())
+            .filter_map(Pacer::next_ready_in);
+        let pings = self
+            .udp_connections
+            .values()
+            .filter_map(|udp_connection| udp_connection.rtt.as_ref())
+            .map(RttEstimator::next_ping_in);
+        let held_back = reliable
+            .chain(acks)
+            .chain(paced)
+            .chain(pings)
+            .min()
+            .map(|delay| clock::now() + delay);
+        match (coalesced, held_back) {
+            (Some(coalesced), Some(held_back)) => Some(coalesced.min(held_back)),
+            (coalesced, held_back) => coalesced.or(held_back),
+        }
+    }
+
+    async fn send_datagrams(
+        &mut self,
+        datagrams: Vec<(Vec<u8>, SocketAddr)>,
+    ) -> Result<(), NaiaServerSocketError> {
+        // datagrams are sealed as they go out, once coalescing & fragmenting
+        // are done with them, & then paced
+        let mut ready = Vec::with_capacity(datagrams.len());
+        for (datagram, address) in datagrams {
+            let connection_id = match self.connection_manager.connection_id(&address) {
+                Some(connection_id) => connection_id,
+                None => {
+                    ready.push((datagram, address));
+                    continue;
+                }
+            };
+            let udp_connection = match self.udp_connections.get_mut(&connection_id) {
+                Some(udp_connection) => udp_connection,
+                None => continue,
+            };
+            let datagram = udp_connection.seal(datagram);
+            match &mut udp_connection.pacer {
+                Some(pacer) => {
+                    if !pacer.push(datagram) {
+                        udp_connection.paced_out += 1;
+                        self.connection_manager.metrics_mut().errors.paced_out += 1;
+                        if let Some(stats) = self.connection_manager.stats_mut(&connection_id) {
+                            stats.paced_out_datagrams += 1;
+                        }
+                    }
+                }
+                None => ready.push((datagram, address)),
+            }
+        }
+        for (connection_id, udp_connection) in self.udp_connections.iter_mut() {
+            let pacer = match &mut udp_connection.pacer {
+                Some(pacer) => pacer,
+                None => continue,
+            };
+            let address = match self.connection_manager.address(connection_id) {
+                Some(address) => address,
+                None => continue,
+            };
+            while let Some(datagram) = pacer.pop_ready() {
+                ready.push((datagram, address));
+            }
+        }
+        self.push_backpressure_events();
+
+        if ready.is_empty() {
+            return Ok(());
+        }
+        if let Err((address, err)) = self.socket.send_batch(&ready).await {
+            if !is_unreachable(&err) {
+                trace_event!(WARN, %address, "send failed");
+                self.connection_manager.metrics_mut().errors.send_failures += 1;
+                return Err(NaiaServerSocketError::SendError(address));
+            }
+            // the report is about an earlier datagram, which may have gone
+            // to another client, so it isn't this one's connection that is
+            // closed. Clients which have gone time out instead
+            trace_event!(DEBUG, %address, "peer unreachable");
+            self.connection_manager.metrics_mut().errors.unreachable += 1;
+        }
+        for (datagram, address) in ready.iter() {
+            if let Some(connection_id) = self.connection_manager.connection_id(address) {
+                self.connection_manager
+                    .record_sent(&connection_id, datagram.len());
+            }
+        }
+        Ok(())
+    }
+
+    // Gets how long a connection can go unheard from before it is closed, if
+    // connections are ever closed for being idle
+    fn idle_timeout(&self) -> Option<Duration> {
+        let idle_timeout = self.config.idle_timeout?;
+        // a connection isn't closed while it can still be resumed
+        Some(match self.config.resumption_grace_period {
+            Some(grace_period) => idle_timeout.max(grace_period),
+            None => idle_timeout,
+        })
+    }
+
+    // Gets the moment the connection heard from longest ago is due to be
+    // closed, if it goes on being idle
+    fn next_idle_deadline(&self) -> Option<Instant> {
+        let idle_timeout = self.idle_timeout()?;
+        self.udp_connections
+            .values()
+            .map(|udp_connection| udp_connection.last_received)
+            .min()
+            .map(|last_received| last_received + idle_timeout)
+    }
+
+    // Closes the connections which haven't been heard from in too long, whose
+    // clients have most likely gone without saying so
+    fn close_idle_connections(&mut self) {
+        let idle_timeout = match self.idle_timeout() {
+            Some(idle_timeout) => idle_timeout,
+            None => return,
+        };
+        let idle: Vec<ConnectionId> = self
+            .udp_connections
+            .iter()
+            .filter(|(_, udp_connection)| {
+                clock::elapsed(udp_connection.last_received) >= idle_timeout
+            })
+            .map(|(connection_id, _)| *connection_id)
+            .collect();
+        for connection_id in idle {
+            self.close_connection(&connection_id, "client timed out");
+        }
+    }
+
+    // Forgets a connection the client has closed or gone away from, without a
+    // word to it, & reports it with a Disconnection event
+    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+    fn close_connection(&mut self, connection_id: &ConnectionId, reason: &str) {
+        let (address, stats) = match self.connection_manager.remove_connection(connection_id) {
+            Some(removed) => removed,
+            None => return,
+        };
+        if let Some(udp_connection) = self.udp_connections.remove(connection_id) {
+            self.connection_tokens.remove(&udp_connection.token);
+            self.resumption_tokens
+                .remove(&udp_connection.resumption_token);
+        }
+        trace_event!(INFO, %address, reason, "connection closed");
+        self.outstanding_events
+            .push_back(ServerSocketEvent::Disconnection(
+                *connection_id,
+                address,
+                stats,
+            ));
+    }
+
+    // Sends a probe padded to the given size once sealed, to find out whether
+    // datagrams that large reach the client whole
+    async fn send_mtu_probe(
+        &mut self,
+        connection_id: &ConnectionId,
+        size: usize,
+        address: SocketAddr,
+    ) {
+        let udp_connection = match self.udp_connections.get_mut(connection_id) {
+            Some(udp_connection) => udp_connection,
+            None => return,
+        };
+        let mut probe = vec![0; size - udp_connection.sealed_overhead()];
+        probe[0] = PacketType::ServerMtuProbe.to_byte();
+        probe[1..9].copy_from_slice(&udp_connection.token.to_be_bytes());
+        let probe = udp_connection.seal(probe);
+        // a probe too large to even leave this host is as good as lost
+        let _ = self.socket.send_to(&probe, address).await;
+    }
+
+    // Decides whether a new client can be admitted, placing it in the waiting
+    // room if it can't & the waiting room is enabled
+    fn admission(&mut self, address: SocketAddr) -> Admission {
+        let max_clients = match self.config.max_clients {
+            Some(max_clients) => max_clients,
+            None => return Admission::Admitted,
+        };
+
+        for address in self.waiting_room.expire() {
+            self.outstanding_events
+                .push_back(ServerSocketEvent::LeftQueue { address });
+        }
+
+        let free_slots = max_clients.saturating_sub(self.connection_manager.connection_count());
+        if !self.config.waiting_room {
+            if free_slots > 0 {
+                return Admission::Admitted;
+            }
+            return Admission::Full;
+        }
+
+        match self.waiting_room.refresh(&address) {
+            Some(index) if index < free_slots => {
+                self.waiting_room.remove(index);
+                Admission::Admitted
+            }
+            Some(index) => Admission::Queued(index + 1),
+            // nobody is waiting for the free slots, so there's no need to queue
+            None if self.waiting_room.len() < free_slots => Admission::Admitted,
+            None => {
+                let position = self.waiting_room.join(address) + 1;
+                self.outstanding_events
+                    .push_back(ServerSocketEvent::Queued { address, position });
+                Admission::Queued(position)
+            }
+        }
+    }
+
+    // Sends a reply to an address which hasn't yet proven that it belongs to the
+    // client, never sending more bytes than were received from it
+    async fn send_to_unvalidated(
+        &self,
+        packet: &[u8],
+        received_len: usize,
+        address: SocketAddr,
+    ) -> Result<(), NaiaServerSocketError> {
+        if packet.len() > received_len {
+            return Ok(());
+        }
+        if let Err(err) = self.socket.send_to(packet, address).await {
+            // an unreachable client has gone, & its handshake with it
+            if is_unreachable(&err) {
+                return Ok(());
+            }
+            return Err(NaiaServerSocketError::SendError(address));
+        }
+        Ok(())
+    }
+
+    // Decides whether a refused client should be reported, which it is once
+    // a REFUSAL_INTERVAL
+    fn refusal_due(&mut self, address: SocketAddr) -> bool {
+        let now = clock::now();
+        if let Some(last_reported) = self.refusals.get(&address) {
+            if now.saturating_duration_since(*last_reported) < REFUSAL_INTERVAL {
+                return false;
+            }
+        }
+        if self.refusals.len() >= MAX_REFUSALS {
+            self.refusals.retain(|_, last_reported| {
+                now.saturating_duration_since(*last_reported) < REFUSAL_INTERVAL
+            });
+            if self.refusals.len() >= MAX_REFUSALS {
+                return false;
+            }
+        }
+        self.refusals.insert(address, now);
+        true
+    }
+
+    // Reports that a client was refused, if it is due being reported
+    fn push_refusal(&mut self, address: SocketAddr, err: HandshakeError) {
+        if self.refusal_due(address) {
+            self.outstanding_events.push_back(ServerSocketEvent::Error(
+                NaiaServerSocketError::Handshake(err),
+            ));
+        }
+    }
+
+    // Finds the connection the client was previously using, if it presented a
+    // resumption token & came back soon enough
+    fn resumable_connection(&self, resumption_token: Option<u128>) -> Option<ConnectionId> {
+        let grace_period = self.config.resumption_grace_period?;
+        let connection_id = *self.resumption_tokens.get(&resumption_token?)?;
+        if clock::elapsed(self.udp_connections[&connection_id].last_received) > grace_period {
+            return None;
+        }
+        Some(connection_id)
+    }
+
+    fn new_resumption_token(&self) -> u128 {
+        loop {
+            let token = (u128::from(Random::gen_u64()) << 64) | u128::from(Random::gen_u64());
+            if !self.resumption_tokens.contains_key(&token) {
+                return token;
+            }
+        }
+    }
+
+    fn new_token(&self) -> u64 {
+        loop {
+            let token = Random::gen_u64();
+            if !self.connection_tokens.contains_key(&token) {
+                return token;
+            }
+        }
+    }
+
+    // Gets the next event which is ready to be returned, if there is one
+    fn ready_event(&mut self) -> Option<ServerSocketEvent> {
+        #[cfg(feature = "metrics")]
+        self.publish_metrics();
+        self.report_metrics();
+        self.push_overflow_event();
+        if let Some(event) = self.outstanding_events.pop_front() {
+            self.tap_incoming(&event);
+            return Some(event);
+        }
+        if self.closed {
+            return Some(ServerSocketEvent::Closed);
+        }
+        None
+    }
+
+    // Gets what there is to deal with right now, without waiting for anything
+    fn try_next(&mut self) -> Option<Next> {
+        match self.to_client_receiver.try_recv() {
+            Ok(packet) => return Some(Next::ToClientMessage(packet)),
+            Err(err) if err.is_closed() => return Some(Next::Closed),
+            Err(_) => {}
+        }
+        let udp_socket = self.socket.get();
+        match self.receive_batch.try_receive(&udp_socket) {
+            Ok(false) => {}
+            result => return Some(Next::FromClientMessage(result.map(|_| ()))),
+        }
+        if let Some(multicast_socket) = &self.multicast_socket {
+            match multicast_socket
+                .get_ref()
+                .recv_from(&mut self.multicast_buffer)
+            {
+                Err(err) if err.kind() == ErrorKind::WouldBlock => {}
+                result => return Some(Next::FromMulticast(result)),
+            }
+        }
+        if self
+            .next_idle_deadline()
+            .is_some_and(|deadline| deadline <= clock::now())
+        {
+            return Some(Next::Idle);
+        }
+        if self
+            .next_send_deadline()
+            .is_some_and(|deadline| deadline <= clock::now())
+        {
+            return Some(Next::Flush);
+        }
+        None
+    }
+
+    // Deals with what the socket was woken up by, or found without waiting
+    async fn handle_next(&mut self, next: Next) -> Result<(), NaiaServerSocketError> {
+        match next {
+            Next::FromClientMessage(from_client_message) => match from_client_message {
+                Ok(()) => {
+                    // if one fails fatally, the rest of the batch is lost,
+                    // as if the network had dropped them
+                    let received_at = clock::now();
+                    for index in 0..self.receive_batch.len() {
+                        let (datagram, address, congestion_experienced) =
+                            self.receive_batch.get(index);
+                        let message = self.buffer_pool.copy_from(datagram);
+                        let result = self
+                            .process_datagram(message, address, congestion_experienced, received_at)
+                            .await;
+                        self.push_transient(result)?;
+                    }
+                }
+                Err(err) if error::is_transient(&err) => {
+                    // receiving again can succeed. Which client an ICMP
+                    // error is about isn't reported, so there is no
+                    // connection to close
+                    trace_event!(DEBUG, error = %err, "transient receive error");
+                    if is_unreachable(&err) {
+                        self.connection_manager.metrics_mut().errors.unreachable += 1;
+                    }
+                }
+                Err(err) => {
+                    return Err(NaiaServerSocketError::Io(err));
+                }
+            },
+            Next::FromMulticast(from_multicast) => match from_multicast {
+                Ok((length, address)) => {
+                    let payload = self.buffer_pool.copy_from(&self.multicast_buffer[..length]);
+                    self.outstanding_events.push_back(ServerSocketEvent::Packet(
+                        Packet::from_bytes(address, payload)
+                            .with_multicast()
+                            .with_received(clock::now(), Transport::Udp),
+                    ));
+                }
+                Err(err) if error::is_transient(&err) => {
+                    // receiving again can succeed. Which client an ICMP
+                    // error is about isn't reported, so there is no
+                    // connection to close
+                    trace_event!(DEBUG, error = %err, "transient receive error");
+                }
+                Err(err) => {
+                    return Err(NaiaServerSocketError::Io(err));
+                }
+            },
+            Next::Closed => {
+                // every sender is gone, so nothing more can be sent
+                self.closed = true;
+            }
+            Next::Idle => self.close_idle_connections(),
+            Next::ToClientMessage(packet) => {
+                // anything else already queued goes out along with it,
+                // most urgent first
+                let mut messages = Vec::new();
+                self.queue_packet(packet);
+                self.push_queued_datagrams(SEND_BATCH_SIZE, &mut messages);
+                if let Some(coalescer) = &mut self.coalescer {
+                    coalescer.flush_due(&mut messages);
+                }
+                self.push_reliable_datagrams(&mut messages);
+                self.push_ack_datagrams(&mut messages);
+                self.push_ping_datagrams(&mut messages);
+
+                let result = self.send_datagrams(messages).await;
+                self.push_transient(result)?;
+            }
+            Next::Flush => {
+                let mut messages = Vec::new();
+                self.push_queued_datagrams(SEND_BATCH_SIZE, &mut messages);
+                if let Some(coalescer) = &mut self.coalescer {
+                    coalescer.flush_due(&mut messages);
+                }
+                self.push_reliable_datagrams(&mut messages);
+                self.push_ack_datagrams(&mut messages);
+                self.push_ping_datagrams(&mut messages);
+
+                let result = self.send_datagrams(messages).await;
+                self.push_transient(result)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Something the socket has to deal with before it has an event to return
+enum Next {
+    FromClientMessage(Result<(), IoError>),
+    FromMulticast(Result<(usize, SocketAddr), IoError>),
+    ToClientMessage(Packet),
+    Flush,
+    Idle,
+    Closed,
 }
 
 #[async_trait]
 impl ServerSocketTrait for ServerSocket {
-    async fn receive(&mut self) -> Result<Packet, NaiaServerSocketError> {
-        enum Next {
-            FromClientMessage(Result<(usize, SocketAddr), IoError>),
-            ToClientMessage(Packet),
-        }
-
+    async fn receive(&mut self) -> Result<ServerSocketEvent, NaiaServerSocketError> {
         loop {
+            if let Some(event) = self.ready_event() {
+                return Ok(event);
+            }
+
+            let flush_deadline = self.next_send_deadline();
+            let idle_deadline = self.next_idle_deadline();
             let next = {
                 let to_client_receiver_next = self.to_client_receiver.next().fuse();
                 pin_mut!(to_client_receiver_next);
 
-                let receive_buffer = &mut self.receive_buffer;
-                let udp_socket = &mut self.socket;
-                let from_client_message_receiver_next = udp_socket.recv_from(receive_buffer).fuse();
+                let receive_batch = &mut self.receive_batch;
+                let udp_socket = self.socket.get();
+                let from_client_message_receiver_next = receive_batch.receive(&udp_socket).fuse();
                 pin_mut!(from_client_message_receiver_next);
 
+                let multicast_socket = &self.multicast_socket;
+                let multicast_buffer = &mut self.multicast_buffer;
+                let from_multicast_next = async move {
+                    match multicast_socket {
+                        Some(multicast_socket) => {
+                            multicast_socket.recv_from(multicast_buffer).await
+                        }
+                        None => future::pending().await,
+                    }
+                }
+                .fuse();
+                pin_mut!(from_multicast_next);
+
+                let flush_timer = async move {
+                    match flush_deadline {
+                        Some(flush_deadline) => {
+                            Timer::after(flush_deadline.saturating_duration_since(clock::now()))
+                                .await;
+                        }
+                        None => future::pending::<()>().await,
+                    }
+                }
+                .fuse();
+                pin_mut!(flush_timer);
+
+                let idle_timer = async move {
+                    match idle_deadline {
+                        Some(idle_deadline) => {
+                            Timer::after(idle_deadline.saturating_duration_since(clock::now()))
+                                .await;
+                        }
+                        None => future::pending::<()>().await,
+                    }
+                }
+                .fuse();
+                pin_mut!(idle_timer);
+
                 select! {
                     from_client_result = from_client_message_receiver_next => {
                         Next::FromClientMessage(from_client_result)
                     }
+                    from_multicast_result = from_multicast_next => {
+                        Next::FromMulticast(from_multicast_result)
+                    }
                     to_client_message = to_client_receiver_next => {
-                        Next::ToClientMessage(
-                            to_client_message.expect("to server message receiver closed")
-                        )
+                        match to_client_message {
+                            Some(packet) => Next::ToClientMessage(packet),
+                            None => Next::Closed,
+                        }
                     }
+                    _ = flush_timer => Next::Flush,
+                    _ = idle_timer => Next::Idle,
                 }
             };
 
-            match next {
-                Next::FromClientMessage(from_client_message) => match from_client_message {
-                    Ok((message_len, message_address)) => {
-                        let payload: Vec<u8> = self.receive_buffer[0..message_len]
-                            .iter()
-                            .cloned()
-                            .collect();
-                        return Ok(Packet::new_raw(message_address, payload.into_boxed_slice()));
-                    }
-                    Err(err) => {
-                        return Err(NaiaServerSocketError::Wrapped(Box::new(err)));
-                    }
-                },
-                Next::ToClientMessage(packet) => {
-                    let address = packet.address();
-
-                    match self.socket.send_to(packet.payload(), address).await {
-                        Err(_) => {
-                            return Err(NaiaServerSocketError::SendError(address));
-                        }
-                        _ => {}
-                    }
-                }
+            self.handle_next(next).await?;
+        }
+    }
+
+    fn try_receive(&mut self) -> Result<Option<ServerSocketEvent>, NaiaServerSocketError> {
+        loop {
+            if let Some(event) = self.ready_event() {
+                return Ok(Some(event));
+            }
+            match self.try_next() {
+                // sending only waits for room in the OS buffer
+                Some(next) => async_io::block_on(self.handle_next(next))?,
+                None => return Ok(None),
             }
         }
     }
 
     fn get_sender(&mut self) -> MessageSender {
-        return MessageSender::new(self.to_client_sender.clone());
+        return self
+            .to_client_sender
+            .get()
+            .with_max_payload_size(self.config.max_payload_size);
     }
 
     fn with_link_conditioner(
         self: Box<Self>,
         config: &LinkConditionerConfig,
     ) -> Box<dyn ServerSocketTrait> {
-        Box::new(LinkConditioner::new(config, self))
+        Box::new(LinkConditioner::new(config, *self))
+    }
+
+    fn with_middleware(
+        self: Box<Self>,
+        middleware: Box<dyn SocketMiddleware>,
+    ) -> Box<dyn ServerSocketTrait> {
+        let mut socket = MiddlewareSocket::new(*self);
+        socket.push(middleware);
+        Box::new(socket)
+    }
+
+    fn split(mut self: Box<Self>) -> (SendHalf, RecvHalf) {
+        let send_half = SendHalf::new(
+            self.socket.clone(),
+            self.fragmenter.clone(),
+            self.config.mtu,
+            self.to_client_sender
+                .get()
+                .with_max_payload_size(self.config.max_payload_size),
+            self.config.acknowledgement.is_some()
+                || self.config.compression.is_some()
+                || self.config.protection().is_some()
+                || self.config.pacing.is_some()
+                || self.config.congestion_control.is_some(),
+            self.config
+                .multicast
+                .as_ref()
+                .map(|multicast| multicast.group),
+        );
+        (send_half, RecvHalf::new(self))
+    }
+
+    async fn rebind(
+        &mut self,
+        socket_address: SocketAddr,
+        _public_address: SocketAddr,
+    ) -> Result<(), NaiaServerSocketError> {
+        let socket = bind_socket(socket_address, &self.config)
+            .map_err(|err| NaiaServerSocketError::from_bind_error(err, socket_address))?;
+        let local_address = socket.get_ref().local_addr().unwrap_or(socket_address);
+        // connection state is keyed by client, so nothing else needs to change
+        self.socket.replace(socket);
+        self.packet_tap.set_local_address(local_address);
+        if let Some(port_mapping) = self.config.port_mapping.clone() {
+            // the old port's mapping is removed before the new one is asked for
+            self.unmap_ports().await;
+            self.port_mapping =
+                PortMapping::request(port_mapping, vec![(MappedProtocol::Udp, local_address)])
+                    .await;
+        }
+        info!("Server socket rebound to {}", socket_address);
+        Ok(())
+    }
+
+    async fn unmap_ports(&mut self) {
+        if let Some(port_mapping) = self.port_mapping.take() {
+            port_mapping.close().await;
+        }
+    }
+
+    async fn flush(&mut self) -> Result<(), NaiaServerSocketError> {
+        let mut messages = Vec::new();
+        self.push_queued_datagrams(usize::MAX, &mut messages);
+        if let Some(coalescer) = &mut self.coalescer {
+            coalescer.flush_all(&mut messages);
+        }
+        self.push_reliable_datagrams(&mut messages);
+        self.push_ack_datagrams(&mut messages);
+
+        self.send_datagrams(messages).await
+    }
+
+    async fn disconnect(
+        &mut self,
+        connection_id: &ConnectionId,
+        reason: Option<&[u8]>,
+    ) -> Result<(), NaiaServerSocketError> {
+        let address = match self.connection_manager.address(connection_id) {
+            Some(address) => address,
+            None => return Ok(()),
+        };
+
+        // anything the application sent before disconnecting goes out first
+        ServerSocketTrait::flush(self).await?;
+
+        let stats = match self.connection_manager.remove_connection(connection_id) {
+            Some((_, stats)) => stats,
+            None => return Ok(()),
+        };
+        let mut udp_connection = match self.udp_connections.remove(connection_id) {
+            Some(udp_connection) => udp_connection,
+            None => return Ok(()),
+        };
+        self.connection_tokens.remove(&udp_connection.token);
+        self.resumption_tokens
+            .remove(&udp_connection.resumption_token);
+
+        let reason = reason.unwrap_or(&[]);
+        let mut message = Vec::with_capacity(9 + reason.len());
+        message.push(PacketType::ServerDisconnect.to_byte());
+        message.extend_from_slice(&udp_connection.token.to_be_bytes());
+        message.extend_from_slice(reason);
+        let message = udp_connection.seal(message);
+        for _ in 0..CONTROL_REDUNDANCY {
+            self.send_handshake_packet(&message, address).await?;
+        }
+
+        self.outstanding_events
+            .push_back(ServerSocketEvent::Disconnection(
+                *connection_id,
+                address,
+                stats,
+            ));
+        Ok(())
+    }
+
+    async fn announce_host_migration(
+        &mut self,
+        new_address: SocketAddr,
+    ) -> Result<(), NaiaServerSocketError> {
+        let new_address = new_address.to_string();
+        for (connection_id, address) in self.connection_manager.connections() {
+            let udp_connection = match self.udp_connections.get_mut(&connection_id) {
+                Some(udp_connection) => udp_connection,
+                None => continue,
+            };
+            let mut message = Vec::with_capacity(9 + new_address.len());
+            message.push(PacketType::ServerHostMigration.to_byte());
+            message.extend_from_slice(&udp_connection.token.to_be_bytes());
+            message.extend_from_slice(new_address.as_bytes());
+            let message = udp_connection.seal(message);
+            for _ in 0..CONTROL_REDUNDANCY {
+                self.send_handshake_packet(&message, address).await?;
+            }
+        }
+        Ok(())
+    }
+
+    fn set_accepting(&mut self, accepting: bool) {
+        self.accepting = accepting;
+    }
+
+    fn connections(&self) -> Vec<(ConnectionId, SocketAddr)> {
+        self.connection_manager.connections()
+    }
+
+    fn connection_id(&self, address: &SocketAddr) -> Option<ConnectionId> {
+        self.connection_manager.connection_id(address)
+    }
+
+    fn connection_address(&self, connection_id: &ConnectionId) -> Option<SocketAddr> {
+        self.connection_manager.address(connection_id)
+    }
+
+    fn connect_token(&self, connection_id: &ConnectionId) -> Option<&ConnectToken> {
+        self.connection_manager.connect_token(connection_id)
+    }
+
+    fn connect_payload(&self, connection_id: &ConnectionId) -> Option<&[u8]> {
+        self.connection_manager.connect_payload(connection_id)
+    }
+
+    fn connection_stats(&self, connection_id: &ConnectionId) -> Option<ConnectionStats> {
+        self.connection_manager.stats(connection_id)
+    }
+
+    fn stats(&self, address: &SocketAddr) -> Option<ConnectionStats> {
+        let connection_id = self.connection_manager.connection_id(address)?;
+        self.connection_manager.stats(&connection_id)
+    }
+
+    fn metrics(&self) -> SocketMetrics {
+        let mut metrics = self.connection_manager.metrics().snapshot(
+            self.connection_manager.connection_count(),
+            self.send_queue.len(),
+        );
+        metrics.slow_consumers = self
+            .udp_connections
+            .values()
+            .filter(|udp_connection| udp_connection.slow)
+            .count();
+        metrics
+    }
+
+    fn connection_mtu(&self, connection_id: &ConnectionId) -> Option<usize> {
+        self.udp_connections
+            .get(connection_id)
+            .map(|udp_connection| udp_connection.mtu_probe.mtu())
+    }
+
+    fn conditioner_handle(&self) -> Option<ConditionerHandle> {
+        None
+    }
+
+    fn conditioner_stats(&self) -> Option<ConditionerStats> {
+        None
+    }
+
+    fn buffer_pool_stats(&self) -> BufferPoolStats {
+        self.buffer_pool.stats()
+    }
+
+    fn socket_buffer_sizes(&self) -> Option<SocketBufferSizes> {
+        let socket = self.socket.get();
+        let socket = SockRef::from(socket.get_ref());
+        Some(SocketBufferSizes {
+            receive: socket.recv_buffer_size().ok()?,
+            send: socket.send_buffer_size().ok()?,
+        })
+    }
+
+    fn local_addr(&self) -> Option<SocketAddr> {
+        self.socket.get().get_ref().local_addr().ok()
+    }
+
+    fn session_server_addr(&self) -> Option<SocketAddr> {
+        None
+    }
+
+    fn mapped_addr(&self) -> Option<SocketAddr> {
+        self.port_mapping
+            .as_ref()
+            .map(|port_mapping| port_mapping.external_address(0))
+    }
+
+    fn set_user_data(&mut self, connection_id: &ConnectionId, data: UserData) -> Option<UserData> {
+        self.connection_manager.set_user_data(connection_id, data)
+    }
+
+    fn user_data(&self, connection_id: &ConnectionId) -> Option<&(dyn Any + Send + Sync)> {
+        self.connection_manager.user_data(connection_id)
+    }
+
+    fn user_data_mut(
+        &mut self,
+        connection_id: &ConnectionId,
+    ) -> Option<&mut (dyn Any + Send + Sync)> {
+        self.connection_manager.user_data_mut(connection_id)
+    }
+
+    fn take_user_data(&mut self, connection_id: &ConnectionId) -> Option<UserData> {
+        self.connection_manager.take_user_data(connection_id)
+    }
+}
+
+// State kept for each connection which is specific to the UDP transport
+#[derive(Debug)]
+struct UdpConnection {
+    token: u64,
+    resumption_token: u128,
+    replay_window: ReceivedWindow<u64>,
+    last_received: Instant,
+    mtu_probe: MtuProbe,
+    reassembler: Option<Reassembler>,
+    reliable: Option<ReliableChannels>,
+    acks: Option<AckTracker>,
+    // whether packets' payloads are compressed, as agreed on while connecting
+    compression: bool,
+    sealing: Option<Sealing>,
+    pacer: Option<Pacer>,
+    rtt: Option<RttEstimator>,
+    quality: Option<QualityTracker>,
+    // datagrams the pacer has dropped which haven't been reported yet
+    paced_out: usize,
+    // when the outgoing queue went above the high-water mark, if it is
+    backed_up_since: Option<Instant>,
+    // whether the client has been reported as a slow consumer
+    slow: bool,
+}
+
+impl UdpConnection {
+    // Creates the state of a connection which has just been accepted, given
+    // its tokens, what was agreed on while connecting & the socket's config
+    fn new(
+        token: u64,
+        resumption_token: u128,
+        compression: bool,
+        sealing: Option<Sealing>,
+        pacer: Option<Pacer>,
+        config: &SocketConfig,
+    ) -> Self {
+        let acks = config.acknowledgement.clone().map(|ack_config| {
+            let acks = AckTracker::new(ack_config);
+            match config.congestion_control.clone() {
+                Some(congestion) => acks.with_congestion_control(congestion),
+                None => acks,
+            }
+        });
+        UdpConnection {
+            token,
+            resumption_token,
+            replay_window: ReceivedWindow::new(),
+            last_received: clock::now(),
+            mtu_probe: MtuProbe::new(
+                config.mtu,
+                config.path_mtu_discovery && cfg!(target_os = "linux"),
+            ),
+            reassembler: config.fragmentation.clone().map(Reassembler::new),
+            reliable: config.reliability.as_ref().map(ReliableChannels::new),
+            acks,
+            compression,
+            sealing,
+            pacer,
+            rtt: config.ping_interval.map(RttEstimator::new),
+            quality: config.quality.map(QualityTracker::new),
+            paced_out: 0,
+            backed_up_since: None,
+            slow: false,
+        }
+    }
+
+    // Seals a packet about to be sent to the client, if the connection is
+    // protected & the packet is of a type which is sealed
+    fn seal(&mut self, packet: Vec<u8>) -> Vec<u8> {
+        let sealing = match &mut self.sealing {
+            Some(sealing) => sealing,
+            None => return packet,
+        };
+        match packet.first().copied().and_then(PacketType::from_byte) {
+            Some(packet_type) if packet_type.is_sealed() => {}
+            _ => return packet,
+        }
+
+        let sequence = sealing.next_sequence;
+        sealing.next_sequence += 1;
+        let mut sealed = Vec::with_capacity(SEALED_OVERHEAD + packet.len());
+        sealed.push(packet[0]);
+        sealed.extend_from_slice(&sequence.to_be_bytes());
+        let body = sealing.keys.seal(sequence, &sealed, &packet[1..]);
+        sealed.extend_from_slice(&body);
+        sealed
+    }
+
+    // Gets how many bytes sealing adds to a packet
+    fn sealed_overhead(&self) -> usize {
+        match self.sealing {
+            Some(_) => SEALED_OVERHEAD,
+            None => 0,
+        }
+    }
+}
+
+// The keys a connection's packets are sealed with, agreed on while connecting
+#[derive(Debug)]
+struct Sealing {
+    keys: SessionKeys,
+    // sent again if the client didn't get the connect response
+    public_key: [u8; encryption::PUBLIC_KEY_SIZE],
+    next_sequence: u64,
+}
+
+impl Sealing {
+    fn new(keys: SessionKeys, public_key: [u8; encryption::PUBLIC_KEY_SIZE]) -> Self {
+        Sealing {
+            keys,
+            public_key,
+            // the resumption token is sealed under sequence number 0
+            next_sequence: 1,
+        }
+    }
+}
+
+// The outcome of a new client asking to be admitted
+enum Admission {
+    Admitted,
+    // holds the client's position in the waiting room, starting from 1
+    Queued(usize),
+    Full,
+}
+
+// Gets whether an error is an ICMP report that a peer can't be reached, which
+// surfaces on a later send or receive (as WSAECONNRESET on Windows), rather
+// than a failure of the socket itself
+fn is_unreachable(err: &IoError) -> bool {
+    matches!(
+        err.kind(),
+        ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionRefused
+            | ErrorKind::HostUnreachable
+            | ErrorKind::NetworkUnreachable
+    )
+}
+
+// Binds a new socket at the given address, with the socket options asked for
+fn bind_socket(address: SocketAddr, config: &SocketConfig) -> Result<Async<UdpSocket>, IoError> {
+    let socket = bind_udp(address)?;
+    let socket_ref = SockRef::from(&socket);
+    if let Some(size) = config.socket_receive_buffer_size {
+        socket_ref.set_recv_buffer_size(size)?;
+    }
+    if let Some(size) = config.socket_send_buffer_size {
+        socket_ref.set_send_buffer_size(size)?;
+    }
+    if config.dscp.is_some() || config.ecn {
+        set_traffic_class(&socket, config.dscp.unwrap_or(0), config.ecn)?;
+    }
+    if config.ecn {
+        batch::receive_traffic_class(&socket)?;
+    }
+    if config.path_mtu_discovery && !mtu_probe::disable_fragmentation(&socket)? {
+        warn!("Path MTU discovery is only supported on Linux");
+    }
+    if let Some(multicast) = &config.multicast {
+        multicast::set_send_options(&socket, multicast)?;
     }
+    Async::new(socket)
 }
@@ -0,0 +1,80 @@
+use async_io::Async;
+use std::{
+    io,
+    net::{SocketAddr, UdpSocket},
+    sync::{Arc, RwLock},
+};
+
+use naia_socket_shared::address_for_socket;
+
+use super::batch;
+
+/// The Server's UDP socket, shared with any SendHalf split off from the
+/// Server so that both move over to the new socket on a rebind
+#[derive(Debug, Clone)]
+pub struct SharedSocket {
+    current: Arc<RwLock<CurrentSocket>>,
+}
+
+#[derive(Debug)]
+struct CurrentSocket {
+    socket: Arc<Async<UdpSocket>>,
+    // whether peers with an IPv4 address are sent to at IPv4-mapped ones
+    ipv6: bool,
+}
+
+impl CurrentSocket {
+    fn new(socket: Async<UdpSocket>) -> Self {
+        let ipv6 = socket
+            .get_ref()
+            .local_addr()
+            .is_ok_and(|address| address.is_ipv6());
+        CurrentSocket {
+            socket: Arc::new(socket),
+            ipv6,
+        }
+    }
+}
+
+impl SharedSocket {
+    /// Create a new SharedSocket around the given socket
+    pub fn new(socket: Async<UdpSocket>) -> Self {
+        SharedSocket {
+            current: Arc::new(RwLock::new(CurrentSocket::new(socket))),
+        }
+    }
+
+    /// Gets the current socket
+    pub fn get(&self) -> Arc<Async<UdpSocket>> {
+        self.current.read().unwrap().socket.clone()
+    }
+
+    /// Replaces the socket for everything sharing it
+    pub fn replace(&self, socket: Async<UdpSocket>) {
+        *self.current.write().unwrap() = CurrentSocket::new(socket);
+    }
+
+    /// Sends a datagram to the given address, in the form the socket's
+    /// address family takes
+    pub async fn send_to(&self, buffer: &[u8], address: SocketAddr) -> io::Result<usize> {
+        let (socket, ipv6) = self.get_with_family();
+        socket
+            .send_to(buffer, address_for_socket(address, ipv6))
+            .await
+    }
+
+    /// Sends each message to its address, like `batch::send_batch`, in the
+    /// form the socket's address family takes
+    pub async fn send_batch(
+        &self,
+        messages: &[(Vec<u8>, SocketAddr)],
+    ) -> Result<(), (SocketAddr, io::Error)> {
+        let (socket, ipv6) = self.get_with_family();
+        batch::send_batch(&socket, ipv6, messages).await
+    }
+
+    fn get_with_family(&self) -> (Arc<Async<UdpSocket>>, bool) {
+        let current = self.current.read().unwrap();
+        (current.socket.clone(), current.ipv6)
+    }
+}
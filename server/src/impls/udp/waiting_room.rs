@@ -0,0 +1,76 @@
+use std::{
+    collections::VecDeque,
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+use naia_socket_shared::clock;
+
+// Queued clients refresh their place about twice a second, so one which hasn't
+// been heard from in this long has given up
+const QUEUE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// An ordered queue of clients waiting for the Server to have room for them
+#[derive(Debug, Default)]
+pub struct WaitingRoom {
+    queue: VecDeque<WaitingClient>,
+}
+
+#[derive(Debug)]
+struct WaitingClient {
+    address: SocketAddr,
+    last_heard: Instant,
+}
+
+impl WaitingRoom {
+    /// Create a new, empty WaitingRoom
+    pub fn new() -> Self {
+        WaitingRoom {
+            queue: VecDeque::new(),
+        }
+    }
+
+    /// Gets the number of clients waiting
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Gets the index in the queue of the client with the given address, &
+    /// notes that the client is still waiting
+    pub fn refresh(&mut self, address: &SocketAddr) -> Option<usize> {
+        let index = self
+            .queue
+            .iter()
+            .position(|client| client.address == *address)?;
+        self.queue[index].last_heard = clock::now();
+        Some(index)
+    }
+
+    /// Adds a client to the back of the queue, returning its index
+    pub fn join(&mut self, address: SocketAddr) -> usize {
+        self.queue.push_back(WaitingClient {
+            address,
+            last_heard: clock::now(),
+        });
+        self.queue.len() - 1
+    }
+
+    /// Removes the client at the given index, once it has been admitted
+    pub fn remove(&mut self, index: usize) {
+        self.queue.remove(index);
+    }
+
+    /// Removes every client which has stopped refreshing its place, returning
+    /// their addresses
+    pub fn expire(&mut self) -> Vec<SocketAddr> {
+        let mut expired = Vec::new();
+        self.queue.retain(|client| {
+            if clock::elapsed(client.last_heard) > QUEUE_TIMEOUT {
+                expired.push(client.address);
+                return false;
+            }
+            true
+        });
+        expired
+    }
+}
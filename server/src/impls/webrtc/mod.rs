@@ -1,2 +1,5 @@
+pub mod packet_ref;
+pub mod send_half;
 pub mod server_socket;
 mod session;
+mod stun;
@@ -0,0 +1,74 @@
+use std::{fmt, net::SocketAddr, time::Instant};
+
+use naia_socket_shared::MessageMode;
+use webrtc_unreliable::{MessageResult, MessageType};
+
+use crate::{Packet, ServerSocketEvent, Transport};
+
+/// What `ServerSocket::receive_ref` hands back: either an event like any
+/// other, or a packet which is still in the WebRTC server's buffer
+#[derive(Debug)]
+pub enum ReceivedRef<'a> {
+    /// An event which isn't a borrowed packet
+    Event(ServerSocketEvent),
+    /// A packet borrowed from the WebRTC server's buffer
+    Packet(PacketRef<'a>),
+}
+
+/// A guard over a packet in the WebRTC server's receive buffer, which saves
+/// copying it out for those who only read it once. Nothing more can be
+/// received until it is dropped
+pub struct PacketRef<'a> {
+    message: MessageResult<'a>,
+    received_at: Instant,
+}
+
+impl<'a> PacketRef<'a> {
+    pub(crate) fn new(message: MessageResult<'a>, received_at: Instant) -> Self {
+        PacketRef {
+            message,
+            received_at,
+        }
+    }
+
+    /// Get the address the packet came from
+    pub fn address(&self) -> SocketAddr {
+        self.message.remote_addr
+    }
+
+    /// Get at the payload of the packet, in place
+    pub fn payload(&self) -> &[u8] {
+        self.message.message.as_ref()
+    }
+
+    /// Gets whether the packet came as a binary message or a text one
+    pub fn mode(&self) -> MessageMode {
+        match self.message.message_type {
+            MessageType::Binary => MessageMode::Binary,
+            MessageType::Text => MessageMode::Text,
+        }
+    }
+
+    /// Gets the moment the packet was received
+    pub fn received_at(&self) -> Instant {
+        self.received_at
+    }
+
+    /// Copies the packet out of the buffer, for keeping it past the guard
+    pub fn to_packet(&self) -> Packet {
+        Packet::from_slice(self.address(), self.payload())
+            .with_mode(self.mode())
+            .with_received(self.received_at, Transport::WebRtc)
+    }
+}
+
+impl fmt::Debug for PacketRef<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PacketRef")
+            .field("address", &self.address())
+            .field("payload", &self.payload())
+            .field("mode", &self.mode())
+            .field("received_at", &self.received_at)
+            .finish()
+    }
+}
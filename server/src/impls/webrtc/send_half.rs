@@ -0,0 +1,27 @@
+use crate::{error::NaiaServerSocketError, MessageSender, Packet};
+
+/// The sending half of a split Server Socket. The WebRTC server can't send
+/// while another task is receiving from it, so packets are queued for the
+/// receive half, which sends them as part of its loop
+#[derive(Debug)]
+pub struct SendHalf {
+    sender: MessageSender,
+}
+
+impl SendHalf {
+    pub(crate) fn new(sender: MessageSender) -> Self {
+        SendHalf { sender }
+    }
+
+    // Create a new SendHalf which queues every packet on the given sender,
+    // as every WebRTC SendHalf does
+    pub(crate) fn queued(sender: MessageSender) -> Self {
+        SendHalf::new(sender)
+    }
+
+    /// Send a Packet to a client, waiting for room if the outgoing queue is
+    /// full
+    pub async fn send(&mut self, packet: Packet) -> Result<(), NaiaServerSocketError> {
+        self.sender.send(packet).await
+    }
+}
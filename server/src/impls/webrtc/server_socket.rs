@@ -1,169 +1,1076 @@
 use std::{
-    io::Error as IoError,
+    any::Any,
+    collections::VecDeque,
+    io::{Error as IoError, ErrorKind},
     net::{IpAddr, SocketAddr, UdpSocket},
+    panic::AssertUnwindSafe,
+    str,
+    sync::{Arc, Mutex},
 };
 
-use log::debug;
+use log::warn;
 
 use async_trait::async_trait;
 
-use webrtc_unreliable::{
-    MessageResult, MessageType, SendError, Server as InnerRtcServer, SessionEndpoint,
-};
+use webrtc_unreliable::{MessageResult, MessageType, SendError, Server as InnerRtcServer};
 
 use futures_channel::mpsc;
-use futures_util::{pin_mut, select, FutureExt, StreamExt};
+use futures_util::{future, pin_mut, select, FutureExt, StreamExt};
 
-use naia_socket_shared::LinkConditionerConfig;
+use naia_socket_shared::{
+    address_for_socket, canonical_address, clock, ConditionerHandle, ConditionerStats,
+    ConnectToken, ControlMessage, LinkConditionerConfig, MessageMode, PacketDirection,
+};
 
-use super::session::start_session_server;
+use super::{
+    packet_ref::{PacketRef, ReceivedRef},
+    send_half::SendHalf,
+    session::{start_session_server, SessionGate, SharedSessionEndpoint},
+    stun,
+};
 
 use crate::{
-    error::NaiaServerSocketError, link_conditioner::LinkConditioner, message_sender::MessageSender,
-    Packet, ServerSocketTrait,
+    buffer_pool::{BufferPool, BufferPoolStats},
+    connection_id::ConnectionId,
+    connection_manager::{ConnectionManager, UserData},
+    connection_stats::ConnectionStats,
+    error::{self, NaiaServerSocketError},
+    link_conditioner::LinkConditioner,
+    message_sender::{MessageSender, SenderSource},
+    middleware::SocketMiddleware,
+    middleware_socket::MiddlewareSocket,
+    packet_tap::PacketTap,
+    port_mapping::{MappedProtocol, PortMapping},
+    send_queue::SendQueue,
+    socket_metrics::SocketMetrics,
+    MetricsReporter, Packet, PortMappingConfig, PublicAddress, QueueDirection, RecvHalf,
+    ServerSocketEvent, ServerSocketTrait, SocketBufferSizes, SocketConfig, Transport,
 };
 
+// how many timed out or failed session requests can wait to be reported
+const SIGNALING_ERROR_QUEUE_SIZE: usize = 64;
+
 /// A socket server which communicates with clients using an underlying
 /// unordered & unreliable network protocol
 #[derive(Debug)]
 pub struct ServerSocket {
     rtc_server: RtcServer,
-    to_client_sender: mpsc::UnboundedSender<Packet>,
-    to_client_receiver: mpsc::UnboundedReceiver<Packet>,
+    to_client_sender: SenderSource,
+    to_client_receiver: mpsc::Receiver<Packet>,
+    // whether the queue packets are sent from has closed, as every
+    // MessageSender has been dropped, which shuts the socket down
+    closed: bool,
+    send_queue: SendQueue,
+    // packets the send queue has shed which haven't been reported yet
+    overflowed: usize,
+    connection_manager: ConnectionManager,
+    session_gate: Arc<SessionGate>,
+    session_address: SocketAddr,
+    // the addresses of session requests which timed out
+    signaling_errors: mpsc::Receiver<NaiaServerSocketError>,
+    // the public IP address rediscovered with STUN whenever it changes
+    public_address_refresh: Option<mpsc::Receiver<IpAddr>>,
+    port_mapping_config: Option<PortMappingConfig>,
+    port_mapping: Option<PortMapping>,
+    outstanding_events: VecDeque<ServerSocketEvent>,
+    buffer_pool: BufferPool,
+    packet_tap: PacketTap,
+    metrics_reporter: Option<MetricsReporter>,
+    max_payload_size: Option<usize>,
 }
 
 impl ServerSocket {
-    /// Returns a new ServerSocket, listening at the given socket address
+    /// Returns a new ServerSocket, listening at the given socket address. If
+    /// its port is 0, the OS assigns one each to the session server & the
+    /// data channel server, see `local_addr` & `session_server_addr`, & the
+    /// latter is advertised at the public address if that has port 0 too.
+    /// The public address can also be discovered, see `PublicAddress`
     pub async fn listen(
         socket_address: SocketAddr,
-        public_address: SocketAddr,
-    ) -> Box<dyn ServerSocketTrait> {
-        let (to_client_sender, to_client_receiver) = mpsc::unbounded();
+        public_address: impl Into<PublicAddress>,
+        config: SocketConfig,
+    ) -> Result<Box<dyn ServerSocketTrait>, NaiaServerSocketError> {
+        let socket = ServerSocket::bind(socket_address, public_address, config).await?;
+        Ok(Box::new(socket))
+    }
+
+    /// Returns a new ServerSocket, listening at the given socket address,
+    /// without boxing it, so that calls to it are statically dispatched
+    pub async fn bind(
+        socket_address: SocketAddr,
+        public_address: impl Into<PublicAddress>,
+        config: SocketConfig,
+    ) -> Result<ServerSocket, NaiaServerSocketError> {
+        if config.socket_receive_buffer_size.is_some()
+            || config.socket_send_buffer_size.is_some()
+            || config.dscp.is_some()
+            || config.ecn
+        {
+            warn!("socket options can't be configured on the WebRTC transport, ignoring them");
+        }
+        if config.path_mtu_discovery {
+            warn!("path MTU discovery isn't available on the WebRTC transport, ignoring it");
+        }
+        if config.fragmentation.is_some() {
+            warn!("fragmentation isn't available on the WebRTC transport, ignoring it");
+        }
+        if config.coalesce_interval.is_some() {
+            warn!("coalescing isn't available on the WebRTC transport, ignoring it");
+        }
+        if config.reliability.is_some() {
+            warn!("reliable channels aren't available on the WebRTC transport, ignoring them");
+        }
+        if config.acknowledgement.is_some() {
+            warn!("acknowledgements aren't available on the WebRTC transport, ignoring them");
+        }
+        if config.compression.is_some() {
+            warn!("compression isn't available on the WebRTC transport, ignoring it");
+        }
+        if config.pacing.is_some() {
+            warn!("pacing isn't available on the WebRTC transport, ignoring it");
+        }
+        if config.congestion_control.is_some() {
+            warn!("congestion control isn't available on the WebRTC transport, ignoring it");
+        }
+        if config.backpressure.is_some() {
+            warn!("backpressure reporting isn't available on the WebRTC transport, ignoring it");
+        }
+        if config.quality.is_some() {
+            warn!("connection quality isn't judged on the WebRTC transport, ignoring it");
+        }
+        if config.multicast.is_some() {
+            warn!("multicast isn't available on the WebRTC transport, ignoring it");
+        }
+
+        let (to_client_sender, to_client_receiver) = mpsc::channel(config.send_queue_size);
 
-        let rtc_server = RtcServer::new(socket_address, public_address).await;
+        let public_address = public_address.into();
+        let refresh = match &public_address {
+            PublicAddress::Discover(stun_config) => stun_config
+                .refresh_interval
+                .map(|interval| (stun_config.clone(), interval)),
+            PublicAddress::Known(_) => None,
+        };
+        let mut rtc_server = RtcServer::new(socket_address, public_address).await?;
+        let session_gate = Arc::new(SessionGate::new(&config));
+        let (error_sender, signaling_errors) = mpsc::channel(SIGNALING_ERROR_QUEUE_SIZE);
+        let session_address = start_session_server(
+            socket_address,
+            rtc_server.session_endpoint(),
+            config.connect_token_key,
+            session_gate.clone(),
+            error_sender,
+        )
+        .map_err(|err| NaiaServerSocketError::from_bind_error(err, socket_address))?;
+        let port_mapping = map_ports(
+            config.port_mapping.clone(),
+            &mut rtc_server,
+            session_address,
+        )
+        .await?;
+        let public_address_refresh = refresh.map(|(stun_config, interval)| {
+            stun::refresh(rtc_server.public_address().ip(), stun_config, interval)
+        });
 
         let socket = ServerSocket {
-            rtc_server,
-            to_client_sender,
+            to_client_sender: SenderSource::new(to_client_sender),
             to_client_receiver,
+            closed: false,
+            send_queue: SendQueue::new(config.send_queue_size, config.send_queue_policy),
+            overflowed: 0,
+            connection_manager: ConnectionManager::new(),
+            packet_tap: PacketTap::new(&config, rtc_server.public_address()),
+            rtc_server,
+            session_gate,
+            session_address,
+            signaling_errors,
+            public_address_refresh,
+            port_mapping_config: config.port_mapping,
+            port_mapping,
+            outstanding_events: VecDeque::new(),
+            buffer_pool: BufferPool::new(config.buffer_pool),
+            metrics_reporter: config.metrics_reporter.clone(),
+            max_payload_size: config.max_payload_size,
         };
 
-        start_session_server(socket_address, socket.rtc_server.session_endpoint());
+        Ok(socket)
+    }
 
-        Box::new(socket)
+    /// Receives the next event, or a packet borrowed from the WebRTC server's
+    /// buffer rather than copied out of it, for high-throughput servers which
+    /// read each packet once. The first packet from a new client is copied,
+    /// as it comes after the Connection event. Packets taken from the
+    /// MessageSenders are sent before waiting, but not while, so a server
+    /// which sends other than in reply should also `flush` now & again
+    pub async fn receive_ref(&mut self) -> Result<ReceivedRef<'_>, NaiaServerSocketError> {
+        while let Ok(packet) = self.to_client_receiver.try_recv() {
+            self.queue_packet(packet);
+        }
+        let public_ip = self
+            .public_address_refresh
+            .as_mut()
+            .and_then(|refresh| refresh.try_recv().ok());
+        if let Some(public_ip) = public_ip {
+            self.readvertise(public_ip).await?;
+        }
+        while let Ok(err) = self.signaling_errors.try_recv() {
+            self.outstanding_events
+                .push_back(ServerSocketEvent::Error(err));
+        }
+        let result = self.send_queued().await;
+        self.push_transient(result)?;
+
+        #[cfg(feature = "metrics")]
+        self.publish_metrics();
+        self.report_metrics();
+        self.push_overflow_event();
+        if let Some(event) = self.outstanding_events.pop_front() {
+            self.tap_incoming(&event);
+            return Ok(ReceivedRef::Event(event));
+        }
+
+        let message = self.rtc_server.recv().await?;
+        let packet = PacketRef::new(message, clock::now());
+        let address = packet.address();
+        let size = packet.payload().len();
+        let oversized = self.max_payload_size.is_some_and(|max| size > max);
+        // only fields other than the WebRTC server's are touched from here, as
+        // the borrow of its buffer is still held
+        if let Some(connection_id) = self.connection_manager.connection_id(&address) {
+            self.connection_manager
+                .record_received(&connection_id, size);
+            if oversized {
+                self.connection_manager.metrics_mut().errors.oversized += 1;
+                return Ok(ReceivedRef::Event(ServerSocketEvent::OversizedPacket {
+                    connection_id,
+                    size,
+                }));
+            }
+            self.packet_tap
+                .tap(PacketDirection::Incoming, &address, packet.payload());
+            return Ok(ReceivedRef::Packet(packet));
+        }
+
+        let connection_id = self.connection_manager.add_connection(&address);
+        self.connection_manager
+            .record_received(&connection_id, size);
+        self.session_gate
+            .set_connection_count(self.connection_manager.connection_count());
+        if oversized {
+            self.connection_manager.metrics_mut().errors.oversized += 1;
+            self.outstanding_events
+                .push_back(ServerSocketEvent::OversizedPacket {
+                    connection_id,
+                    size,
+                });
+        } else {
+            self.outstanding_events
+                .push_back(ServerSocketEvent::Packet(packet.to_packet()));
+        }
+        Ok(ReceivedRef::Event(ServerSocketEvent::Connection(
+            connection_id,
+            address,
+        )))
+    }
+
+    // Lets the application know about a client the first time a packet comes
+    // from it
+    fn add_connection_if_new(&mut self, address: &SocketAddr) {
+        if self.connection_manager.connection_id(address).is_none() {
+            let connection_id = self.connection_manager.add_connection(address);
+            self.update_session_gate();
+            self.outstanding_events
+                .push_back(ServerSocketEvent::Connection(connection_id, *address));
+        }
+    }
+
+    // Sends everything in the send queue, most urgent first, ending the
+    // connections of clients which have gone away
+    async fn send_queued(&mut self) -> Result<(), NaiaServerSocketError> {
+        while self.send_queue.accepts_more() {
+            match self.to_client_receiver.try_recv() {
+                Ok(packet) => self.queue_packet(packet),
+                Err(_) => break,
+            }
+        }
+
+        while let Some(packet) = self.send_queue.pop() {
+            let address = packet.address();
+            if packet.is_expired() {
+                self.count_expired(&address);
+                continue;
+            }
+
+            let message_type = match message_type(&packet) {
+                Some(message_type) => message_type,
+                None => return Err(NaiaServerSocketError::InvalidText(address)),
+            };
+            match self
+                .rtc_server
+                .send(packet.payload(), message_type, &address)
+                .await
+            {
+                Err(SendError::ClientNotConnected) => {
+                    // the client has gone away, so its connection is over
+                    match self.connection_manager.connection_id(&address) {
+                        Some(connection_id) => {
+                            if let Some((_, stats)) =
+                                self.connection_manager.remove_connection(&connection_id)
+                            {
+                                self.update_session_gate();
+                                self.outstanding_events.push_back(
+                                    ServerSocketEvent::Disconnection(connection_id, address, stats),
+                                );
+                            }
+                        }
+                        None => return Err(NaiaServerSocketError::ConnectionClosed { address }),
+                    }
+                }
+                Err(_) => {
+                    trace_event!(WARN, %address, "send failed");
+                    self.connection_manager.metrics_mut().errors.send_failures += 1;
+                    return Err(NaiaServerSocketError::SendError(address));
+                }
+                Ok(_) => self.count_sent(&address, packet.payload().len()),
+            }
+        }
+        Ok(())
+    }
+
+    // Adds a packet taken from the MessageSenders to the send queue, copying
+    // broadcasts for every connection
+    fn queue_packet(&mut self, packet: Packet) {
+        if !packet.is_broadcast() {
+            self.tap_outgoing(&packet);
+            self.overflowed += self.send_queue.push(packet);
+            return;
+        }
+        for (_, address) in self.connection_manager.connections() {
+            let packet = packet.to_address(address);
+            self.tap_outgoing(&packet);
+            self.overflowed += self.send_queue.push(packet);
+        }
+    }
+
+    // Dumps & captures a packet about to be handed to the application
+    fn tap_incoming(&self, event: &ServerSocketEvent) {
+        if let ServerSocketEvent::Packet(packet) = event {
+            self.packet_tap.tap(
+                PacketDirection::Incoming,
+                &packet.address(),
+                packet.payload(),
+            );
+        }
+    }
+
+    // Dumps & captures a packet taken from the application to be sent
+    fn tap_outgoing(&self, packet: &Packet) {
+        self.packet_tap.tap(
+            PacketDirection::Outgoing,
+            &packet.address(),
+            packet.payload(),
+        );
+    }
+
+    // Gives the metrics exporter a fresh snapshot, if there is one
+    #[cfg(feature = "metrics")]
+    fn publish_metrics(&self) {
+        if let Some(exporter) = self.session_gate.metrics_exporter() {
+            exporter.publish(self.metrics());
+        }
+    }
+
+    // Reports the metrics to the application's sink, if it is due a report
+    fn report_metrics(&self) {
+        if let Some(reporter) = self.metrics_reporter.as_ref().filter(|r| r.due()) {
+            reporter.report(self.metrics(), &self.connection_manager.rtts());
+        }
+    }
+
+    // Keeps receiving across an error which only concerned a single send or
+    // datagram, surfacing it as an event, & passes fatal ones on
+    fn push_transient(
+        &mut self,
+        result: Result<(), NaiaServerSocketError>,
+    ) -> Result<(), NaiaServerSocketError> {
+        match result {
+            Err(err) if !err.is_fatal() => {
+                self.outstanding_events
+                    .push_back(ServerSocketEvent::Error(err));
+                Ok(())
+            }
+            result => result,
+        }
+    }
+
+    // Reports the packets the send queue has shed since it was last reported
+    fn push_overflow_event(&mut self) {
+        if self.overflowed > 0 {
+            self.connection_manager.metrics_mut().errors.overflowed += self.overflowed as u64;
+            self.outstanding_events
+                .push_back(ServerSocketEvent::QueueOverflow {
+                    direction: QueueDirection::Outgoing,
+                    dropped: self.overflowed,
+                });
+            self.overflowed = 0;
+        }
+    }
+
+    fn count_sent(&mut self, address: &SocketAddr, bytes: usize) {
+        if let Some(connection_id) = self.connection_manager.connection_id(address) {
+            self.connection_manager.record_sent(&connection_id, bytes);
+        }
+    }
+
+    fn count_expired(&mut self, address: &SocketAddr) {
+        if let Some(connection_id) = self.connection_manager.connection_id(address) {
+            if let Some(stats) = self.connection_manager.stats_mut(&connection_id) {
+                stats.expired_packets += 1;
+            }
+        }
+    }
+
+    // Advertises a new public IP address, which means starting the data
+    // channel server again, & its connections can't survive that
+    async fn readvertise(&mut self, public_ip: IpAddr) -> Result<(), NaiaServerSocketError> {
+        let public_address = SocketAddr::new(public_ip, self.rtc_server.public_address().port());
+        self.rtc_server.readvertise(public_address).await?;
+        self.packet_tap.set_local_address(public_address);
+        self.remove_all_connections();
+        self.outstanding_events
+            .push_back(ServerSocketEvent::PublicAddressChanged(public_address));
+        Ok(())
+    }
+
+    // Drops every connection, as happens when the data channel server goes
+    fn remove_all_connections(&mut self) {
+        for (connection_id, address, stats) in self.connection_manager.remove_all_connections() {
+            self.outstanding_events
+                .push_back(ServerSocketEvent::Disconnection(
+                    connection_id,
+                    address,
+                    stats,
+                ));
+        }
+        self.update_session_gate();
+    }
+
+    // lets the session server know whether there is room for new sessions
+    fn update_session_gate(&self) {
+        self.session_gate
+            .set_connection_count(self.connection_manager.connection_count());
+    }
+
+    // Gets the next event which is ready to be returned, if there is one
+    fn ready_event(&mut self) -> Option<ServerSocketEvent> {
+        #[cfg(feature = "metrics")]
+        self.publish_metrics();
+        self.report_metrics();
+        self.push_overflow_event();
+        if let Some(event) = self.outstanding_events.pop_front() {
+            self.tap_incoming(&event);
+            return Some(event);
+        }
+        if self.closed {
+            return Some(ServerSocketEvent::Closed);
+        }
+        None
+    }
+
+    // Gets what there is to deal with right now, without waiting for anything.
+    // The WebRTC server's recv is dropped whenever `receive` is woken by
+    // something else, so polling it once here loses nothing either
+    fn try_next(&mut self) -> Option<Next> {
+        match self.to_client_receiver.try_recv() {
+            Ok(packet) => return Some(Next::ToClientMessage(packet)),
+            Err(err) if err.is_closed() => return Some(Next::Closed),
+            Err(_) => {}
+        }
+        let public_ip = self
+            .public_address_refresh
+            .as_mut()
+            .and_then(|refresh| refresh.try_recv().ok());
+        if let Some(public_ip) = public_ip {
+            return Some(Next::PublicAddressChange(public_ip));
+        }
+        if let Ok(err) = self.signaling_errors.try_recv() {
+            return Some(Next::SignalingError(err));
+        }
+        let buffer_pool = &mut self.buffer_pool;
+        self.rtc_server
+            .recv()
+            .now_or_never()
+            .map(|result| Next::FromClientMessage(result.map(|msg| to_packet(buffer_pool, msg))))
+    }
+
+    // Deals with what the socket was woken up by, or found without waiting
+    async fn handle_next(&mut self, next: Next) -> Result<(), NaiaServerSocketError> {
+        match next {
+            Next::FromClientMessage(from_client_message) => match from_client_message {
+                Ok(packet) => {
+                    self.add_connection_if_new(&packet.address());
+                    let connection_id =
+                        match self.connection_manager.connection_id(&packet.address()) {
+                            Some(connection_id) => connection_id,
+                            None => return Ok(()),
+                        };
+                    let size = packet.payload().len();
+                    self.connection_manager
+                        .record_received(&connection_id, size);
+                    if self.max_payload_size.is_some_and(|max| size > max) {
+                        trace_event!(DEBUG, address = %packet.address(), bytes = size, "oversized packet");
+                        self.connection_manager.metrics_mut().errors.oversized += 1;
+                        self.outstanding_events
+                            .push_back(ServerSocketEvent::OversizedPacket {
+                                connection_id,
+                                size,
+                            });
+                        return Ok(());
+                    }
+                    self.outstanding_events
+                        .push_back(ServerSocketEvent::Packet(packet));
+                }
+                Err(err) if error::is_transient(&err) => {
+                    // receiving again can succeed
+                    trace_event!(DEBUG, error = %err, "transient receive error");
+                }
+                Err(err) => {
+                    return Err(NaiaServerSocketError::Io(err));
+                }
+            },
+            Next::PublicAddressChange(public_ip) => {
+                self.readvertise(public_ip).await?;
+            }
+            Next::SignalingError(err) => {
+                self.outstanding_events
+                    .push_back(ServerSocketEvent::Error(err));
+            }
+            Next::Closed => {
+                // every sender is gone, so nothing more can be sent
+                self.closed = true;
+            }
+            Next::ToClientMessage(packet) => {
+                // anything else already queued goes out along with it,
+                // most urgent first
+                self.queue_packet(packet);
+                let result = self.send_queued().await;
+                self.push_transient(result)?;
+            }
+        }
+        Ok(())
     }
 }
 
+// Something the socket has to deal with before it has an event to return
+enum Next {
+    FromClientMessage(Result<Packet, IoError>),
+    ToClientMessage(Packet),
+    PublicAddressChange(IpAddr),
+    SignalingError(NaiaServerSocketError),
+    Closed,
+}
+
+// Copies a message out of the WebRTC server's buffer into a Packet
+fn to_packet(buffer_pool: &mut BufferPool, msg: MessageResult<'_>) -> Packet {
+    let payload = buffer_pool.copy_from(msg.message.as_ref());
+    let mode = match msg.message_type {
+        MessageType::Binary => MessageMode::Binary,
+        MessageType::Text => MessageMode::Text,
+    };
+    Packet::from_bytes(msg.remote_addr, payload)
+        .with_mode(mode)
+        .with_received(clock::now(), Transport::WebRtc)
+}
+
 #[async_trait]
 impl ServerSocketTrait for ServerSocket {
-    async fn receive(&mut self) -> Result<Packet, NaiaServerSocketError> {
-        enum Next {
-            FromClientMessage(Result<Packet, IoError>),
-            ToClientMessage(Packet),
-        }
-
+    async fn receive(&mut self) -> Result<ServerSocketEvent, NaiaServerSocketError> {
         loop {
+            if let Some(event) = self.ready_event() {
+                return Ok(event);
+            }
+
             let next = {
                 let to_client_receiver_next = self.to_client_receiver.next().fuse();
                 pin_mut!(to_client_receiver_next);
 
                 let rtc_server = &mut self.rtc_server;
+                let buffer_pool = &mut self.buffer_pool;
                 let from_client_message_receiver_next = rtc_server.recv().fuse();
                 pin_mut!(from_client_message_receiver_next);
 
+                let public_address_refresh = &mut self.public_address_refresh;
+                let public_address_refresh_next = async move {
+                    match public_address_refresh {
+                        Some(refresh) => match refresh.next().await {
+                            Some(public_ip) => public_ip,
+                            None => future::pending().await,
+                        },
+                        None => future::pending().await,
+                    }
+                }
+                .fuse();
+                pin_mut!(public_address_refresh_next);
+
+                let signaling_errors = &mut self.signaling_errors;
+                let signaling_error_next = async move {
+                    match signaling_errors.next().await {
+                        Some(err) => err,
+                        // the session server has stopped
+                        None => future::pending().await,
+                    }
+                }
+                .fuse();
+                pin_mut!(signaling_error_next);
+
                 select! {
                     from_client_result = from_client_message_receiver_next => {
                         Next::FromClientMessage(
-                            match from_client_result {
-                                Ok(msg) => {
-                                    Ok(Packet::new(msg.remote_addr, msg.message.as_ref().to_vec()))
-                                }
-                                Err(err) => { Err(err) }
-                            }
+                            from_client_result.map(|msg| to_packet(buffer_pool, msg))
                         )
                     }
                     to_client_message = to_client_receiver_next => {
-                        Next::ToClientMessage(
-                            to_client_message.expect("to server message receiver closed")
-                        )
-                    }
-                }
-            };
-
-            match next {
-                Next::FromClientMessage(from_client_message) => match from_client_message {
-                    Ok(packet) => {
-                        return Ok(packet);
+                        match to_client_message {
+                            Some(packet) => Next::ToClientMessage(packet),
+                            None => Next::Closed,
+                        }
                     }
-                    Err(err) => {
-                        return Err(NaiaServerSocketError::Wrapped(Box::new(err)));
+                    public_ip = public_address_refresh_next => {
+                        Next::PublicAddressChange(public_ip)
                     }
-                },
-                Next::ToClientMessage(packet) => {
-                    let address = packet.address();
-
-                    match self
-                        .rtc_server
-                        .send(packet.payload(), MessageType::Binary, &address)
-                        .await
-                    {
-                        Err(_) => {
-                            return Err(NaiaServerSocketError::SendError(address));
-                        }
-                        _ => {}
+                    err = signaling_error_next => {
+                        Next::SignalingError(err)
                     }
                 }
+            };
+
+            self.handle_next(next).await?;
+        }
+    }
+
+    fn try_receive(&mut self) -> Result<Option<ServerSocketEvent>, NaiaServerSocketError> {
+        loop {
+            if let Some(event) = self.ready_event() {
+                return Ok(Some(event));
+            }
+            match self.try_next() {
+                // sending only waits for room in the OS buffer
+                Some(next) => async_io::block_on(self.handle_next(next))?,
+                None => return Ok(None),
             }
         }
     }
 
     fn get_sender(&mut self) -> MessageSender {
-        return MessageSender::new(self.to_client_sender.clone());
+        return self
+            .to_client_sender
+            .get()
+            .with_max_payload_size(self.max_payload_size);
     }
 
     fn with_link_conditioner(
         self: Box<Self>,
         config: &LinkConditionerConfig,
     ) -> Box<dyn ServerSocketTrait> {
-        Box::new(LinkConditioner::new(config, self))
+        Box::new(LinkConditioner::new(config, *self))
     }
-}
 
-fn get_available_port(ip: &str) -> Option<u16> {
-    (8000..9000).find(|port| port_is_available(ip, *port))
-}
+    fn with_middleware(
+        self: Box<Self>,
+        middleware: Box<dyn SocketMiddleware>,
+    ) -> Box<dyn ServerSocketTrait> {
+        let mut socket = MiddlewareSocket::new(*self);
+        socket.push(middleware);
+        Box::new(socket)
+    }
+
+    fn split(mut self: Box<Self>) -> (SendHalf, RecvHalf) {
+        let send_half = SendHalf::new(self.get_sender());
+        (send_half, RecvHalf::new(self))
+    }
+
+    async fn rebind(
+        &mut self,
+        socket_address: SocketAddr,
+        public_address: SocketAddr,
+    ) -> Result<(), NaiaServerSocketError> {
+        self.rtc_server
+            .rebind(socket_address, public_address)
+            .await?;
+        if self.port_mapping_config.is_some() {
+            // the old ports' mappings are removed before new ones are asked for
+            self.unmap_ports().await;
+            self.port_mapping = map_ports(
+                self.port_mapping_config.clone(),
+                &mut self.rtc_server,
+                self.session_address,
+            )
+            .await?;
+        }
+        self.packet_tap
+            .set_local_address(self.rtc_server.public_address());
+        // the address is known from here on
+        self.public_address_refresh = None;
+
+        // the old server's sessions went with it
+        self.remove_all_connections();
+
+        Ok(())
+    }
+
+    async fn unmap_ports(&mut self) {
+        if let Some(port_mapping) = self.port_mapping.take() {
+            port_mapping.close().await;
+        }
+    }
+
+    async fn flush(&mut self) -> Result<(), NaiaServerSocketError> {
+        while let Ok(packet) = self.to_client_receiver.try_recv() {
+            self.queue_packet(packet);
+        }
+        while let Some(packet) = self.send_queue.pop() {
+            if packet.is_expired() {
+                self.count_expired(&packet.address());
+                continue;
+            }
+            let message_type = match message_type(&packet) {
+                Some(message_type) => message_type,
+                None => return Err(NaiaServerSocketError::InvalidText(packet.address())),
+            };
+            // clients which have already gone away are found by `receive`
+            if self
+                .rtc_server
+                .send(packet.payload(), message_type, &packet.address())
+                .await
+                .is_ok()
+            {
+                self.count_sent(&packet.address(), packet.payload().len());
+            }
+        }
+        Ok(())
+    }
+
+    async fn disconnect(
+        &mut self,
+        connection_id: &ConnectionId,
+        reason: Option<&[u8]>,
+    ) -> Result<(), NaiaServerSocketError> {
+        let address = match self.connection_manager.address(connection_id) {
+            Some(address) => address,
+            None => return Ok(()),
+        };
+
+        // anything the application sent before disconnecting goes out first
+        ServerSocketTrait::flush(self).await?;
+
+        if let Some(reason) = reason.filter(|reason| !reason.is_empty()) {
+            let message = ControlMessage::Kicked(reason.to_vec()).encode();
+            let _ = self
+                .rtc_server
+                .send(message.as_bytes(), MessageType::Text, &address)
+                .await;
+        }
+        self.rtc_server.disconnect(&address).await?;
+
+        if let Some((_, stats)) = self.connection_manager.remove_connection(connection_id) {
+            self.update_session_gate();
+            self.outstanding_events
+                .push_back(ServerSocketEvent::Disconnection(
+                    *connection_id,
+                    address,
+                    stats,
+                ));
+        }
+        Ok(())
+    }
+
+    async fn announce_host_migration(
+        &mut self,
+        new_address: SocketAddr,
+    ) -> Result<(), NaiaServerSocketError> {
+        let message = ControlMessage::HostMigration(new_address).encode();
+        for (_, address) in self.connection_manager.connections() {
+            // clients which have already gone away are found by `receive`
+            let _ = self
+                .rtc_server
+                .send(message.as_bytes(), MessageType::Text, &address)
+                .await;
+        }
+        Ok(())
+    }
+
+    fn set_accepting(&mut self, accepting: bool) {
+        self.session_gate.set_accepting(accepting);
+    }
+
+    fn connections(&self) -> Vec<(ConnectionId, SocketAddr)> {
+        self.connection_manager.connections()
+    }
+
+    fn connection_id(&self, address: &SocketAddr) -> Option<ConnectionId> {
+        self.connection_manager.connection_id(address)
+    }
+
+    fn connection_address(&self, connection_id: &ConnectionId) -> Option<SocketAddr> {
+        self.connection_manager.address(connection_id)
+    }
+
+    fn connect_token(&self, connection_id: &ConnectionId) -> Option<&ConnectToken> {
+        self.connection_manager.connect_token(connection_id)
+    }
+
+    fn connect_payload(&self, connection_id: &ConnectionId) -> Option<&[u8]> {
+        self.connection_manager.connect_payload(connection_id)
+    }
 
-fn port_is_available(ip: &str, port: u16) -> bool {
-    debug!("Trying to bind to {} {}", ip, port);
+    fn connection_stats(&self, connection_id: &ConnectionId) -> Option<ConnectionStats> {
+        self.connection_manager.stats(connection_id)
+    }
+
+    fn stats(&self, address: &SocketAddr) -> Option<ConnectionStats> {
+        let connection_id = self.connection_manager.connection_id(address)?;
+        self.connection_manager.stats(&connection_id)
+    }
+
+    fn metrics(&self) -> SocketMetrics {
+        self.connection_manager.metrics().snapshot(
+            self.connection_manager.connection_count(),
+            self.send_queue.len(),
+        )
+    }
+
+    fn connection_mtu(&self, _connection_id: &ConnectionId) -> Option<usize> {
+        None
+    }
+
+    fn conditioner_handle(&self) -> Option<ConditionerHandle> {
+        None
+    }
+
+    fn conditioner_stats(&self) -> Option<ConditionerStats> {
+        None
+    }
+
+    fn buffer_pool_stats(&self) -> BufferPoolStats {
+        self.buffer_pool.stats()
+    }
+
+    fn socket_buffer_sizes(&self) -> Option<SocketBufferSizes> {
+        None
+    }
+
+    fn local_addr(&self) -> Option<SocketAddr> {
+        Some(self.rtc_server.local_address())
+    }
+
+    fn session_server_addr(&self) -> Option<SocketAddr> {
+        Some(self.session_address)
+    }
+
+    fn mapped_addr(&self) -> Option<SocketAddr> {
+        self.port_mapping
+            .as_ref()
+            .map(|port_mapping| port_mapping.external_address(1))
+    }
+
+    fn set_user_data(&mut self, connection_id: &ConnectionId, data: UserData) -> Option<UserData> {
+        self.connection_manager.set_user_data(connection_id, data)
+    }
+
+    fn user_data(&self, connection_id: &ConnectionId) -> Option<&(dyn Any + Send + Sync)> {
+        self.connection_manager.user_data(connection_id)
+    }
+
+    fn user_data_mut(
+        &mut self,
+        connection_id: &ConnectionId,
+    ) -> Option<&mut (dyn Any + Send + Sync)> {
+        self.connection_manager.user_data_mut(connection_id)
+    }
 
-    match UdpSocket::bind((ip, port)) {
-        Ok(_) => {
-            debug!("Was able to bind to {} {}", ip, port);
-            true
+    fn take_user_data(&mut self, connection_id: &ConnectionId) -> Option<UserData> {
+        self.connection_manager.take_user_data(connection_id)
+    }
+}
+
+// Starts a WebRTC server listening at the given address. It panics rather than
+// failing if its certificate can't be generated, which is caught & failed
+// with instead
+async fn new_inner_server(
+    address: SocketAddr,
+    public_address: SocketAddr,
+) -> Result<InnerRtcServer, NaiaServerSocketError> {
+    match AssertUnwindSafe(InnerRtcServer::new(address, public_address))
+        .catch_unwind()
+        .await
+    {
+        Ok(result) => result.map_err(|err| NaiaServerSocketError::from_bind_error(err, address)),
+        Err(panic) => {
+            let message = panic
+                .downcast_ref::<&str>()
+                .map(|message| message.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            Err(NaiaServerSocketError::CertError(message))
         }
-        Err(_) => false,
     }
 }
 
+// Asks the router to map the data channel server's port & the session
+// server's, & advertises the data channel at the address mapped for it, as
+// that is where clients outside the local network can reach it
+async fn map_ports(
+    config: Option<PortMappingConfig>,
+    rtc_server: &mut RtcServer,
+    session_address: SocketAddr,
+) -> Result<Option<PortMapping>, NaiaServerSocketError> {
+    let config = match config {
+        Some(config) => config,
+        None => return Ok(None),
+    };
+    let ports = vec![
+        (MappedProtocol::Udp, rtc_server.local_address()),
+        (MappedProtocol::Tcp, session_address),
+    ];
+    let port_mapping = match PortMapping::request(config, ports).await {
+        Some(port_mapping) => port_mapping,
+        None => return Ok(None),
+    };
+    let mapped_address = port_mapping.external_address(0);
+    if mapped_address != rtc_server.public_address() {
+        rtc_server.readvertise(mapped_address).await?;
+    }
+    Ok(Some(port_mapping))
+}
+
+// Picks a port for the WebRTC server if it is to listen at port 0, as it
+// doesn't tell which port it was given, & advertises that port at the public
+// address if it has port 0 too
+fn assign_port(
+    address: SocketAddr,
+    public_address: SocketAddr,
+) -> Result<(SocketAddr, SocketAddr), NaiaServerSocketError> {
+    let assigned_address = match address.port() {
+        0 => UdpSocket::bind(address)
+            .and_then(|socket| socket.local_addr())
+            .map_err(|err| NaiaServerSocketError::from_bind_error(err, address))?,
+        _ => address,
+    };
+    let public_address = match public_address.port() {
+        0 => SocketAddr::new(public_address.ip(), assigned_address.port()),
+        _ => public_address,
+    };
+    Ok((assigned_address, public_address))
+}
+
 struct RtcServer {
-    inner: InnerRtcServer,
+    // only missing while it is being started again
+    inner: Option<InnerRtcServer>,
+    session_endpoint: SharedSessionEndpoint,
+    local_address: SocketAddr,
+    public_address: SocketAddr,
 }
 
 impl RtcServer {
-    pub async fn new(address: SocketAddr, public_address: SocketAddr) -> RtcServer {
-        let inner = InnerRtcServer::new(address, public_address)
-            .await
-            .expect("could not start RTC server");
+    pub async fn new(
+        address: SocketAddr,
+        public_address: PublicAddress,
+    ) -> Result<RtcServer, NaiaServerSocketError> {
+        let (local_address, public_address) = match public_address {
+            PublicAddress::Known(public_address) => assign_port(address, public_address)?,
+            PublicAddress::Discover(stun_config) => {
+                // asked from the port clients will send to, so that a NAT
+                // maps it the same way
+                let (local_address, _) = assign_port(address, address)?;
+                let public_address = stun::discover(local_address, &stun_config)
+                    .await
+                    .map_err(NaiaServerSocketError::StunError)?;
+                (local_address, public_address)
+            }
+        };
+        let inner = new_inner_server(local_address, public_address).await?;
+
+        let session_endpoint = Arc::new(Mutex::new(inner.session_endpoint()));
+
+        return Ok(RtcServer {
+            inner: Some(inner),
+            session_endpoint,
+            local_address,
+            public_address,
+        });
+    }
+
+    // Replaces the inner server with one listening at the new address. New
+    // sessions are negotiated with it, the signaling listener itself stays
+    // where it is
+    pub async fn rebind(
+        &mut self,
+        address: SocketAddr,
+        public_address: SocketAddr,
+    ) -> Result<(), NaiaServerSocketError> {
+        let (local_address, public_address) = assign_port(address, public_address)?;
+        let inner = new_inner_server(local_address, public_address).await?;
+        *self.session_endpoint.lock().unwrap() = inner.session_endpoint();
+        self.inner = Some(inner);
+        self.local_address = local_address;
+        self.public_address = public_address;
+        Ok(())
+    }
+
+    // Starts the inner server again at the same address, advertising a new
+    // public address. The old one has to go first to free up its port, & is
+    // started again if the new one can't be
+    pub async fn readvertise(
+        &mut self,
+        public_address: SocketAddr,
+    ) -> Result<(), NaiaServerSocketError> {
+        self.inner = None;
+        let inner = match new_inner_server(self.local_address, public_address).await {
+            Ok(inner) => inner,
+            Err(err) => {
+                let inner = new_inner_server(self.local_address, self.public_address).await?;
+                *self.session_endpoint.lock().unwrap() = inner.session_endpoint();
+                self.inner = Some(inner);
+                return Err(err);
+            }
+        };
+        *self.session_endpoint.lock().unwrap() = inner.session_endpoint();
+        self.inner = Some(inner);
+        self.public_address = public_address;
+        Ok(())
+    }
+
+    pub fn local_address(&self) -> SocketAddr {
+        self.local_address
+    }
+
+    pub fn public_address(&self) -> SocketAddr {
+        self.public_address
+    }
+
+    // Clients are known by their IPv4 address, but a dual-stack server knows
+    // them by an IPv4-mapped one
+    fn inner_address(&self, remote_addr: &SocketAddr) -> SocketAddr {
+        address_for_socket(*remote_addr, self.local_address.is_ipv6())
+    }
 
-        return RtcServer { inner };
+    pub async fn disconnect(&mut self, remote_addr: &SocketAddr) -> Result<(), IoError> {
+        let remote_addr = self.inner_address(remote_addr);
+        match &mut self.inner {
+            Some(inner) => inner.disconnect(&remote_addr).await,
+            None => Err(not_started()),
+        }
     }
 
-    pub fn session_endpoint(&self) -> SessionEndpoint {
-        self.inner.session_endpoint()
+    pub fn session_endpoint(&self) -> SharedSessionEndpoint {
+        self.session_endpoint.clone()
     }
 
     pub async fn recv(&mut self) -> Result<MessageResult<'_>, IoError> {
-        self.inner.recv().await
+        let inner = self.inner.as_mut().ok_or_else(not_started)?;
+        let mut result = inner.recv().await?;
+        result.remote_addr = canonical_address(result.remote_addr);
+        Ok(result)
     }
 
     pub async fn send(
@@ -172,7 +1079,28 @@ impl RtcServer {
         message_type: MessageType,
         remote_addr: &SocketAddr,
     ) -> Result<(), SendError> {
-        self.inner.send(message, message_type, remote_addr).await
+        let remote_addr = self.inner_address(remote_addr);
+        match &mut self.inner {
+            Some(inner) => inner.send(message, message_type, &remote_addr).await,
+            None => Err(SendError::Io(not_started())),
+        }
+    }
+}
+
+// The error given while the inner server isn't running
+fn not_started() -> IoError {
+    IoError::new(ErrorKind::NotConnected, "the WebRTC server isn't running")
+}
+
+// Gets the kind of message a Packet goes over the data channel as, or None if
+// it is text which can't be sent as such
+fn message_type(packet: &Packet) -> Option<MessageType> {
+    match packet.mode() {
+        MessageMode::Binary => Some(MessageType::Binary),
+        MessageMode::Text => match str::from_utf8(packet.payload()) {
+            Ok(text) if !ControlMessage::is_reserved(text) => Some(MessageType::Text),
+            _ => None,
+        },
     }
 }
 
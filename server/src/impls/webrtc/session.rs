@@ -1,9 +1,16 @@
 use std::{
-    net::{SocketAddr, TcpListener, TcpStream},
+    io::Error as IoError,
+    net::{IpAddr, SocketAddr, TcpListener, TcpStream},
     pin::Pin,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Mutex,
+    },
     task::{Context, Poll},
+    time::Duration,
 };
 
+use futures_channel::mpsc;
 use futures_core::Stream;
 
 use async_dup::Arc;
@@ -11,28 +18,175 @@ use async_dup::Arc;
 use http::{header, HeaderValue, Response};
 
 use smol::{
+    future,
     io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines},
     prelude::*,
-    Async,
+    Async, Timer,
 };
 
 use log::info;
 
+use socket2::{Domain, Protocol, SockAddr, Socket, Type};
+
 use webrtc_unreliable::SessionEndpoint;
 
-pub fn start_session_server(socket_address: SocketAddr, session_endpoint: SessionEndpoint) {
+use naia_socket_shared::{hex, ConnectToken, ConnectTokenKey};
+
+#[cfg(feature = "metrics")]
+use crate::MetricsExporter;
+use crate::{NaiaServerSocketError, SocketConfig};
+
+/// The endpoint new sessions are negotiated with, which is swapped out when the
+/// Server Socket is rebound
+pub type SharedSessionEndpoint = std::sync::Arc<Mutex<SessionEndpoint>>;
+
+/// Lets the Server Socket decide whether new sessions are handed out
+#[derive(Debug)]
+pub struct SessionGate {
+    max_clients: Option<usize>,
+    connection_count: AtomicUsize,
+    accepting: AtomicBool,
+    health_check: bool,
+    signaling_timeout: Duration,
+    #[cfg(feature = "metrics")]
+    metrics_exporter: Option<MetricsExporter>,
+}
+
+impl SessionGate {
+    pub fn new(config: &SocketConfig) -> Self {
+        SessionGate {
+            max_clients: config.max_clients,
+            connection_count: AtomicUsize::new(0),
+            accepting: AtomicBool::new(true),
+            health_check: config.health_check,
+            signaling_timeout: config.signaling_timeout,
+            #[cfg(feature = "metrics")]
+            metrics_exporter: config.metrics_exporter.clone(),
+        }
+    }
+
+    #[cfg(feature = "metrics")]
+    pub fn metrics_exporter(&self) -> Option<&MetricsExporter> {
+        self.metrics_exporter.as_ref()
+    }
+
+    pub fn set_accepting(&self, accepting: bool) {
+        self.accepting.store(accepting, Ordering::Relaxed);
+    }
+
+    pub fn set_connection_count(&self, connection_count: usize) {
+        self.connection_count
+            .store(connection_count, Ordering::Relaxed);
+    }
+
+    // Gets the whole HTTP response to a health check request, if it is one
+    // & they are answered
+    fn health_response(&self, request_line: &str) -> Option<Vec<u8>> {
+        if !self.health_check {
+            return None;
+        }
+        let body = match get_request_path(request_line)? {
+            "/health" => "ok".to_string(),
+            "/status" => {
+                let max_clients = match self.max_clients {
+                    Some(max_clients) => max_clients.to_string(),
+                    None => "null".to_string(),
+                };
+                format!(
+                    "{{\"connections\":{},\"max_clients\":{},\"accepting\":{},\"full\":{}}}",
+                    self.connection_count.load(Ordering::Relaxed),
+                    max_clients,
+                    self.accepting.load(Ordering::Relaxed),
+                    self.refusal() == Some(REFUSAL_FULL),
+                )
+            }
+            _ => return None,
+        };
+        let content_type = if body.starts_with('{') {
+            "application/json"
+        } else {
+            "text/plain"
+        };
+        Some(
+            format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                content_type,
+                body.len(),
+                body
+            )
+            .into_bytes(),
+        )
+    }
+
+    // Gets the reason a new session would be refused, if it would be
+    fn refusal(&self) -> Option<&'static str> {
+        if !self.accepting.load(Ordering::Relaxed) {
+            return Some("not accepting new connections");
+        }
+        match self.max_clients {
+            Some(max_clients) if self.connection_count.load(Ordering::Relaxed) >= max_clients => {
+                Some(REFUSAL_FULL)
+            }
+            _ => None,
+        }
+    }
+}
+
+pub fn start_session_server(
+    socket_address: SocketAddr,
+    session_endpoint: SharedSessionEndpoint,
+    connect_token_key: Option<ConnectTokenKey>,
+    session_gate: std::sync::Arc<SessionGate>,
+    error_sender: mpsc::Sender<NaiaServerSocketError>,
+) -> Result<SocketAddr, IoError> {
+    let listener = bind_listener(socket_address)?;
+    let local_address = listener.get_ref().local_addr()?;
     smol::spawn(async move {
         listen(
-            session_endpoint.clone(),
-            Async::<TcpListener>::bind(socket_address).unwrap(),
+            session_endpoint,
+            listener,
+            connect_token_key,
+            session_gate,
+            error_sender,
         )
         .await;
     })
     .detach();
+    Ok(local_address)
+}
+
+// Binds the session server's listener at the given address, dual-stack, so
+// that IPv4 clients reach it too, if that is the unspecified IPv6 address
+fn bind_listener(address: SocketAddr) -> Result<Async<TcpListener>, IoError> {
+    let socket = Socket::new(
+        Domain::for_address(address),
+        Type::STREAM,
+        Some(Protocol::TCP),
+    )?;
+    if let IpAddr::V6(ip) = address.ip() {
+        if ip.is_unspecified() {
+            socket.set_only_v6(false)?;
+        }
+    }
+    // as std's TcpListener does, so that the port can be bound again right
+    // after the Server stops
+    #[cfg(unix)]
+    socket.set_reuse_address(true)?;
+    socket.bind(&SockAddr::from(address))?;
+    socket.listen(128)?;
+    Async::new(socket.into())
 }
 
-/// Listens for incoming connections and serves them.
-async fn listen(session_endpoint: SessionEndpoint, listener: Async<TcpListener>) {
+/// Listens for incoming connections and serves them, giving up on any which
+/// take longer than the signaling timeout, & telling the Server Socket about
+/// those & any which fail.
+async fn listen(
+    session_endpoint: SharedSessionEndpoint,
+    listener: Async<TcpListener>,
+    connect_token_key: Option<ConnectTokenKey>,
+    session_gate: std::sync::Arc<SessionGate>,
+    error_sender: mpsc::Sender<NaiaServerSocketError>,
+) {
     info!(
         "Session initiator listening on http://{}",
         listener.get_ref().local_addr().unwrap()
@@ -40,22 +194,64 @@ async fn listen(session_endpoint: SessionEndpoint, listener: Async<TcpListener>)
 
     loop {
         // Accept the next connection.
-        let (response_stream, _) = listener.accept().await.unwrap();
+        let (response_stream, remote_address) = listener.accept().await.unwrap();
 
-        let session_endpoint_clone = session_endpoint.clone();
+        let session_endpoint_clone = session_endpoint.lock().unwrap().clone();
+        let session_gate_clone = session_gate.clone();
+        let signaling_timeout = session_gate.signaling_timeout;
+        let mut error_sender_clone = error_sender.clone();
 
         // Spawn a background task serving this connection.
         smol::spawn(async move {
-            serve(session_endpoint_clone, Arc::new(response_stream)).await;
+            let serving = serve(
+                session_endpoint_clone,
+                Arc::new(response_stream),
+                remote_address,
+                connect_token_key,
+                session_gate_clone,
+                error_sender_clone.clone(),
+            );
+            // everything said while negotiating the session belongs to this
+            #[cfg(feature = "tracing")]
+            let serving = tracing::Instrument::instrument(
+                serving,
+                tracing::info_span!("signaling", remote = %remote_address),
+            );
+            let served = future::or(
+                async {
+                    serving.await;
+                    true
+                },
+                async {
+                    Timer::after(signaling_timeout).await;
+                    false
+                },
+            )
+            .await;
+            if !served {
+                // dropping the request closes its connection
+                info!("WebRTC session request from {} timed out", remote_address);
+                trace_event!(DEBUG, remote = %remote_address, "signaling timed out");
+                // only missed if the socket has fallen far behind on these
+                let _ = error_sender_clone
+                    .try_send(NaiaServerSocketError::SignalingTimeout(remote_address));
+            }
         })
         .detach();
     }
 }
 
 /// Reads a request from the client and sends it a response.
-async fn serve(mut session_endpoint: SessionEndpoint, mut stream: Arc<Async<TcpStream>>) {
-    let remote_addr = stream.get_ref().local_addr().unwrap();
+async fn serve(
+    mut session_endpoint: SessionEndpoint,
+    mut stream: Arc<Async<TcpStream>>,
+    remote_addr: SocketAddr,
+    connect_token_key: Option<ConnectTokenKey>,
+    session_gate: std::sync::Arc<SessionGate>,
+    mut error_sender: mpsc::Sender<NaiaServerSocketError>,
+) {
     let mut success: bool = false;
+    let mut authorized: bool = connect_token_key.is_none();
 
     {
         let buf_reader = BufReader::new(stream.clone());
@@ -63,7 +259,38 @@ async fn serve(mut session_endpoint: SessionEndpoint, mut stream: Arc<Async<TcpS
         {
             if let Some(line) = lines.next().await {
                 let line = line.unwrap();
+                if let Some(response) = session_gate.health_response(&line) {
+                    respond(&mut stream, remote_addr, &response).await;
+                    return;
+                }
+                #[cfg(feature = "metrics")]
+                {
+                    if let Some(exporter) = session_gate.metrics_exporter() {
+                        if line.starts_with("GET /metrics") {
+                            respond(&mut stream, remote_addr, &exporter.respond(&line)).await;
+                            return;
+                        }
+                    }
+                }
                 if line.starts_with("POST /new_rtc_session") {
+                    if let Some(key) = &connect_token_key {
+                        match connect_token_from_request_line(&line)
+                            .map(|token_bytes| ConnectToken::decode(&token_bytes, key))
+                        {
+                            Some(Ok(_)) => authorized = true,
+                            Some(Err(err)) => {
+                                info!("Refused WebRTC session from {}: {}", remote_addr, err);
+                                trace_event!(DEBUG, error = %err, "refused session: bad connect token");
+                            }
+                            None => {
+                                info!(
+                                    "Refused WebRTC session from {}: no connect token",
+                                    remote_addr
+                                );
+                                trace_event!(DEBUG, "refused session: no connect token");
+                            }
+                        }
+                    }
                     while let Some(line) = lines.next().await {
                         let line = line.unwrap();
                         if line.len() == 0 {
@@ -75,6 +302,18 @@ async fn serve(mut session_endpoint: SessionEndpoint, mut stream: Arc<Async<TcpS
             }
         }
 
+        if success && !authorized {
+            respond(&mut stream, remote_addr, RESPONSE_UNAUTHORIZED).await;
+            return;
+        }
+
+        if let Some(refusal) = session_gate.refusal().filter(|_| success) {
+            info!("Refused WebRTC session from {}: {}", remote_addr, refusal);
+            trace_event!(DEBUG, reason = refusal, "refused session");
+            respond(&mut stream, remote_addr, RESPONSE_UNAVAILABLE).await;
+            return;
+        }
+
         if success {
             success = false;
 
@@ -93,11 +332,21 @@ async fn serve(mut session_endpoint: SessionEndpoint, mut stream: Arc<Async<TcpS
                     out.extend_from_slice(resp.body().as_bytes());
 
                     info!("WebRTC session request from {}", remote_addr);
+                    trace_event!(INFO, "session negotiated");
 
                     stream.write_all(&out).await.unwrap();
                 }
                 Err(err) => {
-                    info!("error: {}", err);
+                    info!(
+                        "WebRTC session request from {} failed: {}",
+                        remote_addr, err
+                    );
+                    trace_event!(WARN, error = %err, "session negotiation failed");
+                    // only missed if the socket has fallen far behind on these
+                    let _ = error_sender.try_send(NaiaServerSocketError::Signaling(
+                        remote_addr,
+                        err.to_string(),
+                    ));
                 }
             }
         }
@@ -111,6 +360,28 @@ async fn serve(mut session_endpoint: SessionEndpoint, mut stream: Arc<Async<TcpS
     stream.close().await.unwrap();
 }
 
+// Sends a whole response & closes the connection, which the client may
+// already have reset
+async fn respond(stream: &mut Arc<Async<TcpStream>>, remote_addr: SocketAddr, response: &[u8]) {
+    if let Err(err) = write_response(stream, response).await {
+        info!(
+            "Couldn't answer WebRTC session request from {}: {}",
+            remote_addr, err
+        );
+    }
+}
+
+async fn write_response(
+    stream: &mut Arc<Async<TcpStream>>,
+    response: &[u8],
+) -> Result<(), IoError> {
+    stream.write_all(response).await?;
+    stream.flush().await?;
+    stream.close().await
+}
+
+const REFUSAL_FULL: &str = "server is full";
+
 const RESPONSE_BAD: &[u8] = br#"
 HTTP/1.1 404 NOT FOUND
 Content-Type: text/html
@@ -118,6 +389,32 @@ Content-Length: 0
 Access-Control-Allow-Origin: *
 "#;
 
+const RESPONSE_UNAUTHORIZED: &[u8] = b"HTTP/1.1 401 Unauthorized\r\nContent-Length: 0\r\n\r\n";
+
+const RESPONSE_UNAVAILABLE: &[u8] =
+    b"HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\n\r\n";
+
+// Gets the path a GET request line asks for, without any query string
+fn get_request_path(line: &str) -> Option<&str> {
+    let mut parts = line.split_whitespace();
+    if parts.next() != Some("GET") {
+        return None;
+    }
+    parts.next()?.split('?').next()
+}
+
+/// Clients pass their connect token hex-encoded in the query string, e.g.
+/// `POST /new_rtc_session?connect_token=0a1b.. HTTP/1.1`
+fn connect_token_from_request_line(line: &str) -> Option<Vec<u8>> {
+    let target = line.split_whitespace().nth(1)?;
+    let query = target.split_once('?').map(|(_, query)| query)?;
+    let token_hex = query
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("connect_token="))?;
+
+    hex::decode(token_hex)
+}
+
 struct RequestBuffer<'a, R: AsyncBufRead + Unpin> {
     buffer: &'a mut Lines<R>,
     add_newline: bool,
@@ -0,0 +1,222 @@
+use std::{
+    io::{Error as IoError, ErrorKind},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    time::Duration,
+};
+
+use futures_channel::mpsc;
+use log::warn;
+use smol::{future, net, Async, Timer};
+
+use naia_socket_shared::{address_for_socket, bind_udp, canonical_address, Random};
+
+use crate::StunConfig;
+
+const BINDING_REQUEST: u16 = 0x0001;
+const BINDING_SUCCESS: u16 = 0x0101;
+const MAGIC_COOKIE: u32 = 0x2112_A442;
+const MAPPED_ADDRESS: u16 = 0x0001;
+const XOR_MAPPED_ADDRESS: u16 = 0x0020;
+const HEADER_SIZE: usize = 20;
+
+type TransactionId = [u8; 12];
+
+/// Asks the STUN servers, in turn, which address requests sent from the
+/// given local address come from, giving the first answer. The local address
+/// must be free, as it is bound to send the requests from
+pub async fn discover(
+    local_address: SocketAddr,
+    config: &StunConfig,
+) -> Result<SocketAddr, IoError> {
+    let socket = Async::new(bind_udp(local_address)?)?;
+    let mut last_error = IoError::new(ErrorKind::InvalidInput, "no STUN servers to ask");
+    for server in &config.servers {
+        match query(&socket, local_address.is_ipv6(), server, config).await {
+            Ok(address) => return Ok(canonical_address(address)),
+            Err(err) => last_error = err,
+        }
+    }
+    Err(last_error)
+}
+
+/// Asks the STUN servers for the public address again every `interval`, from
+/// a socket of its own as the WebRTC server's is taken, so only the IP
+/// address is kept from the answers. Sends the IP address whenever it changes
+/// from the one given, & stops once the receiver is dropped
+pub fn refresh(ip: IpAddr, config: StunConfig, interval: Duration) -> mpsc::Receiver<IpAddr> {
+    let (mut sender, receiver) = mpsc::channel(1);
+    let local_ip: IpAddr = match ip {
+        IpAddr::V4(_) => Ipv4Addr::UNSPECIFIED.into(),
+        IpAddr::V6(_) => Ipv6Addr::UNSPECIFIED.into(),
+    };
+    smol::spawn(async move {
+        let mut ip = ip;
+        while !sender.is_closed() {
+            Timer::after(interval).await;
+            let address = match discover(SocketAddr::new(local_ip, 0), &config).await {
+                Ok(address) => address,
+                Err(err) => {
+                    warn!("could not refresh the public address: {}", err);
+                    continue;
+                }
+            };
+            if address.ip() != ip {
+                ip = address.ip();
+                if let Err(err) = sender.try_send(ip) {
+                    if err.is_disconnected() {
+                        return;
+                    }
+                }
+            }
+        }
+    })
+    .detach();
+    receiver
+}
+
+// Asks a single STUN server, sending the request again whenever an answer
+// doesn't come in time
+async fn query(
+    socket: &Async<std::net::UdpSocket>,
+    ipv6: bool,
+    server: &str,
+    config: &StunConfig,
+) -> Result<SocketAddr, IoError> {
+    let addresses = net::resolve(server).await?;
+    // a socket reaches servers of its own family, & IPv4 ones too if it is a
+    // dual-stack one
+    let server_address = addresses
+        .iter()
+        .find(|address| address.is_ipv6() == ipv6)
+        .or_else(|| addresses.first())
+        .copied()
+        .ok_or_else(|| IoError::new(ErrorKind::NotFound, "STUN server has no address"))?;
+
+    let transaction_id = new_transaction_id();
+    let request = binding_request(&transaction_id);
+    for _ in 0..config.attempts.max(1) {
+        socket
+            .send_to(&request, address_for_socket(server_address, ipv6))
+            .await?;
+        let answer = future::or(
+            async {
+                receive_response(socket, server_address, &transaction_id)
+                    .await
+                    .map(Some)
+            },
+            async {
+                Timer::after(config.timeout).await;
+                Ok(None)
+            },
+        )
+        .await?;
+        if let Some(address) = answer {
+            return Ok(address);
+        }
+    }
+    Err(IoError::new(
+        ErrorKind::TimedOut,
+        format!("STUN server {} didn't answer", server),
+    ))
+}
+
+// Waits for the server's answer to the request, passing over anything else
+async fn receive_response(
+    socket: &Async<std::net::UdpSocket>,
+    server_address: SocketAddr,
+    transaction_id: &TransactionId,
+) -> Result<SocketAddr, IoError> {
+    let mut buffer = [0; 512];
+    loop {
+        let (len, from) = socket.recv_from(&mut buffer).await?;
+        if canonical_address(from) != canonical_address(server_address) {
+            continue;
+        }
+        if let Some(address) = read_response(&buffer[..len], transaction_id) {
+            return Ok(address);
+        }
+    }
+}
+
+fn new_transaction_id() -> TransactionId {
+    let mut transaction_id = [0; 12];
+    transaction_id[..8].copy_from_slice(&Random::gen_u64().to_be_bytes());
+    transaction_id[8..].copy_from_slice(&Random::gen_u64().to_be_bytes()[..4]);
+    transaction_id
+}
+
+fn binding_request(transaction_id: &TransactionId) -> [u8; HEADER_SIZE] {
+    let mut request = [0; HEADER_SIZE];
+    request[..2].copy_from_slice(&BINDING_REQUEST.to_be_bytes());
+    // bytes 2..4 are the length of the attributes, of which there are none
+    request[4..8].copy_from_slice(&MAGIC_COOKIE.to_be_bytes());
+    request[8..].copy_from_slice(transaction_id);
+    request
+}
+
+// Reads the address out of a successful answer to the request, preferring
+// the XOR-MAPPED-ADDRESS, which NATs that rewrite addresses in payloads
+// leave alone, over the MAPPED-ADDRESS of older servers
+fn read_response(response: &[u8], transaction_id: &TransactionId) -> Option<SocketAddr> {
+    let header = response.get(..HEADER_SIZE)?;
+    let length = u16::from_be_bytes([header[2], header[3]]) as usize;
+    if u16::from_be_bytes([header[0], header[1]]) != BINDING_SUCCESS
+        || header[4..8] != MAGIC_COOKIE.to_be_bytes()
+        || header[8..] != transaction_id[..]
+    {
+        return None;
+    }
+
+    let mut attributes = response.get(HEADER_SIZE..HEADER_SIZE + length)?;
+    let mut mapped_address = None;
+    while attributes.len() >= 4 {
+        let kind = u16::from_be_bytes([attributes[0], attributes[1]]);
+        let len = u16::from_be_bytes([attributes[2], attributes[3]]) as usize;
+        let value = attributes.get(4..4 + len)?;
+        match kind {
+            XOR_MAPPED_ADDRESS => return read_address(value, Some(transaction_id)),
+            MAPPED_ADDRESS => mapped_address = read_address(value, None),
+            _ => {}
+        }
+        // attributes are padded to a multiple of 4 bytes
+        let padded_len = (4 + len + 3) & !3;
+        attributes = attributes.get(padded_len..).unwrap_or(&[]);
+    }
+    mapped_address
+}
+
+// Reads an address attribute, which is XORed with the magic cookie & the
+// transaction id if it is an XOR-MAPPED-ADDRESS
+fn read_address(value: &[u8], xor: Option<&TransactionId>) -> Option<SocketAddr> {
+    let header = value.get(..4)?;
+    let mut port = u16::from_be_bytes([header[2], header[3]]);
+    let mut mask = [0; 16];
+    if let Some(transaction_id) = xor {
+        port ^= (MAGIC_COOKIE >> 16) as u16;
+        mask[..4].copy_from_slice(&MAGIC_COOKIE.to_be_bytes());
+        mask[4..].copy_from_slice(transaction_id);
+    }
+
+    let ip = match header[1] {
+        0x01 => {
+            let mut octets = [0; 4];
+            octets.copy_from_slice(value.get(4..8)?);
+            octets
+                .iter_mut()
+                .zip(&mask)
+                .for_each(|(octet, mask)| *octet ^= mask);
+            IpAddr::V4(Ipv4Addr::from(octets))
+        }
+        0x02 => {
+            let mut octets = [0; 16];
+            octets.copy_from_slice(value.get(4..20)?);
+            octets
+                .iter_mut()
+                .zip(&mask)
+                .for_each(|(octet, mask)| *octet ^= mask);
+            IpAddr::V6(Ipv6Addr::from(octets))
+        }
+        _ => return None,
+    };
+    Some(SocketAddr::new(ip, port))
+}
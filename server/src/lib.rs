@@ -18,21 +18,100 @@ extern crate log;
 #[macro_use]
 extern crate cfg_if;
 
-pub use naia_socket_shared::LinkConditionerConfig;
+#[macro_use]
+mod trace_event;
+
+pub use naia_socket_shared::{
+    BurstLossConfig, ConditionerHandle, ConditionerStats, ConditionerTrace, JitterDistribution,
+    LatencySpikeConfig, LinkConditionerConfig, MiddlewareAction, PacketCapture, PacketDirection,
+    PacketDump, PortSearch, PortStrategy, ProfileError,
+};
 
+mod backpressure_config;
+mod blocking_socket;
+mod buffer_pool;
+mod channel_router;
+mod channel_sender;
+mod connection_id;
+mod connection_manager;
+mod connection_quality;
+mod connection_stats;
+mod duplicate_connection_policy;
 mod error;
 mod impls;
 mod link_conditioner;
 mod message_sender;
+#[cfg(feature = "metrics")]
+mod metrics_exporter;
+mod metrics_sink;
+mod middleware;
+mod middleware_socket;
+mod multicast_config;
+mod pacing_config;
 mod packet;
+mod packet_tap;
+mod polling_socket;
+mod port_mapping;
+mod port_mapping_config;
+#[cfg(feature = "use-webrtc")]
+mod public_address;
+mod queue_full_policy;
+mod recv_half;
+mod replay_socket;
+mod send_queue;
+mod server_socket_event;
 mod server_socket_trait;
+mod session_recorder;
+mod socket_buffer_sizes;
+mod socket_config;
+mod socket_metrics;
+mod socket_stream;
+mod transport;
 
-pub use error::NaiaServerSocketError;
-pub use impls::ServerSocket;
+pub use backpressure_config::BackpressureConfig;
+pub use blocking_socket::BlockingSocket;
+pub use buffer_pool::{BufferPoolConfig, BufferPoolStats};
+pub use channel_router::{ChannelReceiver, ChannelRouter};
+pub use channel_sender::ChannelSender;
+pub use connection_id::ConnectionId;
+pub use connection_manager::UserData;
+pub use connection_quality::{ConnectionQuality, QualityConfig, QualityThresholds};
+pub use connection_stats::ConnectionStats;
+pub use duplicate_connection_policy::DuplicateConnectionPolicy;
+pub use error::{NaiaServerSocketError, TrySendError};
+#[cfg(feature = "use-webrtc")]
+pub use impls::{PacketRef, ReceivedRef};
+pub use impls::{SendHalf, ServerSocket};
+pub use link_conditioner::{Conditioner, LinkConditioner};
 pub use message_sender::MessageSender;
-pub use naia_socket_shared::find_my_ip_address;
+#[cfg(feature = "metrics")]
+pub use metrics_exporter::MetricsExporter;
+pub use metrics_sink::{MetricsReporter, MetricsSink};
+pub use middleware::SocketMiddleware;
+pub use middleware_socket::MiddlewareSocket;
+pub use multicast_config::MulticastConfig;
+pub use naia_socket_shared::{
+    clock::VirtualClock, find_my_ip_address, AckConfig, ChannelMode, CompressionConfig,
+    ConnectToken, ConnectTokenError, ConnectTokenKey, FragmentationConfig, MessageMode, Priority,
+    ReliabilityConfig,
+};
+pub use pacing_config::PacingConfig;
 pub use packet::Packet;
+pub use polling_socket::PollingSocket;
+pub use port_mapping_config::PortMappingConfig;
+#[cfg(feature = "use-webrtc")]
+pub use public_address::{PublicAddress, StunConfig};
+pub use queue_full_policy::{QueueDirection, QueueFullPolicy};
+pub use recv_half::RecvHalf;
+pub use replay_socket::ReplaySocket;
+pub use server_socket_event::ServerSocketEvent;
 pub use server_socket_trait::ServerSocketTrait;
+pub use session_recorder::SessionRecorder;
+pub use socket_buffer_sizes::SocketBufferSizes;
+pub use socket_config::SocketConfig;
+pub use socket_metrics::{ErrorCounts, SocketMetrics};
+pub use socket_stream::SocketStream;
+pub use transport::Transport;
 
 cfg_if! {
     if #[cfg(all(feature = "use-udp", feature = "use-webrtc"))]
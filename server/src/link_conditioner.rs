@@ -1,94 +1,123 @@
-use async_io::Timer;
 use async_trait::async_trait;
-use futures_util::{pin_mut, select, FutureExt};
-use std::time::Duration;
+use std::{
+    any::Any,
+    collections::HashMap,
+    fmt,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use bytes::Bytes;
 
-use naia_socket_shared::{link_condition_logic, LinkConditionerConfig, TimeQueue};
+use naia_socket_shared::{
+    link_condition_logic::{self, ConditionedPacket, LinkState},
+    ConditionerHandle, ConditionerStats, ConnectToken, LinkConditionerConfig, MiddlewareAction,
+    PacketDirection, TimeQueue,
+};
 
 use super::{
-    error::NaiaServerSocketError, message_sender::MessageSender, packet::Packet,
-    server_socket_trait::ServerSocketTrait,
+    buffer_pool::BufferPoolStats, connection_id::ConnectionId, connection_manager::UserData,
+    connection_stats::ConnectionStats, error::NaiaServerSocketError, message_sender::MessageSender,
+    middleware::SocketMiddleware, middleware_socket::MiddlewareSocket, packet::Packet,
+    server_socket_event::ServerSocketEvent, server_socket_trait::ServerSocketTrait, RecvHalf,
+    SendHalf, SocketBufferSizes, SocketMetrics,
 };
 
-pub struct LinkConditioner {
-    config: LinkConditionerConfig,
-    inner_socket: Box<dyn ServerSocketTrait>,
-    time_queue: TimeQueue<Packet>,
+/// Wraps a Server Socket, simulating the given network conditions on the
+/// packets it receives, & optionally on those it sends. Each client has a
+/// link of its own, which can be given its own conditions through the
+/// ConditionerHandle. The conditions are simulated by Conditioner layers of
+/// middleware, so further middleware added to a LinkConditioner sees the
+/// packets which made it through. Wrapping a concrete socket type, rather
+/// than a boxed one, keeps calls to it statically dispatched
+pub struct LinkConditioner<S: ServerSocketTrait = Box<dyn ServerSocketTrait>> {
+    socket: MiddlewareSocket<S>,
+    incoming: Conditioner,
+    outgoing: Option<Conditioner>,
 }
 
-impl LinkConditioner {
-    pub fn new(config: &LinkConditionerConfig, socket: Box<dyn ServerSocketTrait>) -> Self {
+impl<S: ServerSocketTrait> LinkConditioner<S> {
+    /// Create a new LinkConditioner around the given socket
+    pub fn new(config: &LinkConditionerConfig, socket: S) -> Self {
+        LinkConditioner::from_handle(ConditionerHandle::new(config), socket)
+    }
+
+    /// Create a new LinkConditioner around the given socket, simulating the
+    /// conditions the handle is set to, whatever they are changed to
+    pub fn from_handle(handle: ConditionerHandle, socket: S) -> Self {
+        let incoming = Conditioner::new(handle, PacketDirection::Incoming);
         LinkConditioner {
-            config: config.clone(),
-            inner_socket: socket,
-            time_queue: TimeQueue::new(),
+            socket: MiddlewareSocket::new(socket).with_layer(incoming.clone()),
+            incoming,
+            outgoing: None,
         }
     }
+
+    /// Simulates the given network conditions on the packets sent through
+    /// MessageSenders got from the LinkConditioner as well, so that each
+    /// direction of the link can be in its own condition. Packets on a
+    /// channel are passed straight through, as they are by the inner socket's
+    /// reliability
+    pub fn with_outgoing(mut self, config: &LinkConditionerConfig) -> Self {
+        let outgoing = Conditioner::new(ConditionerHandle::new(config), PacketDirection::Outgoing);
+        self.socket.push(Box::new(outgoing.clone()));
+        self.outgoing = Some(outgoing);
+        self
+    }
+
+    /// Gets a handle onto the conditions simulated on received packets, which
+    /// can change them while the socket is live
+    pub fn handle(&self) -> ConditionerHandle {
+        self.incoming.handle()
+    }
+
+    /// Gets a handle onto the conditions simulated on packets being sent, if
+    /// there are any
+    pub fn outgoing_handle(&self) -> Option<ConditionerHandle> {
+        self.outgoing.as_ref().map(|outgoing| outgoing.handle())
+    }
+
+    /// Gets the counters describing what has been done to packets being
+    /// sent, if conditions are simulated on them
+    pub fn outgoing_stats(&self) -> Option<ConditionerStats> {
+        self.outgoing.as_ref().map(|outgoing| outgoing.stats())
+    }
+
+    /// Takes the socket back out of the LinkConditioner. Packets still being
+    /// delayed are dropped
+    pub fn into_inner(self) -> S {
+        self.socket.into_inner()
+    }
+}
+
+impl<S: ServerSocketTrait> fmt::Debug for LinkConditioner<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LinkConditioner")
+            .field("config", &self.incoming.handle.config())
+            .field(
+                "outgoing_config",
+                &self
+                    .outgoing
+                    .as_ref()
+                    .map(|outgoing| outgoing.handle.config()),
+            )
+            .finish()
+    }
 }
 
 #[async_trait]
-impl ServerSocketTrait for LinkConditioner {
-    async fn receive(&mut self) -> Result<Packet, NaiaServerSocketError> {
-        enum Next {
-            Event(Result<Packet, NaiaServerSocketError>),
-            BufferedEvent,
-        }
+impl<S: ServerSocketTrait + 'static> ServerSocketTrait for LinkConditioner<S> {
+    async fn receive(&mut self) -> Result<ServerSocketEvent, NaiaServerSocketError> {
+        self.socket.receive().await
+    }
 
-        loop {
-            let next = {
-                let mut queue_duration: Option<std::time::Instant> = None;
-                if self.time_queue.len() != 0 {
-                    if let Some(container) = self.time_queue.peek_entry() {
-                        queue_duration = Some(container.instant.get_inner());
-                    }
-                }
-
-                let buffered_next = {
-                    match queue_duration {
-                        Some(instant) => Timer::at(instant).fuse(),
-                        None => Timer::at(
-                            std::time::Instant::now()
-                                + Duration::from_secs_f64(60.0 * 60.0 * 24.0 * 365.0), // delay for a year because I couldn't figure out how to get a never-completing future in here
-                        )
-                        .fuse(),
-                    }
-                };
-                pin_mut!(buffered_next);
-
-                let socket_next = self.inner_socket.receive().fuse();
-                pin_mut!(socket_next);
-
-                select! {
-                    socket_result = socket_next => {
-                        Next::Event(socket_result)
-                    }
-
-                    _ = buffered_next => {
-                        Next::BufferedEvent
-                    }
-                }
-            };
-
-            match next {
-                Next::Event(result) => match result {
-                    Ok(packet) => {
-                        self.process_packet(packet);
-                    }
-                    Err(err) => {
-                        return Err(err);
-                    }
-                },
-                Next::BufferedEvent => {
-                    if let Some(packet) = self.time_queue.pop_item() {
-                        return Ok(packet);
-                    }
-                }
-            }
-        }
+    fn try_receive(&mut self) -> Result<Option<ServerSocketEvent>, NaiaServerSocketError> {
+        self.socket.try_receive()
     }
 
     fn get_sender(&mut self) -> MessageSender {
-        self.inner_socket.get_sender()
+        self.socket.get_sender()
     }
 
     fn with_link_conditioner(
@@ -97,12 +126,288 @@ impl ServerSocketTrait for LinkConditioner {
     ) -> Box<dyn ServerSocketTrait> {
         // Absolutely do not recommend decorating a socket with multiple link
         // conditioners... why would you do this??
-        Box::new(LinkConditioner::new(config, self))
+        let inner_socket: Box<dyn ServerSocketTrait> = self;
+        Box::new(LinkConditioner::new(config, inner_socket))
+    }
+
+    fn with_middleware(
+        mut self: Box<Self>,
+        middleware: Box<dyn SocketMiddleware>,
+    ) -> Box<dyn ServerSocketTrait> {
+        self.socket.push(middleware);
+        self
+    }
+
+    fn split(self: Box<Self>) -> (SendHalf, RecvHalf) {
+        // incoming packets are conditioned by the receive half, as before
+        let LinkConditioner {
+            socket, incoming, ..
+        } = *self;
+        let (send_half, recv_socket) = socket.split_layers();
+        let recv_conditioner: LinkConditioner = LinkConditioner {
+            socket: recv_socket,
+            incoming,
+            outgoing: None,
+        };
+        (send_half, RecvHalf::new(Box::new(recv_conditioner)))
+    }
+
+    async fn rebind(
+        &mut self,
+        socket_address: SocketAddr,
+        public_address: SocketAddr,
+    ) -> Result<(), NaiaServerSocketError> {
+        self.socket.rebind(socket_address, public_address).await
+    }
+
+    async fn unmap_ports(&mut self) {
+        self.socket.unmap_ports().await
+    }
+
+    async fn flush(&mut self) -> Result<(), NaiaServerSocketError> {
+        self.socket.flush().await
+    }
+
+    async fn disconnect(
+        &mut self,
+        connection_id: &ConnectionId,
+        reason: Option<&[u8]>,
+    ) -> Result<(), NaiaServerSocketError> {
+        self.socket.disconnect(connection_id, reason).await
+    }
+
+    async fn announce_host_migration(
+        &mut self,
+        new_address: SocketAddr,
+    ) -> Result<(), NaiaServerSocketError> {
+        self.socket.announce_host_migration(new_address).await
+    }
+
+    fn set_accepting(&mut self, accepting: bool) {
+        self.socket.set_accepting(accepting)
+    }
+
+    fn connections(&self) -> Vec<(ConnectionId, SocketAddr)> {
+        self.socket.connections()
+    }
+
+    fn connection_id(&self, address: &SocketAddr) -> Option<ConnectionId> {
+        self.socket.connection_id(address)
+    }
+
+    fn connection_address(&self, connection_id: &ConnectionId) -> Option<SocketAddr> {
+        self.socket.connection_address(connection_id)
+    }
+
+    fn connect_token(&self, connection_id: &ConnectionId) -> Option<&ConnectToken> {
+        self.socket.connect_token(connection_id)
+    }
+
+    fn connect_payload(&self, connection_id: &ConnectionId) -> Option<&[u8]> {
+        self.socket.connect_payload(connection_id)
+    }
+
+    fn connection_stats(&self, connection_id: &ConnectionId) -> Option<ConnectionStats> {
+        self.socket.connection_stats(connection_id)
+    }
+
+    fn stats(&self, address: &SocketAddr) -> Option<ConnectionStats> {
+        self.socket.stats(address)
+    }
+
+    fn metrics(&self) -> SocketMetrics {
+        self.socket.metrics()
+    }
+
+    fn connection_mtu(&self, connection_id: &ConnectionId) -> Option<usize> {
+        self.socket.connection_mtu(connection_id)
+    }
+
+    fn conditioner_handle(&self) -> Option<ConditionerHandle> {
+        Some(self.incoming.handle())
+    }
+
+    fn conditioner_stats(&self) -> Option<ConditionerStats> {
+        Some(self.incoming.stats())
+    }
+
+    fn buffer_pool_stats(&self) -> BufferPoolStats {
+        self.socket.buffer_pool_stats()
+    }
+
+    fn socket_buffer_sizes(&self) -> Option<SocketBufferSizes> {
+        self.socket.socket_buffer_sizes()
+    }
+
+    fn local_addr(&self) -> Option<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    fn session_server_addr(&self) -> Option<SocketAddr> {
+        self.socket.session_server_addr()
+    }
+
+    fn mapped_addr(&self) -> Option<SocketAddr> {
+        self.socket.mapped_addr()
+    }
+
+    fn set_user_data(&mut self, connection_id: &ConnectionId, data: UserData) -> Option<UserData> {
+        self.socket.set_user_data(connection_id, data)
+    }
+
+    fn user_data(&self, connection_id: &ConnectionId) -> Option<&(dyn Any + Send + Sync)> {
+        self.socket.user_data(connection_id)
+    }
+
+    fn user_data_mut(
+        &mut self,
+        connection_id: &ConnectionId,
+    ) -> Option<&mut (dyn Any + Send + Sync)> {
+        self.socket.user_data_mut(connection_id)
+    }
+
+    fn take_user_data(&mut self, connection_id: &ConnectionId) -> Option<UserData> {
+        self.socket.take_user_data(connection_id)
+    }
+}
+
+/// A layer of middleware simulating network conditions on the packets going
+/// one way through it, as a LinkConditioner does. Each client has a link of
+/// its own. Clones share the same links & counters, so a clone can be kept to
+/// read the counters from once the Conditioner has been added to a socket
+#[derive(Clone)]
+pub struct Conditioner {
+    handle: ConditionerHandle,
+    direction: PacketDirection,
+    links: Arc<Mutex<Links>>,
+}
+
+struct Links {
+    // each remote address has a link of its own
+    link_states: HashMap<SocketAddr, LinkState>,
+    stats: ConditionerStats,
+    time_queue: TimeQueue<Packet>,
+}
+
+impl Conditioner {
+    /// Create a new Conditioner, simulating the conditions the handle is set
+    /// to on the packets going the given way
+    pub fn new(handle: ConditionerHandle, direction: PacketDirection) -> Self {
+        Conditioner {
+            handle,
+            direction,
+            links: Arc::new(Mutex::new(Links {
+                link_states: HashMap::new(),
+                stats: ConditionerStats::new(),
+                time_queue: TimeQueue::new(),
+            })),
+        }
+    }
+
+    /// Gets a handle onto the conditions simulated, which can change them
+    /// while the socket is live
+    pub fn handle(&self) -> ConditionerHandle {
+        self.handle.clone()
+    }
+
+    /// Gets which way the packets conditioned are going
+    pub fn direction(&self) -> PacketDirection {
+        self.direction
+    }
+
+    /// Gets the counters describing what has been done to packets
+    pub fn stats(&self) -> ConditionerStats {
+        let links = self.links.lock().unwrap();
+        links.stats.with_queue_depth(links.time_queue.len())
+    }
+
+    // Conditions a packet, handing it straight back if conditioning is off
+    // for its address
+    fn condition(&self, packet: &Packet) -> MiddlewareAction {
+        let address = packet.address();
+        let config = match self.handle.config_for(&address) {
+            Some(config) => config,
+            None => return MiddlewareAction::Keep,
+        };
+        let links = &mut *self.links.lock().unwrap();
+        link_condition_logic::process_packet(
+            &config,
+            links.link_states.entry(address).or_default(),
+            &mut links.stats,
+            &mut links.time_queue,
+            packet.clone(),
+        );
+        MiddlewareAction::Drop
+    }
+
+    fn release(&self, direction: PacketDirection) -> Option<Packet> {
+        if direction != self.direction {
+            return None;
+        }
+        self.links.lock().unwrap().time_queue.pop_item()
+    }
+}
+
+impl SocketMiddleware for Conditioner {
+    fn on_inbound(&mut self, packet: &Packet) -> MiddlewareAction {
+        // packets on reliable channels have already made it through, &
+        // conditioning them would break the channel's guarantees
+        if self.direction != PacketDirection::Incoming || packet.is_reliable() {
+            return MiddlewareAction::Keep;
+        }
+        self.condition(packet)
+    }
+
+    fn on_outbound(&mut self, packet: &Packet) -> MiddlewareAction {
+        // packets on a channel are passed straight through, as they are by
+        // the inner socket's reliability
+        if self.direction != PacketDirection::Outgoing || packet.channel().is_some() {
+            return MiddlewareAction::Keep;
+        }
+        self.condition(packet)
+    }
+
+    fn release_inbound(&mut self) -> Option<Packet> {
+        self.release(PacketDirection::Incoming)
+    }
+
+    fn release_outbound(&mut self) -> Option<Packet> {
+        self.release(PacketDirection::Outgoing)
+    }
+
+    fn next_release(&self) -> Option<Instant> {
+        self.links
+            .lock()
+            .unwrap()
+            .time_queue
+            .peek_entry()
+            .map(|container| container.instant.get_inner())
+    }
+
+    fn handles_outbound(&self) -> bool {
+        self.direction == PacketDirection::Outgoing
+    }
+
+    fn on_disconnection(&mut self, address: &SocketAddr) {
+        self.links.lock().unwrap().link_states.remove(address);
     }
 }
 
-impl LinkConditioner {
-    fn process_packet(&mut self, packet: Packet) {
-        link_condition_logic::process_packet(&self.config, &mut self.time_queue, packet);
+impl fmt::Debug for Conditioner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Conditioner")
+            .field("direction", &self.direction)
+            .field("config", &self.handle.config())
+            .finish()
+    }
+}
+
+impl ConditionedPacket for Packet {
+    fn payload(&self) -> &[u8] {
+        Packet::payload(self)
+    }
+
+    fn with_payload(self, payload: Vec<u8>) -> Self {
+        Packet::with_payload(self, Bytes::from(payload))
     }
 }
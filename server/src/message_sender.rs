@@ -1,30 +1,191 @@
-use std::error::Error;
+use std::{
+    net::SocketAddr,
+    pin::Pin,
+    sync::{Arc, Weak},
+    task::{Context, Poll},
+};
 
-use crate::Packet;
+use bytes::Bytes;
+use futures_channel::mpsc;
+use futures_util::{Sink, SinkExt};
 
-use futures_channel;
+use crate::{NaiaServerSocketError, Packet, TrySendError};
 
 /// Handles sending messages to a Client that has established a connection with
 /// the Server socket
 #[derive(Debug)]
 pub struct MessageSender {
-    internal: futures_channel::mpsc::UnboundedSender<Packet>,
+    internal: mpsc::Sender<Packet>,
+    max_payload_size: Option<usize>,
+    // lets the SenderSource this came from hand out more while it lives
+    _source: Option<Arc<mpsc::Sender<Packet>>>,
 }
 
 impl MessageSender {
     /// Create a new MessageSender, given a reference to a async channel
     /// connected to the RtcServer
-    pub fn new(sender: futures_channel::mpsc::UnboundedSender<Packet>) -> MessageSender {
-        MessageSender { internal: sender }
+    pub fn new(sender: mpsc::Sender<Packet>) -> MessageSender {
+        MessageSender {
+            internal: sender,
+            max_payload_size: None,
+            _source: None,
+        }
+    }
+
+    // Refuses packets with a payload larger than the given size from then on
+    pub(crate) fn with_max_payload_size(mut self, max_payload_size: Option<usize>) -> Self {
+        self.max_payload_size = max_payload_size;
+        self
+    }
+
+    // Gets the largest payload which isn't refused
+    pub(crate) fn max_payload_size(&self) -> Option<usize> {
+        self.max_payload_size
+    }
+
+    // Refuses a Packet whose payload is too large to be sent
+    pub(crate) fn check_payload_size(&self, packet: &Packet) -> Result<(), NaiaServerSocketError> {
+        match self.max_payload_size {
+            Some(max_payload_size) if packet.payload().len() > max_payload_size => {
+                Err(NaiaServerSocketError::PayloadTooLarge {
+                    size: packet.payload().len(),
+                    max_payload_size,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Send a Packet to a client, waiting for room if the Server Socket's
+    /// outgoing queue is full. The queue is drained by
+    /// `ServerSocketTrait::receive`, so awaiting this on the task which calls
+    /// `receive` will wait forever once the queue fills up
+    pub async fn send(&mut self, packet: Packet) -> Result<(), NaiaServerSocketError> {
+        self.check_payload_size(&packet)?;
+        self.internal.send(packet).await.map_err(send_error)
     }
 
-    /// Send a Packet to a client
-    pub fn send(&mut self, packet: Packet) -> Result<(), Box<dyn Error + Send>> {
-        match self.internal.unbounded_send(packet) {
+    /// Send a payload to every client connected when the Server Socket gets
+    /// to it, sharing its buffer between all of them, & waiting for room like
+    /// `send`
+    pub async fn broadcast(
+        &mut self,
+        payload: impl Into<Bytes>,
+    ) -> Result<(), NaiaServerSocketError> {
+        self.send(Packet::broadcast(payload.into())).await
+    }
+
+    /// Send a payload to each of the given clients, sharing its buffer between
+    /// all of them, & waiting for room like `send`
+    pub async fn send_to_many(
+        &mut self,
+        addresses: &[SocketAddr],
+        payload: impl Into<Bytes>,
+    ) -> Result<(), NaiaServerSocketError> {
+        let payload = payload.into();
+        for address in addresses {
+            self.send(Packet::from_bytes(*address, payload.clone()))
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Queue a Packet to be sent to a client without waiting, handing the
+    /// Packet back if the Server Socket's outgoing queue is full
+    pub fn try_send(&mut self, packet: Packet) -> Result<(), TrySendError> {
+        if let Some(max_payload_size) = self.max_payload_size {
+            if packet.payload().len() > max_payload_size {
+                return Err(TrySendError::TooLarge {
+                    packet: Box::new(packet),
+                    max_payload_size,
+                });
+            }
+        }
+        match self.internal.try_send(packet) {
             Ok(content) => Ok(content),
             Err(error) => {
-                return Err(Box::new(error));
+                if error.is_full() {
+                    return Err(TrySendError::Full(Box::new(error.into_inner())));
+                }
+                return Err(TrySendError::Disconnected(Box::new(error.into_inner())));
+            }
+        }
+    }
+}
+
+/// Lets a MessageSender be used with `send_all`, `forward` & other Sink
+/// combinators. Like `send`, it waits for room if the outgoing queue is full
+impl Sink<Packet> for MessageSender {
+    type Error = NaiaServerSocketError;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.internal)
+            .poll_ready(cx)
+            .map_err(send_error)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, packet: Packet) -> Result<(), Self::Error> {
+        self.check_payload_size(&packet)?;
+        Pin::new(&mut self.internal)
+            .start_send(packet)
+            .map_err(send_error)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.internal)
+            .poll_flush(cx)
+            .map_err(send_error)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.internal)
+            .poll_close(cx)
+            .map_err(send_error)
+    }
+}
+
+// Describes why the outgoing queue wouldn't take a Packet
+fn send_error(error: mpsc::SendError) -> NaiaServerSocketError {
+    if error.is_full() {
+        return NaiaServerSocketError::SendQueueFull;
+    }
+    NaiaServerSocketError::Closed
+}
+
+// Hands out MessageSenders for a socket's outgoing queue without keeping one
+// of its own, so the queue closes once every MessageSender has been dropped
+#[derive(Debug)]
+pub(crate) struct SenderSource {
+    // the queue's sender, until the first MessageSender is handed out
+    unclaimed: Option<mpsc::Sender<Packet>>,
+    // shared by every MessageSender handed out, while any are left
+    claimed: Weak<mpsc::Sender<Packet>>,
+}
+
+impl SenderSource {
+    pub(crate) fn new(sender: mpsc::Sender<Packet>) -> Self {
+        SenderSource {
+            unclaimed: Some(sender),
+            claimed: Weak::new(),
+        }
+    }
+
+    // Gets a MessageSender for the queue. Once every MessageSender handed out
+    // has been dropped the queue has closed, & later ones refuse every Packet
+    pub(crate) fn get(&mut self) -> MessageSender {
+        let source = match self.claimed.upgrade() {
+            Some(source) => source,
+            None => {
+                let sender = self.unclaimed.take().unwrap_or_else(|| mpsc::channel(0).0);
+                let source = Arc::new(sender);
+                self.claimed = Arc::downgrade(&source);
+                source
             }
+        };
+        MessageSender {
+            internal: source.as_ref().clone(),
+            max_payload_size: None,
+            _source: Some(source),
         }
     }
 }
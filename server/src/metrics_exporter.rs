@@ -0,0 +1,216 @@
+use std::{
+    fmt::Write as FmtWrite,
+    io,
+    net::{SocketAddr, TcpListener},
+    sync::{Arc, Mutex},
+};
+
+use async_io::Async;
+use futures_util::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    StreamExt,
+};
+use log::info;
+
+use crate::SocketMetrics;
+
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// Exposes a Server Socket's `SocketMetrics` in the Prometheus text format,
+/// so that they can be scraped without any glue. Set it as the
+/// `metrics_exporter` of the SocketConfig & the socket publishes a fresh
+/// snapshot each time it is polled. The WebRTC transport then answers
+/// `GET /metrics` on its session server, otherwise `serve` can be spawned to
+/// answer it on an address of its own. Clones share the same snapshot
+#[derive(Debug, Clone, Default)]
+pub struct MetricsExporter {
+    latest: Arc<Mutex<SocketMetrics>>,
+}
+
+impl MetricsExporter {
+    /// Create a new MetricsExporter, with every metric at zero until the
+    /// first snapshot is published
+    pub fn new() -> Self {
+        MetricsExporter::default()
+    }
+
+    /// Replaces the snapshot which is exported
+    pub fn publish(&self, metrics: SocketMetrics) {
+        *self.latest.lock().unwrap() = metrics;
+    }
+
+    /// Gets the snapshot which is exported
+    pub fn latest(&self) -> SocketMetrics {
+        *self.latest.lock().unwrap()
+    }
+
+    /// Renders the latest snapshot in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let metrics = self.latest();
+        let mut out = String::new();
+        let errors = metrics.errors;
+
+        gauge(
+            &mut out,
+            "active_connections",
+            "Clients currently connected",
+            metrics.active_connections as u64,
+        );
+        counter(
+            &mut out,
+            "packets_sent_total",
+            "Packets sent to connected clients",
+            metrics.packets_sent,
+        );
+        counter(
+            &mut out,
+            "bytes_sent_total",
+            "Bytes sent to connected clients",
+            metrics.bytes_sent,
+        );
+        counter(
+            &mut out,
+            "packets_received_total",
+            "Packets received from connected clients",
+            metrics.packets_received,
+        );
+        counter(
+            &mut out,
+            "bytes_received_total",
+            "Bytes received from connected clients",
+            metrics.bytes_received,
+        );
+        gauge(
+            &mut out,
+            "bytes_sent_per_second",
+            "Bytes sent over the last full second",
+            metrics.bytes_sent_per_second,
+        );
+        gauge(
+            &mut out,
+            "bytes_received_per_second",
+            "Bytes received over the last full second",
+            metrics.bytes_received_per_second,
+        );
+        gauge(
+            &mut out,
+            "send_queue_depth",
+            "Packets waiting in the send queue",
+            metrics.send_queue_depth as u64,
+        );
+        counter(
+            &mut out,
+            "handshakes_total",
+            "Handshakes completed",
+            metrics.handshakes,
+        );
+        gauge(
+            &mut out,
+            "slow_consumers",
+            "Clients currently reported as slow consumers",
+            metrics.slow_consumers as u64,
+        );
+
+        header(
+            &mut out,
+            "errors_total",
+            "Things which have gone wrong, by kind",
+            "counter",
+        );
+        for (kind, count) in [
+            ("malformed", errors.malformed),
+            ("refused_handshake", errors.refused_handshakes),
+            ("forged", errors.forged),
+            ("send_failure", errors.send_failures),
+            ("overflowed", errors.overflowed),
+            ("paced_out", errors.paced_out),
+            ("oversized", errors.oversized),
+            ("unknown_source", errors.unknown_source),
+            ("unreachable", errors.unreachable),
+        ]
+        .iter()
+        {
+            let _ = writeln!(
+                out,
+                "naia_socket_errors_total{{kind=\"{}\"}} {}",
+                kind, count
+            );
+        }
+
+        out
+    }
+
+    /// Answers `GET /metrics` with the latest snapshot on the given address,
+    /// until listening fails. Requests are answered one at a time, which is
+    /// plenty for a scraper
+    pub async fn serve(&self, address: SocketAddr) -> io::Result<()> {
+        let listener = Async::<TcpListener>::bind(address)?;
+        info!(
+            "Metrics exporter listening on http://{}/metrics",
+            listener.get_ref().local_addr()?
+        );
+
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let mut lines = BufReader::new(&stream).lines();
+            let request_line = match lines.next().await {
+                Some(Ok(line)) => line,
+                _ => continue,
+            };
+            // the rest of the request says nothing which changes the answer
+            while let Some(Ok(line)) = lines.next().await {
+                if line.is_empty() {
+                    break;
+                }
+            }
+            let response = self.respond(&request_line);
+            let mut stream = &stream;
+            let _ = stream.write_all(&response).await;
+            let _ = stream.flush().await;
+        }
+    }
+
+    // Gets the whole HTTP response to a request, the metrics if they were
+    // asked for, or a 404 otherwise
+    pub(crate) fn respond(&self, request_line: &str) -> Vec<u8> {
+        if !is_metrics_request(request_line) {
+            return b"HTTP/1.1 404 NOT FOUND\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                .to_vec();
+        }
+        let body = self.render();
+        format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            CONTENT_TYPE,
+            body.len(),
+            body
+        )
+        .into_bytes()
+    }
+}
+
+// Whether a request line asks for the metrics, ignoring any query string
+fn is_metrics_request(request_line: &str) -> bool {
+    let mut parts = request_line.split_whitespace();
+    if parts.next() != Some("GET") {
+        return false;
+    }
+    match parts.next() {
+        Some(target) => target.split('?').next() == Some("/metrics"),
+        None => false,
+    }
+}
+
+fn header(out: &mut String, name: &str, help: &str, kind: &str) {
+    let _ = writeln!(out, "# HELP naia_socket_{} {}", name, help);
+    let _ = writeln!(out, "# TYPE naia_socket_{} {}", name, kind);
+}
+
+fn counter(out: &mut String, name: &str, help: &str, value: u64) {
+    header(out, name, help, "counter");
+    let _ = writeln!(out, "naia_socket_{} {}", name, value);
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, value: u64) {
+    header(out, name, help, "gauge");
+    let _ = writeln!(out, "naia_socket_{} {}", name, value);
+}
@@ -0,0 +1,169 @@
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use naia_socket_shared::clock;
+
+use crate::SocketMetrics;
+
+/// Receives a Server Socket's metrics, to pipe them into whatever telemetry
+/// is already in use, such as StatsD or OpenTelemetry. Counters are given the
+/// amount they went up by since they were last reported, gauges their
+/// current value, & histograms one sample each. Names are bare, like
+/// `packets_sent`, for the sink to put its own prefix on. Set one as the
+/// `metrics_reporter` of the SocketConfig through a `MetricsReporter`
+pub trait MetricsSink: Send + Sync {
+    /// Adds to a counter: `packets_sent`, `bytes_sent`, `packets_received`,
+    /// `bytes_received`, `handshakes`, or `errors` with the kind of error
+    /// after a dot, like `errors.malformed`
+    fn counter(&self, name: &str, increment: u64);
+    /// Sets a gauge: `active_connections`, `send_queue_depth`,
+    /// `slow_consumers`, `bytes_sent_per_second` or
+    /// `bytes_received_per_second`
+    fn gauge(&self, name: &str, value: f64);
+    /// Records a sample in a histogram: `rtt_seconds`, once for every
+    /// connection whose round trip time is known
+    fn histogram(&self, name: &str, value: f64);
+}
+
+/// Reports a Server Socket's metrics to a `MetricsSink` each time the socket
+/// is polled, as long as at least `interval` has passed since the last
+/// report. Clones share the same sink & keep reporting from where each other
+/// left off
+#[derive(Clone)]
+pub struct MetricsReporter {
+    sink: Arc<dyn MetricsSink>,
+    interval: Duration,
+    state: Arc<Mutex<ReportState>>,
+}
+
+#[derive(Default)]
+struct ReportState {
+    last_report: Option<Instant>,
+    // the snapshot last reported, which counters go up from
+    last: SocketMetrics,
+}
+
+impl MetricsReporter {
+    /// Create a new MetricsReporter, which reports to the given sink at most
+    /// once every `interval`
+    pub fn new(sink: impl MetricsSink + 'static, interval: Duration) -> Self {
+        MetricsReporter {
+            sink: Arc::new(sink),
+            interval,
+            state: Arc::new(Mutex::new(ReportState::default())),
+        }
+    }
+
+    /// Gets how often metrics are reported
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    // Gets whether it is time to report again
+    pub(crate) fn due(&self) -> bool {
+        self.state
+            .lock()
+            .unwrap()
+            .last_report
+            .is_none_or(|last_report| clock::elapsed(last_report) >= self.interval)
+    }
+
+    // Reports a snapshot, along with the round trip times of the connections
+    pub(crate) fn report(&self, metrics: SocketMetrics, rtts: &[Duration]) {
+        let mut state = self.state.lock().unwrap();
+        let last = state.last;
+        let sink = &*self.sink;
+
+        let counters = [
+            ("packets_sent", metrics.packets_sent, last.packets_sent),
+            ("bytes_sent", metrics.bytes_sent, last.bytes_sent),
+            (
+                "packets_received",
+                metrics.packets_received,
+                last.packets_received,
+            ),
+            (
+                "bytes_received",
+                metrics.bytes_received,
+                last.bytes_received,
+            ),
+            ("handshakes", metrics.handshakes, last.handshakes),
+            (
+                "errors.malformed",
+                metrics.errors.malformed,
+                last.errors.malformed,
+            ),
+            (
+                "errors.refused_handshake",
+                metrics.errors.refused_handshakes,
+                last.errors.refused_handshakes,
+            ),
+            ("errors.forged", metrics.errors.forged, last.errors.forged),
+            (
+                "errors.send_failure",
+                metrics.errors.send_failures,
+                last.errors.send_failures,
+            ),
+            (
+                "errors.overflowed",
+                metrics.errors.overflowed,
+                last.errors.overflowed,
+            ),
+            (
+                "errors.paced_out",
+                metrics.errors.paced_out,
+                last.errors.paced_out,
+            ),
+            (
+                "errors.oversized",
+                metrics.errors.oversized,
+                last.errors.oversized,
+            ),
+            (
+                "errors.unknown_source",
+                metrics.errors.unknown_source,
+                last.errors.unknown_source,
+            ),
+            (
+                "errors.unreachable",
+                metrics.errors.unreachable,
+                last.errors.unreachable,
+            ),
+        ];
+        for (name, value, last_value) in counters.iter() {
+            let increment = value.saturating_sub(*last_value);
+            if increment > 0 {
+                sink.counter(name, increment);
+            }
+        }
+
+        sink.gauge("active_connections", metrics.active_connections as f64);
+        sink.gauge("send_queue_depth", metrics.send_queue_depth as f64);
+        sink.gauge("slow_consumers", metrics.slow_consumers as f64);
+        sink.gauge(
+            "bytes_sent_per_second",
+            metrics.bytes_sent_per_second as f64,
+        );
+        sink.gauge(
+            "bytes_received_per_second",
+            metrics.bytes_received_per_second as f64,
+        );
+        for rtt in rtts {
+            sink.histogram("rtt_seconds", rtt.as_secs_f64());
+        }
+
+        state.last = metrics;
+        state.last_report = Some(clock::now());
+    }
+}
+
+impl fmt::Debug for MetricsReporter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MetricsReporter")
+            .field("interval", &self.interval)
+            .finish()
+    }
+}
@@ -0,0 +1,53 @@
+use std::{net::SocketAddr, time::Instant};
+
+use naia_socket_shared::MiddlewareAction;
+
+use crate::Packet;
+
+/// A layer which the packets a Server Socket receives & sends pass through,
+/// to be logged, filtered or transformed on their way. Layers are stacked
+/// with `ServerSocketTrait::with_middleware`, each added further from the
+/// socket than the last, so received packets pass through them in the order
+/// they were added & packets being sent in the reverse order. The
+/// LinkConditioner is made of such layers
+pub trait SocketMiddleware: Send + Sync {
+    /// Decides what happens to a packet received from a client, before the
+    /// layers added after this one see it
+    fn on_inbound(&mut self, _packet: &Packet) -> MiddlewareAction {
+        MiddlewareAction::Keep
+    }
+
+    /// Decides what happens to a packet being sent to a client, before the
+    /// layers added before this one see it
+    fn on_outbound(&mut self, _packet: &Packet) -> MiddlewareAction {
+        MiddlewareAction::Keep
+    }
+
+    /// Hands back a received packet this layer held back, once it is due to
+    /// carry on to the layers added after this one
+    fn release_inbound(&mut self) -> Option<Packet> {
+        None
+    }
+
+    /// Hands back a packet being sent which this layer held back, once it is
+    /// due to carry on to the layers added before this one
+    fn release_outbound(&mut self) -> Option<Packet> {
+        None
+    }
+
+    /// Gets the moment the next packet held back is due to be released, if
+    /// there are any, so that the socket wakes up to release it
+    fn next_release(&self) -> Option<Instant> {
+        None
+    }
+
+    /// Whether packets being sent should pass through this layer at all.
+    /// While no layer wants them, they skip the hop through the middleware
+    fn handles_outbound(&self) -> bool {
+        true
+    }
+
+    /// Called when the connection with the given address is closed, so that
+    /// anything kept for it can be let go of
+    fn on_disconnection(&mut self, _address: &SocketAddr) {}
+}
@@ -0,0 +1,488 @@
+use async_io::Timer;
+use async_trait::async_trait;
+use futures_channel::mpsc;
+use futures_util::{future, pin_mut, select, FutureExt, StreamExt};
+use log::{info, warn};
+use std::{any::Any, fmt, net::SocketAddr};
+
+use naia_socket_shared::{
+    ConditionerHandle, ConditionerStats, ConnectToken, LinkConditionerConfig, MiddlewareAction,
+};
+
+use super::{
+    buffer_pool::BufferPoolStats,
+    connection_id::ConnectionId,
+    connection_manager::UserData,
+    connection_stats::ConnectionStats,
+    error::NaiaServerSocketError,
+    link_conditioner::LinkConditioner,
+    message_sender::{MessageSender, SenderSource},
+    middleware::SocketMiddleware,
+    packet::Packet,
+    server_socket_event::ServerSocketEvent,
+    server_socket_trait::ServerSocketTrait,
+    RecvHalf, SendHalf, SocketBufferSizes, SocketMetrics,
+};
+
+const OUTGOING_QUEUE_SIZE: usize = 1024;
+
+/// Wraps a Server Socket, passing the packets it receives & sends through a
+/// stack of SocketMiddleware layers. Layers only see the packets sent through
+/// MessageSenders got from the MiddlewareSocket once they were added, & a
+/// split socket only passes received packets through them. Wrapping a
+/// concrete socket type, rather than a boxed one, keeps calls to it
+/// statically dispatched
+pub struct MiddlewareSocket<S: ServerSocketTrait = Box<dyn ServerSocketTrait>> {
+    inner_socket: S,
+    layers: Vec<Box<dyn SocketMiddleware>>,
+    outgoing: Option<Outgoing>,
+}
+
+// Packets being sent, which are taken from the MiddlewareSocket's own
+// MessageSenders & handed on to the inner socket's once they have passed
+// through the layers. Once every one of its own MessageSenders is gone the
+// inner socket's is dropped too, so the inner socket closes
+struct Outgoing {
+    sender: SenderSource,
+    receiver: mpsc::Receiver<Packet>,
+    inner_sender: MessageSender,
+}
+
+impl<S: ServerSocketTrait> MiddlewareSocket<S> {
+    /// Create a new MiddlewareSocket around the given socket, with no layers
+    pub fn new(socket: S) -> Self {
+        MiddlewareSocket {
+            inner_socket: socket,
+            layers: Vec::new(),
+            outgoing: None,
+        }
+    }
+
+    /// Adds a layer, further from the socket than those already added
+    pub fn push(&mut self, middleware: Box<dyn SocketMiddleware>) {
+        self.layers.push(middleware);
+    }
+
+    /// Adds a layer like `push`, returning the MiddlewareSocket
+    pub fn with_layer(mut self, middleware: impl SocketMiddleware + 'static) -> Self {
+        self.push(Box::new(middleware));
+        self
+    }
+
+    /// Gets the number of layers stacked on the socket
+    pub fn layers(&self) -> usize {
+        self.layers.len()
+    }
+
+    /// Takes the socket back out of the MiddlewareSocket. Packets still held
+    /// back by its layers are dropped
+    pub fn into_inner(self) -> S {
+        self.inner_socket
+    }
+
+    // Passes a received packet through the layers from the given one on,
+    // handing it back if none of them dropped it
+    fn inbound(&mut self, mut packet: Packet, from: usize) -> Option<Packet> {
+        for layer in self.layers[from..].iter_mut() {
+            match layer.on_inbound(&packet) {
+                MiddlewareAction::Keep => {}
+                MiddlewareAction::Drop => return None,
+                MiddlewareAction::Rewrite(payload) => packet = packet.with_payload(payload),
+            }
+        }
+        Some(packet)
+    }
+
+    // Passes a packet being sent through the layers below the given one,
+    // handing it back if none of them dropped it
+    fn outbound(&mut self, mut packet: Packet, below: usize) -> Option<Packet> {
+        for layer in self.layers[..below].iter_mut().rev() {
+            if !layer.handles_outbound() {
+                continue;
+            }
+            match layer.on_outbound(&packet) {
+                MiddlewareAction::Keep => {}
+                MiddlewareAction::Drop => return None,
+                MiddlewareAction::Rewrite(payload) => packet = packet.with_payload(payload),
+            }
+        }
+        Some(packet)
+    }
+
+    // Gets the next received packet a layer has released, once it has passed
+    // through the layers after it
+    fn release_inbound(&mut self) -> Option<Packet> {
+        for index in 0..self.layers.len() {
+            while let Some(packet) = self.layers[index].release_inbound() {
+                if let Some(packet) = self.inbound(packet, index + 1) {
+                    return Some(packet);
+                }
+            }
+        }
+        None
+    }
+
+    // Hands on every packet being sent which the layers have released, once
+    // they have passed through the layers before them
+    fn send_released(&mut self) {
+        for index in (0..self.layers.len()).rev() {
+            while let Some(packet) = self.layers[index].release_outbound() {
+                if let Some(packet) = self.outbound(packet, index) {
+                    self.send_inner(packet);
+                }
+            }
+        }
+    }
+
+    // Passes a packet taken from the MiddlewareSocket's MessageSenders
+    // through every layer, & hands it on if none of them dropped it
+    fn send_outgoing(&mut self, packet: Packet) {
+        let below = self.layers.len();
+        if let Some(packet) = self.outbound(packet, below) {
+            self.send_inner(packet);
+        }
+    }
+
+    // Passes every packet waiting in the MiddlewareSocket's own queue through
+    // the layers, without waiting for more
+    fn send_waiting(&mut self) {
+        let mut waiting = Vec::new();
+        let mut closed = false;
+        if let Some(outgoing) = self.outgoing.as_mut() {
+            loop {
+                match outgoing.receiver.try_recv() {
+                    Ok(packet) => waiting.push(packet),
+                    Err(err) => {
+                        closed = err.is_closed();
+                        break;
+                    }
+                }
+            }
+        }
+        for packet in waiting {
+            self.send_outgoing(packet);
+        }
+        if closed {
+            // every sender is gone, so there's nothing more to intercept
+            self.outgoing = None;
+        }
+    }
+
+    // Hands a packet on to the inner socket, dropping it if there's no room,
+    // as a full link would
+    fn send_inner(&mut self, packet: Packet) {
+        if let Some(outgoing) = self.outgoing.as_mut() {
+            if let Err(err) = outgoing.inner_sender.try_send(packet) {
+                info!("middleware: outgoing packet dropped, {}", err);
+            }
+        }
+    }
+
+    fn next_release(&self) -> Option<std::time::Instant> {
+        self.layers
+            .iter()
+            .filter_map(|layer| layer.next_release())
+            .min()
+    }
+
+    fn handles_outbound(&self) -> bool {
+        self.layers.iter().any(|layer| layer.handles_outbound())
+    }
+
+    // Splits the inner socket, keeping the layers around its receiving half
+    pub(crate) fn split_layers(self) -> (SendHalf, MiddlewareSocket)
+    where
+        S: 'static,
+    {
+        if self.handles_outbound() {
+            warn!("middleware isn't run on packets sent through a split socket, skipping it");
+        }
+        let MiddlewareSocket {
+            inner_socket,
+            layers,
+            ..
+        } = self;
+        let (send_half, recv_half) = Box::new(inner_socket).split();
+        let recv_socket = MiddlewareSocket {
+            inner_socket: recv_half.into_inner(),
+            layers,
+            outgoing: None,
+        };
+        (send_half, recv_socket)
+    }
+}
+
+impl<S: ServerSocketTrait> fmt::Debug for MiddlewareSocket<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MiddlewareSocket")
+            .field("layers", &self.layers.len())
+            .field("intercepting_outbound", &self.outgoing.is_some())
+            .finish()
+    }
+}
+
+#[async_trait]
+impl<S: ServerSocketTrait + 'static> ServerSocketTrait for MiddlewareSocket<S> {
+    async fn receive(&mut self) -> Result<ServerSocketEvent, NaiaServerSocketError> {
+        enum Next {
+            Event(Result<ServerSocketEvent, NaiaServerSocketError>),
+            Outgoing(Option<Packet>),
+            Release,
+        }
+
+        loop {
+            self.send_released();
+            if let Some(packet) = self.release_inbound() {
+                return Ok(ServerSocketEvent::Packet(packet));
+            }
+
+            let next = {
+                let release_next = match self.next_release() {
+                    Some(instant) => Timer::at(instant).left_future(),
+                    None => future::pending().right_future(),
+                }
+                .fuse();
+                pin_mut!(release_next);
+
+                let outgoing_next = match self.outgoing.as_mut() {
+                    Some(outgoing) => outgoing.receiver.next().left_future(),
+                    None => future::pending().right_future(),
+                }
+                .fuse();
+                pin_mut!(outgoing_next);
+
+                let socket_next = self.inner_socket.receive().fuse();
+                pin_mut!(socket_next);
+
+                select! {
+                    socket_result = socket_next => {
+                        Next::Event(socket_result)
+                    }
+
+                    outgoing_packet = outgoing_next => {
+                        Next::Outgoing(outgoing_packet)
+                    }
+
+                    _ = release_next => {
+                        Next::Release
+                    }
+                }
+            };
+
+            match next {
+                Next::Event(Ok(ServerSocketEvent::Packet(packet))) => {
+                    if let Some(packet) = self.inbound(packet, 0) {
+                        return Ok(ServerSocketEvent::Packet(packet));
+                    }
+                }
+                Next::Event(Ok(event)) => {
+                    if let ServerSocketEvent::Disconnection(_, address, _) = &event {
+                        for layer in self.layers.iter_mut() {
+                            layer.on_disconnection(address);
+                        }
+                    }
+                    // only packets pass through the layers
+                    return Ok(event);
+                }
+                Next::Event(Err(err)) => {
+                    return Err(err);
+                }
+                Next::Outgoing(Some(packet)) => self.send_outgoing(packet),
+                // every sender is gone, so there's nothing more to intercept
+                Next::Outgoing(None) => self.outgoing = None,
+                Next::Release => {}
+            }
+        }
+    }
+
+    fn try_receive(&mut self) -> Result<Option<ServerSocketEvent>, NaiaServerSocketError> {
+        loop {
+            self.send_waiting();
+            self.send_released();
+            if let Some(packet) = self.release_inbound() {
+                return Ok(Some(ServerSocketEvent::Packet(packet)));
+            }
+
+            match self.inner_socket.try_receive()? {
+                Some(ServerSocketEvent::Packet(packet)) => {
+                    if let Some(packet) = self.inbound(packet, 0) {
+                        return Ok(Some(ServerSocketEvent::Packet(packet)));
+                    }
+                }
+                Some(event) => {
+                    if let ServerSocketEvent::Disconnection(_, address, _) = &event {
+                        for layer in self.layers.iter_mut() {
+                            layer.on_disconnection(address);
+                        }
+                    }
+                    // only packets pass through the layers
+                    return Ok(Some(event));
+                }
+                None => return Ok(None),
+            }
+        }
+    }
+
+    fn get_sender(&mut self) -> MessageSender {
+        if !self.handles_outbound() {
+            return self.inner_socket.get_sender();
+        }
+        if self.outgoing.is_none() {
+            let (sender, receiver) = mpsc::channel(OUTGOING_QUEUE_SIZE);
+            self.outgoing = Some(Outgoing {
+                sender: SenderSource::new(sender),
+                receiver,
+                inner_sender: self.inner_socket.get_sender(),
+            });
+        }
+        let outgoing = self.outgoing.as_mut().expect("outgoing was just set");
+        let max_payload_size = outgoing.inner_sender.max_payload_size();
+        outgoing
+            .sender
+            .get()
+            .with_max_payload_size(max_payload_size)
+    }
+
+    fn with_link_conditioner(
+        self: Box<Self>,
+        config: &LinkConditionerConfig,
+    ) -> Box<dyn ServerSocketTrait> {
+        let inner_socket: Box<dyn ServerSocketTrait> = self;
+        Box::new(LinkConditioner::new(config, inner_socket))
+    }
+
+    fn with_middleware(
+        mut self: Box<Self>,
+        middleware: Box<dyn SocketMiddleware>,
+    ) -> Box<dyn ServerSocketTrait> {
+        self.push(middleware);
+        self
+    }
+
+    fn split(self: Box<Self>) -> (SendHalf, RecvHalf) {
+        let (send_half, recv_socket) = self.split_layers();
+        (send_half, RecvHalf::new(Box::new(recv_socket)))
+    }
+
+    async fn rebind(
+        &mut self,
+        socket_address: SocketAddr,
+        public_address: SocketAddr,
+    ) -> Result<(), NaiaServerSocketError> {
+        self.inner_socket
+            .rebind(socket_address, public_address)
+            .await
+    }
+
+    async fn unmap_ports(&mut self) {
+        self.inner_socket.unmap_ports().await
+    }
+
+    async fn flush(&mut self) -> Result<(), NaiaServerSocketError> {
+        self.send_waiting();
+        self.send_released();
+        self.inner_socket.flush().await
+    }
+
+    async fn disconnect(
+        &mut self,
+        connection_id: &ConnectionId,
+        reason: Option<&[u8]>,
+    ) -> Result<(), NaiaServerSocketError> {
+        self.inner_socket.disconnect(connection_id, reason).await
+    }
+
+    async fn announce_host_migration(
+        &mut self,
+        new_address: SocketAddr,
+    ) -> Result<(), NaiaServerSocketError> {
+        self.inner_socket.announce_host_migration(new_address).await
+    }
+
+    fn set_accepting(&mut self, accepting: bool) {
+        self.inner_socket.set_accepting(accepting)
+    }
+
+    fn connections(&self) -> Vec<(ConnectionId, SocketAddr)> {
+        self.inner_socket.connections()
+    }
+
+    fn connection_id(&self, address: &SocketAddr) -> Option<ConnectionId> {
+        self.inner_socket.connection_id(address)
+    }
+
+    fn connection_address(&self, connection_id: &ConnectionId) -> Option<SocketAddr> {
+        self.inner_socket.connection_address(connection_id)
+    }
+
+    fn connect_token(&self, connection_id: &ConnectionId) -> Option<&ConnectToken> {
+        self.inner_socket.connect_token(connection_id)
+    }
+
+    fn connect_payload(&self, connection_id: &ConnectionId) -> Option<&[u8]> {
+        self.inner_socket.connect_payload(connection_id)
+    }
+
+    fn connection_stats(&self, connection_id: &ConnectionId) -> Option<ConnectionStats> {
+        self.inner_socket.connection_stats(connection_id)
+    }
+
+    fn stats(&self, address: &SocketAddr) -> Option<ConnectionStats> {
+        self.inner_socket.stats(address)
+    }
+
+    fn metrics(&self) -> SocketMetrics {
+        self.inner_socket.metrics()
+    }
+
+    fn connection_mtu(&self, connection_id: &ConnectionId) -> Option<usize> {
+        self.inner_socket.connection_mtu(connection_id)
+    }
+
+    fn conditioner_handle(&self) -> Option<ConditionerHandle> {
+        self.inner_socket.conditioner_handle()
+    }
+
+    fn conditioner_stats(&self) -> Option<ConditionerStats> {
+        self.inner_socket.conditioner_stats()
+    }
+
+    fn buffer_pool_stats(&self) -> BufferPoolStats {
+        self.inner_socket.buffer_pool_stats()
+    }
+
+    fn socket_buffer_sizes(&self) -> Option<SocketBufferSizes> {
+        self.inner_socket.socket_buffer_sizes()
+    }
+
+    fn local_addr(&self) -> Option<SocketAddr> {
+        self.inner_socket.local_addr()
+    }
+
+    fn session_server_addr(&self) -> Option<SocketAddr> {
+        self.inner_socket.session_server_addr()
+    }
+
+    fn mapped_addr(&self) -> Option<SocketAddr> {
+        self.inner_socket.mapped_addr()
+    }
+
+    fn set_user_data(&mut self, connection_id: &ConnectionId, data: UserData) -> Option<UserData> {
+        self.inner_socket.set_user_data(connection_id, data)
+    }
+
+    fn user_data(&self, connection_id: &ConnectionId) -> Option<&(dyn Any + Send + Sync)> {
+        self.inner_socket.user_data(connection_id)
+    }
+
+    fn user_data_mut(
+        &mut self,
+        connection_id: &ConnectionId,
+    ) -> Option<&mut (dyn Any + Send + Sync)> {
+        self.inner_socket.user_data_mut(connection_id)
+    }
+
+    fn take_user_data(&mut self, connection_id: &ConnectionId) -> Option<UserData> {
+        self.inner_socket.take_user_data(connection_id)
+    }
+}
@@ -0,0 +1,32 @@
+use std::net::{Ipv4Addr, SocketAddr};
+
+/// Settings for joining a multicast group, so that datagrams sent to the
+/// group by anyone on the network are received as Packets, & Packets sent to
+/// the group's address reach everyone in it
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct MulticastConfig {
+    /// The group to join, & the port datagrams sent to it are received on
+    pub group: SocketAddr,
+    /// The local interface IPv4 groups are joined & sent to on. Left
+    /// unspecified, the system picks one. IPv6 groups are always joined on the
+    /// default interface
+    pub interface: Ipv4Addr,
+    /// How many routers datagrams sent to the group may cross. 1 keeps them on
+    /// the local network
+    pub ttl: u32,
+    /// Whether datagrams sent to the group are received back by this host
+    pub loopback: bool,
+}
+
+impl MulticastConfig {
+    /// Create a new MulticastConfig for the given group, kept to the local
+    /// network
+    pub fn new(group: SocketAddr) -> Self {
+        MulticastConfig {
+            group,
+            interface: Ipv4Addr::UNSPECIFIED,
+            ttl: 1,
+            loopback: false,
+        }
+    }
+}
@@ -0,0 +1,28 @@
+use std::time::Duration;
+
+/// Settings for the pacer which spreads out the datagrams sent to each
+/// connection, so that a client on a poor link isn't sent bursts it can't
+/// take, & every client stays within a fair share of the bandwidth
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct PacingConfig {
+    /// The number of bytes each connection can be sent every second, once
+    /// its burst has been used up
+    pub bytes_per_second: usize,
+    /// The number of bytes which can be sent to a connection at once, after
+    /// it has been quiet for long enough
+    pub burst: usize,
+    /// The longest a datagram can wait for its turn. Datagrams which would
+    /// wait any longer are dropped rather than queued, as they would only
+    /// add to the client's latency
+    pub max_delay: Duration,
+}
+
+impl Default for PacingConfig {
+    fn default() -> Self {
+        PacingConfig {
+            bytes_per_second: 125_000,
+            burst: 12_000,
+            max_delay: Duration::from_millis(100),
+        }
+    }
+}
@@ -1,31 +1,269 @@
-use std::net::SocketAddr;
+use std::{
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+
+use naia_socket_shared::{clock, MessageMode, Payload, Priority};
+
+use crate::Transport;
 
 /// A Packet that can be sent to a Client
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq)]
 pub struct Packet {
     /// The address from which it came, or to which it will go
     address: SocketAddr,
-    /// The raw payload of the packet
-    payload: Box<[u8]>,
+    /// The raw payload of the packet, stored inline if it is small, or in a
+    /// buffer which may be shared with other packets
+    payload: Payload,
+    /// The channel the packet is sent on, if any
+    channel: Option<u8>,
+    /// Whether the packet arrived on a reliable channel
+    reliable: bool,
+    /// The id the packet's delivery is reported under, if any
+    ack_id: Option<u64>,
+    /// How urgently the packet should be sent
+    priority: Priority,
+    /// Whether the packet goes over WebRTC as binary or text
+    mode: MessageMode,
+    /// The moment the packet is dropped if it hasn't been sent by, if any
+    expires_at: Option<Instant>,
+    /// Whether the packet is to be sent to every connection, in place of its
+    /// address
+    broadcast: bool,
+    /// Whether the packet was received from a multicast group
+    multicast: bool,
+    /// The moment the packet was received, & the transport it came over, if
+    /// it was received rather than made to be sent
+    received: Option<(Instant, Transport)>,
 }
 
 impl Packet {
-    /// Create a packet from a Vec payload, converts data to a slice on the heap
+    /// Create a packet from a Vec payload, taking ownership of its buffer
     pub fn new(address: SocketAddr, payload: Vec<u8>) -> Packet {
         Packet {
             address,
-            payload: payload.into_boxed_slice(),
+            payload: Payload::from_bytes(Bytes::from(payload)),
+            channel: None,
+            reliable: false,
+            ack_id: None,
+            priority: Priority::Normal,
+            mode: MessageMode::Binary,
+            expires_at: None,
+            broadcast: false,
+            multicast: false,
+            received: None,
         }
     }
 
     /// Create a packet from an existing boxed slice of bytes
     pub fn new_raw(address: SocketAddr, payload: Box<[u8]>) -> Packet {
-        Packet { address, payload }
+        Packet {
+            address,
+            payload: Payload::from_bytes(Bytes::from(payload)),
+            channel: None,
+            reliable: false,
+            ack_id: None,
+            priority: Priority::Normal,
+            mode: MessageMode::Binary,
+            expires_at: None,
+            broadcast: false,
+            multicast: false,
+            received: None,
+        }
+    }
+
+    /// Create a packet holding a copy of the given payload. Payloads of up to
+    /// `INLINE_PAYLOAD_SIZE` bytes are stored inline, without allocating
+    pub fn from_slice(address: SocketAddr, payload: &[u8]) -> Packet {
+        Packet {
+            address,
+            payload: Payload::from_slice(payload),
+            channel: None,
+            reliable: false,
+            ack_id: None,
+            priority: Priority::Normal,
+            mode: MessageMode::Binary,
+            expires_at: None,
+            broadcast: false,
+            multicast: false,
+            received: None,
+        }
+    }
+
+    /// Create a packet sharing an existing buffer, without copying it
+    pub fn from_bytes(address: SocketAddr, payload: Bytes) -> Packet {
+        Packet {
+            address,
+            payload: Payload::from_bytes(payload),
+            channel: None,
+            reliable: false,
+            ack_id: None,
+            priority: Priority::Normal,
+            mode: MessageMode::Binary,
+            expires_at: None,
+            broadcast: false,
+            multicast: false,
+            received: None,
+        }
+    }
+
+    // Create a packet to be sent to every connection, which the Server Socket
+    // copies for each of them, sharing the payload's buffer
+    pub(crate) fn broadcast(payload: Bytes) -> Packet {
+        let mut packet = Packet::from_bytes(SocketAddr::from(([0, 0, 0, 0], 0)), payload);
+        packet.broadcast = true;
+        packet
+    }
+
+    // Gets whether the packet is to be sent to every connection
+    pub(crate) fn is_broadcast(&self) -> bool {
+        self.broadcast
+    }
+
+    // Gets a copy of the packet going to the given address, sharing its
+    // payload's buffer
+    pub(crate) fn to_address(&self, address: SocketAddr) -> Packet {
+        let mut packet = self.clone();
+        packet.address = address;
+        packet.broadcast = false;
+        packet
+    }
+
+    // Marks a received packet as having been sent to a multicast group
+    #[cfg_attr(feature = "use-webrtc", allow(dead_code))]
+    pub(crate) fn with_multicast(mut self) -> Packet {
+        self.multicast = true;
+        self
+    }
+
+    /// Gets whether the packet was received from the multicast group in
+    /// `SocketConfig::multicast`, rather than from a connection. Its address
+    /// is that of whoever sent it to the group
+    pub fn is_multicast(&self) -> bool {
+        self.multicast
+    }
+
+    // Stamps a received packet with the moment, & transport, it came in by
+    pub(crate) fn with_received(mut self, received_at: Instant, transport: Transport) -> Packet {
+        self.received = Some((received_at, transport));
+        self
+    }
+
+    /// Gets the moment a received packet was read from the network, for
+    /// measuring latency. Packets which waited to be delivered in order, on a
+    /// reliable channel or after reassembly, carry the moment the datagram
+    /// completing them was read
+    pub fn received_at(&self) -> Option<Instant> {
+        self.received.map(|(received_at, _)| received_at)
+    }
+
+    /// Gets the transport a received packet came over
+    pub fn transport(&self) -> Option<Transport> {
+        self.received.map(|(_, transport)| transport)
+    }
+
+    /// Moves the packet onto the channel with the given id, which must be one
+    /// of those in `SocketConfig::reliability`. Received packets keep the
+    /// channel they arrived on, & can be told apart with a ChannelRouter. Only
+    /// the UDP transport has channels
+    pub fn with_channel(mut self, channel: u8) -> Packet {
+        self.channel = Some(channel);
+        self
+    }
+
+    /// Gets the channel the packet is sent on, or `None` if it is sent off
+    /// any channel
+    pub fn channel(&self) -> Option<u8> {
+        self.channel
+    }
+
+    // Marks a received packet as having arrived on a reliable channel
+    #[cfg_attr(feature = "use-webrtc", allow(dead_code))]
+    pub(crate) fn with_reliable(mut self) -> Packet {
+        self.reliable = true;
+        self
+    }
+
+    // Gets whether a received packet arrived on a reliable channel
+    pub(crate) fn is_reliable(&self) -> bool {
+        self.reliable
+    }
+
+    /// Has the packet's delivery reported back under the given id, as a
+    /// PacketAcked or PacketLost event, once it is known. Requires
+    /// `SocketConfig::acknowledgement`, which only the UDP transport has.
+    /// Ignored for packets on a reliable channel
+    pub fn with_ack_id(mut self, ack_id: u64) -> Packet {
+        self.ack_id = Some(ack_id);
+        self
+    }
+
+    /// Gets the id the packet's delivery is reported under, if any
+    pub fn ack_id(&self) -> Option<u64> {
+        self.ack_id
+    }
+
+    /// Sets how urgently the packet should be sent, relative to the others
+    /// waiting to be sent. Packets are Normal priority unless set
+    pub fn with_priority(mut self, priority: Priority) -> Packet {
+        self.priority = priority;
+        self
+    }
+
+    /// Gets how urgently the packet should be sent
+    pub fn priority(&self) -> Priority {
+        self.priority
+    }
+
+    /// Sets whether the packet goes over WebRTC as a binary message or a text
+    /// one. Packets are Binary unless set, & text which isn't valid UTF-8, or
+    /// begins the way the WebRTC transport's own messages do, isn't sent
+    pub fn with_mode(mut self, mode: MessageMode) -> Packet {
+        self.mode = mode;
+        self
+    }
+
+    /// Gets whether the packet goes over WebRTC as binary or text, or came
+    /// over it as which
+    pub fn mode(&self) -> MessageMode {
+        self.mode
+    }
+
+    /// Has the packet dropped, & counted in the connection's
+    /// `ConnectionStats::expired_packets`, if it is still waiting to be sent
+    /// once the given time has passed, such as when the outgoing queue is
+    /// backed up. Stale state is often worse than none. Ignored for packets on
+    /// a reliable channel
+    pub fn with_ttl(mut self, ttl: Duration) -> Packet {
+        self.expires_at = Some(clock::now() + ttl);
+        self
+    }
+
+    // Replaces the packet's payload, keeping everything else about it
+    pub(crate) fn with_payload(mut self, payload: Bytes) -> Packet {
+        self.payload = Payload::from_bytes(payload);
+        self
+    }
+
+    // Gets whether the packet's time to live has run out
+    pub(crate) fn is_expired(&self) -> bool {
+        match self.expires_at {
+            Some(expires_at) => clock::now() >= expires_at,
+            None => false,
+        }
     }
 
     /// Get at the underlying byte payload of the packet
     pub fn payload(&self) -> &[u8] {
-        &self.payload
+        self.payload.as_slice()
+    }
+
+    /// Takes the payload of the packet, which can be cloned & sliced without
+    /// copying it
+    pub fn into_payload(self) -> Bytes {
+        self.payload.into_bytes()
     }
 
     /// Get the address the Packet is assigned to
@@ -0,0 +1,41 @@
+use std::net::SocketAddr;
+
+use naia_socket_shared::{PacketCapture, PacketDirection, PacketDump};
+
+use crate::SocketConfig;
+
+/// Hands the packets exchanged with the application to the PacketDump &
+/// PacketCapture configured, if there are any
+#[derive(Debug, Clone)]
+pub struct PacketTap {
+    dump: Option<PacketDump>,
+    capture: Option<PacketCapture>,
+    local_address: SocketAddr,
+}
+
+impl PacketTap {
+    /// Create a new PacketTap, for a socket listening at the given address
+    pub fn new(config: &SocketConfig, local_address: SocketAddr) -> Self {
+        PacketTap {
+            dump: config.packet_dump.clone(),
+            capture: config.packet_capture.clone(),
+            local_address,
+        }
+    }
+
+    /// Changes the address packets are captured as coming to & from, once
+    /// the socket has been rebound
+    pub fn set_local_address(&mut self, local_address: SocketAddr) {
+        self.local_address = local_address;
+    }
+
+    /// Dumps & captures a packet exchanged with the given remote address
+    pub fn tap(&self, direction: PacketDirection, remote_address: &SocketAddr, payload: &[u8]) {
+        if let Some(dump) = &self.dump {
+            dump.dump(direction, remote_address, payload);
+        }
+        if let Some(capture) = &self.capture {
+            capture.capture(direction, &self.local_address, remote_address, payload);
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Condvar, Mutex},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use crate::{
+    NaiaServerSocketError, QueueDirection, QueueFullPolicy, ServerSocketEvent, ServerSocketTrait,
+};
+
+type ReceiveResult = Result<ServerSocketEvent, NaiaServerSocketError>;
+
+// The number of events queued by `PollingSocket::new`
+const DEFAULT_QUEUE_SIZE: usize = 1024;
+// How often a blocked receive thread checks whether the PollingSocket is gone
+const CLOSED_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Runs a Server Socket's receive loop on a thread of its own, queueing the
+/// events it receives, so that a game loop can take them without ever
+/// blocking or needing an async executor. Get a MessageSender from the socket
+/// before wrapping it, as the socket can't be reached once it is running;
+/// `MessageSender::try_send` doesn't need an executor either. The thread
+/// stops once the PollingSocket is dropped & the next event arrives, or once
+/// the socket is Closed
+#[derive(Debug)]
+pub struct PollingSocket {
+    shared: Arc<Shared>,
+    thread: JoinHandle<()>,
+}
+
+#[derive(Debug)]
+struct Shared {
+    queue: Mutex<EventQueue>,
+    room: Condvar,
+}
+
+#[derive(Debug)]
+struct EventQueue {
+    events: VecDeque<ReceiveResult>,
+    capacity: usize,
+    policy: QueueFullPolicy,
+    dropped: usize,
+    closed: bool,
+}
+
+impl PollingSocket {
+    /// Starts receiving from the given Server Socket on a new thread, with
+    /// room for 1024 events, after which the thread waits for some to be
+    /// taken
+    pub fn new(socket: Box<dyn ServerSocketTrait>) -> Self {
+        PollingSocket::with_queue(socket, DEFAULT_QUEUE_SIZE, QueueFullPolicy::Block)
+    }
+
+    /// Starts receiving from the given Server Socket on a new thread, with
+    /// room for the given number of events, after which the policy is
+    /// followed. Only Packet events are ever dropped, & each one dropped is
+    /// counted in a QueueOverflow event. Other events are always queued
+    pub fn with_queue(
+        mut socket: Box<dyn ServerSocketTrait>,
+        capacity: usize,
+        policy: QueueFullPolicy,
+    ) -> Self {
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(EventQueue {
+                events: VecDeque::new(),
+                capacity: capacity.max(1),
+                policy,
+                dropped: 0,
+                closed: false,
+            }),
+            room: Condvar::new(),
+        });
+        let thread_shared = shared.clone();
+        let thread = thread::spawn(move || {
+            async_io::block_on(async move {
+                loop {
+                    let result = socket.receive().await;
+                    let closed = matches!(result, Ok(ServerSocketEvent::Closed));
+                    if !thread_shared.push(result) || closed {
+                        break;
+                    }
+                }
+            })
+        });
+        PollingSocket { shared, thread }
+    }
+
+    /// Takes the next event received, if there is one, without waiting
+    pub fn poll_receive(&mut self) -> Result<Option<ServerSocketEvent>, NaiaServerSocketError> {
+        let mut queue = self.shared.queue.lock().unwrap();
+        if queue.dropped > 0 {
+            let dropped = queue.dropped;
+            queue.dropped = 0;
+            return Ok(Some(ServerSocketEvent::QueueOverflow {
+                direction: QueueDirection::Incoming,
+                dropped,
+            }));
+        }
+        match queue.events.pop_front() {
+            Some(result) => {
+                self.shared.room.notify_one();
+                result.map(Some)
+            }
+            None if self.thread.is_finished() => Err(NaiaServerSocketError::Closed),
+            None => Ok(None),
+        }
+    }
+}
+
+impl Drop for PollingSocket {
+    fn drop(&mut self) {
+        if let Ok(mut queue) = self.shared.queue.lock() {
+            queue.closed = true;
+        }
+        self.shared.room.notify_one();
+    }
+}
+
+impl Shared {
+    // Queues an event, returning false once the PollingSocket is gone
+    fn push(&self, result: ReceiveResult) -> bool {
+        let mut queue = self.queue.lock().unwrap();
+        let is_packet = matches!(result, Ok(ServerSocketEvent::Packet(_)));
+        loop {
+            if queue.closed {
+                return false;
+            }
+            if queue.events.len() < queue.capacity || !is_packet {
+                break;
+            }
+            match queue.policy {
+                QueueFullPolicy::Block => {
+                    queue = self
+                        .room
+                        .wait_timeout(queue, CLOSED_CHECK_INTERVAL)
+                        .unwrap()
+                        .0;
+                }
+                QueueFullPolicy::DropNewest => {
+                    queue.dropped += 1;
+                    return true;
+                }
+                QueueFullPolicy::DropOldest => {
+                    let oldest = queue
+                        .events
+                        .iter()
+                        .position(|event| matches!(event, Ok(ServerSocketEvent::Packet(_))));
+                    // if only other events are waiting, there is nothing to shed
+                    if let Some(oldest) = oldest {
+                        queue.events.remove(oldest);
+                        queue.dropped += 1;
+                    }
+                    break;
+                }
+            }
+        }
+        queue.events.push_back(result);
+        true
+    }
+}
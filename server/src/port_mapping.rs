@@ -0,0 +1,680 @@
+use std::{
+    error::Error,
+    fmt,
+    io::{Error as IoError, ErrorKind, Read, Write},
+    net::{IpAddr, Ipv4Addr, SocketAddr, TcpStream, UdpSocket},
+    sync::mpsc::{self as std_mpsc, RecvTimeoutError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use futures_channel::oneshot;
+use log::{info, warn};
+
+use crate::PortMappingConfig;
+
+const NAT_PMP_PORT: u16 = 5351;
+// NAT-PMP responses have the request's opcode with the top bit set
+const NAT_PMP_RESPONSE: u8 = 128;
+const SSDP_ADDRESS: &str = "239.255.255.250:1900";
+const GATEWAY_DEVICE: &str = "urn:schemas-upnp-org:device:InternetGatewayDevice:1";
+// the UPnP error of routers which only keep mappings until they restart
+const ONLY_PERMANENT_LEASES: &str = "725";
+
+// The transport protocol a port is mapped for
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub(crate) enum MappedProtocol {
+    Udp,
+    // only the WebRTC transport's session server is reached over TCP
+    #[cfg_attr(feature = "use-udp", allow(dead_code))]
+    Tcp,
+}
+
+impl MappedProtocol {
+    fn name(&self) -> &'static str {
+        match self {
+            MappedProtocol::Udp => "UDP",
+            MappedProtocol::Tcp => "TCP",
+        }
+    }
+}
+
+// Ports mapped on the router, which a thread of their own keeps renewed until
+// they are closed or dropped, which removes them
+#[derive(Debug)]
+pub(crate) struct PortMapping {
+    external_addresses: Vec<SocketAddr>,
+    stop: Option<std_mpsc::Sender<()>>,
+    // resolves once the thread is done, & so has removed the mappings
+    finished: Option<oneshot::Receiver<()>>,
+}
+
+impl PortMapping {
+    // Asks the router to forward a port to each of the given local addresses,
+    // giving None if that fails for any of them, which is only logged, as the
+    // Server still works on the local network
+    pub async fn request(
+        config: PortMappingConfig,
+        ports: Vec<(MappedProtocol, SocketAddr)>,
+    ) -> Option<PortMapping> {
+        if ports.iter().any(|(_, address)| !maps_ipv4(address.ip())) {
+            warn!("ports can only be mapped for IPv4 addresses, not mapping them");
+            return None;
+        }
+
+        let (result_sender, result_receiver) = oneshot::channel();
+        let (stop, stopped) = std_mpsc::channel();
+        let (finished_sender, finished) = oneshot::channel::<()>();
+        if let Err(err) = thread::Builder::new()
+            .name("port mapping".to_string())
+            .spawn(move || {
+                let _finished = finished_sender;
+                run(config, ports, result_sender, stopped)
+            })
+        {
+            warn!("could not start mapping ports: {}", err);
+            return None;
+        }
+
+        // if the thread gave up, it has said why
+        let external_addresses = result_receiver.await.ok()?;
+        Some(PortMapping {
+            external_addresses,
+            stop: Some(stop),
+            finished: Some(finished),
+        })
+    }
+
+    // Removes the mappings, waiting until the router has been told without
+    // blocking the executor
+    pub async fn close(mut self) {
+        self.stop.take();
+        if let Some(finished) = self.finished.take() {
+            let _ = finished.await;
+        }
+    }
+
+    // Gets the address outside the local network which the router forwards
+    // to the local address requested at the given index
+    pub fn external_address(&self, index: usize) -> SocketAddr {
+        self.external_addresses[index]
+    }
+}
+
+impl Drop for PortMapping {
+    fn drop(&mut self) {
+        // the thread removes the mappings once it is told to stop. It isn't
+        // waited for, as that would block the executor, which `close` doesn't
+        self.stop.take();
+    }
+}
+
+// Gets whether a socket at the given IP address is reached over IPv4, which
+// is all that NAT-PMP & UPnP routers forward
+fn maps_ipv4(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(_) => true,
+        IpAddr::V6(ip) => ip.is_unspecified() || ip.to_ipv4_mapped().is_some(),
+    }
+}
+
+// Maps the ports, reports where they were mapped to, then renews them until
+// told to stop, when they are removed
+fn run(
+    config: PortMappingConfig,
+    ports: Vec<(MappedProtocol, SocketAddr)>,
+    result_sender: oneshot::Sender<Vec<SocketAddr>>,
+    stopped: std_mpsc::Receiver<()>,
+) {
+    let router = match Router::find(&config) {
+        Ok(router) => router,
+        Err(err) => {
+            warn!("could not find a router to map ports on: {}", err);
+            return;
+        }
+    };
+
+    let mut lifetime = config.lifetime;
+    let mut mappings = Vec::new();
+    for (protocol, address) in &ports {
+        match router.map(*protocol, address.port(), address.port(), &config) {
+            Ok((external_port, granted)) => {
+                lifetime = lifetime.min(granted);
+                mappings.push((*protocol, address.port(), external_port));
+            }
+            Err(err) => {
+                warn!(
+                    "could not map {} port {}: {}",
+                    protocol.name(),
+                    address.port(),
+                    err
+                );
+                router.unmap_all(&mappings, &config);
+                return;
+            }
+        }
+    }
+    let external_ip = match router.external_ip(&config) {
+        Ok(external_ip) => external_ip,
+        Err(err) => {
+            warn!("could not get the router's external address: {}", err);
+            router.unmap_all(&mappings, &config);
+            return;
+        }
+    };
+
+    let external_addresses: Vec<SocketAddr> = mappings
+        .iter()
+        .map(|(_, _, external_port)| SocketAddr::new(external_ip.into(), *external_port))
+        .collect();
+    for ((protocol, internal_port, _), external_address) in mappings.iter().zip(&external_addresses)
+    {
+        info!(
+            "{} port {} mapped to {} with {}",
+            protocol.name(),
+            internal_port,
+            external_address,
+            router.name()
+        );
+    }
+    if result_sender.send(external_addresses).is_err() {
+        // the Server Socket is already gone
+        router.unmap_all(&mappings, &config);
+        return;
+    }
+
+    loop {
+        let renew_after = (lifetime / 2).max(Duration::from_secs(1));
+        match stopped.recv_timeout(renew_after) {
+            Err(RecvTimeoutError::Timeout) => {}
+            _ => break,
+        }
+        for (protocol, internal_port, external_port) in &mappings {
+            match router.map(*protocol, *internal_port, *external_port, &config) {
+                Ok((_, granted)) => lifetime = lifetime.min(granted),
+                Err(err) => warn!(
+                    "could not renew the mapping of {} port {}: {}",
+                    protocol.name(),
+                    internal_port,
+                    err
+                ),
+            }
+        }
+    }
+    router.unmap_all(&mappings, &config);
+}
+
+// The router ports are mapped on, & how it is asked
+enum Router {
+    NatPmp(SocketAddr),
+    Upnp(UpnpGateway),
+}
+
+impl Router {
+    // Finds the router, preferring NAT-PMP, which is quicker to ask
+    fn find(config: &PortMappingConfig) -> Result<Router, IoError> {
+        if let Some(gateway) = config.gateway.or_else(default_gateway) {
+            let address = SocketAddr::new(gateway.into(), NAT_PMP_PORT);
+            if nat_pmp::external_ip(address, config.timeout).is_ok() {
+                return Ok(Router::NatPmp(address));
+            }
+        }
+        UpnpGateway::find(config.timeout).map(Router::Upnp)
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Router::NatPmp(_) => "NAT-PMP",
+            Router::Upnp(_) => "UPnP",
+        }
+    }
+
+    // Maps a port, giving the external port it was mapped to & how long the
+    // mapping lasts
+    fn map(
+        &self,
+        protocol: MappedProtocol,
+        internal_port: u16,
+        external_port: u16,
+        config: &PortMappingConfig,
+    ) -> Result<(u16, Duration), IoError> {
+        match self {
+            Router::NatPmp(address) => nat_pmp::map(
+                *address,
+                protocol,
+                internal_port,
+                external_port,
+                config.lifetime,
+                config.timeout,
+            ),
+            Router::Upnp(gateway) => gateway
+                .map(protocol, internal_port, external_port, config)
+                .map(|_| (external_port, config.lifetime)),
+        }
+    }
+
+    // Removes the mappings, which only fails where nothing more can be done
+    fn unmap_all(&self, mappings: &[(MappedProtocol, u16, u16)], config: &PortMappingConfig) {
+        for (protocol, internal_port, external_port) in mappings {
+            let result = match self {
+                Router::NatPmp(address) => nat_pmp::map(
+                    *address,
+                    *protocol,
+                    *internal_port,
+                    0,
+                    Duration::from_secs(0),
+                    config.timeout,
+                )
+                .map(|_| ()),
+                Router::Upnp(gateway) => gateway.unmap(*protocol, *external_port, config.timeout),
+            };
+            if let Err(err) = result {
+                warn!(
+                    "could not remove the mapping of {} port {}: {}",
+                    protocol.name(),
+                    internal_port,
+                    err
+                );
+            }
+        }
+    }
+
+    fn external_ip(&self, config: &PortMappingConfig) -> Result<Ipv4Addr, IoError> {
+        match self {
+            Router::NatPmp(address) => nat_pmp::external_ip(*address, config.timeout),
+            Router::Upnp(gateway) => gateway.external_ip(config.timeout),
+        }
+    }
+}
+
+// Reads the default gateway from the routing table
+#[cfg(target_os = "linux")]
+fn default_gateway() -> Option<Ipv4Addr> {
+    let routes = std::fs::read_to_string("/proc/net/route").ok()?;
+    routes.lines().skip(1).find_map(|route| {
+        let fields: Vec<&str> = route.split_whitespace().collect();
+        if fields.get(1) != Some(&"00000000") {
+            return None;
+        }
+        // addresses are written as the number their bytes make in memory
+        let gateway = u32::from_str_radix(fields.get(2)?, 16).ok()?;
+        Some(Ipv4Addr::from(gateway.to_ne_bytes())).filter(|gateway| !gateway.is_unspecified())
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn default_gateway() -> Option<Ipv4Addr> {
+    None
+}
+
+// NAT-PMP, as described in RFC 6886
+mod nat_pmp {
+    use super::*;
+
+    pub fn external_ip(gateway: SocketAddr, timeout: Duration) -> Result<Ipv4Addr, IoError> {
+        let response = request(gateway, &[0, 0], 12, timeout)?;
+        Ok(Ipv4Addr::new(
+            response[8],
+            response[9],
+            response[10],
+            response[11],
+        ))
+    }
+
+    // Maps a port, or removes its mapping if the lifetime is 0
+    pub fn map(
+        gateway: SocketAddr,
+        protocol: MappedProtocol,
+        internal_port: u16,
+        external_port: u16,
+        lifetime: Duration,
+        timeout: Duration,
+    ) -> Result<(u16, Duration), IoError> {
+        let opcode = match protocol {
+            MappedProtocol::Udp => 1,
+            MappedProtocol::Tcp => 2,
+        };
+        let lifetime = lifetime.as_secs().min(u32::MAX as u64) as u32;
+        let mut message = [0; 12];
+        message[1] = opcode;
+        message[4..6].copy_from_slice(&internal_port.to_be_bytes());
+        message[6..8].copy_from_slice(&external_port.to_be_bytes());
+        message[8..12].copy_from_slice(&lifetime.to_be_bytes());
+
+        let response = request(gateway, &message, 16, timeout)?;
+        let external_port = u16::from_be_bytes([response[10], response[11]]);
+        let lifetime = u32::from_be_bytes([response[12], response[13], response[14], response[15]]);
+        Ok((external_port, Duration::from_secs(lifetime as u64)))
+    }
+
+    // Sends a request, sending it again halfway through the timeout in case
+    // it was lost, & gives back the response if it was a success
+    fn request(
+        gateway: SocketAddr,
+        message: &[u8],
+        response_size: usize,
+        timeout: Duration,
+    ) -> Result<[u8; 16], IoError> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+        socket.connect(gateway)?;
+        socket.set_read_timeout(Some((timeout / 2).max(Duration::from_millis(1))))?;
+
+        let mut response = [0; 16];
+        for _ in 0..2 {
+            socket.send(message)?;
+            let len = match socket.recv(&mut response) {
+                Ok(len) => len,
+                Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                    continue
+                }
+                Err(err) => return Err(err),
+            };
+            if len < response_size
+                || response[0] != 0
+                || response[1] != NAT_PMP_RESPONSE + message[1]
+            {
+                continue;
+            }
+            return match u16::from_be_bytes([response[2], response[3]]) {
+                0 => Ok(response),
+                result => Err(IoError::other(format!(
+                    "the router refused with NAT-PMP result code {}",
+                    result
+                ))),
+            };
+        }
+        Err(IoError::new(
+            ErrorKind::TimedOut,
+            "the router didn't answer over NAT-PMP",
+        ))
+    }
+}
+
+// A router's UPnP Internet Gateway Device, & the service on it which maps
+// ports
+struct UpnpGateway {
+    address: SocketAddr,
+    control_path: String,
+    service_type: String,
+    // the address the router reaches this host at
+    internal_ip: Ipv4Addr,
+}
+
+impl UpnpGateway {
+    // Searches the local network for a gateway, & reads its description to find
+    // the service which maps ports
+    fn find(timeout: Duration) -> Result<UpnpGateway, IoError> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+        let search = format!(
+            "M-SEARCH * HTTP/1.1\r\nHOST: {}\r\nST: {}\r\nMAN: \"ssdp:discover\"\r\nMX: {}\r\n\r\n",
+            SSDP_ADDRESS,
+            GATEWAY_DEVICE,
+            timeout.as_secs().max(1)
+        );
+        socket.send_to(search.as_bytes(), SSDP_ADDRESS)?;
+
+        let deadline = Instant::now() + timeout;
+        let mut last_error = IoError::new(ErrorKind::NotFound, "no UPnP router answered");
+        let mut buffer = [0; 2048];
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(last_error);
+            }
+            socket.set_read_timeout(Some(remaining))?;
+            let len = match socket.recv(&mut buffer) {
+                Ok(len) => len,
+                Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                    return Err(last_error)
+                }
+                Err(err) => return Err(err),
+            };
+            let response = String::from_utf8_lossy(&buffer[..len]);
+            let location = response.lines().find_map(|line| {
+                let (name, value) = line.split_once(':')?;
+                Some(value.trim()).filter(|_| name.trim().eq_ignore_ascii_case("location"))
+            });
+            if let Some(location) = location {
+                match UpnpGateway::describe(location, timeout) {
+                    Ok(gateway) => return Ok(gateway),
+                    Err(err) => last_error = err,
+                }
+            }
+        }
+    }
+
+    fn describe(location: &str, timeout: Duration) -> Result<UpnpGateway, IoError> {
+        let (address, path) = parse_url(location)?;
+        let request = format!(
+            "GET {} HTTP/1.0\r\nHost: {}\r\nConnection: close\r\n\r\n",
+            path, address
+        );
+        let (status, description, local_address) = http(address, &request, timeout)?;
+        if status != "200" {
+            return Err(IoError::other(format!(
+                "the UPnP router's description gave HTTP status {}",
+                status
+            )));
+        }
+        let internal_ip = match local_address.ip() {
+            IpAddr::V4(ip) => ip,
+            IpAddr::V6(ip) => ip.to_ipv4_mapped().ok_or_else(|| {
+                IoError::new(
+                    ErrorKind::Unsupported,
+                    "the UPnP router is reached over IPv6",
+                )
+            })?,
+        };
+
+        for service in description.split("<service>").skip(1) {
+            let service_type = match tag(service, "serviceType") {
+                Some(service_type) => service_type,
+                None => continue,
+            };
+            if !service_type.contains(":WANIPConnection:")
+                && !service_type.contains(":WANPPPConnection:")
+            {
+                continue;
+            }
+            let control_url = match tag(service, "controlURL") {
+                Some(control_url) => control_url,
+                None => continue,
+            };
+            let (address, control_path) = if control_url.starts_with("http://") {
+                parse_url(control_url)?
+            } else if control_url.starts_with('/') {
+                (address, control_url.to_string())
+            } else {
+                (address, format!("/{}", control_url))
+            };
+            return Ok(UpnpGateway {
+                address,
+                control_path,
+                service_type: service_type.to_string(),
+                internal_ip,
+            });
+        }
+        Err(IoError::new(
+            ErrorKind::NotFound,
+            "the UPnP router has no service which maps ports",
+        ))
+    }
+
+    fn map(
+        &self,
+        protocol: MappedProtocol,
+        internal_port: u16,
+        external_port: u16,
+        config: &PortMappingConfig,
+    ) -> Result<(), IoError> {
+        let add = |lease: u64| {
+            let arguments = format!(
+                "<NewRemoteHost></NewRemoteHost>\
+                 <NewExternalPort>{}</NewExternalPort>\
+                 <NewProtocol>{}</NewProtocol>\
+                 <NewInternalPort>{}</NewInternalPort>\
+                 <NewInternalClient>{}</NewInternalClient>\
+                 <NewEnabled>1</NewEnabled>\
+                 <NewPortMappingDescription>{}</NewPortMappingDescription>\
+                 <NewLeaseDuration>{}</NewLeaseDuration>",
+                external_port,
+                protocol.name(),
+                internal_port,
+                self.internal_ip,
+                escape(&config.description),
+                lease
+            );
+            self.soap("AddPortMapping", &arguments, config.timeout)
+        };
+        // a lease of 0 would make the mapping permanent
+        let lease = config.lifetime.as_secs().clamp(1, u32::MAX as u64);
+        match add(lease) {
+            Err(err) if upnp_error_code(&err) == Some(ONLY_PERMANENT_LEASES) => add(0),
+            result => result,
+        }
+        .map(|_| ())
+    }
+
+    fn unmap(
+        &self,
+        protocol: MappedProtocol,
+        external_port: u16,
+        timeout: Duration,
+    ) -> Result<(), IoError> {
+        let arguments = format!(
+            "<NewRemoteHost></NewRemoteHost>\
+             <NewExternalPort>{}</NewExternalPort>\
+             <NewProtocol>{}</NewProtocol>",
+            external_port,
+            protocol.name()
+        );
+        self.soap("DeletePortMapping", &arguments, timeout)
+            .map(|_| ())
+    }
+
+    fn external_ip(&self, timeout: Duration) -> Result<Ipv4Addr, IoError> {
+        let response = self.soap("GetExternalIPAddress", "", timeout)?;
+        tag(&response, "NewExternalIPAddress")
+            .and_then(|ip| ip.trim().parse().ok())
+            .ok_or_else(|| {
+                IoError::new(
+                    ErrorKind::InvalidData,
+                    "the UPnP router gave no external IPv4 address",
+                )
+            })
+    }
+
+    // Calls an action of the service, giving back the response
+    fn soap(&self, action: &str, arguments: &str, timeout: Duration) -> Result<String, IoError> {
+        let body = format!(
+            "<?xml version=\"1.0\"?>\
+             <s:Envelope xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\" \
+             s:encodingStyle=\"http://schemas.xmlsoap.org/soap/encoding/\">\
+             <s:Body><u:{0} xmlns:u=\"{1}\">{2}</u:{0}></s:Body></s:Envelope>",
+            action, self.service_type, arguments
+        );
+        let request = format!(
+            "POST {} HTTP/1.0\r\nHost: {}\r\nContent-Type: text/xml; charset=\"utf-8\"\r\n\
+             SOAPAction: \"{}#{}\"\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            self.control_path,
+            self.address,
+            self.service_type,
+            action,
+            body.len(),
+            body
+        );
+        let (status, response, _) = http(self.address, &request, timeout)?;
+        if status == "200" {
+            return Ok(response);
+        }
+        Err(IoError::other(UpnpError {
+            action: action.to_string(),
+            code: tag(&response, "errorCode")
+                .map(|code| code.trim().to_string())
+                .unwrap_or(status),
+        }))
+    }
+}
+
+// An action the UPnP router refused
+#[derive(Debug)]
+struct UpnpError {
+    action: String,
+    code: String,
+}
+
+impl fmt::Display for UpnpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "the UPnP router refused {} with error {}",
+            self.action, self.code
+        )
+    }
+}
+
+impl Error for UpnpError {}
+
+fn upnp_error_code(err: &IoError) -> Option<&str> {
+    err.get_ref()?
+        .downcast_ref::<UpnpError>()
+        .map(|err| err.code.as_str())
+}
+
+// Makes a request & reads the response, giving back its status code & body,
+// along with the local address it was made from
+fn http(
+    address: SocketAddr,
+    request: &str,
+    timeout: Duration,
+) -> Result<(String, String, SocketAddr), IoError> {
+    let mut stream = TcpStream::connect_timeout(&address, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    stream.write_all(request.as_bytes())?;
+
+    let mut response = Vec::new();
+    if let Err(err) = stream.read_to_end(&mut response) {
+        // some routers keep the connection open regardless
+        if response.is_empty() || !matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut)
+        {
+            return Err(err);
+        }
+    }
+    let response = String::from_utf8_lossy(&response);
+    let (head, body) = response.split_once("\r\n\r\n").unwrap_or((&response, ""));
+    let status = head
+        .lines()
+        .next()
+        .and_then(|status_line| status_line.split_whitespace().nth(1))
+        .ok_or_else(|| IoError::new(ErrorKind::InvalidData, "malformed HTTP response"))?;
+    Ok((status.to_string(), body.to_string(), stream.local_addr()?))
+}
+
+// Splits an `http://` URL into the address it points at & the path on it
+fn parse_url(url: &str) -> Result<(SocketAddr, String), IoError> {
+    let invalid = || IoError::new(ErrorKind::InvalidData, format!("unsupported URL {}", url));
+    let rest = url.strip_prefix("http://").ok_or_else(invalid)?;
+    let (host, path) = match rest.find('/') {
+        Some(index) => (&rest[..index], &rest[index..]),
+        None => (rest, "/"),
+    };
+    let address = match host.parse::<SocketAddr>() {
+        Ok(address) => address,
+        Err(_) => SocketAddr::new(host.parse::<IpAddr>().map_err(|_| invalid())?, 80),
+    };
+    Ok((address, path.to_string()))
+}
+
+// Gets the text inside the first element with the given name
+fn tag<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+    let start = xml.find(&format!("<{}>", name))? + name.len() + 2;
+    let end = start + xml[start..].find(&format!("</{}>", name))?;
+    Some(&xml[start..end])
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
@@ -0,0 +1,30 @@
+use std::{net::Ipv4Addr, time::Duration};
+
+/// Settings for asking the local router to forward the Server's ports to it,
+/// with NAT-PMP or else UPnP, so that a Server hosted from home can be
+/// reached from outside without configuring the router by hand
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct PortMappingConfig {
+    /// How long the router is asked to keep each mapping. Mappings are
+    /// renewed halfway through, & removed once the Server Socket is dropped
+    pub lifetime: Duration,
+    /// How long to wait for each answer from the router
+    pub timeout: Duration,
+    /// The router to ask with NAT-PMP. Left unset, it is read from the routing
+    /// table on Linux, & NAT-PMP is skipped elsewhere. UPnP routers are found
+    /// on the local network either way
+    pub gateway: Option<Ipv4Addr>,
+    /// The description UPnP routers show next to the mappings
+    pub description: String,
+}
+
+impl Default for PortMappingConfig {
+    fn default() -> Self {
+        PortMappingConfig {
+            lifetime: Duration::from_secs(3600),
+            timeout: Duration::from_secs(2),
+            gateway: None,
+            description: "naia-server-socket".to_string(),
+        }
+    }
+}
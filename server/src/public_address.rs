@@ -0,0 +1,51 @@
+use std::{net::SocketAddr, time::Duration};
+
+/// The address WebRTC clients are told to reach the Server at while their
+/// sessions are negotiated
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum PublicAddress {
+    /// The address is already known
+    Known(SocketAddr),
+    /// The address is discovered at startup by asking STUN servers which
+    /// address requests from the Server's socket come from, for a Server
+    /// behind a NAT, such as a cloud NAT or a home router with the port
+    /// forwarded, which keeps the same public port for it
+    Discover(StunConfig),
+}
+
+impl From<SocketAddr> for PublicAddress {
+    fn from(address: SocketAddr) -> Self {
+        PublicAddress::Known(address)
+    }
+}
+
+/// Settings for discovering the Server's public address with STUN
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct StunConfig {
+    /// The STUN servers to ask, in turn until one answers, as `host:port`
+    pub servers: Vec<String>,
+    /// How long to wait for each answer
+    pub timeout: Duration,
+    /// How many requests are sent to each server before moving on to the next
+    pub attempts: u32,
+    /// If set, the servers are asked again this often, & if the public IP
+    /// address has changed, the data channel server is started again to
+    /// advertise the new one. Its WebRTC connections can't survive that, so a
+    /// Disconnection event is emitted for each of them, followed by a
+    /// PublicAddressChanged event
+    pub refresh_interval: Option<Duration>,
+}
+
+impl Default for StunConfig {
+    fn default() -> Self {
+        StunConfig {
+            servers: vec![
+                "stun.l.google.com:19302".to_string(),
+                "stun1.l.google.com:19302".to_string(),
+            ],
+            timeout: Duration::from_secs(1),
+            attempts: 3,
+            refresh_interval: Some(Duration::from_secs(300)),
+        }
+    }
+}
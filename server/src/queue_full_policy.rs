@@ -0,0 +1,20 @@
+/// What is done with a packet which arrives at a queue that is already full
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub enum QueueFullPolicy {
+    /// Whoever is adding to the queue waits until there is room
+    #[default]
+    Block,
+    /// The packet arriving is dropped
+    DropNewest,
+    /// The packet which has waited longest is dropped to make room
+    DropOldest,
+}
+
+/// Which way the packets shed by a full queue were going
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum QueueDirection {
+    /// Packets being sent to clients
+    Outgoing,
+    /// Events received, waiting for the application
+    Incoming,
+}
@@ -0,0 +1,42 @@
+use std::fmt;
+
+use crate::{NaiaServerSocketError, ServerSocketEvent, ServerSocketTrait};
+
+/// The receiving half of a split Server Socket. Everything other than sending
+/// through the SendHalf is still done through the socket held here
+pub struct RecvHalf {
+    socket: Box<dyn ServerSocketTrait>,
+}
+
+impl RecvHalf {
+    /// Create a new RecvHalf around the given socket
+    pub fn new(socket: Box<dyn ServerSocketTrait>) -> Self {
+        RecvHalf { socket }
+    }
+
+    /// Receive the next event from the socket
+    pub async fn receive(&mut self) -> Result<ServerSocketEvent, NaiaServerSocketError> {
+        self.socket.receive().await
+    }
+
+    /// Gets a reference to the socket
+    pub fn socket(&self) -> &dyn ServerSocketTrait {
+        self.socket.as_ref()
+    }
+
+    /// Gets a mutable reference to the socket
+    pub fn socket_mut(&mut self) -> &mut dyn ServerSocketTrait {
+        self.socket.as_mut()
+    }
+
+    /// Takes the socket back out of the RecvHalf
+    pub fn into_inner(self) -> Box<dyn ServerSocketTrait> {
+        self.socket
+    }
+}
+
+impl fmt::Debug for RecvHalf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RecvHalf").finish()
+    }
+}
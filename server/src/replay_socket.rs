@@ -0,0 +1,419 @@
+use async_io::Timer;
+use async_trait::async_trait;
+use futures_channel::mpsc;
+use futures_util::{future, pin_mut, select, FutureExt, StreamExt};
+use std::{
+    any::Any,
+    collections::{HashSet, VecDeque},
+    fmt,
+    fs::File,
+    io::{self, BufReader, Read},
+    net::SocketAddr,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use naia_socket_shared::{
+    clock, ConditionerHandle, ConditionerStats, ConnectToken, LinkConditionerConfig,
+};
+
+use super::{
+    buffer_pool::BufferPoolStats,
+    connection_id::ConnectionId,
+    connection_manager::{ConnectionManager, UserData},
+    connection_stats::ConnectionStats,
+    error::NaiaServerSocketError,
+    link_conditioner::LinkConditioner,
+    message_sender::{MessageSender, SenderSource},
+    middleware::SocketMiddleware,
+    middleware_socket::MiddlewareSocket,
+    packet::Packet,
+    server_socket_event::ServerSocketEvent,
+    server_socket_trait::ServerSocketTrait,
+    session_recorder::{read_recording, RecordedEntry},
+    RecvHalf, SendHalf, SocketBufferSizes, SocketMetrics,
+};
+
+const OUTGOING_QUEUE_SIZE: usize = 1024;
+
+/// A Server Socket which feeds the packets captured by a SessionRecorder back
+/// to the application, with the timing they were originally received with,
+/// so that a session can be replayed against a debug build of the Server.
+/// Each client gets a Connection event before its first packet, & once the
+/// recording runs out every connection still open is closed. Packets the
+/// application sends are counted & then discarded
+pub struct ReplaySocket {
+    entries: VecDeque<(Duration, RecordedEntry)>,
+    // when the replay began, which is when the socket was first received from
+    started: Option<Instant>,
+    finished: bool,
+    accepting: bool,
+    // addresses the application disconnected, whose packets are left out
+    // until the recording has them disconnect too
+    disconnected: HashSet<SocketAddr>,
+    connection_manager: ConnectionManager,
+    outstanding_events: VecDeque<ServerSocketEvent>,
+    to_client_sender: SenderSource,
+    to_client_receiver: mpsc::Receiver<Packet>,
+    // whether the queue packets are sent from has closed, as every
+    // MessageSender has been dropped, which shuts the socket down
+    closed: bool,
+}
+
+impl ReplaySocket {
+    /// Create a new ReplaySocket, replaying the recording in the file at the
+    /// given path
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        ReplaySocket::from_reader(BufReader::new(File::open(path)?))
+    }
+
+    /// Create a new ReplaySocket, replaying the recording read from the given
+    /// input
+    pub fn from_reader(input: impl Read) -> io::Result<Self> {
+        let entries = read_recording(input)?;
+        let (to_client_sender, to_client_receiver) = mpsc::channel(OUTGOING_QUEUE_SIZE);
+
+        Ok(ReplaySocket {
+            entries: entries.into(),
+            started: None,
+            finished: false,
+            accepting: true,
+            disconnected: HashSet::new(),
+            connection_manager: ConnectionManager::new(),
+            outstanding_events: VecDeque::new(),
+            to_client_sender: SenderSource::new(to_client_sender),
+            to_client_receiver,
+            closed: false,
+        })
+    }
+
+    /// Gets the number of packets & disconnections which are still to be
+    /// replayed
+    pub fn remaining(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn replay(&mut self, entry: RecordedEntry) {
+        match entry {
+            RecordedEntry::Packet {
+                address,
+                channel,
+                payload,
+            } => {
+                if self.disconnected.contains(&address) {
+                    return;
+                }
+                let connection_id = match self.connection_manager.connection_id(&address) {
+                    Some(connection_id) => connection_id,
+                    None => {
+                        if !self.accepting {
+                            return;
+                        }
+                        let connection_id = self.connection_manager.add_connection(&address);
+                        self.outstanding_events
+                            .push_back(ServerSocketEvent::Connection(connection_id, address));
+                        connection_id
+                    }
+                };
+                self.connection_manager
+                    .record_received(&connection_id, payload.len());
+
+                let mut packet = Packet::from_bytes(address, payload);
+                if let Some(channel) = channel {
+                    packet = packet.with_channel(channel);
+                }
+                self.outstanding_events
+                    .push_back(ServerSocketEvent::Packet(packet));
+            }
+            RecordedEntry::Disconnection { address } => {
+                self.disconnected.remove(&address);
+                if let Some(connection_id) = self.connection_manager.connection_id(&address) {
+                    self.push_disconnection(connection_id);
+                }
+            }
+        }
+    }
+
+    fn push_disconnection(&mut self, connection_id: ConnectionId) {
+        if let Some((address, stats)) = self.connection_manager.remove_connection(&connection_id) {
+            self.outstanding_events
+                .push_back(ServerSocketEvent::Disconnection(
+                    connection_id,
+                    address,
+                    stats,
+                ));
+        }
+    }
+
+    // Closes every connection still open, as the recording is over
+    fn finish(&mut self) {
+        self.finished = true;
+        for (connection_id, address, stats) in self.connection_manager.remove_all_connections() {
+            self.outstanding_events
+                .push_back(ServerSocketEvent::Disconnection(
+                    connection_id,
+                    address,
+                    stats,
+                ));
+        }
+    }
+
+    // Counts the packets the application has sent, which have nowhere to go
+    fn discard_outgoing(&mut self) {
+        while let Ok(packet) = self.to_client_receiver.try_recv() {
+            self.discard(packet);
+        }
+    }
+
+    fn discard(&mut self, packet: Packet) {
+        if let Some(connection_id) = self.connection_manager.connection_id(&packet.address()) {
+            self.connection_manager
+                .record_sent(&connection_id, packet.payload().len());
+        }
+    }
+}
+
+impl fmt::Debug for ReplaySocket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReplaySocket")
+            .field("remaining", &self.entries.len())
+            .field("started", &self.started)
+            .field("connection_manager", &self.connection_manager)
+            .finish()
+    }
+}
+
+#[async_trait]
+impl ServerSocketTrait for ReplaySocket {
+    async fn receive(&mut self) -> Result<ServerSocketEvent, NaiaServerSocketError> {
+        let started = *self.started.get_or_insert_with(clock::now);
+
+        loop {
+            self.discard_outgoing();
+            if let Some(event) = self.outstanding_events.pop_front() {
+                return Ok(event);
+            }
+            if self.closed {
+                return Ok(ServerSocketEvent::Closed);
+            }
+
+            if self.entries.is_empty() && !self.finished {
+                self.finish();
+                continue;
+            }
+            let due = self.entries.front().map(|(at, _)| started + *at);
+
+            if let Some(due) = due {
+                if due <= clock::now() {
+                    let (_, entry) = self.entries.pop_front().expect("an entry is due");
+                    self.replay(entry);
+                    continue;
+                }
+            }
+
+            let sent = {
+                let replay_next = match due {
+                    Some(due) => {
+                        Timer::after(due.saturating_duration_since(clock::now())).left_future()
+                    }
+                    None => future::pending().right_future(),
+                }
+                .fuse();
+                pin_mut!(replay_next);
+
+                let outgoing_next = self.to_client_receiver.next().fuse();
+                pin_mut!(outgoing_next);
+
+                select! {
+                    _ = replay_next => Ok(None),
+                    packet = outgoing_next => packet.ok_or(()).map(Some),
+                }
+            };
+            match sent {
+                Ok(Some(packet)) => self.discard(packet),
+                Ok(None) => {}
+                // every sender is gone, so nothing more can be sent
+                Err(()) => self.closed = true,
+            }
+        }
+    }
+
+    fn try_receive(&mut self) -> Result<Option<ServerSocketEvent>, NaiaServerSocketError> {
+        let started = *self.started.get_or_insert_with(clock::now);
+
+        loop {
+            self.discard_outgoing();
+            if let Some(event) = self.outstanding_events.pop_front() {
+                return Ok(Some(event));
+            }
+            if self.closed {
+                return Ok(Some(ServerSocketEvent::Closed));
+            }
+
+            if self.entries.is_empty() && !self.finished {
+                self.finish();
+                continue;
+            }
+            match self.entries.front() {
+                Some((at, _)) if started + *at <= clock::now() => {
+                    let (_, entry) = self.entries.pop_front().expect("an entry is due");
+                    self.replay(entry);
+                }
+                _ => return Ok(None),
+            }
+        }
+    }
+
+    fn get_sender(&mut self) -> MessageSender {
+        self.to_client_sender.get()
+    }
+
+    fn with_link_conditioner(
+        self: Box<Self>,
+        config: &LinkConditionerConfig,
+    ) -> Box<dyn ServerSocketTrait> {
+        Box::new(LinkConditioner::new(config, *self))
+    }
+
+    fn with_middleware(
+        self: Box<Self>,
+        middleware: Box<dyn SocketMiddleware>,
+    ) -> Box<dyn ServerSocketTrait> {
+        let mut socket = MiddlewareSocket::new(*self);
+        socket.push(middleware);
+        Box::new(socket)
+    }
+
+    fn split(mut self: Box<Self>) -> (SendHalf, RecvHalf) {
+        let send_half = SendHalf::queued(self.get_sender());
+        (send_half, RecvHalf::new(self))
+    }
+
+    async fn rebind(
+        &mut self,
+        _socket_address: SocketAddr,
+        _public_address: SocketAddr,
+    ) -> Result<(), NaiaServerSocketError> {
+        // there's no socket to move
+        Ok(())
+    }
+
+    async fn unmap_ports(&mut self) {
+        // there are no ports to unmap
+    }
+
+    async fn flush(&mut self) -> Result<(), NaiaServerSocketError> {
+        self.discard_outgoing();
+        Ok(())
+    }
+
+    async fn disconnect(
+        &mut self,
+        connection_id: &ConnectionId,
+        _reason: Option<&[u8]>,
+    ) -> Result<(), NaiaServerSocketError> {
+        self.discard_outgoing();
+        if let Some(address) = self.connection_manager.address(connection_id) {
+            self.disconnected.insert(address);
+            self.push_disconnection(*connection_id);
+        }
+        Ok(())
+    }
+
+    async fn announce_host_migration(
+        &mut self,
+        _new_address: SocketAddr,
+    ) -> Result<(), NaiaServerSocketError> {
+        Ok(())
+    }
+
+    fn set_accepting(&mut self, accepting: bool) {
+        self.accepting = accepting;
+    }
+
+    fn connections(&self) -> Vec<(ConnectionId, SocketAddr)> {
+        self.connection_manager.connections()
+    }
+
+    fn connection_id(&self, address: &SocketAddr) -> Option<ConnectionId> {
+        self.connection_manager.connection_id(address)
+    }
+
+    fn connection_address(&self, connection_id: &ConnectionId) -> Option<SocketAddr> {
+        self.connection_manager.address(connection_id)
+    }
+
+    fn connect_token(&self, _connection_id: &ConnectionId) -> Option<&ConnectToken> {
+        None
+    }
+
+    fn connect_payload(&self, _connection_id: &ConnectionId) -> Option<&[u8]> {
+        None
+    }
+
+    fn connection_stats(&self, connection_id: &ConnectionId) -> Option<ConnectionStats> {
+        self.connection_manager.stats(connection_id)
+    }
+
+    fn stats(&self, address: &SocketAddr) -> Option<ConnectionStats> {
+        let connection_id = self.connection_manager.connection_id(address)?;
+        self.connection_manager.stats(&connection_id)
+    }
+
+    fn metrics(&self) -> SocketMetrics {
+        self.connection_manager
+            .metrics()
+            .snapshot(self.connection_manager.connection_count(), 0)
+    }
+
+    fn connection_mtu(&self, _connection_id: &ConnectionId) -> Option<usize> {
+        None
+    }
+
+    fn conditioner_handle(&self) -> Option<ConditionerHandle> {
+        None
+    }
+
+    fn conditioner_stats(&self) -> Option<ConditionerStats> {
+        None
+    }
+
+    fn buffer_pool_stats(&self) -> BufferPoolStats {
+        BufferPoolStats::default()
+    }
+
+    fn socket_buffer_sizes(&self) -> Option<SocketBufferSizes> {
+        None
+    }
+
+    fn local_addr(&self) -> Option<SocketAddr> {
+        None
+    }
+
+    fn session_server_addr(&self) -> Option<SocketAddr> {
+        None
+    }
+
+    fn mapped_addr(&self) -> Option<SocketAddr> {
+        None
+    }
+
+    fn set_user_data(&mut self, connection_id: &ConnectionId, data: UserData) -> Option<UserData> {
+        self.connection_manager.set_user_data(connection_id, data)
+    }
+
+    fn user_data(&self, connection_id: &ConnectionId) -> Option<&(dyn Any + Send + Sync)> {
+        self.connection_manager.user_data(connection_id)
+    }
+
+    fn user_data_mut(
+        &mut self,
+        connection_id: &ConnectionId,
+    ) -> Option<&mut (dyn Any + Send + Sync)> {
+        self.connection_manager.user_data_mut(connection_id)
+    }
+
+    fn take_user_data(&mut self, connection_id: &ConnectionId) -> Option<UserData> {
+        self.connection_manager.take_user_data(connection_id)
+    }
+}
@@ -0,0 +1,89 @@
+use std::collections::VecDeque;
+
+use naia_socket_shared::Priority;
+
+use crate::{Packet, QueueFullPolicy};
+
+/// Holds the packets taken from the MessageSenders which are waiting to be
+/// sent, in a lane for each priority, so that urgent packets don't wait
+/// behind those which can
+#[derive(Debug)]
+pub struct SendQueue {
+    capacity: usize,
+    policy: QueueFullPolicy,
+    high: VecDeque<Packet>,
+    normal: VecDeque<Packet>,
+    low: VecDeque<Packet>,
+}
+
+impl SendQueue {
+    /// Create a new, empty SendQueue, which is full once it holds the given
+    /// number of packets, & then follows the given policy
+    pub fn new(capacity: usize, policy: QueueFullPolicy) -> Self {
+        SendQueue {
+            capacity: capacity.max(1),
+            policy,
+            high: VecDeque::new(),
+            normal: VecDeque::new(),
+            low: VecDeque::new(),
+        }
+    }
+
+    /// Adds a packet to the back of its priority's lane, returning the number
+    /// of packets dropped to keep within the capacity. When the oldest are
+    /// dropped, they are taken from the least urgent lane first
+    pub fn push(&mut self, packet: Packet) -> usize {
+        let mut dropped = 0;
+        if self.is_full() {
+            match self.policy {
+                QueueFullPolicy::Block => {}
+                QueueFullPolicy::DropNewest => return 1,
+                QueueFullPolicy::DropOldest => {
+                    self.low
+                        .pop_front()
+                        .or_else(|| self.normal.pop_front())
+                        .or_else(|| self.high.pop_front());
+                    dropped = 1;
+                }
+            }
+        }
+        match packet.priority() {
+            Priority::High => self.high.push_back(packet),
+            Priority::Normal => self.normal.push_back(packet),
+            Priority::Low => self.low.push_back(packet),
+        }
+        dropped
+    }
+
+    /// Takes the packet at the front of the most urgent lane which isn't
+    /// empty
+    pub fn pop(&mut self) -> Option<Packet> {
+        self.high
+            .pop_front()
+            .or_else(|| self.normal.pop_front())
+            .or_else(|| self.low.pop_front())
+    }
+
+    /// Gets whether no more packets should be taken from the MessageSenders
+    /// until some have been sent. Packets left waiting there keep exerting
+    /// backpressure on the senders, unless the policy is to drop them
+    pub fn accepts_more(&self) -> bool {
+        !self.is_full() || self.policy != QueueFullPolicy::Block
+    }
+
+    /// Gets whether the queue holds as many packets as it should
+    pub fn is_full(&self) -> bool {
+        self.len() >= self.capacity
+    }
+
+    /// Gets whether there are no packets waiting
+    #[cfg_attr(feature = "use-webrtc", allow(dead_code))]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Gets the number of packets waiting
+    pub fn len(&self) -> usize {
+        self.high.len() + self.normal.len() + self.low.len()
+    }
+}
@@ -0,0 +1,125 @@
+use std::net::SocketAddr;
+
+use super::{connection_id::ConnectionId, connection_stats::ConnectionStats, packet::Packet};
+use crate::{ConnectionQuality, NaiaServerSocketError, QueueDirection};
+
+/// An Event which has occurred on the Server Socket
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ServerSocketEvent {
+    /// A new client has connected from the given address
+    Connection(ConnectionId, SocketAddr),
+    /// A client has disconnected, the address is the last one it was known
+    /// by, & the stats are the connection's counters as it ended
+    Disconnection(ConnectionId, SocketAddr, ConnectionStats),
+    /// A client whose connection was lost has connected again from the given
+    /// address within the resumption grace period, & kept its ConnectionId.
+    /// Only emitted by the UDP transport
+    Reconnection(ConnectionId, SocketAddr),
+    /// A Packet has been received from a connected client
+    Packet(Packet),
+    /// A packet sent to the client with the given ack id has been received.
+    /// Only emitted by the UDP transport, when `SocketConfig::acknowledgement`
+    /// is set
+    PacketAcked(ConnectionId, u64),
+    /// A packet sent to the client with the given ack id hasn't been
+    /// acknowledged in time, & is assumed lost. Only emitted by the UDP
+    /// transport, when `SocketConfig::acknowledgement` is set
+    PacketLost(ConnectionId, u64),
+    /// A connected client is now sending from a different address, for example
+    /// because a NAT rebound its source port. The connection itself is
+    /// unaffected
+    AddressChanged {
+        /// The connection whose address changed
+        connection_id: ConnectionId,
+        /// The address the connection was previously known by
+        old_address: SocketAddr,
+        /// The address the connection is now known by
+        new_address: SocketAddr,
+    },
+    /// A client tried to connect while the Server was at `max_clients`, & was
+    /// placed in the waiting room. It is admitted with a Connection event once
+    /// a slot frees up. Only emitted by the UDP transport
+    Queued {
+        /// The address of the waiting client
+        address: SocketAddr,
+        /// The client's position in the queue, starting from 1
+        position: usize,
+    },
+    /// A client stopped waiting in the waiting room before it was admitted
+    LeftQueue {
+        /// The address of the client which left
+        address: SocketAddr,
+    },
+    /// A client tried to connect using a different protocol version than the
+    /// server's, and was refused. As it keeps trying, this is only reported,
+    /// & the client only told, once a second for each address
+    VersionMismatch {
+        /// The address the client tried to connect from
+        address: SocketAddr,
+        /// The protocol version the client is using
+        client_version: u16,
+    },
+    /// A full queue has shed packets, as its `QueueFullPolicy` says to
+    QueueOverflow {
+        /// Which way the packets were going
+        direction: QueueDirection,
+        /// The number of packets dropped since the last QueueOverflow event
+        dropped: usize,
+    },
+    /// A client's outgoing queue has stayed above the high-water mark for
+    /// longer than the threshold. Emitted once, until the queue drains below
+    /// the mark again, which is reported with a ConsumerCaughtUp event. Only
+    /// emitted by the UDP transport, when `SocketConfig::backpressure` is set
+    SlowConsumer {
+        /// The connection which isn't keeping up
+        connection_id: ConnectionId,
+        /// The number of datagrams waiting in its outgoing queue
+        queued: usize,
+    },
+    /// A client reported as a slow consumer has drained its outgoing queue
+    /// below the high-water mark
+    ConsumerCaughtUp {
+        /// The connection which has caught up
+        connection_id: ConnectionId,
+    },
+    /// A connection's quality has been judged for the first time, or has
+    /// changed. Only emitted by the UDP transport, when
+    /// `SocketConfig::quality` is set
+    QualityChanged {
+        /// The connection whose quality changed
+        connection_id: ConnectionId,
+        /// The connection's quality now
+        quality: ConnectionQuality,
+    },
+    /// The socket has shut down, as every MessageSender it handed out has
+    /// been dropped. No more events follow, every later receive returns Closed
+    /// again, & a SocketStream ends
+    Closed,
+    /// A Packet received from a client had a payload larger than
+    /// `SocketConfig::max_payload_size`, & was dropped
+    OversizedPacket {
+        /// The connection the Packet was received on
+        connection_id: ConnectionId,
+        /// The size of the Packet's payload
+        size: usize,
+    },
+    /// An error which only concerned a single send or datagram, such as a
+    /// send to a client failing. The socket carries on, but the error is
+    /// surfaced rather than passed over silently
+    Error(NaiaServerSocketError),
+    /// The public address discovered with STUN has changed, & is now
+    /// advertised to new WebRTC clients. Only emitted by the WebRTC
+    /// transport, when `StunConfig::refresh_interval` is set
+    PublicAddressChanged(SocketAddr),
+    /// Datagrams to a client were dropped because its outgoing queue was too
+    /// backed up to take them. Only emitted by the UDP transport, when
+    /// `SocketConfig::backpressure` is set
+    BackpressureDrop {
+        /// The connection the datagrams were meant for
+        connection_id: ConnectionId,
+        /// The number of datagrams dropped since the last BackpressureDrop
+        /// event for the connection
+        dropped: usize,
+    },
+}
@@ -1,21 +1,337 @@
 use async_trait::async_trait;
+use std::{any::Any, net::SocketAddr};
 
-use naia_socket_shared::LinkConditionerConfig;
+use naia_socket_shared::{
+    ConditionerHandle, ConditionerStats, ConnectToken, LinkConditionerConfig,
+};
 
-use super::{message_sender::MessageSender, packet::Packet};
-use crate::error::NaiaServerSocketError;
+use super::{
+    buffer_pool::BufferPoolStats, connection_id::ConnectionId, connection_manager::UserData,
+    connection_stats::ConnectionStats, message_sender::MessageSender, middleware::SocketMiddleware,
+    server_socket_event::ServerSocketEvent,
+};
+use crate::{error::NaiaServerSocketError, RecvHalf, SendHalf, SocketBufferSizes, SocketMetrics};
 
 /// Defines the functionality of a Naia Server Socket
 #[async_trait]
 pub trait ServerSocketTrait: Send + Sync {
-    /// Receive a new packet from the socket, or a tick event
-    async fn receive(&mut self) -> Result<Packet, NaiaServerSocketError>;
+    /// Receive the next event from the socket, such as a new connection or an
+    /// incoming packet. Transient errors are retried or surfaced as Error
+    /// events, so an error returned here is usually fatal, which
+    /// `NaiaServerSocketError::is_fatal` tells for sure
+    async fn receive(&mut self) -> Result<ServerSocketEvent, NaiaServerSocketError>;
+    /// Receives the next event if one is ready, without waiting for traffic
+    /// to arrive, & without cancelling anything in flight the way dropping a
+    /// `receive` future would. Packets it has to send along the way only wait
+    /// for room in the OS buffer
+    fn try_receive(&mut self) -> Result<Option<ServerSocketEvent>, NaiaServerSocketError>;
+    /// Receives every event which is ready, up to `max` of them, into the
+    /// given Vec without waiting for more, returning how many were added. Meant
+    /// for game loops which drain the socket once per tick. If an error is
+    /// returned, the events received before it are still in the Vec
+    fn receive_many(
+        &mut self,
+        events: &mut Vec<ServerSocketEvent>,
+        max: usize,
+    ) -> Result<usize, NaiaServerSocketError> {
+        let mut count = 0;
+        while count < max {
+            match self.try_receive()? {
+                Some(event) => {
+                    events.push(event);
+                    count += 1;
+                }
+                None => break,
+            }
+        }
+        Ok(count)
+    }
     /// Gets a MessageSender you can use to send messages through the Server
-    /// Socket
+    /// Socket. Once every MessageSender has been dropped the socket shuts
+    /// down, so keep one for as long as you mean to send
     fn get_sender(&mut self) -> MessageSender;
     /// Wraps the current socket in a LinkConditioner
     fn with_link_conditioner(
         self: Box<Self>,
         config: &LinkConditionerConfig,
     ) -> Box<dyn ServerSocketTrait>;
+    /// Stacks a layer of middleware on the socket, further from it than any
+    /// added before, wrapping the socket in a MiddlewareSocket if it isn't one
+    fn with_middleware(
+        self: Box<Self>,
+        middleware: Box<dyn SocketMiddleware>,
+    ) -> Box<dyn ServerSocketTrait>;
+    /// Splits the socket into a half which sends packets & a half which
+    /// receives events, so that each can be driven by a different task or
+    /// thread
+    fn split(self: Box<Self>) -> (SendHalf, RecvHalf);
+    /// Moves the socket over to a new address, while it keeps running.
+    /// `public_address` is the address advertised to WebRTC clients during
+    /// signaling, & is ignored by the UDP transport. UDP connections are kept,
+    /// & clients which reconnect to the new address within the resumption
+    /// grace period get their ConnectionIds back. WebRTC connections can't
+    /// survive the move, so a Disconnection event is emitted for each of them
+    async fn rebind(
+        &mut self,
+        socket_address: SocketAddr,
+        public_address: SocketAddr,
+    ) -> Result<(), NaiaServerSocketError>;
+    /// Removes the ports `SocketConfig::port_mapping` mapped on the router,
+    /// waiting until the router has been told. Otherwise they are removed
+    /// once the socket is dropped, by a thread the process doesn't wait for
+    async fn unmap_ports(&mut self);
+    /// Sends every packet still waiting to go out, including those held back
+    /// to be coalesced with others. Packets are otherwise only sent while
+    /// `receive` is being awaited
+    async fn flush(&mut self) -> Result<(), NaiaServerSocketError>;
+    /// Closes the given connection, after sending any packets still waiting to
+    /// go out. If a reason is given, it is sent to the client as the last
+    /// thing it receives, although like any other packet it may be lost. An
+    /// empty reason is the same as none. A Disconnection event is emitted once
+    /// the connection is closed
+    async fn disconnect(
+        &mut self,
+        connection_id: &ConnectionId,
+        reason: Option<&[u8]>,
+    ) -> Result<(), NaiaServerSocketError>;
+    /// Tells every connected client to move over to the host at the given
+    /// address, such as a client which has been promoted to host. For WebRTC
+    /// clients this is the new host's signaling address. Clients emit a
+    /// HostMigration event, & decide for themselves whether to follow
+    async fn announce_host_migration(
+        &mut self,
+        new_address: SocketAddr,
+    ) -> Result<(), NaiaServerSocketError>;
+    /// Sets whether new clients are accepted. While not accepting, new
+    /// clients are refused but existing connections are unaffected, & UDP
+    /// clients can still resume their previous connection
+    fn set_accepting(&mut self, accepting: bool);
+    /// Gets every open connection, with the address it is currently known by.
+    /// Along with each connection's user data, this is the state to hand over
+    /// when another host takes over
+    fn connections(&self) -> Vec<(ConnectionId, SocketAddr)>;
+    /// Gets the ConnectionId of the connection with the given address
+    fn connection_id(&self, address: &SocketAddr) -> Option<ConnectionId>;
+    /// Gets the address the given connection is currently known by
+    fn connection_address(&self, connection_id: &ConnectionId) -> Option<SocketAddr>;
+    /// Gets the connect token the given connection presented when it was
+    /// accepted. Only available on the UDP transport, where the token is part
+    /// of the handshake; WebRTC clients are checked during signaling instead
+    fn connect_token(&self, connection_id: &ConnectionId) -> Option<&ConnectToken>;
+    /// Gets the application payload the given connection attached to its
+    /// handshake, available as soon as its Connection event is received. Only
+    /// available on the UDP transport, WebRTC clients can send theirs as their
+    /// first packet instead
+    fn connect_payload(&self, connection_id: &ConnectionId) -> Option<&[u8]>;
+    /// Gets the counters kept for the given connection
+    fn connection_stats(&self, connection_id: &ConnectionId) -> Option<ConnectionStats>;
+    /// Gets the counters kept for the connection with the given address
+    fn stats(&self, address: &SocketAddr) -> Option<ConnectionStats>;
+    /// Gets a snapshot of the socket's traffic as a whole, which is cheap
+    /// enough to take every tick
+    fn metrics(&self) -> SocketMetrics;
+    /// Gets the largest datagram known to reach the given connection without
+    /// IP fragmentation, in bytes of UDP payload. This is `SocketConfig::mtu`
+    /// until path MTU discovery finds a larger one. Only available on the UDP
+    /// transport, WebRTC takes care of this itself
+    fn connection_mtu(&self, connection_id: &ConnectionId) -> Option<usize>;
+    /// Gets a handle onto the conditions simulated on received packets, which
+    /// can change them while the socket is live, if the socket is wrapped in
+    /// a LinkConditioner
+    fn conditioner_handle(&self) -> Option<ConditionerHandle>;
+    /// Gets the counters describing what has been done to received packets,
+    /// if the socket is wrapped in a LinkConditioner
+    fn conditioner_stats(&self) -> Option<ConditionerStats>;
+    /// Gets the counters kept by the pool of buffers which received packets
+    /// are stored in
+    fn buffer_pool_stats(&self) -> BufferPoolStats;
+    /// Gets the sizes of the OS buffers for the Server's socket. Only
+    /// available on the UDP transport, as the WebRTC server's socket can't be
+    /// reached
+    fn socket_buffer_sizes(&self) -> Option<SocketBufferSizes>;
+    /// Gets the address the Server's socket is bound to, which tells the
+    /// port the OS assigned if it was bound to port 0. On the WebRTC
+    /// transport, this is the data channel server's
+    fn local_addr(&self) -> Option<SocketAddr>;
+    /// Gets the address the session server is listening at, which clients
+    /// start their WebRTC sessions with. Only available on the WebRTC
+    /// transport
+    fn session_server_addr(&self) -> Option<SocketAddr>;
+    /// Gets the address outside the local network which the router forwards
+    /// to the Server, if `SocketConfig::port_mapping` got it to map one. On
+    /// the WebRTC transport, this is the session server's
+    fn mapped_addr(&self) -> Option<SocketAddr>;
+    /// Attaches an application-defined value to the given connection,
+    /// returning the value which was previously attached, if any
+    fn set_user_data(&mut self, connection_id: &ConnectionId, data: UserData) -> Option<UserData>;
+    /// Gets a reference to the value attached to the given connection
+    fn user_data(&self, connection_id: &ConnectionId) -> Option<&(dyn Any + Send + Sync)>;
+    /// Gets a mutable reference to the value attached to the given connection
+    fn user_data_mut(
+        &mut self,
+        connection_id: &ConnectionId,
+    ) -> Option<&mut (dyn Any + Send + Sync)>;
+    /// Removes and returns the value attached to the given connection
+    fn take_user_data(&mut self, connection_id: &ConnectionId) -> Option<UserData>;
+}
+
+/// Lets a boxed socket be used wherever a concrete socket type is expected,
+/// such as inside a LinkConditioner
+#[async_trait]
+impl ServerSocketTrait for Box<dyn ServerSocketTrait> {
+    async fn receive(&mut self) -> Result<ServerSocketEvent, NaiaServerSocketError> {
+        self.as_mut().receive().await
+    }
+
+    fn try_receive(&mut self) -> Result<Option<ServerSocketEvent>, NaiaServerSocketError> {
+        self.as_mut().try_receive()
+    }
+
+    fn receive_many(
+        &mut self,
+        events: &mut Vec<ServerSocketEvent>,
+        max: usize,
+    ) -> Result<usize, NaiaServerSocketError> {
+        self.as_mut().receive_many(events, max)
+    }
+
+    fn get_sender(&mut self) -> MessageSender {
+        self.as_mut().get_sender()
+    }
+
+    fn with_link_conditioner(
+        self: Box<Self>,
+        config: &LinkConditionerConfig,
+    ) -> Box<dyn ServerSocketTrait> {
+        (*self).with_link_conditioner(config)
+    }
+
+    fn with_middleware(
+        self: Box<Self>,
+        middleware: Box<dyn SocketMiddleware>,
+    ) -> Box<dyn ServerSocketTrait> {
+        (*self).with_middleware(middleware)
+    }
+
+    fn split(self: Box<Self>) -> (SendHalf, RecvHalf) {
+        (*self).split()
+    }
+
+    async fn rebind(
+        &mut self,
+        socket_address: SocketAddr,
+        public_address: SocketAddr,
+    ) -> Result<(), NaiaServerSocketError> {
+        self.as_mut().rebind(socket_address, public_address).await
+    }
+
+    async fn unmap_ports(&mut self) {
+        self.as_mut().unmap_ports().await
+    }
+
+    async fn flush(&mut self) -> Result<(), NaiaServerSocketError> {
+        self.as_mut().flush().await
+    }
+
+    async fn disconnect(
+        &mut self,
+        connection_id: &ConnectionId,
+        reason: Option<&[u8]>,
+    ) -> Result<(), NaiaServerSocketError> {
+        self.as_mut().disconnect(connection_id, reason).await
+    }
+
+    async fn announce_host_migration(
+        &mut self,
+        new_address: SocketAddr,
+    ) -> Result<(), NaiaServerSocketError> {
+        self.as_mut().announce_host_migration(new_address).await
+    }
+
+    fn set_accepting(&mut self, accepting: bool) {
+        self.as_mut().set_accepting(accepting)
+    }
+
+    fn connections(&self) -> Vec<(ConnectionId, SocketAddr)> {
+        self.as_ref().connections()
+    }
+
+    fn connection_id(&self, address: &SocketAddr) -> Option<ConnectionId> {
+        self.as_ref().connection_id(address)
+    }
+
+    fn connection_address(&self, connection_id: &ConnectionId) -> Option<SocketAddr> {
+        self.as_ref().connection_address(connection_id)
+    }
+
+    fn connect_token(&self, connection_id: &ConnectionId) -> Option<&ConnectToken> {
+        self.as_ref().connect_token(connection_id)
+    }
+
+    fn connect_payload(&self, connection_id: &ConnectionId) -> Option<&[u8]> {
+        self.as_ref().connect_payload(connection_id)
+    }
+
+    fn connection_stats(&self, connection_id: &ConnectionId) -> Option<ConnectionStats> {
+        self.as_ref().connection_stats(connection_id)
+    }
+
+    fn stats(&self, address: &SocketAddr) -> Option<ConnectionStats> {
+        self.as_ref().stats(address)
+    }
+
+    fn metrics(&self) -> SocketMetrics {
+        self.as_ref().metrics()
+    }
+
+    fn connection_mtu(&self, connection_id: &ConnectionId) -> Option<usize> {
+        self.as_ref().connection_mtu(connection_id)
+    }
+
+    fn conditioner_handle(&self) -> Option<ConditionerHandle> {
+        self.as_ref().conditioner_handle()
+    }
+
+    fn conditioner_stats(&self) -> Option<ConditionerStats> {
+        self.as_ref().conditioner_stats()
+    }
+
+    fn buffer_pool_stats(&self) -> BufferPoolStats {
+        self.as_ref().buffer_pool_stats()
+    }
+
+    fn socket_buffer_sizes(&self) -> Option<SocketBufferSizes> {
+        self.as_ref().socket_buffer_sizes()
+    }
+
+    fn local_addr(&self) -> Option<SocketAddr> {
+        self.as_ref().local_addr()
+    }
+
+    fn session_server_addr(&self) -> Option<SocketAddr> {
+        self.as_ref().session_server_addr()
+    }
+
+    fn mapped_addr(&self) -> Option<SocketAddr> {
+        self.as_ref().mapped_addr()
+    }
+
+    fn set_user_data(&mut self, connection_id: &ConnectionId, data: UserData) -> Option<UserData> {
+        self.as_mut().set_user_data(connection_id, data)
+    }
+
+    fn user_data(&self, connection_id: &ConnectionId) -> Option<&(dyn Any + Send + Sync)> {
+        self.as_ref().user_data(connection_id)
+    }
+
+    fn user_data_mut(
+        &mut self,
+        connection_id: &ConnectionId,
+    ) -> Option<&mut (dyn Any + Send + Sync)> {
+        self.as_mut().user_data_mut(connection_id)
+    }
+
+    fn take_user_data(&mut self, connection_id: &ConnectionId) -> Option<UserData> {
+        self.as_mut().take_user_data(connection_id)
+    }
 }
@@ -0,0 +1,253 @@
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufWriter, Read, Write},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use log::warn;
+
+use naia_socket_shared::{clock, MiddlewareAction};
+
+use crate::{middleware::SocketMiddleware, Packet};
+
+const MAGIC: &[u8; 8] = b"NAIAREC\0";
+const FORMAT_VERSION: u16 = 1;
+
+const ENTRY_PACKET: u8 = 0;
+const ENTRY_DISCONNECTION: u8 = 1;
+const HAS_CHANNEL: u8 = 1;
+
+/// Records every packet a Server Socket receives, & when each connection
+/// closes, with how long into the session it happened, so that the session
+/// can be fed back to a debug build of the Server by a ReplaySocket. Stack it
+/// on the socket with `ServerSocketTrait::with_middleware`, & it sees the
+/// packets the layers below it let through. Clones share the same recording,
+/// which is flushed once the last of them is dropped
+#[derive(Clone)]
+pub struct SessionRecorder {
+    recording: Arc<Mutex<Recording>>,
+}
+
+struct Recording {
+    // `None` once writing has failed, as there's nothing more to do
+    output: Option<Box<dyn Write + Send>>,
+    began: Instant,
+    entries: u64,
+}
+
+impl SessionRecorder {
+    /// Create a new SessionRecorder, which writes the recording to the given
+    /// output
+    pub fn new(mut output: impl Write + Send + 'static) -> io::Result<Self> {
+        output.write_all(MAGIC)?;
+        output.write_all(&FORMAT_VERSION.to_le_bytes())?;
+
+        Ok(SessionRecorder {
+            recording: Arc::new(Mutex::new(Recording {
+                output: Some(Box::new(output)),
+                began: clock::now(),
+                entries: 0,
+            })),
+        })
+    }
+
+    /// Create a new SessionRecorder, which writes the recording to the file
+    /// at the given path, replacing anything already there
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        SessionRecorder::new(BufWriter::new(File::create(path)?))
+    }
+
+    /// Gets the number of packets & disconnections recorded so far
+    pub fn entries(&self) -> u64 {
+        self.recording.lock().unwrap().entries
+    }
+
+    /// Writes out anything recorded but not yet written to the output
+    pub fn flush(&self) -> io::Result<()> {
+        match self.recording.lock().unwrap().output.as_mut() {
+            Some(output) => output.flush(),
+            None => Ok(()),
+        }
+    }
+
+    fn record(&self, entry: RecordedEntry) {
+        let mut recording = self.recording.lock().unwrap();
+        let at = clock::elapsed(recording.began);
+        let output = match recording.output.as_mut() {
+            Some(output) => output,
+            None => return,
+        };
+        match output.write_all(&entry.encode(at)) {
+            Ok(()) => recording.entries += 1,
+            Err(error) => {
+                warn!("couldn't write session recording: {}", error);
+                recording.output = None;
+            }
+        }
+    }
+}
+
+impl SocketMiddleware for SessionRecorder {
+    fn on_inbound(&mut self, packet: &Packet) -> MiddlewareAction {
+        self.record(RecordedEntry::Packet {
+            address: packet.address(),
+            channel: packet.channel(),
+            payload: Bytes::copy_from_slice(packet.payload()),
+        });
+        MiddlewareAction::Keep
+    }
+
+    fn handles_outbound(&self) -> bool {
+        false
+    }
+
+    fn on_disconnection(&mut self, address: &SocketAddr) {
+        self.record(RecordedEntry::Disconnection { address: *address });
+    }
+}
+
+impl fmt::Debug for SessionRecorder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let recording = self.recording.lock().unwrap();
+        f.debug_struct("SessionRecorder")
+            .field("writing", &recording.output.is_some())
+            .field("entries", &recording.entries)
+            .finish()
+    }
+}
+
+// Something which happened during a recorded session
+#[derive(Debug, Clone)]
+pub(crate) enum RecordedEntry {
+    Packet {
+        address: SocketAddr,
+        channel: Option<u8>,
+        payload: Bytes,
+    },
+    // the connection with the given address closed
+    Disconnection {
+        address: SocketAddr,
+    },
+}
+
+impl RecordedEntry {
+    // Lays the entry out as [kind][micros u64][address][rest], the rest of a
+    // packet being [flags][channel if any][length u32][payload]
+    fn encode(&self, at: Duration) -> Vec<u8> {
+        let mut out = Vec::new();
+        let address = match self {
+            RecordedEntry::Packet { address, .. } => {
+                out.push(ENTRY_PACKET);
+                address
+            }
+            RecordedEntry::Disconnection { address } => {
+                out.push(ENTRY_DISCONNECTION);
+                address
+            }
+        };
+        out.extend_from_slice(&(at.as_micros() as u64).to_le_bytes());
+        match address.ip() {
+            IpAddr::V4(ip) => {
+                out.push(4);
+                out.extend_from_slice(&ip.octets());
+            }
+            IpAddr::V6(ip) => {
+                out.push(6);
+                out.extend_from_slice(&ip.octets());
+            }
+        }
+        out.extend_from_slice(&address.port().to_le_bytes());
+
+        if let RecordedEntry::Packet {
+            channel, payload, ..
+        } = self
+        {
+            match channel {
+                Some(channel) => out.extend_from_slice(&[HAS_CHANNEL, *channel]),
+                None => out.push(0),
+            }
+            out.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+            out.extend_from_slice(payload);
+        }
+        out
+    }
+}
+
+// Reads back every entry of a recording, with how long into the session each
+// happened
+pub(crate) fn read_recording(mut input: impl Read) -> io::Result<Vec<(Duration, RecordedEntry)>> {
+    let mut data = Vec::new();
+    input.read_to_end(&mut data)?;
+    let mut data = &data[..];
+
+    if take(&mut data, MAGIC.len())? != MAGIC {
+        return Err(invalid("not a session recording"));
+    }
+    let version = u16::from_le_bytes([take(&mut data, 1)?[0], take(&mut data, 1)?[0]]);
+    if version != FORMAT_VERSION {
+        return Err(invalid("unsupported session recording version"));
+    }
+
+    let mut entries = Vec::new();
+    while !data.is_empty() {
+        let kind = take(&mut data, 1)?[0];
+        let mut micros = [0; 8];
+        micros.copy_from_slice(take(&mut data, 8)?);
+        let at = Duration::from_micros(u64::from_le_bytes(micros));
+        let ip = match take(&mut data, 1)?[0] {
+            4 => {
+                let mut octets = [0; 4];
+                octets.copy_from_slice(take(&mut data, 4)?);
+                IpAddr::V4(Ipv4Addr::from(octets))
+            }
+            6 => {
+                let mut octets = [0; 16];
+                octets.copy_from_slice(take(&mut data, 16)?);
+                IpAddr::V6(Ipv6Addr::from(octets))
+            }
+            _ => return Err(invalid("bad address in session recording")),
+        };
+        let port = take(&mut data, 2)?;
+        let address = SocketAddr::new(ip, u16::from_le_bytes([port[0], port[1]]));
+
+        let entry = match kind {
+            ENTRY_PACKET => {
+                let channel = match take(&mut data, 1)?[0] {
+                    HAS_CHANNEL => Some(take(&mut data, 1)?[0]),
+                    _ => None,
+                };
+                let mut len = [0; 4];
+                len.copy_from_slice(take(&mut data, 4)?);
+                let payload = take(&mut data, u32::from_le_bytes(len) as usize)?;
+                RecordedEntry::Packet {
+                    address,
+                    channel,
+                    payload: Bytes::copy_from_slice(payload),
+                }
+            }
+            ENTRY_DISCONNECTION => RecordedEntry::Disconnection { address },
+            _ => return Err(invalid("unknown entry in session recording")),
+        };
+        entries.push((at, entry));
+    }
+    Ok(entries)
+}
+
+// Takes the given number of bytes off the front of the data
+fn take<'a>(data: &mut &'a [u8], len: usize) -> io::Result<&'a [u8]> {
+    if data.len() < len {
+        return Err(invalid("session recording ends part way through an entry"));
+    }
+    let (taken, rest) = data.split_at(len);
+    *data = rest;
+    Ok(taken)
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
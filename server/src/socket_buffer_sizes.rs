@@ -0,0 +1,11 @@
+/// The sizes of the operating system's buffers for the Server's socket, as
+/// granted by the OS, which may differ from the sizes asked for in
+/// `SocketConfig`. Linux, for example, doubles the size asked for & caps it at
+/// `net.core.rmem_max`/`net.core.wmem_max`
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct SocketBufferSizes {
+    /// The size of the receive buffer (`SO_RCVBUF`), in bytes
+    pub receive: usize,
+    /// The size of the send buffer (`SO_SNDBUF`), in bytes
+    pub send: usize,
+}
@@ -0,0 +1,277 @@
+use std::time::Duration;
+
+use naia_socket_shared::{
+    encryption::{Protection, ServerIdentity},
+    AckConfig, CompressionConfig, CongestionConfig, ConnectTokenKey, FragmentationConfig,
+    PacketCapture, PacketDump, ReliabilityConfig,
+};
+
+#[cfg(feature = "metrics")]
+use crate::MetricsExporter;
+use crate::{
+    BackpressureConfig, BufferPoolConfig, DuplicateConnectionPolicy, MetricsReporter,
+    MulticastConfig, PacingConfig, PortMappingConfig, QualityConfig, QueueFullPolicy,
+};
+
+/// Contains settings which determine how the Server Socket behaves
+#[derive(Debug, Clone)]
+pub struct SocketConfig {
+    /// If set, clients must present a connect token signed with this key
+    /// before they are accepted. See `ConnectToken`
+    pub connect_token_key: Option<ConnectTokenKey>,
+    /// If set, packets which have already been received on a connection, or
+    /// are too far behind the newest one to tell, are dropped before reaching
+    /// the application, & those which arrive out of order are counted in
+    /// `ConnectionStats`. Only applies to the UDP transport, as WebRTC already
+    /// protects against replays
+    pub replay_protection: bool,
+    /// If set, a client which reconnects within this long of the Server last
+    /// hearing from it gets its previous ConnectionId back, rather than being
+    /// treated as a new connection. Only applies to the UDP transport
+    pub resumption_grace_period: Option<Duration>,
+    /// If set, a connection the Server hasn't heard from in this long is
+    /// closed & reported with a Disconnection event, so that clients which
+    /// went away without saying so don't keep their slots. Clients ping the
+    /// Server every second by default, which keeps quiet connections open. A
+    /// connection which can still be resumed isn't closed until its
+    /// `resumption_grace_period` is over too. Only applies to the UDP
+    /// transport
+    pub idle_timeout: Option<Duration>,
+    /// If set, the Server stops admitting new clients once this many are
+    /// connected
+    pub max_clients: Option<usize>,
+    /// If set, clients which try to connect while the Server is at
+    /// `max_clients` wait in an ordered queue, & are told their position in it
+    /// until a slot frees up. Otherwise they are refused. Only applies to the
+    /// UDP transport, WebRTC clients are always refused
+    pub waiting_room: bool,
+    /// If set, the session server also answers `GET /health` with 200 OK
+    /// while it is up, & `GET /status` with the number of clients connected
+    /// & whether new ones are being accepted, as JSON, so that load balancers
+    /// & orchestrators can check on the Server without a sidecar. Only
+    /// applies to the WebRTC transport, which is the one with a session
+    /// server
+    pub health_check: bool,
+    /// How long the session server gives a client to send its request for a
+    /// WebRTC session & receive the answer, before dropping the request &
+    /// reporting a SignalingTimeout error, so that a slow or stalled client
+    /// can't hold on to it. Only applies to the WebRTC transport
+    pub signaling_timeout: Duration,
+    /// What to do when a client connects with the same identity as one which
+    /// is already connected. Requires `connect_token_key` to be set, & only
+    /// applies to the UDP transport
+    pub duplicate_connection_policy: DuplicateConnectionPolicy,
+    /// Sizes the pool of buffers which received packets are stored in. See
+    /// `ServerSocketTrait::buffer_pool_stats` for how well it fits
+    pub buffer_pool: BufferPoolConfig,
+    /// How many packets can wait to be sent before `MessageSender::try_send`
+    /// starts failing & `MessageSender::send` starts waiting for room. As many
+    /// again are taken from there to be sent in order of priority
+    pub send_queue_size: usize,
+    /// What is done with packets to be sent once the queue above is full.
+    /// Unless they Block, each packet shed is counted in a QueueOverflow
+    /// event. The packets are only taken from the MessageSenders while the
+    /// socket is receiving, so `MessageSender::send` can still wait if it
+    /// isn't
+    pub send_queue_policy: QueueFullPolicy,
+    /// If set, packets the application sends with a payload larger than this
+    /// many bytes are refused with a PayloadTooLarge error before they are
+    /// queued, & packets received with one are dropped, counted in
+    /// `SocketMetrics` & reported with OversizedPacket events, so that neither
+    /// can blow past what the transport carries or waste the Server's memory
+    pub max_payload_size: Option<usize>,
+    /// The most datagrams the UDP transport receives with a single system
+    /// call, each of which needs a 64KB buffer. Only applies on Linux, other
+    /// platforms receive one datagram at a time
+    pub receive_batch_size: usize,
+    /// If set, asks the OS for a receive buffer (`SO_RCVBUF`) of this many
+    /// bytes for the Server's socket, as the default can overflow during
+    /// bursts of traffic & silently drop packets. Only applies to the UDP
+    /// transport, see `ServerSocketTrait::socket_buffer_sizes` for the size
+    /// which was granted
+    pub socket_receive_buffer_size: Option<usize>,
+    /// If set, asks the OS for a send buffer (`SO_SNDBUF`) of this many bytes
+    /// for the Server's socket. Only applies to the UDP transport
+    pub socket_send_buffer_size: Option<usize>,
+    /// If set, every packet the Server sends is marked with this DSCP value,
+    /// so that routers which honor it can prioritize the traffic. For example,
+    /// 46 is Expedited Forwarding. Only applies to the UDP transport
+    pub dscp: Option<u8>,
+    /// If set, packets the Server sends are marked as ECN-capable, so that
+    /// congested routers can mark them rather than drop them, & packets
+    /// received with the congestion mark are counted in each connection's
+    /// `ConnectionStats`. Only applies to the UDP transport, & marks are only
+    /// read on Linux
+    pub ecn: bool,
+    /// The largest datagram, in bytes of UDP payload, assumed to reach every
+    /// client without IP fragmentation. Only applies to the UDP transport
+    pub mtu: usize,
+    /// If set, the Server sends each connection probes larger than `mtu`, to
+    /// discover the largest datagram which actually reaches it. See
+    /// `ServerSocketTrait::connection_mtu`. Only applies to the UDP transport,
+    /// & only on Linux, where probes can be kept from being fragmented
+    pub path_mtu_discovery: bool,
+    /// If set, packets too large for a single datagram are split into
+    /// fragments, & fragments received from clients are put back together.
+    /// Clients must enable it too. Only applies to the UDP transport
+    pub fragmentation: Option<FragmentationConfig>,
+    /// If set, small packets sent to the same client are packed together into
+    /// one datagram, which is sent once it is full, once this long has passed
+    /// since the first of them was sent, or on `ServerSocketTrait::flush`.
+    /// Clients understand coalesced datagrams whether or not they coalesce
+    /// their own. Only applies to the UDP transport
+    pub coalesce_interval: Option<Duration>,
+    /// If set, each connection gets the channels described, reliable or not,
+    /// which packets are moved onto with `Packet::with_channel`. Clients must
+    /// have the same channels. Only applies to the UDP transport
+    pub reliability: Option<ReliabilityConfig>,
+    /// If set, unreliable packets are numbered & acknowledged, & those sent
+    /// with `Packet::with_ack_id` are reported back as PacketAcked or
+    /// PacketLost events. Clients must have acknowledgements enabled too. Only
+    /// applies to the UDP transport
+    pub acknowledgement: Option<AckConfig>,
+    /// If set, the payloads of packets are compressed for clients which ask
+    /// for it while connecting, by having compression enabled too. Only
+    /// applies to the UDP transport
+    pub compression: Option<CompressionConfig>,
+    /// If set, every packet exchanged with a client after the handshake is
+    /// encrypted & authenticated with XChaCha20-Poly1305, under keys agreed on
+    /// with an X25519 key exchange while connecting, & clients which don't ask
+    /// for encryption are refused. Unless clients pin the Server's `identity`,
+    /// it isn't verified, so this guards against eavesdropping & tampering but
+    /// not against a man in the middle. Clients must enable it too. Only applies to the UDP
+    /// transport, as the WebRTC transport is always encrypted by DTLS
+    pub encryption: bool,
+    /// If set, & `encryption` isn't, every packet exchanged with a client
+    /// after the handshake carries an HMAC-SHA256 tag which authenticates it,
+    /// under keys agreed on while connecting, but isn't encrypted, which is
+    /// cheaper. Packets which fail authentication are dropped & counted in
+    /// `ConnectionStats`, & clients which don't ask for authentication are
+    /// refused. Clients must enable it too. Only applies to the UDP transport
+    pub authentication: bool,
+    /// If set, the Server's long-lived key pair, whose public key clients can
+    /// pin with their `server_public_key`, so that a man in the middle can't
+    /// stand in for the Server while they connect with `encryption` or
+    /// `authentication`. Clients which pin a key are refused while this isn't
+    /// set, & those which don't still connect. Only applies to the UDP
+    /// transport
+    pub identity: Option<ServerIdentity>,
+    /// If set, the datagrams sent to each connection are held back as needed
+    /// to keep within the bandwidth budget described, & dropped if they would
+    /// wait too long, which is counted in `ConnectionStats`. Only applies to
+    /// the UDP transport
+    pub pacing: Option<PacingConfig>,
+    /// If set, the bandwidth each connection can take is estimated from the
+    /// acks of the packets sent to it, growing while they get through & cut
+    /// back when they are lost, & reported in `ConnectionStats`. Datagrams to
+    /// the connection are paced to the estimate, within `pacing` if that is
+    /// set too. Only applies when `acknowledgement` is set, & to the UDP
+    /// transport
+    pub congestion_control: Option<CongestionConfig>,
+    /// If set, clients whose outgoing queue stays backed up for too long are
+    /// reported with SlowConsumer events, & datagrams dropped because of it
+    /// with BackpressureDrop events, so that they can be dealt with. Only
+    /// applies to the UDP transport
+    pub backpressure: Option<BackpressureConfig>,
+    /// If set, each connection's quality is judged from its round trip time,
+    /// jitter & loss against the thresholds described, kept in its
+    /// `ConnectionStats` & reported with QualityChanged events. Only applies
+    /// to the UDP transport
+    pub quality: Option<QualityConfig>,
+    /// If set, each connection is pinged this often, & the round trip time &
+    /// jitter measured from its replies are reported in `ConnectionStats`.
+    /// Pings from clients are answered either way. Only applies to the UDP
+    /// transport
+    pub ping_interval: Option<Duration>,
+    /// If set, the multicast group described is joined. Datagrams sent to it
+    /// are received as Packets from whoever sent them, marked by
+    /// `Packet::is_multicast`, & Packets sent to the group's address go out
+    /// to it. Either way their payloads are sent as they are, with none of the
+    /// framing, protection or compression of connections, so they must fit in
+    /// a single datagram. Only applies to the UDP transport
+    pub multicast: Option<MulticastConfig>,
+    /// If set, the local router is asked to forward the Server's ports to it
+    /// while it listens, so that clients outside the local network can reach
+    /// it, see `ServerSocketTrait::mapped_addr`. Failing that, the Server
+    /// still listens, & only logs why. On the WebRTC transport, both the
+    /// session server's port & the data channel's are mapped, & the data
+    /// channel is advertised at the address mapped for it
+    pub port_mapping: Option<PortMappingConfig>,
+    /// If set, every packet the application sends or receives is handed to
+    /// this to be logged while it is enabled. See `PacketDump`
+    pub packet_dump: Option<PacketDump>,
+    /// If set, every packet the application sends or receives is written to
+    /// this capture, to be inspected offline in Wireshark. See
+    /// `PacketCapture`
+    pub packet_capture: Option<PacketCapture>,
+    /// If set, the socket publishes a snapshot of its `SocketMetrics` to the
+    /// exporter each time it is polled, to be scraped by Prometheus. The
+    /// WebRTC transport also answers `GET /metrics` on its session server
+    #[cfg(feature = "metrics")]
+    pub metrics_exporter: Option<MetricsExporter>,
+    /// If set, the socket reports its `SocketMetrics`, & the round trip
+    /// times of its connections, to a `MetricsSink` of the application's
+    /// own. See `MetricsReporter`
+    pub metrics_reporter: Option<MetricsReporter>,
+}
+
+impl Default for SocketConfig {
+    fn default() -> Self {
+        SocketConfig {
+            connect_token_key: None,
+            replay_protection: false,
+            resumption_grace_period: None,
+            idle_timeout: Some(Duration::from_secs(10)),
+            max_clients: None,
+            waiting_room: false,
+            health_check: false,
+            signaling_timeout: Duration::from_secs(10),
+            duplicate_connection_policy: DuplicateConnectionPolicy::default(),
+            buffer_pool: BufferPoolConfig::default(),
+            send_queue_size: 1024,
+            send_queue_policy: QueueFullPolicy::Block,
+            max_payload_size: None,
+            receive_batch_size: 16,
+            socket_receive_buffer_size: None,
+            socket_send_buffer_size: None,
+            dscp: None,
+            ecn: false,
+            mtu: 1200,
+            path_mtu_discovery: false,
+            fragmentation: None,
+            coalesce_interval: None,
+            reliability: None,
+            acknowledgement: None,
+            compression: None,
+            encryption: false,
+            authentication: false,
+            identity: None,
+            pacing: None,
+            congestion_control: None,
+            backpressure: None,
+            quality: None,
+            ping_interval: Some(Duration::from_secs(1)),
+            multicast: None,
+            port_mapping: None,
+            packet_dump: None,
+            packet_capture: None,
+            #[cfg(feature = "metrics")]
+            metrics_exporter: None,
+            metrics_reporter: None,
+        }
+    }
+}
+
+impl SocketConfig {
+    // Gets how packets must be protected, if at all
+    #[cfg_attr(feature = "use-webrtc", allow(dead_code))]
+    pub(crate) fn protection(&self) -> Option<Protection> {
+        if self.encryption {
+            return Some(Protection::Encrypted);
+        }
+        if self.authentication {
+            return Some(Protection::Authenticated);
+        }
+        None
+    }
+}
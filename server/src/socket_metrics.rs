@@ -0,0 +1,170 @@
+use std::time::{Duration, Instant};
+
+use naia_socket_shared::clock;
+
+/// A snapshot of the Server Socket's traffic as a whole, cheap enough to take
+/// every tick, for dashboards & autoscaling to go on
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct SocketMetrics {
+    /// The number of clients currently connected
+    pub active_connections: usize,
+    /// The number of packets sent to connected clients since the socket began
+    pub packets_sent: u64,
+    /// The number of bytes sent to connected clients since the socket began
+    pub bytes_sent: u64,
+    /// The number of packets received from connected clients since the
+    /// socket began
+    pub packets_received: u64,
+    /// The number of bytes received from connected clients since the socket
+    /// began
+    pub bytes_received: u64,
+    /// The number of bytes sent over the last full second
+    pub bytes_sent_per_second: u64,
+    /// The number of bytes received over the last full second
+    pub bytes_received_per_second: u64,
+    /// The number of packets waiting in the send queue to go out
+    pub send_queue_depth: usize,
+    /// The number of handshakes completed since the socket began, each of
+    /// which connected a client
+    pub handshakes: u64,
+    /// The number of handshakes completed over the last full second
+    pub handshakes_per_second: u64,
+    /// The number of clients currently reported as slow consumers
+    pub slow_consumers: usize,
+    /// The number of things which have gone wrong, by what they were
+    pub errors: ErrorCounts,
+}
+
+/// The number of things which have gone wrong on a Server Socket since it
+/// began, by what they were
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct ErrorCounts {
+    /// Datagrams which couldn't be made sense of, & were discarded
+    pub malformed: u64,
+    /// Clients refused during the handshake, for a bad connect token, a
+    /// mismatched protocol version or packet protection, or because the
+    /// Server wasn't accepting them
+    pub refused_handshakes: u64,
+    /// Packets which failed authentication, & were discarded
+    pub forged: u64,
+    /// Packets the OS failed to send
+    pub send_failures: u64,
+    /// Packets shed by a full send queue
+    pub overflowed: u64,
+    /// Datagrams dropped by a connection's pacer, as they would have waited
+    /// for too long
+    pub paced_out: u64,
+    /// Packets received with a payload larger than
+    /// `SocketConfig::max_payload_size`, which were dropped
+    pub oversized: u64,
+    /// Datagrams from addresses which never completed a handshake, such as
+    /// those of scans, which carried no valid connection token & were
+    /// dropped. Only counted on the UDP transport
+    pub unknown_source: u64,
+    /// ICMP reports that a peer couldn't be reached. They don't say which
+    /// client they are about, so no connection is closed for them, & clients
+    /// which have gone time out instead. Only counted on the UDP transport
+    pub unreachable: u64,
+}
+
+/// Keeps the running totals a SocketMetrics snapshot is taken from
+#[derive(Debug, Default)]
+pub struct MetricsTracker {
+    packets_sent: u64,
+    bytes_sent: u64,
+    packets_received: u64,
+    bytes_received: u64,
+    bytes_sent_rate: RateMeter,
+    bytes_received_rate: RateMeter,
+    handshakes: u64,
+    handshake_rate: RateMeter,
+    /// The things which have gone wrong, counted where they happen
+    pub errors: ErrorCounts,
+}
+
+impl MetricsTracker {
+    /// Counts a packet of the given size received from a connected client
+    pub fn received(&mut self, bytes: usize) {
+        self.packets_received += 1;
+        self.bytes_received += bytes as u64;
+        self.bytes_received_rate.add(bytes as u64);
+    }
+
+    /// Counts a packet of the given size sent to a connected client
+    pub fn sent(&mut self, bytes: usize) {
+        self.packets_sent += 1;
+        self.bytes_sent += bytes as u64;
+        self.bytes_sent_rate.add(bytes as u64);
+    }
+
+    /// Counts a completed handshake
+    pub fn handshake(&mut self) {
+        self.handshakes += 1;
+        self.handshake_rate.add(1);
+    }
+
+    /// Takes a snapshot of the totals, along with the given figures which are
+    /// kept elsewhere
+    pub fn snapshot(&self, active_connections: usize, send_queue_depth: usize) -> SocketMetrics {
+        SocketMetrics {
+            active_connections,
+            packets_sent: self.packets_sent,
+            bytes_sent: self.bytes_sent,
+            packets_received: self.packets_received,
+            bytes_received: self.bytes_received,
+            bytes_sent_per_second: self.bytes_sent_rate.rate(),
+            bytes_received_per_second: self.bytes_received_rate.rate(),
+            send_queue_depth,
+            handshakes: self.handshakes,
+            handshakes_per_second: self.handshake_rate.rate(),
+            slow_consumers: 0,
+            errors: self.errors,
+        }
+    }
+}
+
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+// Counts how much of something happens in each second, so that the last full
+// second's count can be read back without any work being done in between
+#[derive(Debug, Default)]
+struct RateMeter {
+    window_start: Option<Instant>,
+    current: u64,
+    previous: u64,
+}
+
+impl RateMeter {
+    fn add(&mut self, amount: u64) {
+        let now = clock::now();
+        match self.window_start {
+            Some(start) if now < start + RATE_WINDOW => {}
+            Some(start) if now < start + RATE_WINDOW * 2 => {
+                self.previous = self.current;
+                self.current = 0;
+                self.window_start = Some(start + RATE_WINDOW);
+            }
+            _ => {
+                self.previous = 0;
+                self.current = 0;
+                self.window_start = Some(now);
+            }
+        }
+        self.current += amount;
+    }
+
+    fn rate(&self) -> u64 {
+        let start = match self.window_start {
+            Some(start) => start,
+            None => return 0,
+        };
+        let elapsed = clock::elapsed(start);
+        if elapsed < RATE_WINDOW {
+            return self.previous;
+        }
+        if elapsed < RATE_WINDOW * 2 {
+            return self.current;
+        }
+        0
+    }
+}
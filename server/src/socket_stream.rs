@@ -0,0 +1,95 @@
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_util::stream::Stream;
+
+use crate::{NaiaServerSocketError, ServerSocketEvent, ServerSocketTrait};
+
+type ReceiveResult = Result<ServerSocketEvent, NaiaServerSocketError>;
+type ReceiveFuture =
+    Pin<Box<dyn Future<Output = (Box<dyn ServerSocketTrait>, ReceiveResult)> + Send>>;
+
+/// Adapts a Server Socket into a Stream of the events it receives, so that it
+/// can be used with `select!` & other Stream combinators. The Stream ends
+/// after the socket's Closed event. Get a MessageSender from the socket before wrapping it, as the socket
+/// can't be reached while a receive is in progress
+pub struct SocketStream {
+    state: StreamState,
+}
+
+enum StreamState {
+    Idle(Box<dyn ServerSocketTrait>),
+    Receiving(ReceiveFuture),
+    Taken,
+    Ended(Box<dyn ServerSocketTrait>),
+}
+
+impl SocketStream {
+    /// Wraps the given Server Socket in a Stream
+    pub fn new(socket: Box<dyn ServerSocketTrait>) -> Self {
+        SocketStream {
+            state: StreamState::Idle(socket),
+        }
+    }
+
+    /// Gets the socket back, unless it is in the middle of receiving an event,
+    /// in which case it is dropped along with the receive
+    pub fn into_inner(self) -> Option<Box<dyn ServerSocketTrait>> {
+        match self.state {
+            StreamState::Idle(socket) | StreamState::Ended(socket) => Some(socket),
+            _ => None,
+        }
+    }
+}
+
+impl Stream for SocketStream {
+    type Item = ReceiveResult;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut future: ReceiveFuture = match std::mem::replace(&mut self.state, StreamState::Taken)
+        {
+            StreamState::Idle(mut socket) => Box::pin(async move {
+                let result = socket.receive().await;
+                (socket, result)
+            }),
+            StreamState::Receiving(future) => future,
+            StreamState::Ended(socket) => {
+                self.state = StreamState::Ended(socket);
+                return Poll::Ready(None);
+            }
+            StreamState::Taken => panic!("socket stream was lost while receiving"),
+        };
+
+        match future.as_mut().poll(cx) {
+            Poll::Ready((socket, result)) => {
+                self.state = match result {
+                    Ok(ServerSocketEvent::Closed) => StreamState::Ended(socket),
+                    _ => StreamState::Idle(socket),
+                };
+                Poll::Ready(Some(result))
+            }
+            Poll::Pending => {
+                self.state = StreamState::Receiving(future);
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl fmt::Debug for SocketStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = match self.state {
+            StreamState::Idle(_) => "Idle",
+            StreamState::Receiving(_) => "Receiving",
+            StreamState::Taken => "Taken",
+            StreamState::Ended(_) => "Ended",
+        };
+        f.debug_struct("SocketStream")
+            .field("state", &state)
+            .finish()
+    }
+}
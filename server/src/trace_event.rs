@@ -0,0 +1,13 @@
+// Emits a `tracing` event at the given level when the `tracing` feature is
+// on, & compiles to nothing otherwise, so that call sites don't each need a
+// `cfg` of their own. Takes the same fields & message as `tracing::event!`
+macro_rules! trace_event {
+    (parent: $parent:expr, $level:ident, $($arg:tt)+) => {
+        #[cfg(feature = "tracing")]
+        tracing::event!(parent: $parent, tracing::Level::$level, $($arg)+);
+    };
+    ($level:ident, $($arg:tt)+) => {
+        #[cfg(feature = "tracing")]
+        tracing::event!(tracing::Level::$level, $($arg)+);
+    };
+}
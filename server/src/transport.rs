@@ -0,0 +1,8 @@
+/// The kind of transport a Packet was received over
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Transport {
+    /// A plain UDP socket, with Naia's own handshake
+    Udp,
+    /// A WebRTC data channel
+    WebRtc,
+}
@@ -0,0 +1,47 @@
+//! A BlockingSocket's receive timeouts, racing the packets arriving from a
+//! native client
+#![cfg(feature = "use-udp")]
+
+use std::time::{Duration, Instant};
+
+use naia_client_socket::{
+    ClientSocket, Packet as ClientPacket, SocketConfig as ClientConfig, SocketEvent,
+};
+use naia_server_socket::{BlockingSocket, ServerSocket, ServerSocketEvent, SocketConfig};
+
+const PACKETS: usize = 200;
+
+#[test]
+fn timeouts_racing_arrivals_lose_no_packets() {
+    let server = async_io::block_on(ServerSocket::listen(
+        "127.0.0.1:0".parse().unwrap(),
+        SocketConfig::default(),
+    ))
+    .unwrap();
+    let server_address = server.local_addr().unwrap();
+    let mut server = BlockingSocket::new(server);
+    let mut client = ClientSocket::connect(server_address, ClientConfig::default());
+
+    // the client sends all of its packets at once as soon as it connects,
+    // while every receive times out almost immediately
+    let started = Instant::now();
+    let mut received = 0;
+    while received < PACKETS && started.elapsed() < Duration::from_secs(5) {
+        while let Some(event) = client.receive().unwrap() {
+            if let SocketEvent::Connection = event {
+                let mut sender = client.get_sender();
+                for index in 0..PACKETS {
+                    sender
+                        .send(ClientPacket::new((index as u32).to_be_bytes().to_vec()))
+                        .unwrap();
+                }
+            }
+        }
+        if let Some(ServerSocketEvent::Packet(_)) =
+            server.receive_timeout(Duration::from_micros(50)).unwrap()
+        {
+            received += 1;
+        }
+    }
+    assert_eq!(received, PACKETS);
+}
@@ -0,0 +1,62 @@
+//! The UDP Server shutting down once every MessageSender it handed out has
+//! been dropped
+#![cfg(feature = "use-udp")]
+
+use naia_server_socket::{
+    Packet, ServerSocket, ServerSocketEvent, ServerSocketTrait, SocketConfig, SocketMiddleware,
+    TrySendError,
+};
+
+// Lets every packet through, but has the packets being sent pass through it
+struct Outbound;
+
+impl SocketMiddleware for Outbound {}
+
+fn listen() -> Box<dyn ServerSocketTrait> {
+    async_io::block_on(ServerSocket::listen(
+        "127.0.0.1:0".parse().unwrap(),
+        SocketConfig::default(),
+    ))
+    .unwrap()
+}
+
+#[test]
+fn sockets_close_once_every_sender_is_dropped() {
+    let mut server = listen();
+    assert!(server.try_receive().unwrap().is_none());
+
+    let first = server.get_sender();
+    let second = server.get_sender();
+    drop(first);
+    assert!(server.try_receive().unwrap().is_none());
+
+    drop(second);
+    assert!(matches!(
+        server.try_receive().unwrap(),
+        Some(ServerSocketEvent::Closed)
+    ));
+    assert!(matches!(
+        server.try_receive().unwrap(),
+        Some(ServerSocketEvent::Closed)
+    ));
+
+    let mut late = server.get_sender();
+    let address = "127.0.0.1:1".parse().unwrap();
+    assert!(matches!(
+        late.try_send(Packet::new(address, b"too late".to_vec())),
+        Err(TrySendError::Disconnected(_))
+    ));
+}
+
+#[test]
+fn middleware_sockets_close_once_every_sender_is_dropped() {
+    let mut server = listen().with_middleware(Box::new(Outbound));
+    let sender = server.get_sender();
+    assert!(server.try_receive().unwrap().is_none());
+
+    drop(sender);
+    assert!(matches!(
+        server.try_receive().unwrap(),
+        Some(ServerSocketEvent::Closed)
+    ));
+}
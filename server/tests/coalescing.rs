@@ -0,0 +1,89 @@
+//! Which packets the UDP Server holds back to coalesce, on a connection
+//! numbering its packets with acks
+#![cfg(feature = "use-udp")]
+
+use std::{net::SocketAddr, thread, time::Duration};
+
+use naia_client_socket::{
+    ClientSocket, ClientSocketTrait, ConnectionState, SocketConfig as ClientConfig, SocketEvent,
+};
+use naia_server_socket::{
+    AckConfig, Packet, Priority, ServerSocket, ServerSocketEvent, ServerSocketTrait, SocketConfig,
+    VirtualClock,
+};
+
+const STEP: Duration = Duration::from_millis(100);
+
+fn poll_server(server: &mut Box<dyn ServerSocketTrait>, client_address: &mut Option<SocketAddr>) {
+    let mut events = Vec::new();
+    server.receive_many(&mut events, 64).unwrap();
+    for event in events {
+        if let ServerSocketEvent::Connection(_, address) = event {
+            *client_address = Some(address);
+        }
+    }
+}
+
+fn poll_client(client: &mut Box<dyn ClientSocketTrait>, payloads: &mut Vec<Vec<u8>>) {
+    while let Some(event) = client.receive().unwrap() {
+        if let SocketEvent::Packet(packet) = event {
+            payloads.push(packet.payload().to_vec());
+        }
+    }
+}
+
+#[test]
+fn high_priority_packets_are_not_coalesced_on_acked_connections() {
+    let clock = VirtualClock::install();
+    let config = SocketConfig {
+        coalesce_interval: Some(Duration::from_secs(1)),
+        acknowledgement: Some(AckConfig::default()),
+        ..SocketConfig::default()
+    };
+    let mut server =
+        async_io::block_on(ServerSocket::listen("127.0.0.1:0".parse().unwrap(), config)).unwrap();
+    let client_config = ClientConfig {
+        acknowledgement: Some(AckConfig::default()),
+        ..ClientConfig::default()
+    };
+    let mut client = ClientSocket::connect(server.local_addr().unwrap(), client_config);
+
+    let mut client_address = None;
+    let mut payloads = Vec::new();
+    clock.run_for(Duration::from_secs(1), STEP, || {
+        poll_server(&mut server, &mut client_address);
+        poll_client(&mut client, &mut payloads);
+        true
+    });
+    assert_eq!(client.state(), ConnectionState::Connected);
+    let client_address = client_address.unwrap();
+
+    // without time passing, only what isn't coalesced goes out
+    let poll_without_time_passing =
+        |server: &mut Box<dyn ServerSocketTrait>,
+         client: &mut Box<dyn ClientSocketTrait>,
+         payloads: &mut Vec<Vec<u8>>| {
+            for _ in 0..20 {
+                poll_server(server, &mut None);
+                thread::sleep(Duration::from_millis(1));
+                poll_client(client, payloads);
+            }
+        };
+
+    let mut sender = server.get_sender();
+    sender
+        .try_send(Packet::new(client_address, b"urgent".to_vec()).with_priority(Priority::High))
+        .unwrap();
+    poll_without_time_passing(&mut server, &mut client, &mut payloads);
+    assert_eq!(payloads, vec![b"urgent".to_vec()]);
+
+    sender
+        .try_send(Packet::new(client_address, b"normal".to_vec()))
+        .unwrap();
+    poll_without_time_passing(&mut server, &mut client, &mut payloads);
+    assert_eq!(payloads.len(), 1);
+
+    clock.advance(Duration::from_secs(1));
+    poll_without_time_passing(&mut server, &mut client, &mut payloads);
+    assert_eq!(payloads, vec![b"urgent".to_vec(), b"normal".to_vec()]);
+}
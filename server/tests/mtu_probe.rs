@@ -0,0 +1,151 @@
+//! Path MTU discovery on an authenticated connection, through a relay which
+//! sees every datagram exchanged & can forge acks of its own
+#![cfg(feature = "use-udp")]
+
+use std::{
+    convert::TryInto,
+    io::ErrorKind,
+    net::{SocketAddr, UdpSocket},
+    time::Duration,
+};
+
+use naia_client_socket::{
+    ClientSocket, ConnectionState, Packet as ClientPacket, SocketConfig as ClientConfig,
+};
+use naia_server_socket::{
+    ServerSocket, ServerSocketEvent, ServerSocketTrait, SocketConfig, VirtualClock,
+};
+use naia_socket_shared::{encryption::TAG_SIZE, PacketType};
+
+const STEP: Duration = Duration::from_millis(100);
+
+// Passes datagrams between a client & the Server, noting the connection token
+// the client sends in the clear
+struct Relay {
+    socket: UdpSocket,
+    server_address: SocketAddr,
+    client_address: Option<SocketAddr>,
+    token: Option<[u8; 8]>,
+    drop_probes: bool,
+}
+
+impl Relay {
+    fn new(server_address: SocketAddr, drop_probes: bool) -> Self {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.set_nonblocking(true).unwrap();
+        Relay {
+            socket,
+            server_address,
+            client_address: None,
+            token: None,
+            drop_probes,
+        }
+    }
+
+    fn address(&self) -> SocketAddr {
+        self.socket.local_addr().unwrap()
+    }
+
+    fn forward(&mut self) {
+        let mut buffer = [0; 2048];
+        loop {
+            let (len, from) = match self.socket.recv_from(&mut buffer) {
+                Ok(received) => received,
+                Err(err) if err.kind() == ErrorKind::WouldBlock => return,
+                Err(err) => panic!("relay couldn't receive: {}", err),
+            };
+            let datagram = &buffer[..len];
+            if from == self.server_address {
+                if self.drop_probes && datagram[0] == PacketType::ServerMtuProbe.to_byte() {
+                    continue;
+                }
+                if let Some(client_address) = self.client_address {
+                    self.socket.send_to(datagram, client_address).unwrap();
+                }
+            } else {
+                self.client_address = Some(from);
+                if datagram[0] == PacketType::Data.to_byte() && len >= 17 {
+                    self.token = Some(datagram[1..9].try_into().unwrap());
+                }
+                self.socket.send_to(datagram, self.server_address).unwrap();
+            }
+        }
+    }
+
+    // Sends acks for the largest probe, as an attacker who has seen the token
+    // but not the connection's keys could
+    fn forge_acks(&self) {
+        let token = self.token.expect("the client has sent data");
+        let size = 1472u16.to_be_bytes();
+
+        let mut unsealed = vec![PacketType::ClientMtuProbeAck.to_byte()];
+        unsealed.extend_from_slice(&token);
+        unsealed.extend_from_slice(&size);
+        self.socket.send_to(&unsealed, self.server_address).unwrap();
+
+        let mut forged = vec![PacketType::ClientMtuProbeAck.to_byte()];
+        forged.extend_from_slice(&token);
+        forged.extend_from_slice(&u64::MAX.to_be_bytes());
+        forged.extend_from_slice(&size);
+        forged.extend_from_slice(&[0; TAG_SIZE]);
+        self.socket.send_to(&forged, self.server_address).unwrap();
+    }
+}
+
+// Connects an authenticated client to an authenticated Server through a
+// Relay, with the client sending a packet every step
+fn run(drop_probes: bool, forge_acks: bool) -> (Box<dyn ServerSocketTrait>, Relay) {
+    let clock = VirtualClock::install();
+    let config = SocketConfig {
+        authentication: true,
+        path_mtu_discovery: true,
+        ..SocketConfig::default()
+    };
+    let mut server =
+        async_io::block_on(ServerSocket::listen("127.0.0.1:0".parse().unwrap(), config)).unwrap();
+    let mut relay = Relay::new(server.local_addr().unwrap(), drop_probes);
+    let client_config = ClientConfig {
+        authentication: true,
+        ..ClientConfig::default()
+    };
+    let mut client = ClientSocket::connect(relay.address(), client_config);
+
+    clock.run_for(Duration::from_secs(10), STEP, || {
+        while client.receive().unwrap().is_some() {}
+        if client.state() == ConnectionState::Connected {
+            client
+                .get_sender()
+                .send(ClientPacket::new(b"hello".to_vec()))
+                .unwrap();
+        }
+        relay.forward();
+        if forge_acks && relay.token.is_some() {
+            relay.forge_acks();
+        }
+        let mut events = Vec::new();
+        server.receive_many(&mut events, 64).unwrap();
+        for event in events {
+            assert!(!matches!(event, ServerSocketEvent::Disconnection(..)));
+        }
+        relay.forward();
+        true
+    });
+    assert_eq!(client.state(), ConnectionState::Connected);
+    (server, relay)
+}
+
+#[test]
+fn probes_raise_the_mtu_of_authenticated_connections() {
+    let (server, _relay) = run(false, false);
+    let (connection_id, _) = server.connections()[0];
+    assert_eq!(server.connection_mtu(&connection_id), Some(1472));
+}
+
+#[test]
+fn forged_probe_acks_leave_the_mtu_alone() {
+    let (server, _relay) = run(true, true);
+    let (connection_id, _) = server.connections()[0];
+    assert_eq!(server.connection_mtu(&connection_id), Some(1200));
+    let stats = server.connection_stats(&connection_id).unwrap();
+    assert!(stats.forged_packets > 0);
+}
@@ -0,0 +1,223 @@
+//! The UDP Server's timers, run through seconds of virtual time against a
+//! native client polled from the same thread
+#![cfg(feature = "use-udp")]
+
+use std::{
+    net::UdpSocket,
+    time::{Duration, Instant},
+};
+
+use naia_client_socket::{
+    ClientSocket, ClientSocketTrait, ConnectionState, Packet as ClientPacket,
+    SocketConfig as ClientConfig, SocketEvent,
+};
+use naia_server_socket::{
+    LinkConditionerConfig, ServerSocket, ServerSocketEvent, ServerSocketTrait, SocketConfig,
+    VirtualClock,
+};
+use naia_socket_shared::{handshake, PacketType};
+
+const STEP: Duration = Duration::from_millis(100);
+
+fn listen() -> Box<dyn ServerSocketTrait> {
+    listen_with(SocketConfig::default())
+}
+
+fn listen_with(config: SocketConfig) -> Box<dyn ServerSocketTrait> {
+    async_io::block_on(ServerSocket::listen("127.0.0.1:0".parse().unwrap(), config)).unwrap()
+}
+
+// The client sends a packet as soon as it connects
+fn poll_client(client: &mut Box<dyn ClientSocketTrait>) {
+    while let Some(event) = client.receive().unwrap() {
+        if let SocketEvent::Connection = event {
+            client
+                .get_sender()
+                .send(ClientPacket::new(b"hello".to_vec()))
+                .unwrap();
+        }
+    }
+}
+
+fn count_packets(server: &mut Box<dyn ServerSocketTrait>, packets: &mut usize) {
+    let mut events = Vec::new();
+    server.receive_many(&mut events, 64).unwrap();
+    *packets += events
+        .iter()
+        .filter(|event| matches!(event, ServerSocketEvent::Packet(_)))
+        .count();
+}
+
+#[test]
+fn conditioned_packets_are_held_for_their_latency_in_virtual_time() {
+    let wall = Instant::now();
+    let clock = VirtualClock::install();
+    let mut server = listen().with_link_conditioner(&LinkConditionerConfig::new(5000, 0, 0.0, 0.0));
+    let mut client = ClientSocket::connect(server.local_addr().unwrap(), ClientConfig::default());
+
+    let mut packets = 0;
+    clock.run_for(Duration::from_secs(1), STEP, || {
+        poll_client(&mut client);
+        count_packets(&mut server, &mut packets);
+        true
+    });
+    assert_eq!(client.state(), ConnectionState::Connected);
+
+    // held for five seconds from when it arrived, around a second in
+    clock.run_for(Duration::from_secs(4), STEP, || {
+        count_packets(&mut server, &mut packets);
+        true
+    });
+    assert_eq!(packets, 0);
+
+    clock.run_for(Duration::from_secs(2), STEP, || {
+        count_packets(&mut server, &mut packets);
+        true
+    });
+    assert_eq!(packets, 1);
+    assert!(wall.elapsed() < Duration::from_secs(5));
+}
+
+fn count_disconnections(server: &mut Box<dyn ServerSocketTrait>, disconnections: &mut usize) {
+    let mut events = Vec::new();
+    server.receive_many(&mut events, 64).unwrap();
+    *disconnections += events
+        .iter()
+        .filter(|event| matches!(event, ServerSocketEvent::Disconnection(..)))
+        .count();
+}
+
+#[test]
+fn idle_connections_are_closed_once_the_timeout_passes() {
+    let clock = VirtualClock::install();
+    let mut server = listen_with(SocketConfig {
+        idle_timeout: Some(Duration::from_secs(5)),
+        ..SocketConfig::default()
+    });
+    let mut client = ClientSocket::connect(server.local_addr().unwrap(), ClientConfig::default());
+
+    let mut disconnections = 0;
+    clock.run_for(Duration::from_secs(1), STEP, || {
+        poll_client(&mut client);
+        count_disconnections(&mut server, &mut disconnections);
+        true
+    });
+    assert_eq!(client.state(), ConnectionState::Connected);
+
+    // the client goes quiet, having connected a little after starting
+    clock.run_for(Duration::from_millis(3900), STEP, || {
+        count_disconnections(&mut server, &mut disconnections);
+        true
+    });
+    assert_eq!(disconnections, 0);
+
+    // & was last heard from at most a second in
+    clock.run_for(Duration::from_millis(1200), STEP, || {
+        count_disconnections(&mut server, &mut disconnections);
+        true
+    });
+    assert_eq!(disconnections, 1);
+}
+
+#[test]
+fn clients_which_disconnect_are_closed_at_once() {
+    let clock = VirtualClock::install();
+    let mut server = listen();
+    let mut client = ClientSocket::connect(server.local_addr().unwrap(), ClientConfig::default());
+
+    let mut disconnections = 0;
+    clock.run_for(Duration::from_secs(1), STEP, || {
+        poll_client(&mut client);
+        count_disconnections(&mut server, &mut disconnections);
+        true
+    });
+    assert_eq!(client.state(), ConnectionState::Connected);
+
+    // without any time passing
+    client.disconnect();
+    for _ in 0..20 {
+        std::thread::sleep(Duration::from_millis(1));
+        count_disconnections(&mut server, &mut disconnections);
+    }
+    assert_eq!(disconnections, 1);
+}
+
+#[test]
+fn clients_lose_their_connection_once_the_server_goes_quiet() {
+    let clock = VirtualClock::install();
+    let mut server = listen();
+    let config = ClientConfig {
+        idle_timeout: Some(Duration::from_secs(5)),
+        ..ClientConfig::default()
+    };
+    let mut client = ClientSocket::connect(server.local_addr().unwrap(), config);
+
+    let mut packets = 0;
+    clock.run_for(Duration::from_secs(1), STEP, || {
+        poll_client(&mut client);
+        count_packets(&mut server, &mut packets);
+        true
+    });
+    assert_eq!(client.state(), ConnectionState::Connected);
+
+    // the Server stops answering, & pinging, having last done so at most a
+    // second in
+    let mut events = Vec::new();
+    clock.run_for(Duration::from_millis(3900), STEP, || {
+        while let Some(event) = client.receive().unwrap() {
+            events.push(event);
+        }
+        true
+    });
+    assert_eq!(client.state(), ConnectionState::Connected);
+
+    clock.run_for(Duration::from_millis(1200), STEP, || {
+        while let Some(event) = client.receive().unwrap() {
+            events.push(event);
+        }
+        true
+    });
+    assert_ne!(client.state(), ConnectionState::Connected);
+    assert!(events
+        .iter()
+        .any(|event| matches!(event, SocketEvent::Disconnection)));
+}
+
+#[test]
+fn version_mismatches_are_answered_once_a_second() {
+    let clock = VirtualClock::install();
+    let mut server = listen();
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client
+        .set_read_timeout(Some(Duration::from_millis(10)))
+        .unwrap();
+
+    // a connect request from a client speaking another version of the protocol
+    let mut request = vec![PacketType::ClientConnectRequest.to_byte()];
+    request.extend_from_slice(&handshake::PROTOCOL_MAGIC);
+    request.extend_from_slice(&(handshake::PROTOCOL_VERSION + 1).to_be_bytes());
+    request.resize(handshake::CONNECT_REQUEST_SIZE, 0);
+
+    let mut mismatches = 0;
+    let mut replies = 0;
+    let mut buffer = [0; 64];
+    clock.run_for(Duration::from_millis(2500), STEP, || {
+        client
+            .send_to(&request, server.local_addr().unwrap())
+            .unwrap();
+        std::thread::sleep(Duration::from_millis(1));
+        let mut events = Vec::new();
+        server.receive_many(&mut events, 64).unwrap();
+        mismatches += events
+            .iter()
+            .filter(|event| matches!(event, ServerSocketEvent::VersionMismatch { .. }))
+            .count();
+        while client.recv(&mut buffer).is_ok() {
+            replies += 1;
+        }
+        true
+    });
+    // at 0.1, 1.1 & 2.1 seconds in
+    assert_eq!(mismatches, 3);
+    assert_eq!(replies, 3);
+}
@@ -0,0 +1,304 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    convert::TryInto,
+    time::Duration,
+};
+
+use super::{
+    congestion::{CongestionConfig, CongestionController},
+    sequence::{ReceivedWindow, SequenceCheck},
+    Instant,
+};
+
+/// Acknowledged packets are preceded by their sequence number (u16), followed
+/// by an ack of the packets received from the other side
+pub const ACK_HEADER_SIZE: usize = 8;
+
+/// An ack is made of the latest sequence number received (u16) & a bitfield
+/// (u32) where bit `i` is set if the packet `i` before it was received too
+pub const ACK_SIZE: usize = 6;
+
+// The number of packets each ack covers, the latest one included
+const ACK_BITS: u16 = 32;
+
+// How much each new round trip moves the smoothed one, as in TCP
+const RTT_GAIN: f64 = 0.125;
+
+/// Settings for numbering the unreliable packets of a connection, so that the
+/// sender hears which of them arrived. Both sides of a connection must have
+/// acknowledgements enabled
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct AckConfig {
+    /// How long a packet may go without being acknowledged before it is
+    /// reported lost
+    pub loss_timeout: Duration,
+    /// How long to wait for a packet to carry an ack back to the other side,
+    /// before sending the ack on its own
+    pub ack_delay: Duration,
+}
+
+impl Default for AckConfig {
+    fn default() -> Self {
+        AckConfig {
+            loss_timeout: Duration::from_secs(1),
+            ack_delay: Duration::from_millis(50),
+        }
+    }
+}
+
+/// What became of a packet which was sent with an ack id
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Delivery {
+    /// The packet with the given ack id has been received by the other side
+    Acked(u64),
+    /// The packet with the given ack id is not known to have been received,
+    /// & is assumed lost
+    Lost(u64),
+}
+
+/// Numbers the packets sent over a single connection & keeps track of those
+/// received from the other side, so that each side can tell which of its
+/// packets arrived
+#[derive(Debug)]
+pub struct AckTracker {
+    config: AckConfig,
+    next_sequence: u16,
+    // packets sent with an ack id, by sequence number
+    sent: HashMap<u16, SentPacket>,
+    received: ReceivedWindow<u16>,
+    ack_pending_since: Option<Instant>,
+    deliveries: VecDeque<Delivery>,
+    congestion: Option<CongestionController>,
+    smoothed_rtt: Option<Duration>,
+    acked: u64,
+    lost: u64,
+}
+
+// Packets without an ack id are only kept when congestion control needs to
+// hear of them
+#[derive(Debug)]
+struct SentPacket {
+    ack_id: Option<u64>,
+    sent: Instant,
+}
+
+impl AckTracker {
+    /// Create a new AckTracker, which hasn't sent or received anything yet
+    pub fn new(config: AckConfig) -> Self {
+        AckTracker {
+            config,
+            next_sequence: 0,
+            sent: HashMap::new(),
+            received: ReceivedWindow::new(),
+            ack_pending_since: None,
+            deliveries: VecDeque::new(),
+            congestion: None,
+            smoothed_rtt: None,
+            acked: 0,
+            lost: 0,
+        }
+    }
+
+    /// Has the deliveries & losses of every packet sent, with an ack id or
+    /// not, feed a bandwidth estimate
+    pub fn with_congestion_control(mut self, config: CongestionConfig) -> Self {
+        self.congestion = Some(CongestionController::new(config));
+        self
+    }
+
+    /// Gets the bandwidth estimate, if congestion control is enabled
+    pub fn congestion(&self) -> Option<&CongestionController> {
+        self.congestion.as_ref()
+    }
+
+    /// Gets the smoothed round trip time, once a packet has been acknowledged
+    pub fn rtt(&self) -> Option<Duration> {
+        self.smoothed_rtt
+    }
+
+    /// Gets the number of packets followed which have been acknowledged.
+    /// Packets without an ack id are only followed under congestion control
+    pub fn acked_count(&self) -> u64 {
+        self.acked
+    }
+
+    /// Gets the number of packets followed which have been reported lost
+    pub fn lost_count(&self) -> u64 {
+        self.lost
+    }
+
+    /// Gets the header to send a packet with, noting the packet's ack id if it
+    /// has one so that its delivery is reported. The header carries an ack of
+    /// everything received so far
+    pub fn write_header(&mut self, ack_id: Option<u64>) -> [u8; ACK_HEADER_SIZE] {
+        let sequence = self.next_sequence;
+        self.next_sequence = sequence.wrapping_add(1);
+        // the previous packet with this number can no longer be acknowledged
+        if let Some(old) = self.sent.remove(&sequence) {
+            self.lost(old);
+        }
+        if ack_id.is_some() || self.congestion.is_some() {
+            self.sent.insert(
+                sequence,
+                SentPacket {
+                    ack_id,
+                    sent: Instant::now(),
+                },
+            );
+        }
+
+        let mut header = [0; ACK_HEADER_SIZE];
+        header[0..2].copy_from_slice(&sequence.to_be_bytes());
+        header[2..].copy_from_slice(&self.write_ack());
+        header
+    }
+
+    /// Gets an ack of everything received so far, to send on its own
+    pub fn write_ack(&mut self) -> [u8; ACK_SIZE] {
+        self.ack_pending_since = None;
+        let mut ack = [0; ACK_SIZE];
+        // only as many packets as the bitfield has room for are acknowledged
+        let bits = self.received.bits() as u32;
+        ack[0..2].copy_from_slice(&self.received.newest().unwrap_or(0).to_be_bytes());
+        ack[2..].copy_from_slice(&bits.to_be_bytes());
+        ack
+    }
+
+    /// Takes in the header of a received packet, returning false if the packet
+    /// has been received before & should be dropped
+    pub fn read_header(&mut self, header: &[u8]) -> bool {
+        if header.len() < ACK_HEADER_SIZE {
+            return false;
+        }
+        let sequence = u16::from_be_bytes(header[0..2].try_into().unwrap());
+        self.read_ack(&header[2..ACK_HEADER_SIZE]);
+
+        // packets too old to tell are let through, as they would be without acks
+        if self.received.check(sequence) == SequenceCheck::Duplicate {
+            return false;
+        }
+        if self.ack_pending_since.is_none() {
+            self.ack_pending_since = Some(Instant::now());
+        }
+        true
+    }
+
+    /// Takes in an ack from the other side, reporting the packets it covers as
+    /// delivered, & those it has left behind as lost
+    pub fn read_ack(&mut self, ack: &[u8]) {
+        if ack.len() < ACK_SIZE {
+            return;
+        }
+        let latest = u16::from_be_bytes(ack[0..2].try_into().unwrap());
+        let bits = u32::from_be_bytes(ack[2..ACK_SIZE].try_into().unwrap());
+        // the other side hasn't received anything yet
+        if bits == 0 {
+            return;
+        }
+
+        // the oldest are reported first
+        for index in (0..ACK_BITS).rev() {
+            if bits & (1 << index) == 0 {
+                continue;
+            }
+            if let Some(packet) = self.sent.remove(&latest.wrapping_sub(index)) {
+                self.acked(packet);
+            }
+        }
+
+        // packets sent before those covered by the ack never made it
+        let mut lost: Vec<u16> = self
+            .sent
+            .keys()
+            .copied()
+            .filter(|sequence| {
+                let behind = latest.wrapping_sub(*sequence);
+                (ACK_BITS..0x8000).contains(&behind)
+            })
+            .collect();
+        lost.sort_by_key(|sequence| latest.wrapping_sub(*sequence));
+        for sequence in lost.into_iter().rev() {
+            if let Some(packet) = self.sent.remove(&sequence) {
+                self.lost(packet);
+            }
+        }
+    }
+
+    /// Gets whether an ack has waited long enough for a packet to carry it,
+    /// & should be sent on its own
+    pub fn ack_due(&self) -> bool {
+        match &self.ack_pending_since {
+            Some(since) => since.elapsed() >= self.config.ack_delay,
+            None => false,
+        }
+    }
+
+    /// Gets how long until an ack is due to be sent, or a packet is due to be
+    /// reported lost, if either is expected
+    pub fn next_deadline_in(&self) -> Option<Duration> {
+        let ack = self
+            .ack_pending_since
+            .as_ref()
+            .map(|since| self.config.ack_delay.saturating_sub(since.elapsed()));
+        let loss = self
+            .sent
+            .values()
+            .map(|packet| {
+                self.config
+                    .loss_timeout
+                    .saturating_sub(packet.sent.elapsed())
+            })
+            .min();
+        match (ack, loss) {
+            (Some(ack), Some(loss)) => Some(ack.min(loss)),
+            (ack, loss) => ack.or(loss),
+        }
+    }
+
+    /// Gets the next packet whose delivery is known, reporting those which
+    /// have gone unacknowledged for too long as lost
+    pub fn poll_delivery(&mut self) -> Option<Delivery> {
+        if self.deliveries.is_empty() {
+            let loss_timeout = self.config.loss_timeout;
+            let mut lost: Vec<(u16, Duration)> = self
+                .sent
+                .iter()
+                .map(|(sequence, packet)| (*sequence, packet.sent.elapsed()))
+                .filter(|(_, elapsed)| *elapsed >= loss_timeout)
+                .collect();
+            // the oldest are reported first
+            lost.sort_by_key(|(_, elapsed)| *elapsed);
+            for (sequence, _) in lost.into_iter().rev() {
+                if let Some(packet) = self.sent.remove(&sequence) {
+                    self.lost(packet);
+                }
+            }
+        }
+        self.deliveries.pop_front()
+    }
+
+    fn acked(&mut self, packet: SentPacket) {
+        let rtt = packet.sent.elapsed();
+        self.smoothed_rtt = Some(match self.smoothed_rtt {
+            Some(smoothed_rtt) => smoothed_rtt.mul_f64(1.0 - RTT_GAIN) + rtt.mul_f64(RTT_GAIN),
+            None => rtt,
+        });
+        self.acked += 1;
+        if let Some(congestion) = &mut self.congestion {
+            congestion.on_acked(rtt);
+        }
+        if let Some(ack_id) = packet.ack_id {
+            self.deliveries.push_back(Delivery::Acked(ack_id));
+        }
+    }
+
+    fn lost(&mut self, packet: SentPacket) {
+        self.lost += 1;
+        if let Some(congestion) = &mut self.congestion {
+            congestion.on_lost();
+        }
+        if let Some(ack_id) = packet.ack_id {
+            self.deliveries.push_back(Delivery::Lost(ack_id));
+        }
+    }
+}
@@ -0,0 +1,87 @@
+use std::{
+    cell::Cell,
+    marker::PhantomData,
+    time::{Duration, Instant},
+};
+
+thread_local! {
+    static VIRTUAL_NOW: Cell<Option<Instant>> = const { Cell::new(None) };
+}
+
+/// Gets the time as the sockets' timers see it: the moment the method is
+/// called, unless a VirtualClock is installed on the current thread, in which
+/// case its time
+pub fn now() -> Instant {
+    VIRTUAL_NOW
+        .with(|virtual_now| virtual_now.get())
+        .unwrap_or_else(Instant::now)
+}
+
+/// Gets the time which has passed since the given Instant, as the sockets'
+/// timers see it
+pub fn elapsed(since: Instant) -> Duration {
+    now().saturating_duration_since(since)
+}
+
+/// A clock which stops time on the thread it is installed on, & only moves it
+/// forward when told to, so that tests can run the conditioner's delays,
+/// pings, keepalives & timeouts of a socket polled from that thread through
+/// seconds of time in milliseconds. Only native sockets read it, & a Server
+/// only follows it while polled with `try_receive` or `receive_many`, as the
+/// waits of its async methods still pass in real time. Time goes back to
+/// normal once it is dropped
+#[derive(Debug)]
+pub struct VirtualClock {
+    // time is kept per thread, so the clock can't be sent away from it
+    _thread: PhantomData<*const ()>,
+}
+
+impl VirtualClock {
+    /// Installs a VirtualClock on the current thread, stopping time at the
+    /// moment the method is called. Panics if one is already installed
+    pub fn install() -> Self {
+        VIRTUAL_NOW.with(|virtual_now| {
+            if virtual_now.get().is_some() {
+                panic!("a VirtualClock is already installed on this thread");
+            }
+            virtual_now.set(Some(Instant::now()));
+        });
+        VirtualClock {
+            _thread: PhantomData,
+        }
+    }
+
+    /// Gets the clock's current time
+    pub fn now(&self) -> Instant {
+        now()
+    }
+
+    /// Moves the clock's time forward by the given duration
+    pub fn advance(&self, duration: Duration) {
+        VIRTUAL_NOW.with(|virtual_now| {
+            virtual_now.set(virtual_now.get().map(|instant| instant + duration));
+        });
+    }
+
+    /// Moves the clock's time forward by `duration`, `step` at a time,
+    /// calling `poll` after each step, the way a game loop would poll a
+    /// socket once a frame. Stops early once `poll` returns false
+    pub fn run_for(&self, duration: Duration, step: Duration, mut poll: impl FnMut() -> bool) {
+        assert!(step > Duration::from_secs(0), "step must be more than zero");
+        let mut remaining = duration;
+        while remaining > Duration::from_secs(0) {
+            let step = step.min(remaining);
+            self.advance(step);
+            remaining -= step;
+            if !poll() {
+                return;
+            }
+        }
+    }
+}
+
+impl Drop for VirtualClock {
+    fn drop(&mut self) {
+        VIRTUAL_NOW.with(|virtual_now| virtual_now.set(None));
+    }
+}
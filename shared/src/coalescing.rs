@@ -0,0 +1,30 @@
+use std::{convert::TryInto, ops::Range};
+
+/// Each packet packed into a Coalesced datagram is preceded by its length, as
+/// a u16
+pub const SUBFRAME_HEADER_SIZE: usize = 2;
+
+/// Appends a packet to a Coalesced datagram being built
+pub fn write_subframe(buffer: &mut Vec<u8>, payload: &[u8]) {
+    buffer.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+    buffer.extend_from_slice(payload);
+}
+
+/// Finds where each packet packed into the given data starts & ends. Returns
+/// `None` if the data is malformed, in which case none of it can be trusted
+pub fn read_subframes(data: &[u8]) -> Option<Vec<Range<usize>>> {
+    let mut subframes = Vec::new();
+    let mut start = 0;
+    while start < data.len() {
+        let header = data.get(start..start + SUBFRAME_HEADER_SIZE)?;
+        let len = usize::from(u16::from_be_bytes(header.try_into().unwrap()));
+        let payload_start = start + SUBFRAME_HEADER_SIZE;
+        let payload_end = payload_start + len;
+        if payload_end > data.len() {
+            return None;
+        }
+        subframes.push(payload_start..payload_end);
+        start = payload_end;
+    }
+    Some(subframes)
+}
@@ -0,0 +1,61 @@
+use miniz_oxide::{deflate, inflate};
+
+/// When compression is enabled, each packet's payload is preceded by a byte
+/// telling whether it was compressed
+pub const COMPRESSION_HEADER_SIZE: usize = 1;
+
+/// Marks a payload which is sent as it is
+pub const STORED: u8 = 0;
+
+/// Marks a payload which has been compressed with DEFLATE
+pub const DEFLATED: u8 = 1;
+
+/// Settings for compressing the payloads of packets, which is agreed on while
+/// connecting, & only used if both sides of a connection have it enabled
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct CompressionConfig {
+    /// Packets smaller than this, in bytes, are sent as they are, as there is
+    /// little to gain from compressing them
+    pub threshold: usize,
+    /// How hard to compress, from 1 (fastest) to 10 (smallest)
+    pub level: u8,
+    /// The largest received packet which may be decompressed, in bytes.
+    /// Packets which would decompress to more than this are dropped
+    pub max_decompressed_size: usize,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        CompressionConfig {
+            threshold: 64,
+            level: 1,
+            max_decompressed_size: 0x10000,
+        }
+    }
+}
+
+/// Gets the payload to send in place of the given one, header included. It
+/// is only compressed if it is at least the threshold & compressing it makes
+/// it smaller
+pub fn compress(payload: &[u8], config: &CompressionConfig) -> Vec<u8> {
+    if payload.len() >= config.threshold {
+        let compressed = deflate::compress_to_vec(payload, config.level.clamp(1, 10));
+        if compressed.len() < payload.len() {
+            let mut output = Vec::with_capacity(COMPRESSION_HEADER_SIZE + compressed.len());
+            output.push(DEFLATED);
+            output.extend_from_slice(&compressed);
+            return output;
+        }
+    }
+
+    let mut output = Vec::with_capacity(COMPRESSION_HEADER_SIZE + payload.len());
+    output.push(STORED);
+    output.extend_from_slice(payload);
+    output
+}
+
+/// Decompresses a payload which was marked as DEFLATED, returning `None` if
+/// it is malformed or decompresses to more than `max_size` bytes
+pub fn decompress(compressed: &[u8], max_size: usize) -> Option<Vec<u8>> {
+    inflate::decompress_to_vec_with_limit(compressed, max_size).ok()
+}
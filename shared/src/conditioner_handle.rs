@@ -0,0 +1,103 @@
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use crate::LinkConditionerConfig;
+
+/// A handle onto the conditions a LinkConditioner simulates, which can change
+/// them, or turn conditioning off, while the socket is live. Clones share the
+/// same conditions
+#[derive(Debug, Clone)]
+pub struct ConditionerHandle {
+    conditions: Arc<Mutex<Conditions>>,
+}
+
+#[derive(Debug)]
+struct Conditions {
+    config: Option<LinkConditionerConfig>,
+    // conditions which stand in for `config` for particular remote addresses,
+    // `None` to leave those unconditioned
+    per_address: HashMap<SocketAddr, Option<LinkConditionerConfig>>,
+}
+
+impl ConditionerHandle {
+    /// Create a new ConditionerHandle, simulating the given conditions
+    pub fn new(config: &LinkConditionerConfig) -> Self {
+        ConditionerHandle {
+            conditions: Arc::new(Mutex::new(Conditions {
+                config: Some(config.clone()),
+                per_address: HashMap::new(),
+            })),
+        }
+    }
+
+    /// Gets the conditions being simulated, or `None` if conditioning is off
+    pub fn config(&self) -> Option<LinkConditionerConfig> {
+        self.conditions.lock().unwrap().config.clone()
+    }
+
+    /// Simulates the given conditions from now on, turning conditioning back
+    /// on if it was off
+    pub fn set_config(&self, config: &LinkConditionerConfig) {
+        self.conditions.lock().unwrap().config = Some(config.clone());
+    }
+
+    /// Changes the conditions being simulated in place, such as to raise the
+    /// latency alone. Does nothing while conditioning is off
+    pub fn update(&self, update: impl FnOnce(&mut LinkConditionerConfig)) {
+        if let Some(config) = self.conditions.lock().unwrap().config.as_mut() {
+            update(config);
+        }
+    }
+
+    /// Turns conditioning off, so that packets pass straight through. Those
+    /// already being delayed are still delivered when they are due
+    pub fn disable(&self) {
+        self.conditions.lock().unwrap().config = None;
+    }
+
+    /// Gets whether conditions are being simulated
+    pub fn is_enabled(&self) -> bool {
+        self.conditions.lock().unwrap().config.is_some()
+    }
+
+    /// Gets the conditions simulated on the packets of the given remote
+    /// address, which are its own if it has any, or otherwise those of
+    /// everyone else
+    pub fn config_for(&self, address: &SocketAddr) -> Option<LinkConditionerConfig> {
+        let conditions = self.conditions.lock().unwrap();
+        match conditions.per_address.get(address) {
+            Some(config) => config.clone(),
+            None => conditions.config.clone(),
+        }
+    }
+
+    /// Simulates the given conditions on the packets of one remote address,
+    /// such as to make a single player laggy. Only the Server's conditioner
+    /// tells remote addresses apart
+    pub fn set_config_for(&self, address: SocketAddr, config: &LinkConditionerConfig) {
+        self.conditions
+            .lock()
+            .unwrap()
+            .per_address
+            .insert(address, Some(config.clone()));
+    }
+
+    /// Leaves the packets of one remote address unconditioned, whatever is
+    /// simulated on everyone else's
+    pub fn disable_for(&self, address: SocketAddr) {
+        self.conditions
+            .lock()
+            .unwrap()
+            .per_address
+            .insert(address, None);
+    }
+
+    /// Goes back to simulating the same conditions on the packets of the
+    /// given remote address as on everyone else's
+    pub fn clear_config_for(&self, address: &SocketAddr) {
+        self.conditions.lock().unwrap().per_address.remove(address);
+    }
+}
@@ -0,0 +1,85 @@
+use crate::conditioner_trace::TraceEntry;
+
+/// The upper bounds, in milliseconds, of all but the last bucket of
+/// `ConditionerStats::latency_histogram`. The last bucket holds every
+/// latency of at least the final bound
+pub const LATENCY_BUCKETS: [u32; 7] = [10, 25, 50, 100, 250, 500, 1000];
+
+/// Counters describing what a LinkConditioner has done to the packets it has
+/// conditioned, so that tests can check the conditions were applied, & debug
+/// overlays can show them
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct ConditionerStats {
+    /// The number of packets conditioned. Packets which pass straight
+    /// through, as conditioning is off or they are on a channel, aren't
+    /// counted
+    pub conditioned: u64,
+    /// The number of copies of packets delayed & then delivered, which is
+    /// more than the packets kept when some are duplicated
+    pub delayed: u64,
+    /// The number of packets dropped, whether lost or turned away by a full
+    /// throttled link
+    pub dropped: u64,
+    /// The number of packets delivered twice
+    pub duplicated: u64,
+    /// The number of packets which had a bit flipped
+    pub corrupted: u64,
+    /// The number of packets which were cut short
+    pub truncated: u64,
+    /// The number of packets currently waiting to be delivered
+    pub queue_depth: usize,
+    /// The total latency added to delivered copies, in milliseconds
+    pub total_latency: u64,
+    /// The number of delivered copies whose added latency fell into each of
+    /// the buckets bounded by `LATENCY_BUCKETS`
+    pub latency_histogram: [u64; LATENCY_BUCKETS.len() + 1],
+}
+
+impl ConditionerStats {
+    /// Create a new ConditionerStats, with every count at zero
+    pub fn new() -> Self {
+        ConditionerStats::default()
+    }
+
+    /// Gets the mean latency added to delivered copies, in milliseconds, if
+    /// any have been delivered
+    pub fn mean_latency(&self) -> Option<f64> {
+        if self.delayed == 0 {
+            return None;
+        }
+        Some(self.total_latency as f64 / self.delayed as f64)
+    }
+
+    /// Gets a copy of the counters, with the queue depth set to the given
+    /// number of packets waiting
+    pub fn with_queue_depth(mut self, queue_depth: usize) -> Self {
+        self.queue_depth = queue_depth;
+        self
+    }
+
+    // Counts what was decided for a packet
+    pub(crate) fn record(&mut self, entry: &TraceEntry) {
+        self.conditioned += 1;
+        if entry.delays.is_empty() {
+            self.dropped += 1;
+        }
+        if entry.delays.len() > 1 {
+            self.duplicated += 1;
+        }
+        if entry.corrupted_bit.is_some() {
+            self.corrupted += 1;
+        }
+        if entry.truncated_to.is_some() {
+            self.truncated += 1;
+        }
+        for delay in entry.delays.iter() {
+            self.delayed += 1;
+            self.total_latency += u64::from(*delay);
+            let bucket = LATENCY_BUCKETS
+                .iter()
+                .position(|bound| delay < bound)
+                .unwrap_or(LATENCY_BUCKETS.len());
+            self.latency_histogram[bucket] += 1;
+        }
+    }
+}
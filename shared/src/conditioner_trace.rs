@@ -0,0 +1,216 @@
+use std::{
+    collections::VecDeque,
+    fmt,
+    io::{self, Write},
+    sync::{Arc, Mutex},
+};
+#[cfg(not(target_arch = "wasm32"))]
+use std::{fs, path::Path};
+
+use log::warn;
+
+use crate::Instant;
+
+const TRACE_HEADER: &str = "# naia link conditioner trace v1";
+
+/// A record of what a LinkConditioner decided for each packet it conditioned,
+/// which is either being written as packets arrive, or being replayed so that
+/// a later run drops, delays & tampers with its packets exactly as the
+/// recorded one did. Replayed decisions are taken in the order packets
+/// arrive, so the packets should arrive in the same order as they did when
+/// recorded. Once a replayed trace runs out, packets are conditioned as
+/// usual. Clones share the same trace
+#[derive(Clone)]
+pub struct ConditionerTrace {
+    mode: Arc<Mutex<Mode>>,
+}
+
+enum Mode {
+    Recording {
+        began: Instant,
+        output: Box<dyn Write + Send>,
+    },
+    Replaying {
+        entries: VecDeque<TraceEntry>,
+    },
+    // recording has failed, so there's nothing more to do
+    Stopped,
+}
+
+/// What was decided for a single packet
+#[derive(Debug, Clone, Default)]
+pub(crate) struct TraceEntry {
+    // the size of the packet's payload as it arrived
+    pub(crate) size: usize,
+    // the number of milliseconds each copy of the packet was delayed by, none
+    // if the packet was dropped, or two if it was duplicated
+    pub(crate) delays: Vec<u32>,
+    // the bit flipped to corrupt the packet, if it was
+    pub(crate) corrupted_bit: Option<u32>,
+    // the length the packet was cut down to, if it was
+    pub(crate) truncated_to: Option<u32>,
+}
+
+impl ConditionerTrace {
+    /// Create a new ConditionerTrace, which writes a line to the given output
+    /// for every packet conditioned
+    pub fn record(mut output: impl Write + Send + 'static) -> io::Result<Self> {
+        writeln!(output, "{}", TRACE_HEADER)?;
+        writeln!(
+            output,
+            "# arrived_at_ms size delays_ms corrupted_bit truncated_to"
+        )?;
+        Ok(ConditionerTrace::from_mode(Mode::Recording {
+            began: Instant::now(),
+            output: Box::new(output),
+        }))
+    }
+
+    /// Create a new ConditionerTrace, which records to the file at the given
+    /// path, replacing anything already there
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn record_to_file(path: impl AsRef<Path>) -> io::Result<Self> {
+        ConditionerTrace::record(fs::File::create(path)?)
+    }
+
+    /// Create a new ConditionerTrace, which replays the decisions in the
+    /// given trace, as it was recorded
+    pub fn replay(trace: &str) -> io::Result<Self> {
+        let mut entries = VecDeque::new();
+        for (index, line) in trace.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let entry = parse_entry(line).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("link conditioner trace line {} is malformed", index + 1),
+                )
+            })?;
+            entries.push_back(entry);
+        }
+        Ok(ConditionerTrace::from_mode(Mode::Replaying { entries }))
+    }
+
+    /// Create a new ConditionerTrace, which replays the trace in the file at
+    /// the given path
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn replay_file(path: impl AsRef<Path>) -> io::Result<Self> {
+        ConditionerTrace::replay(&fs::read_to_string(path)?)
+    }
+
+    /// Gets whether the trace is being replayed, rather than recorded
+    pub fn is_replaying(&self) -> bool {
+        matches!(*self.mode.lock().unwrap(), Mode::Replaying { .. })
+    }
+
+    /// Gets the number of decisions left to replay
+    pub fn remaining(&self) -> usize {
+        match &*self.mode.lock().unwrap() {
+            Mode::Replaying { entries } => entries.len(),
+            _ => 0,
+        }
+    }
+
+    fn from_mode(mode: Mode) -> Self {
+        ConditionerTrace {
+            mode: Arc::new(Mutex::new(mode)),
+        }
+    }
+
+    // Takes the next decision to replay, if the trace is being replayed & has
+    // any left
+    pub(crate) fn next_entry(&self) -> Option<TraceEntry> {
+        match &mut *self.mode.lock().unwrap() {
+            Mode::Replaying { entries } => {
+                let entry = entries.pop_front();
+                if entry.is_some() && entries.is_empty() {
+                    warn!("link conditioner trace has been replayed in full");
+                }
+                entry
+            }
+            _ => None,
+        }
+    }
+
+    // Writes down a decision, if the trace is being recorded
+    pub(crate) fn record_entry(&self, entry: &TraceEntry) {
+        let mut mode = self.mode.lock().unwrap();
+        if let Mode::Recording { began, output } = &mut *mode {
+            let line = format!(
+                "{} {} {} {} {}\n",
+                began.elapsed().as_millis(),
+                entry.size,
+                format_delays(&entry.delays),
+                format_optional(entry.corrupted_bit),
+                format_optional(entry.truncated_to)
+            );
+            if let Err(error) = output.write_all(line.as_bytes()) {
+                warn!("couldn't record link conditioner trace: {}", error);
+                *mode = Mode::Stopped;
+            }
+        }
+    }
+}
+
+impl fmt::Debug for ConditionerTrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mode = match &*self.mode.lock().unwrap() {
+            Mode::Recording { .. } => "Recording",
+            Mode::Replaying { .. } => "Replaying",
+            Mode::Stopped => "Stopped",
+        };
+        f.debug_struct("ConditionerTrace")
+            .field("mode", &mode)
+            .finish()
+    }
+}
+
+fn format_delays(delays: &[u32]) -> String {
+    if delays.is_empty() {
+        return "-".to_string();
+    }
+    let delays: Vec<String> = delays.iter().map(u32::to_string).collect();
+    delays.join(",")
+}
+
+fn format_optional(value: Option<u32>) -> String {
+    match value {
+        Some(value) => value.to_string(),
+        None => "-".to_string(),
+    }
+}
+
+// Reads a line back, skipping over the moment the packet arrived, which is
+// only written down to help make sense of a trace
+fn parse_entry(line: &str) -> Option<TraceEntry> {
+    let mut fields = line.split_whitespace();
+    let _arrived_at: u64 = fields.next()?.parse().ok()?;
+    let size = fields.next()?.parse().ok()?;
+    let delays = match fields.next()? {
+        "-" => Vec::new(),
+        delays => delays
+            .split(',')
+            .map(|delay| delay.parse().ok())
+            .collect::<Option<Vec<u32>>>()?,
+    };
+    let corrupted_bit = parse_optional(fields.next()?)?;
+    let truncated_to = parse_optional(fields.next()?)?;
+    if fields.next().is_some() {
+        return None;
+    }
+    Some(TraceEntry {
+        size,
+        delays,
+        corrupted_bit,
+        truncated_to,
+    })
+}
+
+fn parse_optional(field: &str) -> Option<Option<u32>> {
+    if field == "-" {
+        return Some(None);
+    }
+    field.parse().ok().map(Some)
+}
@@ -0,0 +1,114 @@
+use std::time::Duration;
+
+use super::Instant;
+
+// The weight given to each new round trip time sample, as in TCP
+const RTT_GAIN: f64 = 0.125;
+
+/// Settings for estimating how much bandwidth a connection can take, from the
+/// packets it loses & how long they take to be acknowledged. The estimate
+/// grows steadily while packets get through, & is cut back whenever some are
+/// lost
+#[derive(Debug, Clone, PartialEq)]
+pub struct CongestionConfig {
+    /// The estimate a connection starts with, in bytes per second
+    pub initial_bandwidth: usize,
+    /// The estimate never drops below this, in bytes per second
+    pub min_bandwidth: usize,
+    /// The estimate never grows past this, in bytes per second
+    pub max_bandwidth: usize,
+    /// How much the estimate grows by every round trip without a loss, in
+    /// bytes per second
+    pub additive_increase: usize,
+    /// What the estimate is multiplied by when packets are lost, at most once
+    /// per round trip
+    pub multiplicative_decrease: f64,
+}
+
+impl Default for CongestionConfig {
+    fn default() -> Self {
+        CongestionConfig {
+            initial_bandwidth: 64_000,
+            min_bandwidth: 8_000,
+            max_bandwidth: 1_250_000,
+            additive_increase: 4_000,
+            multiplicative_decrease: 0.5,
+        }
+    }
+}
+
+/// Keeps the bandwidth estimate of a single connection, following the
+/// deliveries & losses of the packets sent over it
+#[derive(Debug)]
+pub struct CongestionController {
+    config: CongestionConfig,
+    bandwidth: f64,
+    smoothed_rtt: Option<Duration>,
+    last_increase: Instant,
+    last_decrease: Option<Instant>,
+}
+
+impl CongestionController {
+    /// Create a new CongestionController, starting from the initial estimate
+    pub fn new(config: CongestionConfig) -> Self {
+        let bandwidth = config.initial_bandwidth.clamp(
+            config.min_bandwidth,
+            config.max_bandwidth.max(config.min_bandwidth),
+        ) as f64;
+        CongestionController {
+            config,
+            bandwidth,
+            smoothed_rtt: None,
+            last_increase: Instant::now(),
+            last_decrease: None,
+        }
+    }
+
+    /// Gets the number of bytes per second the connection is estimated to be
+    /// able to take
+    pub fn bandwidth(&self) -> usize {
+        self.bandwidth as usize
+    }
+
+    /// Gets the smoothed round trip time, once a packet has been acknowledged
+    pub fn rtt(&self) -> Option<Duration> {
+        self.smoothed_rtt
+    }
+
+    /// Takes in the acknowledgement of a packet sent the given time ago
+    pub fn on_acked(&mut self, rtt: Duration) {
+        let smoothed_rtt = match self.smoothed_rtt {
+            Some(smoothed_rtt) => smoothed_rtt.mul_f64(1.0 - RTT_GAIN) + rtt.mul_f64(RTT_GAIN),
+            None => rtt,
+        };
+        self.smoothed_rtt = Some(smoothed_rtt);
+
+        // grows by the additive increase over each round trip
+        let elapsed = self.last_increase.elapsed();
+        self.last_increase = Instant::now();
+        let round_trips = elapsed.as_secs_f64() / smoothed_rtt.as_secs_f64().max(0.001);
+        self.bandwidth = (self.bandwidth + self.config.additive_increase as f64 * round_trips)
+            .min(self.max_bandwidth());
+    }
+
+    /// Takes in the loss of a packet. Losses within a round trip of the last
+    /// cut are taken to be part of the same congestion
+    pub fn on_lost(&mut self) {
+        let rtt = self
+            .smoothed_rtt
+            .unwrap_or_else(|| Duration::from_millis(100));
+        if let Some(last_decrease) = &self.last_decrease {
+            if last_decrease.elapsed() < rtt {
+                return;
+            }
+        }
+        self.last_decrease = Some(Instant::now());
+        self.bandwidth = (self.bandwidth * self.config.multiplicative_decrease)
+            .max(self.config.min_bandwidth as f64)
+            .min(self.max_bandwidth());
+    }
+
+    fn max_bandwidth(&self) -> f64 {
+        self.config.max_bandwidth.max(self.config.min_bandwidth) as f64
+    }
+}
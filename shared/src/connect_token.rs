@@ -0,0 +1,137 @@
+use std::{
+    convert::TryInto,
+    error::Error,
+    fmt,
+    time::{Duration, SystemTime},
+};
+
+use hmac::{Hmac, Mac, NewMac};
+use sha2::Sha256;
+
+/// The secret key shared between the service which mints connect tokens & the
+/// Server Socket which validates them
+pub type ConnectTokenKey = [u8; 32];
+
+/// The maximum number of bytes of user data a ConnectToken can carry
+pub const MAX_CONNECT_TOKEN_USER_DATA: usize = 256;
+
+const MAC_SIZE: usize = 32;
+const FIXED_SIZE: usize = 8 + 8 + 2;
+
+/// A short-lived token, signed by a backend service, that a client must
+/// present to the Server Socket before it will be accepted. The client treats
+/// the encoded token as opaque bytes
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ConnectToken {
+    /// The identity of the client the token was minted for
+    pub client_id: u64,
+    /// The time at which the token stops being valid, in seconds since the
+    /// Unix epoch
+    pub expire_timestamp: u64,
+    /// Application-defined data which is passed along to the server, at most
+    /// `MAX_CONNECT_TOKEN_USER_DATA` bytes long
+    pub user_data: Vec<u8>,
+}
+
+impl ConnectToken {
+    /// Creates a new ConnectToken for the given client, valid from now until
+    /// `lifetime` has elapsed
+    pub fn new(client_id: u64, lifetime: Duration, user_data: Vec<u8>) -> Self {
+        ConnectToken {
+            client_id,
+            expire_timestamp: unix_time() + lifetime.as_secs(),
+            user_data,
+        }
+    }
+
+    /// Returns whether the token has passed its expiry time
+    pub fn is_expired(&self) -> bool {
+        unix_time() >= self.expire_timestamp
+    }
+
+    /// Serializes the token & signs it with the given key, producing the bytes
+    /// which should be handed to the client
+    pub fn encode(&self, key: &ConnectTokenKey) -> Result<Vec<u8>, ConnectTokenError> {
+        if self.user_data.len() > MAX_CONNECT_TOKEN_USER_DATA {
+            return Err(ConnectTokenError::Malformed);
+        }
+
+        let mut buffer = Vec::with_capacity(FIXED_SIZE + self.user_data.len() + MAC_SIZE);
+        buffer.extend_from_slice(&self.client_id.to_be_bytes());
+        buffer.extend_from_slice(&self.expire_timestamp.to_be_bytes());
+        buffer.extend_from_slice(&(self.user_data.len() as u16).to_be_bytes());
+        buffer.extend_from_slice(&self.user_data);
+
+        let mut mac = new_mac(key);
+        mac.update(&buffer);
+        buffer.extend_from_slice(&mac.finalize().into_bytes());
+
+        Ok(buffer)
+    }
+
+    /// Verifies the signature on an encoded token and that it hasn't expired,
+    /// returning the decoded token if so
+    pub fn decode(buffer: &[u8], key: &ConnectTokenKey) -> Result<ConnectToken, ConnectTokenError> {
+        if buffer.len() < FIXED_SIZE + MAC_SIZE {
+            return Err(ConnectTokenError::Malformed);
+        }
+        let user_data_len = u16::from_be_bytes(buffer[16..18].try_into().unwrap()) as usize;
+        if user_data_len > MAX_CONNECT_TOKEN_USER_DATA
+            || buffer.len() != FIXED_SIZE + user_data_len + MAC_SIZE
+        {
+            return Err(ConnectTokenError::Malformed);
+        }
+
+        let (body, signature) = buffer.split_at(buffer.len() - MAC_SIZE);
+        let mut mac = new_mac(key);
+        mac.update(body);
+        if mac.verify(signature).is_err() {
+            return Err(ConnectTokenError::InvalidSignature);
+        }
+
+        let token = ConnectToken {
+            client_id: u64::from_be_bytes(body[0..8].try_into().unwrap()),
+            expire_timestamp: u64::from_be_bytes(body[8..16].try_into().unwrap()),
+            user_data: body[FIXED_SIZE..].to_vec(),
+        };
+        if token.is_expired() {
+            return Err(ConnectTokenError::Expired);
+        }
+
+        Ok(token)
+    }
+}
+
+/// The reasons an encoded ConnectToken can be rejected
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ConnectTokenError {
+    /// The token is not laid out correctly, or carries too much user data
+    Malformed,
+    /// The token was not signed with the expected key
+    InvalidSignature,
+    /// The token is past its expiry time
+    Expired,
+}
+
+impl fmt::Display for ConnectTokenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            ConnectTokenError::Malformed => write!(f, "malformed connect token"),
+            ConnectTokenError::InvalidSignature => write!(f, "connect token signature is invalid"),
+            ConnectTokenError::Expired => write!(f, "connect token has expired"),
+        }
+    }
+}
+
+impl Error for ConnectTokenError {}
+
+fn new_mac(key: &ConnectTokenKey) -> Hmac<Sha256> {
+    Hmac::<Sha256>::new_varkey(key).expect("HMAC can take a key of any size")
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .expect("timing error!")
+        .as_secs()
+}
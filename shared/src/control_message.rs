@@ -0,0 +1,46 @@
+use std::net::SocketAddr;
+
+use super::hex;
+
+const KICKED_PREFIX: &str = "kicked:";
+const HOST_MIGRATION_PREFIX: &str = "host_migration:";
+
+/// A message a WebRTC server sends to a client about the connection itself,
+/// rather than on behalf of the application. These are sent as text, so
+/// application text mustn't begin the way they do, see `is_reserved`
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ControlMessage {
+    /// The server is closing the connection, for the given reason
+    Kicked(Vec<u8>),
+    /// The client should move over to the server at the given address
+    HostMigration(SocketAddr),
+}
+
+impl ControlMessage {
+    /// Writes the message as text
+    pub fn encode(&self) -> String {
+        match self {
+            ControlMessage::Kicked(reason) => format!("{}{}", KICKED_PREFIX, hex::encode(reason)),
+            ControlMessage::HostMigration(address) => {
+                format!("{}{}", HOST_MIGRATION_PREFIX, address)
+            }
+        }
+    }
+
+    /// Gets whether text begins the way a message does, so that it would be
+    /// taken for one rather than passed on to the application
+    pub fn is_reserved(text: &str) -> bool {
+        text.starts_with(KICKED_PREFIX) || text.starts_with(HOST_MIGRATION_PREFIX)
+    }
+
+    /// Reads a message written by `encode`, if it is a valid one
+    pub fn decode(text: &str) -> Option<ControlMessage> {
+        if let Some(reason) = text.strip_prefix(KICKED_PREFIX) {
+            return hex::decode(reason).map(ControlMessage::Kicked);
+        }
+        if let Some(address) = text.strip_prefix(HOST_MIGRATION_PREFIX) {
+            return address.parse().ok().map(ControlMessage::HostMigration);
+        }
+        None
+    }
+}
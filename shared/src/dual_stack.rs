@@ -0,0 +1,49 @@
+use std::{
+    io::Error,
+    net::{IpAddr, SocketAddr, UdpSocket},
+};
+
+use socket2::{Domain, Protocol, SockAddr, Socket, Type};
+
+/// Gets the address a peer is known by, which is its IPv4 address if it
+/// reached an IPv6 socket through an IPv4-mapped one, so that it is the same
+/// whichever kind of socket it reached
+pub fn canonical_address(address: SocketAddr) -> SocketAddr {
+    match address {
+        SocketAddr::V6(v6_address) => match v6_address.ip().to_ipv4_mapped() {
+            Some(ip) => SocketAddr::new(IpAddr::V4(ip), address.port()),
+            None => address,
+        },
+        SocketAddr::V4(_) => address,
+    }
+}
+
+/// Gets the address to send to a peer at from a socket, which is its
+/// IPv4-mapped address if the socket is an IPv6 one & the peer has an IPv4
+/// address
+pub fn address_for_socket(address: SocketAddr, ipv6_socket: bool) -> SocketAddr {
+    match canonical_address(address) {
+        SocketAddr::V4(v4_address) if ipv6_socket => {
+            SocketAddr::new(IpAddr::V6(v4_address.ip().to_ipv6_mapped()), address.port())
+        }
+        address => address,
+    }
+}
+
+/// Binds a UDP socket at the given address. A socket bound to the
+/// unspecified IPv6 address, `[::]`, is dual-stack, reaching IPv4 peers too,
+/// on every platform rather than only those where that is the default
+pub fn bind_udp(address: SocketAddr) -> Result<UdpSocket, Error> {
+    let socket = Socket::new(
+        Domain::for_address(address),
+        Type::DGRAM,
+        Some(Protocol::UDP),
+    )?;
+    if let IpAddr::V6(ip) = address.ip() {
+        if ip.is_unspecified() {
+            socket.set_only_v6(false)?;
+        }
+    }
+    socket.bind(&SockAddr::from(address))?;
+    Ok(socket.into())
+}
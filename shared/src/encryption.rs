@@ -0,0 +1,325 @@
+use std::fmt;
+
+use hmac::{Hmac, Mac, NewMac};
+use rand::{rngs::OsRng, RngCore};
+use sha2::Sha256;
+
+use super::{handshake, x25519, xchacha20poly1305};
+
+/// The size of the public key each side sends the other during the handshake
+pub const PUBLIC_KEY_SIZE: usize = 32;
+
+/// The number of bytes a sealed packet grows by, as its payload is followed
+/// by a tag which authenticates it
+pub const TAG_SIZE: usize = xchacha20poly1305::TAG_SIZE;
+
+const CLIENT_TO_SERVER_LABEL: &[u8] = b"naia client to server";
+const SERVER_TO_CLIENT_LABEL: &[u8] = b"naia server to client";
+
+/// How the packets of a protected connection are sealed
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Protection {
+    /// Payloads are encrypted & authenticated with XChaCha20-Poly1305
+    Encrypted,
+    /// Payloads are sent as they are, followed by an HMAC-SHA256 tag, cut
+    /// down to `TAG_SIZE`, which authenticates them along with their header.
+    /// This is cheaper than encrypting them
+    Authenticated,
+}
+
+impl Protection {
+    /// Gets the protection asked for or agreed to by the given handshake
+    /// feature flags, if any
+    pub fn from_features(features: u8) -> Option<Protection> {
+        if features & handshake::FEATURE_ENCRYPTION != 0 {
+            return Some(Protection::Encrypted);
+        }
+        if features & handshake::FEATURE_AUTHENTICATION != 0 {
+            return Some(Protection::Authenticated);
+        }
+        None
+    }
+
+    /// Gets the handshake feature flag which stands for this protection
+    pub fn feature(self) -> u8 {
+        match self {
+            Protection::Encrypted => handshake::FEATURE_ENCRYPTION,
+            Protection::Authenticated => handshake::FEATURE_AUTHENTICATION,
+        }
+    }
+}
+
+/// A Server's long-lived key pair. Clients given its public key ahead of
+/// time can pin it, in which case it is mixed into the keys of their
+/// connections, so that only a Server holding the secret can complete their
+/// handshakes & a man in the middle can't stand in for it
+#[derive(Clone)]
+pub struct ServerIdentity {
+    secret: [u8; 32],
+    public_key: [u8; PUBLIC_KEY_SIZE],
+}
+
+impl ServerIdentity {
+    /// Create a new ServerIdentity, with a secret picked at random
+    pub fn generate() -> Self {
+        let mut secret = [0; 32];
+        OsRng.fill_bytes(&mut secret);
+        ServerIdentity::from_secret(secret)
+    }
+
+    /// Create a ServerIdentity from a secret kept from an earlier `generate`,
+    /// so that its public key stays the same across restarts
+    pub fn from_secret(secret: [u8; 32]) -> Self {
+        let public_key = x25519::x25519(&secret, &x25519::BASE_POINT);
+        ServerIdentity { secret, public_key }
+    }
+
+    /// Gets the secret, to be stored somewhere safe
+    pub fn secret(&self) -> &[u8; 32] {
+        &self.secret
+    }
+
+    /// Gets the public key, to be given to clients to pin
+    pub fn public_key(&self) -> &[u8; PUBLIC_KEY_SIZE] {
+        &self.public_key
+    }
+}
+
+impl fmt::Debug for ServerIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ServerIdentity")
+            .field("public_key", &self.public_key)
+            .finish()
+    }
+}
+
+/// One side's half of the key exchange which begins a protected connection.
+/// Each side sends the other its public key, & derives the connection's keys
+/// from the public key it gets back. Both sides make a new one for every
+/// connection, so every connection's keys are fresh. Without a pinned
+/// `ServerIdentity` the exchange is anonymous: it guards against
+/// eavesdropping, but not against a man in the middle
+pub struct KeyExchange {
+    secret: [u8; 32],
+    public_key: [u8; PUBLIC_KEY_SIZE],
+}
+
+impl KeyExchange {
+    /// Create a new KeyExchange, with a secret picked at random
+    pub fn new() -> Self {
+        let mut secret = [0; 32];
+        OsRng.fill_bytes(&mut secret);
+        let public_key = x25519::x25519(&secret, &x25519::BASE_POINT);
+        KeyExchange { secret, public_key }
+    }
+
+    /// Gets the public key to send to the other side
+    pub fn public_key(&self) -> &[u8; PUBLIC_KEY_SIZE] {
+        &self.public_key
+    }
+
+    /// Derives a client's keys from the public key the server sent back, &
+    /// the public key of the server's identity if it is pinned. Returns `None`
+    /// if either key is one which could have been picked to force a known
+    /// shared secret
+    pub fn client_keys(
+        &self,
+        server_public_key: &[u8; PUBLIC_KEY_SIZE],
+        pinned_key: Option<&[u8; PUBLIC_KEY_SIZE]>,
+        protection: Protection,
+    ) -> Option<SessionKeys> {
+        let shared_secret = self.shared_secret(server_public_key)?;
+        let identity_secret = match pinned_key {
+            Some(pinned_key) => Some(self.shared_secret(pinned_key)?),
+            None => None,
+        };
+        let derive = |label| {
+            derive_key(
+                &shared_secret,
+                identity_secret.as_ref(),
+                label,
+                &self.public_key,
+                server_public_key,
+            )
+        };
+        Some(SessionKeys {
+            protection,
+            send: derive(CLIENT_TO_SERVER_LABEL),
+            receive: derive(SERVER_TO_CLIENT_LABEL),
+        })
+    }
+
+    /// Derives the server's keys for a connection from the public key the
+    /// client sent, & the server's identity if the client pinned it. Returns
+    /// `None` if the key is one which could have been picked to force a known
+    /// shared secret
+    pub fn server_keys(
+        &self,
+        client_public_key: &[u8; PUBLIC_KEY_SIZE],
+        identity: Option<&ServerIdentity>,
+        protection: Protection,
+    ) -> Option<SessionKeys> {
+        let shared_secret = self.shared_secret(client_public_key)?;
+        let identity_secret = match identity {
+            Some(identity) => Some(shared_secret_of(&identity.secret, client_public_key)?),
+            None => None,
+        };
+        let derive = |label| {
+            derive_key(
+                &shared_secret,
+                identity_secret.as_ref(),
+                label,
+                client_public_key,
+                &self.public_key,
+            )
+        };
+        Some(SessionKeys {
+            protection,
+            send: derive(SERVER_TO_CLIENT_LABEL),
+            receive: derive(CLIENT_TO_SERVER_LABEL),
+        })
+    }
+
+    fn shared_secret(&self, public_key: &[u8; PUBLIC_KEY_SIZE]) -> Option<[u8; 32]> {
+        shared_secret_of(&self.secret, public_key)
+    }
+}
+
+impl Default for KeyExchange {
+    fn default() -> Self {
+        KeyExchange::new()
+    }
+}
+
+impl fmt::Debug for KeyExchange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyExchange")
+            .field("public_key", &self.public_key)
+            .finish()
+    }
+}
+
+// Gets the secret shared with the holder of the given public key, unless it
+// is all zeros, as low order keys give
+fn shared_secret_of(secret: &[u8; 32], public_key: &[u8; PUBLIC_KEY_SIZE]) -> Option<[u8; 32]> {
+    let shared_secret = x25519::x25519(secret, public_key);
+    let combined = shared_secret
+        .iter()
+        .fold(0, |combined, byte| combined | byte);
+    if combined == 0 {
+        return None;
+    }
+    Some(shared_secret)
+}
+
+// The secret shared through the server's identity, if pinned, is mixed in
+// after the one shared by the two sides' fresh keys
+fn derive_key(
+    shared_secret: &[u8; 32],
+    identity_secret: Option<&[u8; 32]>,
+    label: &[u8],
+    client_public_key: &[u8; PUBLIC_KEY_SIZE],
+    server_public_key: &[u8; PUBLIC_KEY_SIZE],
+) -> [u8; 32] {
+    let mut mac = Hmac::<Sha256>::new_varkey(shared_secret).expect("HMAC takes a key of any size");
+    if let Some(identity_secret) = identity_secret {
+        mac.update(identity_secret);
+    }
+    mac.update(label);
+    mac.update(client_public_key);
+    mac.update(server_public_key);
+    let mut key = [0; 32];
+    key.copy_from_slice(&mac.finalize().into_bytes());
+    key
+}
+
+/// The keys one side of a protected connection seals the packets it sends
+/// with, & opens those it receives with. Every packet is sealed under a
+/// different sequence number, which the nonce is made from when encrypting,
+/// so a sequence number must never be used twice with the same keys
+pub struct SessionKeys {
+    protection: Protection,
+    send: [u8; 32],
+    receive: [u8; 32],
+}
+
+impl SessionKeys {
+    /// Gets how packets are sealed with these keys
+    pub fn protection(&self) -> Protection {
+        self.protection
+    }
+
+    /// Seals a packet's payload, returning it, encrypted if need be, followed
+    /// by a tag authenticating it along with the header it is sent behind
+    pub fn seal(&self, sequence: u64, header: &[u8], payload: &[u8]) -> Vec<u8> {
+        match self.protection {
+            Protection::Encrypted => {
+                xchacha20poly1305::seal(&self.send, &nonce(sequence), header, payload)
+            }
+            Protection::Authenticated => {
+                let mut sealed = Vec::with_capacity(payload.len() + TAG_SIZE);
+                sealed.extend_from_slice(payload);
+                sealed.extend_from_slice(&hmac_tag(&self.send, sequence, header, payload));
+                sealed
+            }
+        }
+    }
+
+    /// Opens a received packet's payload, returning `None` if it or the
+    /// header it was sent behind have been tampered with, or it wasn't sealed
+    /// with the other side's keys
+    pub fn open(&self, sequence: u64, header: &[u8], sealed: &[u8]) -> Option<Vec<u8>> {
+        match self.protection {
+            Protection::Encrypted => {
+                xchacha20poly1305::open(&self.receive, &nonce(sequence), header, sealed)
+            }
+            Protection::Authenticated => {
+                if sealed.len() < TAG_SIZE {
+                    return None;
+                }
+                let (payload, tag) = sealed.split_at(sealed.len() - TAG_SIZE);
+                let expected_tag = hmac_tag(&self.receive, sequence, header, payload);
+                let difference = expected_tag
+                    .iter()
+                    .zip(tag)
+                    .fold(0, |difference, (a, b)| difference | (a ^ b));
+                if difference != 0 {
+                    return None;
+                }
+                Some(payload.to_vec())
+            }
+        }
+    }
+}
+
+impl fmt::Debug for SessionKeys {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SessionKeys")
+            .field("protection", &self.protection)
+            .finish()
+    }
+}
+
+// The first 16 bytes of every nonce are zero, & only the sequence number
+// tells nonces apart. That is only safe because no keys outlive their
+// connection: every connection's keys are derived from fresh key exchange
+// secrets on both sides, so a (key, nonce) pair is never used twice as long
+// as a connection never reuses a sequence number
+fn nonce(sequence: u64) -> [u8; xchacha20poly1305::NONCE_SIZE] {
+    let mut nonce = [0; xchacha20poly1305::NONCE_SIZE];
+    nonce[16..].copy_from_slice(&sequence.to_be_bytes());
+    nonce
+}
+
+// Covers the packet's sequence number as well as its header & payload, with
+// the header's length so that bytes can't be moved from one to the other
+fn hmac_tag(key: &[u8; 32], sequence: u64, header: &[u8], payload: &[u8]) -> [u8; TAG_SIZE] {
+    let mut mac = Hmac::<Sha256>::new_varkey(key).expect("HMAC takes a key of any size");
+    mac.update(&sequence.to_be_bytes());
+    mac.update(&(header.len() as u16).to_be_bytes());
+    mac.update(header);
+    mac.update(payload);
+    let mut tag = [0; TAG_SIZE];
+    tag.copy_from_slice(&mac.finalize().into_bytes()[..TAG_SIZE]);
+    tag
+}
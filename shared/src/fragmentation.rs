@@ -0,0 +1,156 @@
+use std::{collections::HashMap, convert::TryInto, time::Duration};
+
+use super::Instant;
+
+/// Each fragment begins with the id of the packet it belongs to (u16), its
+/// index within the packet (u8) & the number of fragments in the packet (u8)
+pub const FRAGMENT_HEADER_SIZE: usize = 4;
+
+/// The most fragments a packet can be split into
+pub const MAX_FRAGMENTS: usize = 255;
+
+/// Settings for splitting packets too large for a single datagram into
+/// fragments, & putting them back together on the other side. Both sides of
+/// a connection must have fragmentation enabled
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct FragmentationConfig {
+    /// The largest packet which may be sent or reassembled, in bytes. Larger
+    /// packets are dropped
+    pub max_packet_size: usize,
+    /// How long the fragments of a packet are kept while waiting for the rest
+    /// of them. If any fragment is lost, the whole packet is
+    pub reassembly_timeout: Duration,
+    /// The most packets being reassembled at once for each connection. When a
+    /// fragment of another packet arrives, the oldest one is given up on
+    pub max_pending_packets: usize,
+}
+
+impl Default for FragmentationConfig {
+    fn default() -> Self {
+        FragmentationConfig {
+            max_packet_size: 0x10000,
+            reassembly_timeout: Duration::from_secs(1),
+            max_pending_packets: 16,
+        }
+    }
+}
+
+/// Splits the given payload into fragments of at most `chunk_size` bytes,
+/// returning the header & data of each. Returns `None` if it would take more
+/// than `MAX_FRAGMENTS` fragments
+pub fn split_into_fragments(
+    payload: &[u8],
+    id: u16,
+    chunk_size: usize,
+) -> Option<Vec<([u8; FRAGMENT_HEADER_SIZE], &[u8])>> {
+    let chunk_size = chunk_size.max(1);
+    let count = payload.len().div_ceil(chunk_size).max(1);
+    if count > MAX_FRAGMENTS {
+        return None;
+    }
+
+    let id = id.to_be_bytes();
+    let fragments = (0..count)
+        .map(|index| {
+            let start = index * chunk_size;
+            let end = (start + chunk_size).min(payload.len());
+            let header = [id[0], id[1], index as u8, count as u8];
+            (header, &payload[start..end])
+        })
+        .collect();
+    Some(fragments)
+}
+
+/// Puts packets back together from the fragments received for them, for a
+/// single connection
+#[derive(Debug)]
+pub struct Reassembler {
+    config: FragmentationConfig,
+    pending: HashMap<u16, PendingPacket>,
+}
+
+#[derive(Debug)]
+struct PendingPacket {
+    fragments: Vec<Option<Vec<u8>>>,
+    received: usize,
+    size: usize,
+    started: Instant,
+}
+
+impl Reassembler {
+    /// Create a new Reassembler, with nothing pending
+    pub fn new(config: FragmentationConfig) -> Self {
+        Reassembler {
+            config,
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Takes in a fragment, header included, returning the packet it belongs
+    /// to if it was the last one missing. Malformed fragments are ignored
+    pub fn receive(&mut self, fragment: &[u8]) -> Option<Vec<u8>> {
+        if fragment.len() < FRAGMENT_HEADER_SIZE {
+            return None;
+        }
+        let id = u16::from_be_bytes(fragment[0..2].try_into().unwrap());
+        let index = usize::from(fragment[2]);
+        let count = usize::from(fragment[3]);
+        let data = &fragment[FRAGMENT_HEADER_SIZE..];
+        if index >= count {
+            return None;
+        }
+
+        let timeout = self.config.reassembly_timeout;
+        self.pending
+            .retain(|_, packet| packet.started.elapsed() < timeout);
+
+        if !self.pending.contains_key(&id) {
+            if self.pending.len() >= self.config.max_pending_packets {
+                let oldest = self
+                    .pending
+                    .iter()
+                    .max_by_key(|(_, packet)| packet.started.elapsed())
+                    .map(|(id, _)| *id);
+                if let Some(oldest) = oldest {
+                    self.pending.remove(&oldest);
+                }
+            }
+            self.pending.insert(
+                id,
+                PendingPacket {
+                    fragments: vec![None; count],
+                    received: 0,
+                    size: 0,
+                    started: Instant::now(),
+                },
+            );
+        }
+
+        let packet = self.pending.get_mut(&id).unwrap();
+        if packet.fragments.len() != count {
+            // the id has wrapped around onto a packet which never completed
+            self.pending.remove(&id);
+            return None;
+        }
+        if packet.fragments[index].is_some() {
+            return None;
+        }
+        packet.size += data.len();
+        if packet.size > self.config.max_packet_size {
+            self.pending.remove(&id);
+            return None;
+        }
+        packet.fragments[index] = Some(data.to_vec());
+        packet.received += 1;
+        if packet.received < count {
+            return None;
+        }
+
+        let packet = self.pending.remove(&id).unwrap();
+        let mut payload = Vec::with_capacity(packet.size);
+        for fragment in packet.fragments.into_iter().flatten() {
+            payload.extend_from_slice(&fragment);
+        }
+        Some(payload)
+    }
+}
@@ -0,0 +1,149 @@
+use std::convert::TryInto;
+
+/// Bytes which follow the packet type in every handshake packet, so that stray
+/// traffic arriving at the port can be discarded cheaply
+pub const PROTOCOL_MAGIC: [u8; 4] = *b"NAIA";
+
+/// Version of the protocol spoken between a native client & a UDP server. This
+/// must be incremented whenever the protocol changes in an incompatible way
+pub const PROTOCOL_VERSION: u16 = 22;
+
+/// The size of the header written by `write_header`
+pub const HANDSHAKE_HEADER_SIZE: usize = 6;
+
+/// The size a connect request must be padded to. The server never answers a
+/// connect request with more bytes than it received, so that it can't be used
+/// to amplify traffic sent with a spoofed source address
+pub const CONNECT_REQUEST_SIZE: usize = 64;
+
+/// The size of the token a client can present when reconnecting, to pick up
+/// its previous connection where it left off
+pub const RESUMPTION_TOKEN_SIZE: usize = 16;
+
+/// The largest application payload a client can attach to its handshake
+pub const MAX_CONNECT_PAYLOAD_SIZE: usize = 512;
+
+/// The size of the cookie carried by a challenge. Clients treat the cookie as
+/// opaque & echo it back unchanged
+pub const CHALLENGE_COOKIE_SIZE: usize = 24;
+
+/// Set in the feature flags of a challenge response by a client which wants
+/// its packets compressed, & in those of the connect response if the server
+/// agrees to it
+pub const FEATURE_COMPRESSION: u8 = 1;
+
+/// Set in the feature flags of a challenge response by a client which wants
+/// its packets encrypted, in which case its public key follows the connect
+/// payload, & in those of the connect response if the server agrees to it,
+/// in which case the server's public key follows the feature flags
+pub const FEATURE_ENCRYPTION: u8 = 2;
+
+/// Set in the feature flags like `FEATURE_ENCRYPTION`, by a client which wants
+/// its packets authenticated but not encrypted, & followed by the same public
+/// keys
+pub const FEATURE_AUTHENTICATION: u8 = 4;
+
+/// Set in the feature flags of a challenge response, along with
+/// `FEATURE_ENCRYPTION` or `FEATURE_AUTHENTICATION`, by a client which has
+/// pinned the server's identity key, which is then mixed into the keys of
+/// the connection. A server without an identity refuses it
+pub const FEATURE_PINNED_KEY: u8 = 8;
+
+/// Writes the protocol magic & this crate's protocol version into a handshake
+/// packet
+pub fn write_header(buffer: &mut Vec<u8>) {
+    buffer.extend_from_slice(&PROTOCOL_MAGIC);
+    buffer.extend_from_slice(&PROTOCOL_VERSION.to_be_bytes());
+}
+
+/// Reads the protocol magic & version from the beginning of a handshake
+/// packet's body, returning the version if the magic is correct
+pub fn read_header(buffer: &[u8]) -> Option<u16> {
+    if buffer.len() < HANDSHAKE_HEADER_SIZE || buffer[..4] != PROTOCOL_MAGIC {
+        return None;
+    }
+    Some(u16::from_be_bytes(buffer[4..6].try_into().unwrap()))
+}
+
+/// Writes the connect token a client presents when answering a challenge,
+/// which is empty if it doesn't have one. Follows the cookie
+pub fn write_connect_token(buffer: &mut Vec<u8>, connect_token: &[u8]) {
+    write_length_prefixed(buffer, connect_token);
+}
+
+/// Reads the connect token from the body of a challenge response, starting
+/// just after the cookie
+pub fn read_connect_token(buffer: &[u8]) -> Option<&[u8]> {
+    read_length_prefixed(buffer)
+}
+
+/// Writes the application payload a client attaches to its challenge
+/// response, which is empty if it doesn't have one. Follows the connect token
+pub fn write_connect_payload(buffer: &mut Vec<u8>, connect_payload: &[u8]) {
+    write_length_prefixed(buffer, connect_payload);
+}
+
+/// Reads the application payload from the body of a challenge response,
+/// starting just after the connect token
+pub fn read_connect_payload(buffer: &[u8]) -> Option<&[u8]> {
+    read_length_prefixed(buffer).filter(|payload| payload.len() <= MAX_CONNECT_PAYLOAD_SIZE)
+}
+
+/// The parts of a client's challenge response which follow its cookie
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct ChallengeResponseBody<'a> {
+    /// The connect token the client presents, empty if it has none
+    pub connect_token: &'a [u8],
+    /// The application payload attached, empty if there is none
+    pub connect_payload: &'a [u8],
+    /// The client's public key, if it asked for its packets to be protected
+    pub public_key: Option<&'a [u8]>,
+    /// The token the client presents to resume its previous connection, if
+    /// it is reconnecting
+    pub resumption_token: Option<u128>,
+}
+
+/// Reads the body of a challenge response, starting just after the cookie. A
+/// client which asked for its packets to be protected follows the connect
+/// payload with a public key of `public_key_size` bytes. Returns `None` if
+/// anything is cut short or the connect payload is too large
+pub fn read_challenge_response_body(
+    buffer: &[u8],
+    public_key_size: Option<usize>,
+) -> Option<ChallengeResponseBody<'_>> {
+    let connect_token = read_connect_token(buffer)?;
+    let payload_start = 2 + connect_token.len();
+    let connect_payload = read_connect_payload(&buffer[payload_start..])?;
+    let mut resumption_start = payload_start + 2 + connect_payload.len();
+    let public_key = match public_key_size {
+        Some(public_key_size) => {
+            let public_key_end = resumption_start + public_key_size;
+            let public_key = buffer.get(resumption_start..public_key_end)?;
+            resumption_start = public_key_end;
+            Some(public_key)
+        }
+        None => None,
+    };
+    let resumption_token = buffer
+        .get(resumption_start..resumption_start + RESUMPTION_TOKEN_SIZE)
+        .map(|bytes| u128::from_be_bytes(bytes.try_into().unwrap()));
+    Some(ChallengeResponseBody {
+        connect_token,
+        connect_payload,
+        public_key,
+        resumption_token,
+    })
+}
+
+fn write_length_prefixed(buffer: &mut Vec<u8>, bytes: &[u8]) {
+    buffer.extend_from_slice(&(bytes.len() as u16).to_be_bytes());
+    buffer.extend_from_slice(bytes);
+}
+
+fn read_length_prefixed(buffer: &[u8]) -> Option<&[u8]> {
+    if buffer.len() < 2 {
+        return None;
+    }
+    let len = u16::from_be_bytes(buffer[0..2].try_into().unwrap()) as usize;
+    buffer.get(2..2 + len)
+}
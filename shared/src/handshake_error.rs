@@ -0,0 +1,43 @@
+use std::{error::Error, fmt};
+
+/// The reasons a Server refuses to let a client connect, which both sides
+/// tell apart the same way
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum HandshakeError {
+    /// The client & the Server speak different protocol versions
+    VersionMismatch {
+        /// The protocol version spoken by the client
+        client_version: u16,
+        /// The protocol version spoken by the Server
+        server_version: u16,
+    },
+    /// The Server is at capacity
+    ServerFull,
+    /// A client with the same identity is already connected
+    DuplicateConnection,
+    /// The Server isn't accepting new clients at the moment
+    NotAccepting,
+}
+
+impl fmt::Display for HandshakeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            HandshakeError::VersionMismatch {
+                client_version,
+                server_version,
+            } => write!(
+                f,
+                "protocol version mismatch (client {}, server {})",
+                client_version, server_version
+            ),
+            HandshakeError::ServerFull => write!(f, "server is full"),
+            HandshakeError::DuplicateConnection => {
+                write!(f, "already connected with the same identity")
+            }
+            HandshakeError::NotAccepting => write!(f, "server isn't accepting new connections"),
+        }
+    }
+}
+
+impl Error for HandshakeError {}
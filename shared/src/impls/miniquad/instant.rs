@@ -1,10 +1,9 @@
-extern "C" {
-    pub fn naia_now() -> f64;
-}
-
 use std::{cmp::Ordering, time::Duration};
 
-/// Represents a specific moment in time
+use super::performance;
+
+/// Represents a specific moment in time, measured by `performance.now()` so
+/// that it keeps steady when the wall clock is set
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
 pub struct Instant {
     inner: f64,
@@ -13,27 +12,19 @@ pub struct Instant {
 impl Instant {
     /// Creates an Instant from the moment the method is called
     pub fn now() -> Self {
-        unsafe { Instant { inner: naia_now() } }
+        Instant {
+            inner: performance::now(),
+        }
     }
 
     /// Returns time elapsed since the Instant
     pub fn elapsed(&self) -> Duration {
-        unsafe {
-            let inner_duration = naia_now() - self.inner;
-            let seconds: u64 = (inner_duration as u64) / 1000;
-            let nanos: u32 = ((inner_duration as u32) % 1000) * 1000000;
-            return Duration::new(seconds, nanos);
-        }
+        return performance::millis_to_duration(performance::now() - self.inner);
     }
 
     /// Returns time until the Instant occurs
     pub fn until(&self) -> Duration {
-        unsafe {
-            let inner_duration = self.inner - naia_now();
-            let seconds: u64 = (inner_duration as u64) / 1000;
-            let nanos: u32 = ((inner_duration as u32) % 1000) * 1000000;
-            return Duration::new(seconds, nanos);
-        }
+        return performance::millis_to_duration(self.inner - performance::now());
     }
 
     /// Adds a given number of milliseconds to the Instant
@@ -41,6 +32,11 @@ impl Instant {
         let millis_f64: f64 = millis.into();
         self.inner += millis_f64;
     }
+
+    /// Adds a given duration to the Instant
+    pub fn add_duration(&mut self, duration: Duration) {
+        self.inner += duration.as_secs_f64() * 1000.0;
+    }
 }
 
 impl Eq for Instant {}
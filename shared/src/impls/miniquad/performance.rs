@@ -0,0 +1,17 @@
+extern "C" {
+    pub fn naia_performance_now() -> f64;
+}
+
+use std::time::Duration;
+
+/// Gets the number of milliseconds since the page began. Unlike the wall
+/// clock, this never jumps when the system's time is set, & is finer than a
+/// millisecond, so delays measured with it hold up
+pub fn now() -> f64 {
+    unsafe { naia_performance_now() }
+}
+
+/// Gets the Duration of a number of milliseconds, none if it is negative
+pub fn millis_to_duration(millis: f64) -> Duration {
+    Duration::from_secs_f64(millis.max(0.0) / 1000.0)
+}
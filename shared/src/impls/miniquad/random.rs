@@ -29,4 +29,13 @@ impl Random {
             return naia_random() < 0.5;
         }
     }
+
+    /// returns a random u64 value, spanning the full range of the type
+    pub fn gen_u64() -> u64 {
+        unsafe {
+            let high: u64 = (naia_random() * f64::from(u32::MAX)) as u64;
+            let low: u64 = (naia_random() * f64::from(u32::MAX)) as u64;
+            return (high << 32) | low;
+        }
+    }
 }
@@ -1,9 +1,7 @@
-extern "C" {
-    pub fn naia_now() -> f64;
-}
-
 use std::time::Duration;
 
+use super::performance;
+
 /// A Timer with a given duration after which it will enter into a "Ringing"
 /// state. The Timer can be reset at an given time, or manually set to start
 /// "Ringing" again.
@@ -16,26 +14,22 @@ pub struct Timer {
 impl Timer {
     /// Creates a new Timer with a given Duration
     pub fn new(duration: Duration) -> Self {
-        unsafe {
-            Timer {
-                last: naia_now(),
-                duration: duration.as_millis() as f64,
-            }
+        Timer {
+            last: performance::now(),
+            duration: duration.as_secs_f64() * 1000.0,
         }
     }
 
     /// Reset the Timer to stop ringing and wait till 'Duration' has elapsed
     /// again
     pub fn reset(&mut self) {
-        unsafe {
-            self.last = naia_now();
-        }
+        self.last = performance::now();
     }
 
     /// Gets whether or not the Timer is "Ringing" (i.e. the given Duration has
     /// elapsed since the last "reset")
     pub fn ringing(&self) -> bool {
-        unsafe { (naia_now() - self.last) > self.duration }
+        (performance::now() - self.last) > self.duration
     }
 
     /// Manually causes the Timer to enter into a "Ringing" state
@@ -1,5 +1,7 @@
 use std::time::Duration;
 
+use crate::clock;
+
 /// Represents a specific moment in time
 #[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd)]
 pub struct Instant {
@@ -10,18 +12,18 @@ impl Instant {
     /// Creates an Instant from the moment the method is called
     pub fn now() -> Self {
         Instant {
-            inner: std::time::Instant::now(),
+            inner: clock::now(),
         }
     }
 
     /// Returns time elapsed since the Instant
     pub fn elapsed(&self) -> Duration {
-        self.inner.elapsed()
+        clock::elapsed(self.inner)
     }
 
     /// Returns time until the Instant occurs
     pub fn until(&self) -> Duration {
-        return self.inner.duration_since(std::time::Instant::now());
+        return self.inner.saturating_duration_since(clock::now());
     }
 
     /// Adds a given number of milliseconds to the Instant
@@ -29,6 +31,11 @@ impl Instant {
         self.inner += Duration::from_millis(millis.into());
     }
 
+    /// Adds a given duration to the Instant
+    pub fn add_duration(&mut self, duration: Duration) {
+        self.inner += duration;
+    }
+
     /// Returns inner Instant implementation
     pub fn get_inner(&self) -> std::time::Instant {
         return self.inner.clone();
@@ -19,4 +19,9 @@ impl Random {
     pub fn gen_bool() -> bool {
         return rand::thread_rng().gen_bool(0.5);
     }
+
+    /// returns a random u64 value, spanning the full range of the type
+    pub fn gen_u64() -> u64 {
+        return rand::thread_rng().gen();
+    }
 }
@@ -1,5 +1,7 @@
 use std::time::{Duration, Instant};
 
+use crate::clock;
+
 /// A Timer with a given duration after which it will enter into a "Ringing"
 /// state. The Timer can be reset at an given time, or manually set to start
 /// "Ringing" again.
@@ -13,7 +15,7 @@ impl Timer {
     /// Creates a new Timer with a given Duration
     pub fn new(duration: Duration) -> Self {
         Timer {
-            last: Instant::now(),
+            last: clock::now(),
             duration,
         }
     }
@@ -21,13 +23,13 @@ impl Timer {
     /// Reset the Timer to stop ringing and wait till 'Duration' has elapsed
     /// again
     pub fn reset(&mut self) {
-        self.last = Instant::now();
+        self.last = clock::now();
     }
 
     /// Gets whether or not the Timer is "Ringing" (i.e. the given Duration has
     /// elapsed since the last "reset")
     pub fn ringing(&self) -> bool {
-        Instant::now().saturating_duration_since(self.last) > self.duration
+        clock::now().saturating_duration_since(self.last) > self.duration
     }
 
     /// Manually causes the Timer to enter into a "Ringing" state
@@ -1,4 +1,5 @@
 pub mod instant;
+pub mod performance;
 pub mod random;
 pub mod timer;
 pub mod timestamp;
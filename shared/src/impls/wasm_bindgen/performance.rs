@@ -0,0 +1,22 @@
+use std::time::Duration;
+
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen]
+extern "C" {
+    // `performance` is a global in pages & workers alike, unlike `window`
+    #[wasm_bindgen(js_namespace = performance, js_name = now)]
+    fn performance_now() -> f64;
+}
+
+/// Gets the number of milliseconds since the page or worker began. Unlike
+/// the wall clock, this never jumps when the system's time is set, & is finer
+/// than a millisecond, so delays measured with it hold up
+pub fn now() -> f64 {
+    performance_now()
+}
+
+/// Gets the Duration of a number of milliseconds, none if it is negative
+pub fn millis_to_duration(millis: f64) -> Duration {
+    Duration::from_secs_f64(millis.max(0.0) / 1000.0)
+}
@@ -21,4 +21,11 @@ impl Random {
     pub fn gen_bool() -> bool {
         return random() < 0.5;
     }
+
+    /// returns a random u64 value, spanning the full range of the type
+    pub fn gen_u64() -> u64 {
+        let high: u64 = (random() * f64::from(u32::MAX)) as u64;
+        let low: u64 = (random() * f64::from(u32::MAX)) as u64;
+        return (high << 32) | low;
+    }
 }
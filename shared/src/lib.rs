@@ -18,18 +18,116 @@ extern crate cfg_if;
 /// conditions
 pub mod link_condition_logic;
 
+/// Helpers for reading/writing the handshake which begins a connection between
+/// a native client & a UDP server
+pub mod handshake;
+
+/// Helpers for passing binary data over channels which only carry text, such
+/// as the signaling url & WebRTC text messages
+pub mod hex;
+
+/// Helpers for packing several packets into a single datagram, & unpacking
+/// them
+pub mod coalescing;
+
+/// Helpers for splitting packets too large for a single datagram into
+/// fragments, & reassembling them
+pub mod fragmentation;
+
+/// Optional compression of the payloads of packets
+pub mod compression;
+
+/// Optional numbering of unreliable packets, so that the sender hears which
+/// of them were delivered
+pub mod acknowledgement;
+
+/// Optional estimation of how much bandwidth a connection can take, from the
+/// acknowledgements of the packets sent over it
+pub mod congestion;
+
+/// Helpers for comparing sequence numbers which wrap around, & telling which
+/// have been received
+pub mod sequence;
+
+/// An optional layer of channels, reliable or not, carried alongside the
+/// packets sent off any channel
+pub mod reliability;
+
+/// Pings which measure the round trip time & jitter of a connection between
+/// a native client & a UDP server
+pub mod ping;
+
+/// The time the native sockets' timers read, which tests can stop & step
+/// through by hand with a VirtualClock
+pub mod clock;
+
+mod conditioner_handle;
+mod conditioner_stats;
+mod conditioner_trace;
+mod control_message;
 mod find_available_port;
 mod find_my_ip_address;
+mod handshake_error;
 mod impls;
 mod link_conditioner_config;
+mod link_profile;
+mod message_mode;
+mod middleware_action;
+mod packet_capture;
+mod packet_dump;
 mod packet_reader;
+mod packet_type;
+mod payload;
+mod port_search;
+mod priority;
 mod reference;
 mod time_queue;
 
+pub use acknowledgement::{AckConfig, Delivery};
+pub use compression::CompressionConfig;
+pub use conditioner_handle::ConditionerHandle;
+pub use conditioner_stats::{ConditionerStats, LATENCY_BUCKETS};
+pub use conditioner_trace::ConditionerTrace;
+pub use congestion::CongestionConfig;
+pub use control_message::ControlMessage;
 pub use find_available_port::find_available_port;
 pub use find_my_ip_address::find_my_ip_address;
+pub use fragmentation::FragmentationConfig;
+pub use handshake_error::HandshakeError;
 pub use impls::{Instant, Random, Timer, Timestamp};
-pub use link_conditioner_config::LinkConditionerConfig;
+pub use link_conditioner_config::{
+    BurstLossConfig, JitterDistribution, LatencySpikeConfig, LinkConditionerConfig,
+};
+pub use link_profile::ProfileError;
+pub use message_mode::MessageMode;
+pub use middleware_action::MiddlewareAction;
+pub use packet_capture::PacketCapture;
+pub use packet_dump::{PacketDirection, PacketDump};
 pub use packet_reader::PacketReader;
+pub use packet_type::PacketType;
+pub use payload::{Payload, INLINE_PAYLOAD_SIZE};
+pub use port_search::{PortSearch, PortStrategy};
+pub use priority::Priority;
 pub use reference::Ref;
+pub use reliability::{ChannelMode, ReliabilityConfig};
 pub use time_queue::TimeQueue;
+
+cfg_if! {
+    if #[cfg(not(target_arch = "wasm32"))] {
+        mod connect_token;
+        mod dual_stack;
+        mod traffic_class;
+        mod x25519;
+        mod xchacha20poly1305;
+
+        /// Optional encryption or authentication of the packets a native client
+        /// & a UDP server exchange, with keys agreed on during the handshake
+        pub mod encryption;
+
+        pub use connect_token::{
+            ConnectToken, ConnectTokenError, ConnectTokenKey, MAX_CONNECT_TOKEN_USER_DATA,
+        };
+        pub use dual_stack::{address_for_socket, bind_udp, canonical_address};
+        pub use traffic_class::set_traffic_class;
+    }
+}
@@ -1,36 +1,356 @@
 extern crate log;
 use log::info;
 
-use super::{link_conditioner_config::LinkConditionerConfig, time_queue::TimeQueue, Instant};
+use std::{collections::VecDeque, f32::consts::PI, time::Duration};
+
+use super::{
+    conditioner_stats::ConditionerStats,
+    conditioner_trace::TraceEntry,
+    link_conditioner_config::{JitterDistribution, LinkConditionerConfig},
+    time_queue::TimeQueue,
+    Instant,
+};
 use crate::Random;
 
+/// A packet the network conditions can be simulated on, which can be
+/// tampered with as well as delayed
+pub trait ConditionedPacket: Eq + Clone {
+    /// Get at the payload of the packet
+    fn payload(&self) -> &[u8];
+
+    /// Replace the payload of the packet, keeping everything else about it
+    fn with_payload(self, payload: Vec<u8>) -> Self;
+}
+
+/// What a link conditioner keeps track of from one packet to the next
+#[derive(Debug, Default)]
+pub struct LinkState {
+    // the moments the packets still on a throttled link finish crossing it,
+    // in order
+    on_link: VecDeque<Instant>,
+    // the generator decisions are made with, & the seed it started from, if
+    // the config asks for them to be reproducible
+    seeded: Option<(u64, SeededRandom)>,
+    // whether a link following a burst loss model is in its bad state
+    bursting: bool,
+    // the moment the current spike in latency passes, if there is one
+    spike_until: Option<Instant>,
+}
+
+impl LinkState {
+    /// Create the state of a link no packets have crossed yet
+    pub fn new() -> Self {
+        LinkState {
+            on_link: VecDeque::new(),
+            seeded: None,
+            bursting: false,
+            spike_until: None,
+        }
+    }
+
+    // Moves a burst loss model on a packet, & decides whether the packet is
+    // lost, by the model if there is one or otherwise at random
+    fn is_lost(&mut self, config: &LinkConditionerConfig) -> bool {
+        let burst_loss = match config.burst_loss.as_ref() {
+            Some(burst_loss) => burst_loss,
+            None => return self.gen_range_f32(0.0, 1.0) <= config.incoming_loss,
+        };
+        let flip = if self.bursting {
+            burst_loss.bad_to_good
+        } else {
+            burst_loss.good_to_bad
+        };
+        if self.gen_range_f32(0.0, 1.0) < flip {
+            self.bursting = !self.bursting;
+        }
+        let loss = if self.bursting {
+            burst_loss.bad_loss
+        } else {
+            burst_loss.good_loss
+        };
+        self.gen_range_f32(0.0, 1.0) < loss
+    }
+
+    // Gets the latency a spike adds to a packet, starting one now & again
+    fn spike_latency(&mut self, config: &LinkConditionerConfig) -> u32 {
+        let spikes = match config.latency_spikes.as_ref() {
+            Some(spikes) => spikes,
+            None => return 0,
+        };
+        let now = Instant::now();
+        if self.spike_until.as_ref().is_some_and(|until| *until <= now) {
+            self.spike_until = None;
+        }
+        if self.spike_until.is_none() && self.gen_range_f32(0.0, 1.0) < spikes.chance {
+            info!("link conditioner: latency spike");
+            let mut until = now;
+            until.add_millis(spikes.duration);
+            self.spike_until = Some(until);
+        }
+        if self.spike_until.is_some() {
+            return spikes.extra_latency;
+        }
+        0
+    }
+
+    // Starts making decisions from the config's seed, if it has one which
+    // isn't already in use, or goes back to making them at random
+    fn follow_seed(&mut self, config: &LinkConditionerConfig) {
+        match config.seed {
+            Some(seed) if self.seeded.as_ref().map(|(current, _)| *current) != Some(seed) => {
+                self.seeded = Some((seed, SeededRandom::new(seed)));
+            }
+            Some(_) => {}
+            None => self.seeded = None,
+        }
+    }
+
+    fn gen_range_f32(&mut self, lower: f32, upper: f32) -> f32 {
+        match self.seeded.as_mut() {
+            Some((_, random)) => lower + random.next_f32() * (upper - lower),
+            None => Random::gen_range_f32(lower, upper),
+        }
+    }
+
+    fn gen_range_u32(&mut self, lower: u32, upper: u32) -> u32 {
+        match self.seeded.as_mut() {
+            Some((_, random)) => lower + (random.next_u64() % u64::from(upper - lower)) as u32,
+            None => Random::gen_range_u32(lower, upper),
+        }
+    }
+
+    fn gen_bool(&mut self) -> bool {
+        match self.seeded.as_mut() {
+            Some((_, random)) => random.next_u64() & 1 == 1,
+            None => Random::gen_bool(),
+        }
+    }
+
+    /// Gets the number of packets waiting to cross a throttled link
+    pub fn link_queue_len(&self) -> usize {
+        let now = Instant::now();
+        self.on_link
+            .iter()
+            .filter(|crossed_at| **crossed_at > now)
+            .count()
+    }
+
+    // Gets the moment a packet of the given size will have crossed the link,
+    // if there is room for it to wait its turn
+    fn throttle(&mut self, config: &LinkConditionerConfig, size: usize) -> Option<Instant> {
+        let now = Instant::now();
+        let bandwidth = match config.incoming_bandwidth {
+            Some(bandwidth) if bandwidth > 0 => bandwidth,
+            _ => return Some(now),
+        };
+        while self
+            .on_link
+            .front()
+            .is_some_and(|crossed_at| *crossed_at <= now)
+        {
+            self.on_link.pop_front();
+        }
+        if self.on_link.len() >= config.bandwidth_queue_limit {
+            return None;
+        }
+        let mut crossed_at = match self.on_link.back() {
+            Some(last) if *last > now => last.clone(),
+            _ => now,
+        };
+        crossed_at.add_duration(Duration::from_secs_f64(size as f64 / f64::from(bandwidth)));
+        self.on_link.push_back(crossed_at.clone());
+        Some(crossed_at)
+    }
+}
+
 /// Given a config object which describes the network conditions to be
 /// simulated, process an incoming packet, adding it to a TimeQueue at the
-/// correct timestamp
-pub fn process_packet<T: Eq>(
+/// correct timestamp, & counting what was done to it in the stats. If the
+/// config has a trace being replayed, the next decision in it is applied
+/// instead, & if it has one being recorded, the decision made is written to
+/// it
+pub fn process_packet<T: ConditionedPacket>(
     config: &LinkConditionerConfig,
+    state: &mut LinkState,
+    stats: &mut ConditionerStats,
     time_queue: &mut TimeQueue<T>,
     packet: T,
 ) {
-    if Random::gen_range_f32(0.0, 1.0) <= config.incoming_loss {
+    if let Some(entry) = config.trace.as_ref().and_then(|trace| trace.next_entry()) {
+        stats.record(&entry);
+        replay(entry, time_queue, packet);
+        return;
+    }
+    let entry = condition(config, state, time_queue, packet);
+    stats.record(&entry);
+    if let Some(trace) = config.trace.as_ref() {
+        trace.record_entry(&entry);
+    }
+}
+
+// Decides what happens to a packet, returning the decision so it can be
+// traced
+fn condition<T: ConditionedPacket>(
+    config: &LinkConditionerConfig,
+    state: &mut LinkState,
+    time_queue: &mut TimeQueue<T>,
+    mut packet: T,
+) -> TraceEntry {
+    let mut entry = TraceEntry {
+        size: packet.payload().len(),
+        ..TraceEntry::default()
+    };
+    state.follow_seed(config);
+    if state.is_lost(config) {
         // drop the packet
         info!("link conditioner: packet lost");
-        return;
+        return entry;
     }
-    if Random::gen_range_f32(0.0, 1.0) <= config.incoming_corruption {
-        //TODO: corrupt the packet
+    if state.gen_range_f32(0.0, 1.0) < config.incoming_corruption && !packet.payload().is_empty() {
         info!("link conditioner: packet corrupted");
-        return;
+        let bit = state.gen_range_u32(0, packet.payload().len() as u32 * 8);
+        packet = corrupt(packet, bit);
+        entry.corrupted_bit = Some(bit);
     }
-    let mut latency: u32 = config.incoming_latency;
-    if config.incoming_jitter > 0 {
-        if Random::gen_bool() {
-            latency += Random::gen_range_u32(0, config.incoming_jitter);
-        } else {
-            latency -= Random::gen_range_u32(0, config.incoming_jitter);
+    if state.gen_range_f32(0.0, 1.0) < config.incoming_truncation && !packet.payload().is_empty() {
+        info!("link conditioner: packet truncated");
+        let length = state.gen_range_u32(0, packet.payload().len() as u32);
+        packet = truncate(packet, length);
+        entry.truncated_to = Some(length);
+    }
+    if state.gen_range_f32(0.0, 1.0) < config.incoming_duplication {
+        info!("link conditioner: packet duplicated");
+        entry
+            .delays
+            .extend(schedule(config, state, time_queue, packet.clone(), 0));
+    }
+    let reorder_delay = reorder_delay(config, state);
+    entry
+        .delays
+        .extend(schedule(config, state, time_queue, packet, reorder_delay));
+    entry
+}
+
+// Does to a packet exactly what was done to one in the run a trace was
+// recorded from
+fn replay<T: ConditionedPacket>(entry: TraceEntry, time_queue: &mut TimeQueue<T>, mut packet: T) {
+    if entry.size != packet.payload().len() {
+        info!(
+            "link conditioner: replaying decision for a packet of {} bytes on one of {}",
+            entry.size,
+            packet.payload().len()
+        );
+    }
+    if let Some(bit) = entry.corrupted_bit {
+        if (bit as usize) < packet.payload().len() * 8 {
+            packet = corrupt(packet, bit);
         }
     }
-    let mut packet_timestamp = Instant::now();
-    packet_timestamp.add_millis(latency);
+    if let Some(length) = entry.truncated_to {
+        if (length as usize) < packet.payload().len() {
+            packet = truncate(packet, length);
+        }
+    }
+    for delay in entry.delays {
+        let mut packet_timestamp = Instant::now();
+        packet_timestamp.add_millis(delay);
+        time_queue.add_item(packet_timestamp, packet.clone());
+    }
+}
+
+// Queues a packet up for once it has crossed the link & been delayed,
+// returning how many milliseconds from now that is, or `None` if the packet
+// was dropped
+fn schedule<T: ConditionedPacket>(
+    config: &LinkConditionerConfig,
+    state: &mut LinkState,
+    time_queue: &mut TimeQueue<T>,
+    packet: T,
+    extra_delay: u32,
+) -> Option<u32> {
+    let mut packet_timestamp = match state.throttle(config, packet.payload().len()) {
+        Some(crossed_at) => crossed_at,
+        None => {
+            info!("link conditioner: packet dropped, link queue full");
+            return None;
+        }
+    };
+    packet_timestamp.add_millis(latency(config, state) + state.spike_latency(config) + extra_delay);
+    let delay = (packet_timestamp.until().as_secs_f64() * 1000.0).round() as u32;
     time_queue.add_item(packet_timestamp, packet);
+    Some(delay)
+}
+
+// Flips a single bit of the payload
+fn corrupt<T: ConditionedPacket>(packet: T, bit: u32) -> T {
+    let bit = bit as usize;
+    let mut payload = packet.payload().to_vec();
+    payload[bit / 8] ^= 1 << (bit % 8);
+    packet.with_payload(payload)
+}
+
+// Cuts the payload short, possibly down to nothing
+fn truncate<T: ConditionedPacket>(packet: T, length: u32) -> T {
+    let payload = packet.payload()[..length as usize].to_vec();
+    packet.with_payload(payload)
+}
+
+// Picks how much longer a packet is held back so that others overtake it, if
+// it is to be reordered at all
+fn reorder_delay(config: &LinkConditionerConfig, state: &mut LinkState) -> u32 {
+    if config.reorder_window == 0 || state.gen_range_f32(0.0, 1.0) >= config.incoming_reorder {
+        return 0;
+    }
+    info!("link conditioner: packet reordered");
+    state.gen_range_u32(1, config.reorder_window + 1)
+}
+
+/// Picks the latency to delay a packet by, in milliseconds, which is never
+/// less than zero however much jitter is taken off
+pub fn latency(config: &LinkConditionerConfig, state: &mut LinkState) -> u32 {
+    let latency = config.incoming_latency;
+    if config.incoming_jitter == 0 {
+        return latency;
+    }
+    let jitter = match config.jitter_distribution {
+        JitterDistribution::Uniform => state.gen_range_u32(0, config.incoming_jitter) as f32,
+        JitterDistribution::Normal => {
+            // Box-Muller, with the first sample kept away from zero
+            let magnitude = state.gen_range_f32(f32::EPSILON, 1.0);
+            let angle = state.gen_range_f32(0.0, 1.0);
+            let deviation = (-2.0 * magnitude.ln()).sqrt() * (2.0 * PI * angle).cos();
+            deviation.abs() * config.incoming_jitter as f32
+        }
+    };
+    if state.gen_bool() {
+        return latency.saturating_add(jitter.round() as u32);
+    } else {
+        return latency.saturating_sub(jitter.round() as u32);
+    }
+}
+
+// SplitMix64, which is small & quick, & good enough to pick which packets to
+// drop
+#[derive(Debug)]
+struct SeededRandom {
+    state: u64,
+}
+
+impl SeededRandom {
+    fn new(seed: u64) -> Self {
+        SeededRandom { state: seed }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // in [0, 1), from the top 24 bits, as many as an f32 holds exactly
+    fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u32 << 24) as f32
+    }
 }
@@ -1,3 +1,77 @@
+#[cfg(not(target_arch = "wasm32"))]
+use std::{fs, path::Path};
+
+use crate::{
+    link_profile::{self, ProfileError},
+    ConditionerTrace,
+};
+
+/// How the jitter a LinkConditioner adds is spread
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub enum JitterDistribution {
+    /// Any latency within `incoming_jitter` either side of
+    /// `incoming_latency` is as likely as any other
+    #[default]
+    Uniform,
+    /// Latencies cluster around `incoming_latency`, with `incoming_jitter` as
+    /// the standard deviation, so that the odd packet is far later or earlier
+    /// than the rest
+    Normal,
+}
+
+/// A two state, Gilbert-Elliott, model of loss, in which the link flips
+/// between a good state & a bad one, losing packets in bursts while it is bad
+/// as WiFi & cellular links do
+#[derive(Debug, Clone, PartialEq)]
+pub struct BurstLossConfig {
+    /// The % chance, for each packet, that a link in the good state turns
+    /// bad. Represented as a value between 0 and 1
+    pub good_to_bad: f32,
+    /// The % chance, for each packet, that a link in the bad state recovers.
+    /// Represented as a value between 0 and 1
+    pub bad_to_good: f32,
+    /// The % chance that a packet is dropped while the link is good.
+    /// Represented as a value between 0 and 1
+    pub good_loss: f32,
+    /// The % chance that a packet is dropped while the link is bad.
+    /// Represented as a value between 0 and 1
+    pub bad_loss: f32,
+}
+
+impl Default for BurstLossConfig {
+    fn default() -> Self {
+        BurstLossConfig {
+            good_to_bad: 0.01,
+            bad_to_good: 0.25,
+            good_loss: 0.0,
+            bad_loss: 0.5,
+        }
+    }
+}
+
+/// Sudden rises in latency, which last a while before passing, as happen
+/// when a link is briefly congested or a radio is scanning
+#[derive(Debug, Clone, PartialEq)]
+pub struct LatencySpikeConfig {
+    /// The % chance, for each packet, that a spike begins. Represented as a
+    /// value between 0 and 1
+    pub chance: f32,
+    /// How long each spike lasts, in milliseconds
+    pub duration: u32,
+    /// The latency added to packets during a spike, in milliseconds
+    pub extra_latency: u32,
+}
+
+impl Default for LatencySpikeConfig {
+    fn default() -> Self {
+        LatencySpikeConfig {
+            chance: 0.001,
+            duration: 1000,
+            extra_latency: 300,
+        }
+    }
+}
+
 /// Contains configuration required to initialize a LinkConditioner
 #[derive(Debug, Clone)]
 pub struct LinkConditionerConfig {
@@ -7,12 +81,47 @@ pub struct LinkConditionerConfig {
     /// messages in milliseconds. This may be added OR subtracted from the
     /// latency determined in the `incoming_latency` property above
     pub incoming_jitter: u32,
+    /// How the random latency is spread around `incoming_latency`
+    pub jitter_distribution: JitterDistribution,
     /// The % chance that an incoming packet will be dropped.
     /// Represented as a value between 0 and 1
     pub incoming_loss: f32,
     /// The % chance that an incoming packet will have a single bit tampered
     /// with. Represented as a value between 0 and 1
     pub incoming_corruption: f32,
+    /// The % chance that an incoming packet will be cut short, at a random
+    /// length. Represented as a value between 0 and 1
+    pub incoming_truncation: f32,
+    /// The % chance that an incoming packet will be delivered twice, each
+    /// copy with its own latency. Represented as a value between 0 and 1
+    pub incoming_duplication: f32,
+    /// The % chance that an incoming packet will be held back, so that those
+    /// received shortly after it overtake it. Represented as a value between
+    /// 0 and 1
+    pub incoming_reorder: f32,
+    /// The most a packet held back for reordering is delayed by, on top of
+    /// its latency, in milliseconds
+    pub reorder_window: u32,
+    /// The most bytes per second which can cross the link, if it is
+    /// throttled. Packets wait their turn to cross, before being delayed by
+    /// their latency
+    pub incoming_bandwidth: Option<u32>,
+    /// The most packets which can wait to cross a throttled link, beyond
+    /// which packets are dropped
+    pub bandwidth_queue_limit: usize,
+    /// The seed to make the conditioner's random decisions from, if they
+    /// should be the same from one run to the next. Packets which arrive in
+    /// the same order are then dropped, delayed & tampered with alike
+    pub seed: Option<u64>,
+    /// The model of bursts of loss to drop packets by, if any, which takes
+    /// the place of `incoming_loss`
+    pub burst_loss: Option<BurstLossConfig>,
+    /// The spikes in latency to add to the usual latency & jitter, if any
+    pub latency_spikes: Option<LatencySpikeConfig>,
+    /// The trace to record the conditioner's decisions to, or to replay
+    /// them from, if any. Replaying one makes a run see exactly the delays,
+    /// drops & tampering the recorded run did
+    pub trace: Option<ConditionerTrace>,
 }
 
 impl LinkConditionerConfig {
@@ -26,8 +135,19 @@ impl LinkConditionerConfig {
         LinkConditionerConfig {
             incoming_latency,
             incoming_jitter,
+            jitter_distribution: JitterDistribution::Uniform,
             incoming_loss,
             incoming_corruption,
+            incoming_truncation: 0.0,
+            incoming_duplication: 0.0,
+            incoming_reorder: 0.0,
+            reorder_window: 0,
+            incoming_bandwidth: None,
+            bandwidth_queue_limit: 64,
+            seed: None,
+            burst_loss: None,
+            latency_spikes: None,
+            trace: None,
         }
     }
 
@@ -37,8 +157,19 @@ impl LinkConditionerConfig {
         LinkConditionerConfig {
             incoming_latency: 50,
             incoming_jitter: 10,
+            jitter_distribution: JitterDistribution::Uniform,
             incoming_loss: 0.01,
             incoming_corruption: 0.0000015,
+            incoming_truncation: 0.0,
+            incoming_duplication: 0.0,
+            incoming_reorder: 0.0,
+            reorder_window: 0,
+            incoming_bandwidth: None,
+            bandwidth_queue_limit: 64,
+            seed: None,
+            burst_loss: None,
+            latency_spikes: None,
+            trace: None,
         }
     }
 
@@ -48,8 +179,19 @@ impl LinkConditionerConfig {
         LinkConditionerConfig {
             incoming_latency: 275,
             incoming_jitter: 20,
+            jitter_distribution: JitterDistribution::Uniform,
             incoming_loss: 0.055,
             incoming_corruption: 0.000015,
+            incoming_truncation: 0.0,
+            incoming_duplication: 0.0,
+            incoming_reorder: 0.0,
+            reorder_window: 0,
+            incoming_bandwidth: None,
+            bandwidth_queue_limit: 64,
+            seed: None,
+            burst_loss: None,
+            latency_spikes: None,
+            trace: None,
         }
     }
 
@@ -59,8 +201,144 @@ impl LinkConditionerConfig {
         LinkConditionerConfig {
             incoming_latency: 500,
             incoming_jitter: 30,
+            jitter_distribution: JitterDistribution::Uniform,
             incoming_loss: 0.1,
             incoming_corruption: 0.00015,
+            incoming_truncation: 0.0,
+            incoming_duplication: 0.0,
+            incoming_reorder: 0.0,
+            reorder_window: 0,
+            incoming_bandwidth: None,
+            bandwidth_queue_limit: 64,
+            seed: None,
+            burst_loss: None,
+            latency_spikes: None,
+            trace: None,
+        }
+    }
+
+    /// Creates a new LinkConditioner that simulates a home WiFi connection,
+    /// which is quick but loses packets in bursts
+    pub fn wifi() -> Self {
+        LinkConditionerConfig {
+            jitter_distribution: JitterDistribution::Normal,
+            burst_loss: Some(BurstLossConfig {
+                good_to_bad: 0.005,
+                bad_to_good: 0.3,
+                good_loss: 0.0,
+                bad_loss: 0.3,
+            }),
+            latency_spikes: Some(LatencySpikeConfig {
+                chance: 0.001,
+                duration: 500,
+                extra_latency: 100,
+            }),
+            ..LinkConditionerConfig::new(15, 8, 0.0, 0.0)
         }
     }
+
+    /// Creates a new LinkConditioner that simulates a 4G mobile connection
+    pub fn mobile_4g() -> Self {
+        LinkConditionerConfig {
+            jitter_distribution: JitterDistribution::Normal,
+            incoming_bandwidth: Some(2_500_000),
+            latency_spikes: Some(LatencySpikeConfig {
+                chance: 0.002,
+                duration: 800,
+                extra_latency: 200,
+            }),
+            ..LinkConditionerConfig::new(50, 15, 0.005, 0.0)
+        }
+    }
+
+    /// Creates a new LinkConditioner that simulates a 3G mobile connection,
+    /// which is slow & narrow
+    pub fn mobile_3g() -> Self {
+        LinkConditionerConfig {
+            jitter_distribution: JitterDistribution::Normal,
+            incoming_bandwidth: Some(93_750),
+            bandwidth_queue_limit: 32,
+            latency_spikes: Some(LatencySpikeConfig {
+                chance: 0.005,
+                duration: 1500,
+                extra_latency: 500,
+            }),
+            ..LinkConditionerConfig::new(150, 40, 0.02, 0.0)
+        }
+    }
+
+    /// Creates a new LinkConditioner that simulates a wired connection from
+    /// one side of the Atlantic to the other, which is steady but far
+    pub fn transatlantic() -> Self {
+        LinkConditionerConfig {
+            jitter_distribution: JitterDistribution::Normal,
+            ..LinkConditionerConfig::new(45, 3, 0.001, 0.0)
+        }
+    }
+
+    /// Creates a new LinkConditioner that simulates a connection which is in
+    /// about as bad a condition as a game could hope to be played over
+    pub fn terrible() -> Self {
+        LinkConditionerConfig {
+            jitter_distribution: JitterDistribution::Normal,
+            incoming_truncation: 0.001,
+            incoming_duplication: 0.02,
+            incoming_reorder: 0.05,
+            reorder_window: 100,
+            incoming_bandwidth: Some(16_000),
+            burst_loss: Some(BurstLossConfig {
+                good_to_bad: 0.05,
+                bad_to_good: 0.2,
+                good_loss: 0.02,
+                bad_loss: 0.6,
+            }),
+            latency_spikes: Some(LatencySpikeConfig {
+                chance: 0.01,
+                duration: 2000,
+                extra_latency: 1000,
+            }),
+            ..LinkConditionerConfig::new(400, 150, 0.0, 0.001)
+        }
+    }
+
+    /// Gets one of the presets by name: "good", "average", "poor", "wifi",
+    /// "4g", "3g", "transatlantic" or "terrible"
+    pub fn preset(name: &str) -> Option<Self> {
+        match name {
+            "good" => Some(LinkConditionerConfig::good_condition()),
+            "average" => Some(LinkConditionerConfig::average_condition()),
+            "poor" => Some(LinkConditionerConfig::poor_condition()),
+            "wifi" => Some(LinkConditionerConfig::wifi()),
+            "4g" => Some(LinkConditionerConfig::mobile_4g()),
+            "3g" => Some(LinkConditionerConfig::mobile_3g()),
+            "transatlantic" => Some(LinkConditionerConfig::transatlantic()),
+            "terrible" => Some(LinkConditionerConfig::terrible()),
+            _ => None,
+        }
+    }
+
+    /// Reads a profile of network conditions, written in a subset of TOML, so
+    /// that teams can share the conditions they test under. A profile can
+    /// start from a preset, then override any of the config's fields by name,
+    /// with `burst_loss` & `latency_spikes` as tables:
+    ///
+    /// ```toml
+    /// preset = "wifi"
+    /// incoming_latency = 30
+    /// jitter_distribution = "normal"
+    ///
+    /// [latency_spikes]
+    /// chance = 0.01
+    /// ```
+    pub fn from_profile(profile: &str) -> Result<Self, ProfileError> {
+        link_profile::parse(profile)
+    }
+
+    /// Reads a profile of network conditions from a file, as in
+    /// `from_profile`
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_profile(path: impl AsRef<Path>) -> Result<Self, ProfileError> {
+        let profile = fs::read_to_string(path).map_err(ProfileError::Io)?;
+        link_profile::parse(&profile)
+    }
 }
@@ -0,0 +1,238 @@
+use std::{error::Error, fmt, io, str::FromStr};
+
+use crate::{BurstLossConfig, JitterDistribution, LatencySpikeConfig, LinkConditionerConfig};
+
+/// The reasons a profile of network conditions can't be read
+#[derive(Debug)]
+pub enum ProfileError {
+    /// The profile's file couldn't be read
+    Io(io::Error),
+    /// A line of the profile isn't a setting, a table or a comment
+    Syntax {
+        /// The number of the line, counting from 1
+        line: usize,
+    },
+    /// A setting or table isn't one a LinkConditionerConfig has
+    UnknownKey {
+        /// The number of the line, counting from 1
+        line: usize,
+        /// The name of the setting or table
+        key: String,
+    },
+    /// A setting's value isn't one it can take
+    InvalidValue {
+        /// The number of the line, counting from 1
+        line: usize,
+        /// The name of the setting
+        key: String,
+    },
+    /// The profile names a preset which doesn't exist, or names one after
+    /// other settings, which it would undo
+    InvalidPreset {
+        /// The number of the line, counting from 1
+        line: usize,
+    },
+}
+
+impl fmt::Display for ProfileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            ProfileError::Io(err) => write!(f, "couldn't read link profile: {}", err),
+            ProfileError::Syntax { line } => write!(f, "link profile line {} is malformed", line),
+            ProfileError::UnknownKey { line, key } => {
+                write!(f, "link profile line {}: unknown setting `{}`", line, key)
+            }
+            ProfileError::InvalidValue { line, key } => {
+                write!(f, "link profile line {}: invalid value for `{}`", line, key)
+            }
+            ProfileError::InvalidPreset { line } => write!(
+                f,
+                "link profile line {}: unknown preset, or preset after other settings",
+                line
+            ),
+        }
+    }
+}
+
+impl Error for ProfileError {}
+
+#[derive(Clone, Copy, Eq, PartialEq)]
+enum Table {
+    Root,
+    BurstLoss,
+    LatencySpikes,
+}
+
+// Reads a profile, line by line, onto the preset it starts from or the
+// default conditions
+pub(crate) fn parse(profile: &str) -> Result<LinkConditionerConfig, ProfileError> {
+    let mut config = LinkConditionerConfig::new(0, 0, 0.0, 0.0);
+    let mut table = Table::Root;
+    let mut any_settings = false;
+
+    for (index, line) in profile.lines().enumerate() {
+        let line_number = index + 1;
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        if line.starts_with('[') {
+            if !line.ends_with(']') {
+                return Err(ProfileError::Syntax { line: line_number });
+            }
+            let name = line[1..line.len() - 1].trim();
+            table = match name {
+                "burst_loss" => {
+                    config
+                        .burst_loss
+                        .get_or_insert_with(BurstLossConfig::default);
+                    Table::BurstLoss
+                }
+                "latency_spikes" => {
+                    config
+                        .latency_spikes
+                        .get_or_insert_with(LatencySpikeConfig::default);
+                    Table::LatencySpikes
+                }
+                _ => {
+                    return Err(ProfileError::UnknownKey {
+                        line: line_number,
+                        key: name.to_string(),
+                    })
+                }
+            };
+            any_settings = true;
+            continue;
+        }
+
+        let (key, value) = match line.find('=') {
+            Some(split) => (line[..split].trim(), line[split + 1..].trim()),
+            None => return Err(ProfileError::Syntax { line: line_number }),
+        };
+        let invalid = || ProfileError::InvalidValue {
+            line: line_number,
+            key: key.to_string(),
+        };
+
+        match (table, key) {
+            (Table::Root, "preset") => {
+                let preset = parse_string(value)
+                    .filter(|_| !any_settings)
+                    .and_then(LinkConditionerConfig::preset);
+                config = preset.ok_or(ProfileError::InvalidPreset { line: line_number })?;
+            }
+            (Table::Root, "incoming_latency") => {
+                config.incoming_latency = parse_number(value).ok_or_else(invalid)?
+            }
+            (Table::Root, "incoming_jitter") => {
+                config.incoming_jitter = parse_number(value).ok_or_else(invalid)?
+            }
+            (Table::Root, "jitter_distribution") => {
+                config.jitter_distribution = match parse_string(value) {
+                    Some("uniform") => JitterDistribution::Uniform,
+                    Some("normal") => JitterDistribution::Normal,
+                    _ => return Err(invalid()),
+                }
+            }
+            (Table::Root, "incoming_loss") => {
+                config.incoming_loss = parse_chance(value).ok_or_else(invalid)?
+            }
+            (Table::Root, "incoming_corruption") => {
+                config.incoming_corruption = parse_chance(value).ok_or_else(invalid)?
+            }
+            (Table::Root, "incoming_truncation") => {
+                config.incoming_truncation = parse_chance(value).ok_or_else(invalid)?
+            }
+            (Table::Root, "incoming_duplication") => {
+                config.incoming_duplication = parse_chance(value).ok_or_else(invalid)?
+            }
+            (Table::Root, "incoming_reorder") => {
+                config.incoming_reorder = parse_chance(value).ok_or_else(invalid)?
+            }
+            (Table::Root, "reorder_window") => {
+                config.reorder_window = parse_number(value).ok_or_else(invalid)?
+            }
+            (Table::Root, "incoming_bandwidth") => {
+                config.incoming_bandwidth = Some(parse_number(value).ok_or_else(invalid)?)
+            }
+            (Table::Root, "bandwidth_queue_limit") => {
+                config.bandwidth_queue_limit = parse_number(value).ok_or_else(invalid)?
+            }
+            (Table::Root, "seed") => config.seed = Some(parse_number(value).ok_or_else(invalid)?),
+            (Table::BurstLoss, _) => {
+                let burst_loss = config.burst_loss.as_mut().expect("table was just begun");
+                let field = match key {
+                    "good_to_bad" => &mut burst_loss.good_to_bad,
+                    "bad_to_good" => &mut burst_loss.bad_to_good,
+                    "good_loss" => &mut burst_loss.good_loss,
+                    "bad_loss" => &mut burst_loss.bad_loss,
+                    _ => {
+                        return Err(ProfileError::UnknownKey {
+                            line: line_number,
+                            key: key.to_string(),
+                        })
+                    }
+                };
+                *field = parse_chance(value).ok_or_else(invalid)?;
+            }
+            (Table::LatencySpikes, _) => {
+                let spikes = config
+                    .latency_spikes
+                    .as_mut()
+                    .expect("table was just begun");
+                match key {
+                    "chance" => spikes.chance = parse_chance(value).ok_or_else(invalid)?,
+                    "duration" => spikes.duration = parse_number(value).ok_or_else(invalid)?,
+                    "extra_latency" => {
+                        spikes.extra_latency = parse_number(value).ok_or_else(invalid)?
+                    }
+                    _ => {
+                        return Err(ProfileError::UnknownKey {
+                            line: line_number,
+                            key: key.to_string(),
+                        })
+                    }
+                }
+            }
+            (Table::Root, _) => {
+                return Err(ProfileError::UnknownKey {
+                    line: line_number,
+                    key: key.to_string(),
+                })
+            }
+        }
+        any_settings = true;
+    }
+
+    Ok(config)
+}
+
+// Cuts a comment off the end of a line, leaving any `#` inside a string
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    for (index, character) in line.char_indices() {
+        match character {
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..index],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn parse_string(value: &str) -> Option<&str> {
+    if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+        return Some(&value[1..value.len() - 1]);
+    }
+    None
+}
+
+// TOML allows underscores between digits, to make large numbers readable
+fn parse_number<T: FromStr>(value: &str) -> Option<T> {
+    value.replace('_', "").parse().ok()
+}
+
+fn parse_chance(value: &str) -> Option<f32> {
+    parse_number::<f32>(value).filter(|chance| (0.0..=1.0).contains(chance))
+}
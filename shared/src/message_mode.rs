@@ -0,0 +1,12 @@
+/// Whether a packet goes over a WebRTC data channel as a binary message or a
+/// text one, for JS consumers which read `event.data` as a string. The mode
+/// is kept from end to end between a WebRTC server & a browser client, while
+/// a UDP server & a native client send both the same way
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Default)]
+pub enum MessageMode {
+    /// The payload is sent as it is
+    #[default]
+    Binary,
+    /// The payload is sent as text, so it must be valid UTF-8
+    Text,
+}
@@ -0,0 +1,15 @@
+use bytes::Bytes;
+
+/// What a socket middleware decides should happen to a packet passing
+/// through it
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum MiddlewareAction {
+    /// Hand the packet on unchanged
+    Keep,
+    /// Go no further with the packet. A middleware which holds packets back
+    /// to release them later drops them this way too
+    Drop,
+    /// Hand the packet on with its payload replaced by this one, keeping
+    /// everything else about it
+    Rewrite(Bytes),
+}
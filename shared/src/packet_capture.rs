@@ -0,0 +1,219 @@
+use std::{
+    fmt,
+    io::{self, Write},
+    net::{IpAddr, Ipv6Addr, SocketAddr},
+    sync::{Arc, Mutex},
+};
+#[cfg(not(target_arch = "wasm32"))]
+use std::{fs, io::BufWriter, path::Path, time::SystemTime};
+
+use log::warn;
+
+use crate::{Instant, PacketDirection};
+
+const PCAP_MAGIC: u32 = 0xa1b2_c3d4;
+// each packet begins with its IP header, with no link layer header before it
+const LINKTYPE_RAW: u32 = 101;
+const SNAPLEN: u32 = 65535;
+const IPV4_HEADER_SIZE: usize = 20;
+const IPV6_HEADER_SIZE: usize = 40;
+const UDP_HEADER_SIZE: usize = 8;
+const UDP_PROTOCOL: u8 = 17;
+
+/// Writes every payload a socket sends & receives to a `.pcap` capture, each
+/// wrapped in made up IP & UDP headers between the socket's address & the
+/// remote one, so that the traffic can be inspected offline in Wireshark
+/// even though what actually goes over the wire is encrypted. Payloads are
+/// captured as the application sent them or will receive them, so framing,
+/// acknowledgements & the like aren't seen. Use one per socket, so that each
+/// session gets a capture of its own. Clones share the same capture, which
+/// is flushed once the last of them is dropped
+#[derive(Clone)]
+pub struct PacketCapture {
+    capture: Arc<Mutex<Capture>>,
+}
+
+struct Capture {
+    // `None` once writing has failed, as there's nothing more to do
+    output: Option<Box<dyn Write + Send>>,
+    began: Instant,
+    // the wall clock time the capture began, in microseconds since the Unix
+    // epoch, which packets are timestamped from
+    began_at_micros: u64,
+    packets: u64,
+}
+
+impl PacketCapture {
+    /// Create a new PacketCapture, which writes the capture to the given
+    /// output
+    pub fn new(mut output: impl Write + Send + 'static) -> io::Result<Self> {
+        let mut header = Vec::with_capacity(24);
+        header.extend_from_slice(&PCAP_MAGIC.to_le_bytes());
+        header.extend_from_slice(&2u16.to_le_bytes());
+        header.extend_from_slice(&4u16.to_le_bytes());
+        // the timezone offset & timestamp accuracy, which are always zero
+        header.extend_from_slice(&[0; 8]);
+        header.extend_from_slice(&SNAPLEN.to_le_bytes());
+        header.extend_from_slice(&LINKTYPE_RAW.to_le_bytes());
+        output.write_all(&header)?;
+
+        Ok(PacketCapture {
+            capture: Arc::new(Mutex::new(Capture {
+                output: Some(Box::new(output)),
+                began: Instant::now(),
+                began_at_micros: unix_micros(),
+                packets: 0,
+            })),
+        })
+    }
+
+    /// Create a new PacketCapture, which writes the capture to the file at
+    /// the given path, replacing anything already there
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        PacketCapture::new(BufWriter::new(fs::File::create(path)?))
+    }
+
+    /// Gets the number of packets captured so far
+    pub fn packets(&self) -> u64 {
+        self.capture.lock().unwrap().packets
+    }
+
+    /// Writes out any packets captured but not yet written to the output
+    pub fn flush(&self) -> io::Result<()> {
+        match self.capture.lock().unwrap().output.as_mut() {
+            Some(output) => output.flush(),
+            None => Ok(()),
+        }
+    }
+
+    /// Captures a payload exchanged between the socket's own address & the
+    /// given remote one
+    pub fn capture(
+        &self,
+        direction: PacketDirection,
+        local_address: &SocketAddr,
+        remote_address: &SocketAddr,
+        payload: &[u8],
+    ) {
+        let mut capture = self.capture.lock().unwrap();
+        if capture.output.is_none() {
+            return;
+        }
+
+        let (source, destination) = match direction {
+            PacketDirection::Incoming => (remote_address, local_address),
+            PacketDirection::Outgoing => (local_address, remote_address),
+        };
+        let datagram = synthesize_datagram(source, destination, payload);
+        // anything beyond the snapshot length is cut off, as in a real capture
+        let captured_len = datagram.len().min(SNAPLEN as usize);
+        let timestamp = capture.began_at_micros + capture.began.elapsed().as_micros() as u64;
+
+        let mut record = Vec::with_capacity(16 + captured_len);
+        record.extend_from_slice(&((timestamp / 1_000_000) as u32).to_le_bytes());
+        record.extend_from_slice(&((timestamp % 1_000_000) as u32).to_le_bytes());
+        record.extend_from_slice(&(captured_len as u32).to_le_bytes());
+        record.extend_from_slice(&(datagram.len() as u32).to_le_bytes());
+        record.extend_from_slice(&datagram[..captured_len]);
+
+        let written = capture
+            .output
+            .as_mut()
+            .expect("output was just checked")
+            .write_all(&record);
+        match written {
+            Ok(()) => capture.packets += 1,
+            Err(error) => {
+                warn!("couldn't write packet capture: {}", error);
+                capture.output = None;
+            }
+        }
+    }
+}
+
+impl fmt::Debug for PacketCapture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let capture = self.capture.lock().unwrap();
+        f.debug_struct("PacketCapture")
+            .field("writing", &capture.output.is_some())
+            .field("packets", &capture.packets)
+            .finish()
+    }
+}
+
+// Wraps a payload in an IP header & a UDP header, as if it had been sent
+// between the given addresses. Both are treated as IPv6 unless they are both
+// IPv4
+fn synthesize_datagram(source: &SocketAddr, destination: &SocketAddr, payload: &[u8]) -> Vec<u8> {
+    let udp_len = UDP_HEADER_SIZE + payload.len();
+    let mut datagram = Vec::with_capacity(IPV6_HEADER_SIZE + udp_len);
+
+    match (source.ip(), destination.ip()) {
+        (IpAddr::V4(source_ip), IpAddr::V4(destination_ip)) => {
+            let total_len = (IPV4_HEADER_SIZE + udp_len).min(u16::MAX as usize) as u16;
+            datagram.push(0x45);
+            datagram.push(0);
+            datagram.extend_from_slice(&total_len.to_be_bytes());
+            // no identification, & the don't fragment flag
+            datagram.extend_from_slice(&[0, 0, 0x40, 0]);
+            datagram.push(64);
+            datagram.push(UDP_PROTOCOL);
+            datagram.extend_from_slice(&[0, 0]);
+            datagram.extend_from_slice(&source_ip.octets());
+            datagram.extend_from_slice(&destination_ip.octets());
+            let checksum = ipv4_checksum(&datagram);
+            datagram[10..12].copy_from_slice(&checksum.to_be_bytes());
+        }
+        (source_ip, destination_ip) => {
+            let payload_len = udp_len.min(u16::MAX as usize) as u16;
+            datagram.extend_from_slice(&[0x60, 0, 0, 0]);
+            datagram.extend_from_slice(&payload_len.to_be_bytes());
+            datagram.push(UDP_PROTOCOL);
+            datagram.push(64);
+            datagram.extend_from_slice(&to_ipv6(source_ip).octets());
+            datagram.extend_from_slice(&to_ipv6(destination_ip).octets());
+        }
+    }
+
+    datagram.extend_from_slice(&source.port().to_be_bytes());
+    datagram.extend_from_slice(&destination.port().to_be_bytes());
+    datagram.extend_from_slice(&(udp_len.min(u16::MAX as usize) as u16).to_be_bytes());
+    // a zero checksum, which Wireshark doesn't check by default
+    datagram.extend_from_slice(&[0, 0]);
+    datagram.extend_from_slice(payload);
+    datagram
+}
+
+fn to_ipv6(ip: IpAddr) -> Ipv6Addr {
+    match ip {
+        IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+        IpAddr::V6(ip) => ip,
+    }
+}
+
+fn ipv4_checksum(header: &[u8]) -> u16 {
+    let mut sum: u32 = header
+        .chunks(2)
+        .map(|word| u32::from(u16::from_be_bytes([word[0], word[1]])))
+        .sum();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn unix_micros() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|since_epoch| since_epoch.as_micros() as u64)
+        .unwrap_or(0)
+}
+
+// Browsers' clocks aren't read here, so captures made in them are timestamped
+// from the Unix epoch, which still shows how far apart packets were
+#[cfg(target_arch = "wasm32")]
+fn unix_micros() -> u64 {
+    0
+}
@@ -0,0 +1,204 @@
+use std::{
+    collections::HashSet,
+    fmt::Write,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use log::info;
+
+use crate::Instant;
+
+const BYTES_PER_LINE: usize = 16;
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+/// Which way a dumped packet was going
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum PacketDirection {
+    /// Received from the remote end
+    Incoming,
+    /// Sent to the remote end
+    Outgoing,
+}
+
+/// A debug mode which logs every packet a socket sends & receives, with its
+/// direction, address, length & a hexdump of the start of its payload, to
+/// debug protocols inside transports which tools like Wireshark can't see
+/// into, such as DTLS. It starts off, & can be turned on & off, filtered to
+/// particular addresses or rate limited while the socket is live. Payloads
+/// are dumped as the application sent them or will receive them, before any
+/// framing, protection or compression. Clones share the same settings
+#[derive(Debug, Clone)]
+pub struct PacketDump {
+    settings: Arc<Mutex<Settings>>,
+}
+
+#[derive(Debug)]
+struct Settings {
+    enabled: bool,
+    max_bytes: usize,
+    // `None` to dump the packets of every address
+    addresses: Option<HashSet<SocketAddr>>,
+    max_per_second: Option<u32>,
+    window_start: Option<Instant>,
+    dumped_in_window: u32,
+    // packets the rate limit has skipped since the last one dumped
+    skipped: u64,
+}
+
+impl PacketDump {
+    /// Create a new PacketDump, which is off until `enable` is called, &
+    /// dumps up to 64 bytes of each payload
+    pub fn new() -> Self {
+        PacketDump {
+            settings: Arc::new(Mutex::new(Settings {
+                enabled: false,
+                max_bytes: 64,
+                addresses: None,
+                max_per_second: None,
+                window_start: None,
+                dumped_in_window: 0,
+                skipped: 0,
+            })),
+        }
+    }
+
+    /// Starts dumping packets
+    pub fn enable(&self) {
+        self.settings.lock().unwrap().enabled = true;
+    }
+
+    /// Stops dumping packets
+    pub fn disable(&self) {
+        self.settings.lock().unwrap().enabled = false;
+    }
+
+    /// Gets whether packets are being dumped
+    pub fn is_enabled(&self) -> bool {
+        self.settings.lock().unwrap().enabled
+    }
+
+    /// Sets how many bytes of each payload are dumped, the rest being
+    /// summarized by how many there were
+    pub fn set_max_bytes(&self, max_bytes: usize) {
+        self.settings.lock().unwrap().max_bytes = max_bytes;
+    }
+
+    /// Dumps the packets of the given address, & from then on only those of
+    /// the addresses filtered to this way
+    pub fn filter_address(&self, address: SocketAddr) {
+        self.settings
+            .lock()
+            .unwrap()
+            .addresses
+            .get_or_insert_with(HashSet::new)
+            .insert(address);
+    }
+
+    /// Goes back to dumping the packets of every address
+    pub fn clear_address_filter(&self) {
+        self.settings.lock().unwrap().addresses = None;
+    }
+
+    /// Dumps at most the given number of packets each second, counting those
+    /// skipped, or any number of them if `None`
+    pub fn set_rate_limit(&self, max_per_second: Option<u32>) {
+        self.settings.lock().unwrap().max_per_second = max_per_second;
+    }
+
+    /// Logs the given packet, if dumping is on & it passes the filters
+    pub fn dump(&self, direction: PacketDirection, address: &SocketAddr, payload: &[u8]) {
+        let mut settings = self.settings.lock().unwrap();
+        if !settings.enabled {
+            return;
+        }
+        if let Some(addresses) = &settings.addresses {
+            if !addresses.contains(address) {
+                return;
+            }
+        }
+        if !settings.take_rate_slot() {
+            settings.skipped += 1;
+            return;
+        }
+
+        let mut out = format!(
+            "{} {} {} bytes",
+            match direction {
+                PacketDirection::Incoming => "<<",
+                PacketDirection::Outgoing => ">>",
+            },
+            address,
+            payload.len()
+        );
+        if settings.skipped > 0 {
+            let _ = write!(
+                out,
+                " ({} packets skipped by the rate limit)",
+                settings.skipped
+            );
+            settings.skipped = 0;
+        }
+        let shown = &payload[..payload.len().min(settings.max_bytes)];
+        write_hexdump(&mut out, shown);
+        if shown.len() < payload.len() {
+            let _ = write!(out, "\n  .. {} more bytes", payload.len() - shown.len());
+        }
+        info!("{}", out);
+    }
+}
+
+impl Default for PacketDump {
+    fn default() -> Self {
+        PacketDump::new()
+    }
+}
+
+impl Settings {
+    // Whether another packet can be dumped within the rate limit, counting it
+    // if so
+    fn take_rate_slot(&mut self) -> bool {
+        let max_per_second = match self.max_per_second {
+            Some(max_per_second) => max_per_second,
+            None => return true,
+        };
+        let window_over = match &self.window_start {
+            Some(start) => start.elapsed() >= RATE_WINDOW,
+            None => true,
+        };
+        if window_over {
+            self.window_start = Some(Instant::now());
+            self.dumped_in_window = 0;
+        }
+        if self.dumped_in_window >= max_per_second {
+            return false;
+        }
+        self.dumped_in_window += 1;
+        true
+    }
+}
+
+// Writes lines of the offset, the bytes in hex & those of them which are
+// printable as text
+fn write_hexdump(out: &mut String, bytes: &[u8]) {
+    for (line, chunk) in bytes.chunks(BYTES_PER_LINE).enumerate() {
+        let _ = write!(out, "\n  {:04x} ", line * BYTES_PER_LINE);
+        for index in 0..BYTES_PER_LINE {
+            match chunk.get(index) {
+                Some(byte) => {
+                    let _ = write!(out, " {:02x}", byte);
+                }
+                None => out.push_str("   "),
+            }
+        }
+        out.push_str("  |");
+        for byte in chunk {
+            out.push(match byte {
+                0x20..=0x7e => *byte as char,
+                _ => '.',
+            });
+        }
+        out.push('|');
+    }
+}
@@ -0,0 +1,177 @@
+/// The types of packets which are exchanged between a native client & a UDP
+/// server. The packet type is always written as the first byte of a packet.
+/// On an encrypted connection, the payload of a sealed packet sent by the
+/// client is encrypted behind its token & sequence number, & everything after
+/// the packet type of one sent by the server is encrypted behind a sequence
+/// number of its own
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum PacketType {
+    /// A packet containing an application payload. When sent from the client,
+    /// the payload is preceded by the client's connection token & the packet's
+    /// sequence number
+    Data,
+    /// Sent by a client to request a connection with the server, contains the
+    /// handshake header
+    ClientConnectRequest,
+    /// Sent by the server to accept a connection, contains the connection
+    /// token the client must include with every Data packet it sends, the
+    /// optional features agreed to, & a resumption token it can present if it
+    /// has to reconnect. When encrypting, the server's public key comes before
+    /// the resumption token, which is sealed
+    ServerConnectResponse,
+    /// Sent by the server to refuse a connection from a client speaking a
+    /// different protocol version, contains the server's handshake header.
+    /// The layout of this packet must never change between versions
+    ServerVersionMismatch,
+    /// Sent by the server in reply to a connect request from an unknown
+    /// address, contains a cookie the client must echo back before the server
+    /// will keep any state for it
+    ServerChallenge,
+    /// Sent by a client in reply to a ServerChallenge, contains the handshake
+    /// header, the optional features wanted, the echoed cookie, the client's
+    /// connect token & connect payload, its public key if it wants encryption,
+    /// & optionally the resumption token of its previous connection
+    ClientChallengeResponse,
+    /// Sent by the server to refuse a connection because it is at capacity
+    ServerFull,
+    /// Sent by the server in reply to a ClientChallengeResponse while the
+    /// client waits for the server to have room, contains the client's
+    /// position in the queue
+    ServerQueuePosition,
+    /// Sent by the server when it closes a connection, contains the
+    /// connection token & the reason given by the application, if any
+    ServerDisconnect,
+    /// Sent by the server to refuse a connection because a client with the
+    /// same identity is already connected
+    ServerDuplicateConnection,
+    /// Sent by the server to refuse a connection because it isn't accepting
+    /// new clients at the moment
+    ServerNotAccepting,
+    /// Sent by the server to tell a client to move over to a new host,
+    /// contains the connection token & the new host's address as text
+    ServerHostMigration,
+    /// Sent by the server to find out whether datagrams of a given size reach
+    /// the client, contains the connection token & is padded to the size
+    /// being probed
+    ServerMtuProbe,
+    /// Sent by a client in reply to a ServerMtuProbe. Laid out like a Data
+    /// packet, with the size of the probe which arrived as its payload
+    ClientMtuProbeAck,
+    /// A fragment of an application payload too large for a single datagram.
+    /// Laid out like a Data packet, with the payload replaced by the
+    /// fragment's header & data
+    Fragment,
+    /// Several application payloads packed into a single datagram. Laid out
+    /// like a Data packet, with the payload replaced by the packed payloads,
+    /// each preceded by its length
+    Coalesced,
+    /// A message sent on a reliable channel. Laid out like a Data packet,
+    /// with the payload preceded by the channel id & the message id
+    Reliable,
+    /// Acknowledges a message received on a reliable channel. Laid out like a
+    /// Data packet, with the channel id & message id as the payload
+    ReliableAck,
+    /// Acknowledges the packets received so far, when there are no packets
+    /// going the other way to carry the ack. Laid out like a Data packet, with
+    /// the ack as the payload
+    Ack,
+    /// A packet sent on an unreliable channel. Laid out like a Data packet,
+    /// with the payload preceded by the channel id
+    ChannelData,
+    /// Sent by either side to measure the round trip time, contains the
+    /// moment it was sent. Laid out like a Data packet, with the timestamp as
+    /// the payload
+    Ping,
+    /// Sent in reply to a Ping, echoing its timestamp. Laid out like a Data
+    /// packet, with the timestamp followed by the time on the responder's
+    /// system clock as the payload
+    Pong,
+    /// Sent by a client when it closes its connection, several times over, as
+    /// it may be the last the server hears from it. Laid out like a Data
+    /// packet, with no payload
+    ClientDisconnect,
+}
+
+impl PacketType {
+    /// Gets the byte that represents the PacketType on the wire
+    pub fn to_byte(self) -> u8 {
+        match self {
+            PacketType::Data => 0,
+            PacketType::ClientConnectRequest => 1,
+            PacketType::ServerConnectResponse => 2,
+            PacketType::ServerVersionMismatch => 3,
+            PacketType::ServerChallenge => 4,
+            PacketType::ClientChallengeResponse => 5,
+            PacketType::ServerFull => 6,
+            PacketType::ServerQueuePosition => 7,
+            PacketType::ServerDisconnect => 8,
+            PacketType::ServerDuplicateConnection => 9,
+            PacketType::ServerNotAccepting => 10,
+            PacketType::ServerHostMigration => 11,
+            PacketType::ServerMtuProbe => 12,
+            PacketType::ClientMtuProbeAck => 13,
+            PacketType::Fragment => 14,
+            PacketType::Coalesced => 15,
+            PacketType::Reliable => 16,
+            PacketType::ReliableAck => 17,
+            PacketType::Ack => 18,
+            PacketType::ChannelData => 19,
+            PacketType::Ping => 20,
+            PacketType::Pong => 21,
+            PacketType::ClientDisconnect => 22,
+        }
+    }
+
+    /// Reads a PacketType from the given byte, if it is a valid one
+    pub fn from_byte(byte: u8) -> Option<PacketType> {
+        match byte {
+            0 => Some(PacketType::Data),
+            1 => Some(PacketType::ClientConnectRequest),
+            2 => Some(PacketType::ServerConnectResponse),
+            3 => Some(PacketType::ServerVersionMismatch),
+            4 => Some(PacketType::ServerChallenge),
+            5 => Some(PacketType::ClientChallengeResponse),
+            6 => Some(PacketType::ServerFull),
+            7 => Some(PacketType::ServerQueuePosition),
+            8 => Some(PacketType::ServerDisconnect),
+            9 => Some(PacketType::ServerDuplicateConnection),
+            10 => Some(PacketType::ServerNotAccepting),
+            11 => Some(PacketType::ServerHostMigration),
+            12 => Some(PacketType::ServerMtuProbe),
+            13 => Some(PacketType::ClientMtuProbeAck),
+            14 => Some(PacketType::Fragment),
+            15 => Some(PacketType::Coalesced),
+            16 => Some(PacketType::Reliable),
+            17 => Some(PacketType::ReliableAck),
+            18 => Some(PacketType::Ack),
+            19 => Some(PacketType::ChannelData),
+            20 => Some(PacketType::Ping),
+            21 => Some(PacketType::Pong),
+            22 => Some(PacketType::ClientDisconnect),
+            _ => None,
+        }
+    }
+
+    /// Gets whether packets of this type are sealed on an encrypted
+    /// connection. Handshake packets are never sealed, & MTU probes are
+    /// padded so that they are the size being probed once sealed
+    pub fn is_sealed(self) -> bool {
+        matches!(
+            self,
+            PacketType::Data
+                | PacketType::Fragment
+                | PacketType::Coalesced
+                | PacketType::Reliable
+                | PacketType::ReliableAck
+                | PacketType::Ack
+                | PacketType::ChannelData
+                | PacketType::Ping
+                | PacketType::Pong
+                | PacketType::ClientDisconnect
+                | PacketType::ServerDisconnect
+                | PacketType::ServerMtuProbe
+                | PacketType::ClientMtuProbeAck
+                | PacketType::ServerHostMigration
+        )
+    }
+}
@@ -0,0 +1,94 @@
+use std::fmt;
+
+use bytes::Bytes;
+
+/// Payloads of up to this many bytes can be stored inline
+pub const INLINE_PAYLOAD_SIZE: usize = 128;
+
+/// The payload of a Packet, stored inline when it is small enough to be
+/// copied in, & otherwise in a buffer which may be shared with other packets.
+/// Most game packets are small, & so never touch the heap
+#[derive(Clone)]
+pub struct Payload {
+    storage: Storage,
+}
+
+#[derive(Clone)]
+enum Storage {
+    Inline {
+        length: u8,
+        bytes: [u8; INLINE_PAYLOAD_SIZE],
+    },
+    Shared(Bytes),
+}
+
+impl Payload {
+    /// Create a Payload holding a copy of the given bytes, inline if they fit
+    pub fn from_slice(bytes: &[u8]) -> Self {
+        if bytes.len() > INLINE_PAYLOAD_SIZE {
+            return Payload {
+                storage: Storage::Shared(Bytes::copy_from_slice(bytes)),
+            };
+        }
+        let mut inline = [0; INLINE_PAYLOAD_SIZE];
+        inline[..bytes.len()].copy_from_slice(bytes);
+        Payload {
+            storage: Storage::Inline {
+                length: bytes.len() as u8,
+                bytes: inline,
+            },
+        }
+    }
+
+    /// Create a Payload sharing the given buffer, without copying it
+    pub fn from_bytes(bytes: Bytes) -> Self {
+        Payload {
+            storage: Storage::Shared(bytes),
+        }
+    }
+
+    /// Gets whether the payload is stored inline
+    pub fn is_inline(&self) -> bool {
+        matches!(self.storage, Storage::Inline { .. })
+    }
+
+    /// Get at the bytes of the payload
+    pub fn as_slice(&self) -> &[u8] {
+        match &self.storage {
+            Storage::Inline { length, bytes } => &bytes[..*length as usize],
+            Storage::Shared(bytes) => bytes,
+        }
+    }
+
+    /// Takes the payload as a buffer which can be cloned & sliced without
+    /// copying it. Inline payloads are copied into one
+    pub fn into_bytes(self) -> Bytes {
+        match self.storage {
+            Storage::Inline { length, bytes } => Bytes::copy_from_slice(&bytes[..length as usize]),
+            Storage::Shared(bytes) => bytes,
+        }
+    }
+}
+
+impl Default for Payload {
+    fn default() -> Self {
+        Payload::from_slice(&[])
+    }
+}
+
+impl PartialEq for Payload {
+    fn eq(&self, other: &Payload) -> bool {
+        self.as_slice() == other.as_slice()
+    }
+}
+
+impl Eq for Payload {}
+
+impl fmt::Debug for Payload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Payload")
+            .field("inline", &self.is_inline())
+            .field("bytes", &self.as_slice())
+            .finish()
+    }
+}
@@ -0,0 +1,163 @@
+use std::{
+    convert::TryInto,
+    time::{Duration, SystemTime},
+};
+
+use super::Instant;
+
+/// The size of the timestamp a Ping carries, & its Pong echoes back
+pub const PING_SIZE: usize = 8;
+
+/// The size of a Pong's payload, the echoed timestamp followed by the time on
+/// the responder's system clock
+pub const PONG_SIZE: usize = 2 * PING_SIZE;
+
+// The weights given to each new sample, as in TCP
+const RTT_GAIN: f64 = 0.125;
+const JITTER_GAIN: f64 = 0.25;
+const OFFSET_GAIN: f64 = 0.125;
+
+/// Writes the payload of a Pong answering the Ping given, or None if the Ping
+/// is malformed. The responder's clock is read as time since the Unix epoch,
+/// so this is only used by the native client & the UDP server
+pub fn write_pong(ping: &[u8]) -> Option<[u8; PONG_SIZE]> {
+    let ping = ping.get(..PING_SIZE)?;
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default();
+
+    let mut pong = [0; PONG_SIZE];
+    pong[..PING_SIZE].copy_from_slice(ping);
+    pong[PING_SIZE..].copy_from_slice(&(now.as_micros() as u64).to_be_bytes());
+    Some(pong)
+}
+
+/// Pings a connection at a regular interval, & keeps the smoothed round trip
+/// time & jitter measured from the Pongs which come back. Each Ping carries
+/// the moment it was sent, which its Pong echoes, so nothing needs to be
+/// remembered about the Pings in flight. The responder's clock, which each
+/// Pong carries too, is compared with the middle of the round trip like NTP
+/// does, to estimate how far ahead of ours it is
+#[derive(Debug)]
+pub struct RttEstimator {
+    interval: Duration,
+    // the moment the timestamps in Pings count from
+    epoch: Instant,
+    last_ping: Option<Instant>,
+    smoothed_rtt: Option<Duration>,
+    jitter: Duration,
+    // how far the responder's clock is ahead of the epoch, in microseconds
+    offset: Option<i64>,
+}
+
+impl RttEstimator {
+    /// Create a new RttEstimator, which pings every `interval`, starting
+    /// right away
+    pub fn new(interval: Duration) -> Self {
+        RttEstimator {
+            interval,
+            epoch: Instant::now(),
+            last_ping: None,
+            smoothed_rtt: None,
+            jitter: Duration::ZERO,
+            offset: None,
+        }
+    }
+
+    /// Gets whether it is time to send another Ping
+    pub fn ping_due(&self) -> bool {
+        self.last_ping
+            .as_ref()
+            .is_none_or(|last_ping| last_ping.elapsed() >= self.interval)
+    }
+
+    /// Gets how long until the next Ping is due
+    pub fn next_ping_in(&self) -> Duration {
+        match &self.last_ping {
+            Some(last_ping) => self.interval.saturating_sub(last_ping.elapsed()),
+            None => Duration::ZERO,
+        }
+    }
+
+    /// Writes the timestamp of a Ping about to be sent
+    pub fn write_ping(&mut self) -> [u8; PING_SIZE] {
+        self.last_ping = Some(Instant::now());
+        (self.epoch.elapsed().as_micros() as u64).to_be_bytes()
+    }
+
+    /// Measures a round trip from the timestamp a Pong echoed back, & the
+    /// responder's clock from the time it carries, returning whether it was
+    /// one of ours
+    pub fn read_pong(&mut self, pong: &[u8]) -> bool {
+        let sent = match pong.get(..PING_SIZE) {
+            Some(sent) => Duration::from_micros(u64::from_be_bytes(sent.try_into().unwrap())),
+            None => return false,
+        };
+        let now = self.epoch.elapsed();
+        // a timestamp from the future wasn't written by us
+        if sent > now {
+            return false;
+        }
+        let rtt = now - sent;
+        if let Some(remote) = pong.get(PING_SIZE..PONG_SIZE) {
+            let remote = u64::from_be_bytes(remote.try_into().unwrap()) as i64;
+            self.read_remote_time(remote, sent, rtt);
+        }
+
+        match self.smoothed_rtt {
+            Some(smoothed_rtt) => {
+                let deviation = rtt.abs_diff(smoothed_rtt);
+                self.jitter =
+                    self.jitter.mul_f64(1.0 - JITTER_GAIN) + deviation.mul_f64(JITTER_GAIN);
+                self.smoothed_rtt =
+                    Some(smoothed_rtt.mul_f64(1.0 - RTT_GAIN) + rtt.mul_f64(RTT_GAIN));
+            }
+            None => {
+                self.jitter = rtt / 2;
+                self.smoothed_rtt = Some(rtt);
+            }
+        }
+        true
+    }
+
+    // Estimates the responder's clock from the time it was read, assuming
+    // that was halfway through the round trip
+    fn read_remote_time(&mut self, remote: i64, sent: Duration, rtt: Duration) {
+        let midpoint = (sent + rtt / 2).as_micros() as i64;
+        let sample = remote.saturating_sub(midpoint);
+
+        match (self.offset, self.smoothed_rtt) {
+            (Some(offset), Some(smoothed_rtt)) => {
+                // a round trip delayed more than usual is likely lopsided,
+                // which would skew the estimate
+                if rtt > smoothed_rtt + self.jitter * 2 {
+                    return;
+                }
+                let correction = (sample.saturating_sub(offset) as f64 * OFFSET_GAIN) as i64;
+                self.offset = Some(offset.saturating_add(correction));
+            }
+            _ => {
+                self.offset = Some(sample);
+            }
+        }
+    }
+
+    /// Gets the smoothed round trip time, once a Pong has come back
+    pub fn rtt(&self) -> Option<Duration> {
+        self.smoothed_rtt
+    }
+
+    /// Gets how much the round trip time varies from one Ping to the next,
+    /// once a Pong has come back
+    pub fn jitter(&self) -> Option<Duration> {
+        self.smoothed_rtt.map(|_| self.jitter)
+    }
+
+    /// Estimates the current time on the responder's system clock, as time
+    /// since the Unix epoch, once a Pong carrying it has come back
+    pub fn remote_time(&self) -> Option<Duration> {
+        let now = self.epoch.elapsed().as_micros() as i64;
+        self.offset
+            .map(|offset| Duration::from_micros(now.saturating_add(offset).max(0) as u64))
+    }
+}
@@ -0,0 +1,73 @@
+use std::{
+    net::{IpAddr, SocketAddr, UdpSocket},
+    ops::RangeInclusive,
+};
+
+use crate::{find_my_ip_address, Random};
+
+/// The order in which a PortSearch tries ports
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum PortStrategy {
+    /// Tries the ports of the range from the lowest up
+    Sequential,
+    /// Tries the ports of the range from a random one up, wrapping around, so
+    /// that several hosts searching at once are less likely to collide
+    Random,
+    /// Leaves the range aside & lets the OS assign a free port
+    OsAssigned,
+}
+
+/// Where & how to look for a free port to bind a socket to
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct PortSearch {
+    /// The IP address to bind to. If `None`, the current host's address, as
+    /// found by `find_my_ip_address`
+    pub ip: Option<IpAddr>,
+    /// The ports which can be chosen, unless the OS assigns one
+    pub range: RangeInclusive<u16>,
+    /// The order the ports are tried in
+    pub strategy: PortStrategy,
+}
+
+impl Default for PortSearch {
+    fn default() -> Self {
+        PortSearch {
+            ip: None,
+            range: 1025..=65534,
+            strategy: PortStrategy::Sequential,
+        }
+    }
+}
+
+impl PortSearch {
+    /// Finds an address with a port which is free to bind to, if there is one.
+    /// The port is only tried, not held on to, so another socket could still
+    /// take it before it is bound
+    pub fn find(&self) -> Option<SocketAddr> {
+        let ip = match self.ip {
+            Some(ip) => ip,
+            None => find_my_ip_address()?,
+        };
+        let (start, end) = (*self.range.start(), *self.range.end());
+        if self.strategy == PortStrategy::OsAssigned {
+            return test_bind_udp(SocketAddr::new(ip, 0));
+        }
+        if start > end {
+            return None;
+        }
+
+        let count = u32::from(end - start) + 1;
+        let offset = match self.strategy {
+            PortStrategy::Random => Random::gen_range_u32(0, count),
+            _ => 0,
+        };
+        (0..count)
+            .map(|i| start + ((offset + i) % count) as u16)
+            .find_map(|port| test_bind_udp(SocketAddr::new(ip, port)))
+    }
+}
+
+// Gets the address bound to, if binding to the given one works
+fn test_bind_udp(address: SocketAddr) -> Option<SocketAddr> {
+    UdpSocket::bind(address).ok()?.local_addr().ok()
+}
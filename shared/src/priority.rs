@@ -0,0 +1,14 @@
+/// How urgently a packet should be sent, relative to the others waiting to be
+/// sent to the same side. Packets of higher priority go out first, & packets
+/// of the same priority go out in the order they were sent
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash, Default)]
+pub enum Priority {
+    /// Time-critical packets, such as state updates. They are sent ahead of
+    /// everything else, & never held back to be coalesced
+    High,
+    /// The priority packets are given unless another is set
+    #[default]
+    Normal,
+    /// Packets which can wait, such as bulk transfers
+    Low,
+}
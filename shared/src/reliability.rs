@@ -0,0 +1,251 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    time::Duration,
+};
+
+use super::{sequence::SequenceNumber, Instant};
+
+/// Reliable messages are preceded by the id of their channel (u8) & their
+/// message id within it (u16). Acks are made of the same header
+pub const RELIABLE_HEADER_SIZE: usize = 3;
+
+/// Reads the channel id & message id from the front of a reliable message or
+/// ack, returning them along with whatever follows
+pub fn read_header(buffer: &[u8]) -> Option<(u8, u16, &[u8])> {
+    if buffer.len() < RELIABLE_HEADER_SIZE {
+        return None;
+    }
+    let id = u16::from_be_bytes([buffer[1], buffer[2]]);
+    Some((buffer[0], id, &buffer[RELIABLE_HEADER_SIZE..]))
+}
+
+/// How the messages sent on a channel are delivered
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ChannelMode {
+    /// Messages are sent once, & may be lost or arrive out of order, like
+    /// packets sent off any channel. The channel only keeps them apart from
+    /// the rest of the traffic
+    Unreliable,
+    /// Every message is delivered exactly once, as soon as it arrives
+    ReliableUnordered,
+    /// Every message is delivered exactly once, in the order it was sent.
+    /// Messages which arrive early wait for those sent before them
+    ReliableOrdered,
+}
+
+/// Settings for the channels carried alongside the packets sent off any
+/// channel. Both sides of a connection must have the same channels
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ReliabilityConfig {
+    /// The mode of each channel. A channel's id is its index here
+    pub channels: Vec<ChannelMode>,
+    /// How long to wait for a message to be acknowledged before sending it
+    /// again
+    pub resend_interval: Duration,
+    /// The most messages on each channel which may be waiting to be
+    /// acknowledged. Messages sent beyond this are queued until earlier ones
+    /// are acknowledged
+    pub max_in_flight: usize,
+}
+
+impl Default for ReliabilityConfig {
+    fn default() -> Self {
+        ReliabilityConfig {
+            channels: vec![ChannelMode::ReliableOrdered],
+            resend_interval: Duration::from_millis(100),
+            max_in_flight: 256,
+        }
+    }
+}
+
+/// A reliable message which is due to be sent, for the first time or again
+#[derive(Debug)]
+pub struct OutgoingMessage {
+    /// The id of the channel the message was sent on
+    pub channel: u8,
+    /// The message's id within its channel
+    pub id: u16,
+    /// The message itself
+    pub payload: Vec<u8>,
+}
+
+/// The state of every channel of a single connection. Unreliable channels
+/// have no state, & are only recorded so their mode can be looked up
+#[derive(Debug)]
+pub struct ReliableChannels {
+    resend_interval: Duration,
+    max_in_flight: usize,
+    channels: Vec<Channel>,
+}
+
+#[derive(Debug)]
+struct Channel {
+    mode: ChannelMode,
+    next_send_id: u16,
+    queued: VecDeque<Vec<u8>>,
+    in_flight: HashMap<u16, InFlightMessage>,
+    // every message before this one has been received
+    next_receive_id: u16,
+    // messages received after a gap, by id
+    received_ahead: HashSet<u16>,
+    // messages waiting for those before them, on ordered channels
+    buffered: HashMap<u16, Vec<u8>>,
+}
+
+#[derive(Debug)]
+struct InFlightMessage {
+    payload: Vec<u8>,
+    last_sent: Option<Instant>,
+}
+
+impl ReliableChannels {
+    /// Create the channels described by the given config
+    pub fn new(config: &ReliabilityConfig) -> Self {
+        ReliableChannels {
+            resend_interval: config.resend_interval,
+            max_in_flight: config.max_in_flight.min(0x7fff),
+            channels: config
+                .channels
+                .iter()
+                .map(|mode| Channel {
+                    mode: *mode,
+                    next_send_id: 0,
+                    queued: VecDeque::new(),
+                    in_flight: HashMap::new(),
+                    next_receive_id: 0,
+                    received_ahead: HashSet::new(),
+                    buffered: HashMap::new(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Gets the mode of the given channel, if there is such a channel
+    pub fn mode(&self, channel: u8) -> Option<ChannelMode> {
+        self.channels
+            .get(usize::from(channel))
+            .map(|channel| channel.mode)
+    }
+
+    /// Queues a message to be sent on the given channel, returning false if
+    /// there is no such reliable channel. Call `outgoing` to get it back, ready
+    /// to go
+    pub fn send(&mut self, channel: u8, payload: Vec<u8>) -> bool {
+        match self.channels.get_mut(usize::from(channel)) {
+            Some(channel) if channel.mode != ChannelMode::Unreliable => {
+                channel.queued.push_back(payload);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Gets every message which is due to be sent, either because it was just
+    /// queued or because it hasn't been acknowledged in time
+    pub fn outgoing(&mut self) -> Vec<OutgoingMessage> {
+        let mut outgoing = Vec::new();
+        for (index, channel) in self.channels.iter_mut().enumerate() {
+            while channel.in_flight.len() < self.max_in_flight {
+                let payload = match channel.queued.pop_front() {
+                    Some(payload) => payload,
+                    None => break,
+                };
+                let id = channel.next_send_id;
+                channel.next_send_id = id.wrapping_add(1);
+                channel.in_flight.insert(
+                    id,
+                    InFlightMessage {
+                        payload,
+                        last_sent: None,
+                    },
+                );
+            }
+
+            for (id, message) in channel.in_flight.iter_mut() {
+                let due = match &message.last_sent {
+                    Some(last_sent) => last_sent.elapsed() >= self.resend_interval,
+                    None => true,
+                };
+                if due {
+                    message.last_sent = Some(Instant::now());
+                    outgoing.push(OutgoingMessage {
+                        channel: index as u8,
+                        id: *id,
+                        payload: message.payload.clone(),
+                    });
+                }
+            }
+        }
+        outgoing
+    }
+
+    /// Gets how long until a message is next due to be sent, if any are
+    /// queued or waiting to be acknowledged
+    pub fn next_send_in(&self) -> Option<Duration> {
+        let sendable = self.channels.iter().any(|channel| {
+            !channel.queued.is_empty() && channel.in_flight.len() < self.max_in_flight
+        });
+        if sendable {
+            return Some(Duration::from_secs(0));
+        }
+        self.channels
+            .iter()
+            .flat_map(|channel| channel.in_flight.values())
+            .map(|message| match &message.last_sent {
+                Some(last_sent) => self.resend_interval.saturating_sub(last_sent.elapsed()),
+                None => Duration::from_secs(0),
+            })
+            .min()
+    }
+
+    /// Notes that the other side has received the given message
+    pub fn acked(&mut self, channel: u8, id: u16) {
+        if let Some(channel) = self.channels.get_mut(usize::from(channel)) {
+            channel.in_flight.remove(&id);
+        }
+    }
+
+    /// Takes in a message received on the given channel, returning those
+    /// which are now ready to be delivered, in order. Returns `None` if the
+    /// message should be ignored without being acknowledged, because there is
+    /// no such reliable channel or it is too far ahead to keep track of
+    pub fn receive(&mut self, channel: u8, id: u16, payload: &[u8]) -> Option<Vec<Vec<u8>>> {
+        let max_in_flight = self.max_in_flight;
+        let channel = self.channels.get_mut(usize::from(channel))?;
+        if channel.mode == ChannelMode::Unreliable {
+            return None;
+        }
+
+        let ahead = match id.ahead_of(channel.next_receive_id) {
+            Some(ahead) if ahead < max_in_flight as u64 => ahead,
+            Some(_) => return None,
+            // received before, & the ack must have been lost
+            None => return Some(Vec::new()),
+        };
+        if channel.received_ahead.contains(&id) {
+            return Some(Vec::new());
+        }
+
+        let mut delivered = Vec::new();
+        if ahead > 0 {
+            channel.received_ahead.insert(id);
+            match channel.mode {
+                ChannelMode::ReliableOrdered => {
+                    channel.buffered.insert(id, payload.to_vec());
+                }
+                _ => delivered.push(payload.to_vec()),
+            }
+            return Some(delivered);
+        }
+
+        delivered.push(payload.to_vec());
+        channel.next_receive_id = id.wrapping_add(1);
+        while channel.received_ahead.remove(&channel.next_receive_id) {
+            if let Some(payload) = channel.buffered.remove(&channel.next_receive_id) {
+                delivered.push(payload);
+            }
+            channel.next_receive_id = channel.next_receive_id.wrapping_add(1);
+        }
+        Some(delivered)
+    }
+}
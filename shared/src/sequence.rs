@@ -0,0 +1,131 @@
+/// The number of sequence numbers behind the newest one a ReceivedWindow
+/// remembers
+pub const WINDOW_SIZE: u64 = 64;
+
+/// A sequence number which wraps around once it reaches its largest value
+pub trait SequenceNumber: Copy + Eq {
+    /// Gets how far ahead of `other` this sequence number is, or `None` if it
+    /// is behind it. Numbers more than half the range apart are taken to have
+    /// wrapped around
+    fn ahead_of(self, other: Self) -> Option<u64>;
+}
+
+impl SequenceNumber for u16 {
+    fn ahead_of(self, other: Self) -> Option<u64> {
+        let distance = self.wrapping_sub(other);
+        if distance < 0x8000 {
+            return Some(u64::from(distance));
+        }
+        None
+    }
+}
+
+impl SequenceNumber for u64 {
+    fn ahead_of(self, other: Self) -> Option<u64> {
+        let distance = self.wrapping_sub(other);
+        if distance < 1 << 63 {
+            return Some(distance);
+        }
+        None
+    }
+}
+
+/// Gets whether `a` comes after `b`, allowing for wrapping
+pub fn sequence_greater_than<S: SequenceNumber>(a: S, b: S) -> bool {
+    matches!(a.ahead_of(b), Some(distance) if distance > 0)
+}
+
+/// Gets whether `a` comes before `b`, allowing for wrapping
+pub fn sequence_less_than<S: SequenceNumber>(a: S, b: S) -> bool {
+    sequence_greater_than(b, a)
+}
+
+/// The outcome of checking a sequence number against a ReceivedWindow
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum SequenceCheck {
+    /// The sequence number is the newest received so far
+    New,
+    /// The sequence number hasn't been seen before, but a newer one has
+    OutOfOrder,
+    /// The same sequence number has already been received
+    Duplicate,
+    /// The sequence number is too far behind the newest one to tell
+    Stale,
+}
+
+impl SequenceCheck {
+    /// Gets whether the packet hasn't been received before
+    pub fn is_accepted(self) -> bool {
+        matches!(self, SequenceCheck::New | SequenceCheck::OutOfOrder)
+    }
+}
+
+/// Remembers which of the most recent sequence numbers received have been
+/// seen, so that duplicates can be dropped & packets which arrive out of order
+/// noticed
+#[derive(Debug, Default)]
+pub struct ReceivedWindow<S: SequenceNumber> {
+    newest: Option<S>,
+    // bit `n` is set if `newest - n` has been received
+    received: u64,
+}
+
+impl<S: SequenceNumber> ReceivedWindow<S> {
+    /// Create a new, empty ReceivedWindow
+    pub fn new() -> Self {
+        ReceivedWindow {
+            newest: None,
+            received: 0,
+        }
+    }
+
+    /// Checks the given sequence number, recording it if it hasn't been seen
+    /// before
+    pub fn check(&mut self, sequence: S) -> SequenceCheck {
+        let newest = match self.newest {
+            Some(newest) => newest,
+            None => {
+                self.newest = Some(sequence);
+                self.received = 1;
+                return SequenceCheck::New;
+            }
+        };
+
+        match sequence.ahead_of(newest) {
+            Some(0) => return SequenceCheck::Duplicate,
+            Some(shift) => {
+                self.received = if shift >= WINDOW_SIZE {
+                    0
+                } else {
+                    self.received << shift
+                };
+                self.received |= 1;
+                self.newest = Some(sequence);
+                return SequenceCheck::New;
+            }
+            None => {}
+        }
+
+        let age = newest.ahead_of(sequence).unwrap_or(WINDOW_SIZE);
+        if age >= WINDOW_SIZE {
+            return SequenceCheck::Stale;
+        }
+        let bit = 1 << age;
+        if self.received & bit != 0 {
+            return SequenceCheck::Duplicate;
+        }
+        self.received |= bit;
+        SequenceCheck::OutOfOrder
+    }
+
+    /// Gets the newest sequence number received, if any
+    pub fn newest(&self) -> Option<S> {
+        self.newest
+    }
+
+    /// Gets which of the sequence numbers before the newest have been
+    /// received, where bit `n` is set if `newest - n` has been
+    pub fn bits(&self) -> u64 {
+        self.received
+    }
+}
@@ -0,0 +1,62 @@
+use std::{
+    io::{Error, ErrorKind},
+    net::UdpSocket,
+};
+
+use socket2::SockRef;
+
+// ECN-Capable Transport, ECT(0)
+const ECN_CAPABLE: u32 = 0b10;
+
+/// Marks every packet sent from the given socket with a DSCP value, so that
+/// routers which honor it can prioritize the traffic, & optionally as
+/// ECN-capable, so that congested routers can mark packets rather than drop
+/// them. For DSCP, 46 is Expedited Forwarding, meant for low-latency traffic,
+/// & 0 is the default
+pub fn set_traffic_class(socket: &UdpSocket, dscp: u8, ecn: bool) -> Result<(), Error> {
+    if dscp > 63 {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "DSCP values are 6 bits",
+        ));
+    }
+    // the DSCP is the upper 6 bits of the IPv4 TOS / IPv6 traffic class byte,
+    // & ECN the lower 2
+    let mut traffic_class = u32::from(dscp) << 2;
+    if ecn {
+        traffic_class |= ECN_CAPABLE;
+    }
+
+    if socket.local_addr()?.is_ipv4() {
+        return SockRef::from(socket).set_tos(traffic_class);
+    }
+    // IPv4 traffic through a dual-stack socket takes the IPv4 option, which
+    // an IPv6-only socket refuses
+    let _ = SockRef::from(socket).set_tos(traffic_class);
+
+    cfg_if! {
+        if #[cfg(unix)] {
+            use std::os::unix::io::AsRawFd;
+
+            let value = traffic_class as libc::c_int;
+            let result = unsafe {
+                libc::setsockopt(
+                    socket.as_raw_fd(),
+                    libc::IPPROTO_IPV6,
+                    libc::IPV6_TCLASS,
+                    std::ptr::addr_of!(value).cast(),
+                    size_of::<libc::c_int>() as libc::socklen_t,
+                )
+            };
+            if result != 0 {
+                return Err(Error::last_os_error());
+            }
+            Ok(())
+        } else {
+            Err(Error::new(
+                ErrorKind::Other,
+                "marking IPv6 traffic isn't supported on this platform",
+            ))
+        }
+    }
+}
@@ -0,0 +1,268 @@
+//! The X25519 function of RFC 7748, over field elements held in five limbs of
+//! 51 bits each. Every operation takes the same time whatever the values, so
+//! that secrets can't be told from timings
+
+use std::convert::TryInto;
+
+type Fe = [u64; 5];
+
+const MASK: u64 = (1 << 51) - 1;
+const A24: Fe = [121665, 0, 0, 0, 0];
+
+/// The u-coordinate of the base point, to get a public key from a secret
+pub const BASE_POINT: [u8; 32] = {
+    let mut point = [0; 32];
+    point[0] = 9;
+    point
+};
+
+/// Multiplies the point with the given u-coordinate by the given scalar,
+/// which is clamped first
+pub fn x25519(scalar: &[u8; 32], point: &[u8; 32]) -> [u8; 32] {
+    let mut k = *scalar;
+    k[0] &= 248;
+    k[31] &= 127;
+    k[31] |= 64;
+
+    let x1 = from_bytes(point);
+    let mut x2: Fe = [1, 0, 0, 0, 0];
+    let mut z2: Fe = [0; 5];
+    let mut x3 = x1;
+    let mut z3: Fe = [1, 0, 0, 0, 0];
+    let mut swap = 0;
+
+    for t in (0..255).rev() {
+        let bit = u64::from((k[t / 8] >> (t % 8)) & 1);
+        swap ^= bit;
+        conditional_swap(&mut x2, &mut x3, swap);
+        conditional_swap(&mut z2, &mut z3, swap);
+        swap = bit;
+
+        let a = add(&x2, &z2);
+        let aa = square(&a);
+        let b = sub(&x2, &z2);
+        let bb = square(&b);
+        let e = sub(&aa, &bb);
+        let c = add(&x3, &z3);
+        let d = sub(&x3, &z3);
+        let da = mul(&d, &a);
+        let cb = mul(&c, &b);
+        x3 = square(&add(&da, &cb));
+        z3 = mul(&x1, &square(&sub(&da, &cb)));
+        x2 = mul(&aa, &bb);
+        z2 = mul(&e, &add(&aa, &mul(&A24, &e)));
+    }
+    conditional_swap(&mut x2, &mut x3, swap);
+    conditional_swap(&mut z2, &mut z3, swap);
+
+    to_bytes(&mul(&x2, &invert(&z2)))
+}
+
+fn from_bytes(bytes: &[u8; 32]) -> Fe {
+    let load = |index: usize| u64::from_le_bytes(bytes[index..index + 8].try_into().unwrap());
+    // the top bit is ignored
+    [
+        load(0) & MASK,
+        (load(6) >> 3) & MASK,
+        (load(12) >> 6) & MASK,
+        (load(19) >> 1) & MASK,
+        (load(24) >> 12) & MASK,
+    ]
+}
+
+fn to_bytes(f: &Fe) -> [u8; 32] {
+    let mut f = carry(&carry(f));
+    // subtracts the prime if the value is still at least as large
+    let mut q = (f[0] + 19) >> 51;
+    q = (f[1] + q) >> 51;
+    q = (f[2] + q) >> 51;
+    q = (f[3] + q) >> 51;
+    q = (f[4] + q) >> 51;
+    f[0] += 19 * q;
+    for index in 0..4 {
+        f[index + 1] += f[index] >> 51;
+        f[index] &= MASK;
+    }
+    f[4] &= MASK;
+
+    let mut bytes = [0; 32];
+    let mut accumulator: u128 = 0;
+    let mut bits = 0;
+    let mut position = 0;
+    for limb in f.iter() {
+        accumulator |= u128::from(*limb) << bits;
+        bits += 51;
+        while bits >= 8 {
+            bytes[position] = accumulator as u8;
+            accumulator >>= 8;
+            bits -= 8;
+            position += 1;
+        }
+    }
+    bytes[position] = accumulator as u8;
+    bytes
+}
+
+// Brings every limb back under 52 bits
+fn carry(f: &Fe) -> Fe {
+    let mut f = *f;
+    for index in 0..4 {
+        f[index + 1] += f[index] >> 51;
+        f[index] &= MASK;
+    }
+    f[0] += 19 * (f[4] >> 51);
+    f[4] &= MASK;
+    f[1] += f[0] >> 51;
+    f[0] &= MASK;
+    f
+}
+
+fn add(a: &Fe, b: &Fe) -> Fe {
+    carry(&[
+        a[0] + b[0],
+        a[1] + b[1],
+        a[2] + b[2],
+        a[3] + b[3],
+        a[4] + b[4],
+    ])
+}
+
+// Adds four times the prime first, so that no limb goes below zero
+fn sub(a: &Fe, b: &Fe) -> Fe {
+    carry(&[
+        (a[0] + 0x1f_ffff_ffff_ffb4) - b[0],
+        (a[1] + 0x1f_ffff_ffff_fffc) - b[1],
+        (a[2] + 0x1f_ffff_ffff_fffc) - b[2],
+        (a[3] + 0x1f_ffff_ffff_fffc) - b[3],
+        (a[4] + 0x1f_ffff_ffff_fffc) - b[4],
+    ])
+}
+
+fn mul(a: &Fe, b: &Fe) -> Fe {
+    let m = |x: u64, y: u64| u128::from(x) * u128::from(y);
+    let b1 = b[1] * 19;
+    let b2 = b[2] * 19;
+    let b3 = b[3] * 19;
+    let b4 = b[4] * 19;
+
+    let r0 = m(a[0], b[0]) + m(a[1], b4) + m(a[2], b3) + m(a[3], b2) + m(a[4], b1);
+    let mut r1 = m(a[0], b[1]) + m(a[1], b[0]) + m(a[2], b4) + m(a[3], b3) + m(a[4], b2);
+    let mut r2 = m(a[0], b[2]) + m(a[1], b[1]) + m(a[2], b[0]) + m(a[3], b4) + m(a[4], b3);
+    let mut r3 = m(a[0], b[3]) + m(a[1], b[2]) + m(a[2], b[1]) + m(a[3], b[0]) + m(a[4], b4);
+    let mut r4 = m(a[0], b[4]) + m(a[1], b[3]) + m(a[2], b[2]) + m(a[3], b[1]) + m(a[4], b[0]);
+
+    let mask = u128::from(MASK);
+    r1 += r0 >> 51;
+    r2 += r1 >> 51;
+    r3 += r2 >> 51;
+    r4 += r3 >> 51;
+    let mut f0 = (r0 & mask) + 19 * (r4 >> 51);
+    let f1 = (r1 & mask) + (f0 >> 51);
+    f0 &= mask;
+    carry(&[
+        f0 as u64,
+        f1 as u64,
+        (r2 & mask) as u64,
+        (r3 & mask) as u64,
+        (r4 & mask) as u64,
+    ])
+}
+
+fn square(a: &Fe) -> Fe {
+    mul(a, a)
+}
+
+// Raises to the power of the prime minus two, which is 2^255 - 21
+fn invert(z: &Fe) -> Fe {
+    let mut result: Fe = [1, 0, 0, 0, 0];
+    for bit in (0..255).rev() {
+        result = square(&result);
+        if bit != 2 && bit != 4 {
+            result = mul(&result, z);
+        }
+    }
+    result
+}
+
+fn conditional_swap(a: &mut Fe, b: &mut Fe, swap: u64) {
+    let mask = 0u64.wrapping_sub(swap);
+    for index in 0..5 {
+        let difference = mask & (a[index] ^ b[index]);
+        a[index] ^= difference;
+        b[index] ^= difference;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryInto;
+
+    use super::{x25519, BASE_POINT};
+    use crate::hex;
+
+    fn bytes(hex: &str) -> [u8; 32] {
+        hex::decode(hex).unwrap().try_into().unwrap()
+    }
+
+    // RFC 7748, section 5.2
+    #[test]
+    fn scalar_multiplication_vectors() {
+        assert_eq!(
+            x25519(
+                &bytes("a546e36bf0527c9d3b16154b82465edd62144c0ac1fc5a18506a2244ba449ac4"),
+                &bytes("e6db6867583030db3594c1a424b15f7c726624ec26b3353b10a903a6d0ab1c4c"),
+            ),
+            bytes("c3da55379de9c6908e94ea4df28d084f32eccf03491c71f754b4075577a28552"),
+        );
+        assert_eq!(
+            x25519(
+                &bytes("4b66e9d4d1b4673c5ad22691957d6af5c11b6421e0ea01d42ca4169e7918ba0d"),
+                &bytes("e5210f12786811d3f4b7959d0538ae2c31dbe7106fc03c3efc4cd549c715a493"),
+            ),
+            bytes("95cbde9476e8907d7aade45cb4b873f88b595a68799fa152e6f8f7647aac7957"),
+        );
+    }
+
+    // RFC 7748, section 5.2
+    #[test]
+    fn iterated_vectors() {
+        let mut k = BASE_POINT;
+        let mut u = BASE_POINT;
+        for iteration in 1..=1000 {
+            let result = x25519(&k, &u);
+            u = k;
+            k = result;
+            if iteration == 1 {
+                assert_eq!(
+                    k,
+                    bytes("422c8e7a6227d7bca1350b3e2bb7279f7897b87bb6854b783c60e80311ae3079")
+                );
+            }
+        }
+        assert_eq!(
+            k,
+            bytes("684cf59ba83309552800ef566f2f4d3c1c3887c49360e3875f2eb94d99532c51")
+        );
+    }
+
+    // RFC 7748, section 6.1
+    #[test]
+    fn diffie_hellman_vectors() {
+        let alice = bytes("77076d0a7318a57d3c16c17251b26645df4c2f87ebc0992ab177fba51db92c2a");
+        let bob = bytes("5dab087e624a8a4b79e17f8b83800ee66f3bb1292618b6fd1c2f8b27ff88e0eb");
+        let alice_public = x25519(&alice, &BASE_POINT);
+        let bob_public = x25519(&bob, &BASE_POINT);
+        assert_eq!(
+            alice_public,
+            bytes("8520f0098930a754748b7ddcb43ef75a0dbf3a0d26381af4eba4a98eaa9b4e6a")
+        );
+        assert_eq!(
+            bob_public,
+            bytes("de9edb7d7b7dc1b4d35b61c2ece435373f8343c85b78674dadfc7e146f882b4f")
+        );
+
+        let shared = bytes("4a5d9d5ba4ce2de1728e3bf480350f25e07e21c947d19e3376f09b3c1e161742");
+        assert_eq!(x25519(&alice, &bob_public), shared);
+        assert_eq!(x25519(&bob, &alice_public), shared);
+    }
+}
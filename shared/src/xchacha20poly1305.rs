@@ -0,0 +1,375 @@
+//! The XChaCha20-Poly1305 AEAD, as described by RFC 8439 & the XChaCha draft
+//! (draft-irtf-cfrg-xchacha). Every operation takes the same time whatever
+//! the key, so that it can't be told from timings
+
+use std::convert::TryInto;
+
+/// Authentication tags are 16 bytes long, & follow the ciphertext
+pub const TAG_SIZE: usize = 16;
+
+/// Nonces are 24 bytes long, long enough to be picked at random
+pub const NONCE_SIZE: usize = 24;
+
+const CONSTANTS: [u32; 4] = [0x6170_7865, 0x3320_646e, 0x7962_2d32, 0x6b20_6574];
+
+/// Encrypts the plaintext with the given key & nonce, returning the
+/// ciphertext followed by the tag authenticating it & the associated data
+pub fn seal(
+    key: &[u8; 32],
+    nonce: &[u8; NONCE_SIZE],
+    associated_data: &[u8],
+    plaintext: &[u8],
+) -> Vec<u8> {
+    let (subkey, chacha_nonce) = derive(key, nonce);
+    let mut output = Vec::with_capacity(plaintext.len() + TAG_SIZE);
+    output.extend_from_slice(plaintext);
+    apply_keystream(&subkey, &chacha_nonce, 1, &mut output);
+    let tag = tag(&subkey, &chacha_nonce, associated_data, &output);
+    output.extend_from_slice(&tag);
+    output
+}
+
+/// Decrypts the ciphertext, tag included, with the given key & nonce,
+/// returning `None` if it or the associated data have been tampered with
+pub fn open(
+    key: &[u8; 32],
+    nonce: &[u8; NONCE_SIZE],
+    associated_data: &[u8],
+    ciphertext: &[u8],
+) -> Option<Vec<u8>> {
+    if ciphertext.len() < TAG_SIZE {
+        return None;
+    }
+    let (ciphertext, received_tag) = ciphertext.split_at(ciphertext.len() - TAG_SIZE);
+    let (subkey, chacha_nonce) = derive(key, nonce);
+    let expected_tag = tag(&subkey, &chacha_nonce, associated_data, ciphertext);
+    let difference = expected_tag
+        .iter()
+        .zip(received_tag)
+        .fold(0, |difference, (a, b)| difference | (a ^ b));
+    if difference != 0 {
+        return None;
+    }
+
+    let mut output = ciphertext.to_vec();
+    apply_keystream(&subkey, &chacha_nonce, 1, &mut output);
+    Some(output)
+}
+
+// Gets the ChaCha20 key & 12-byte nonce the XChaCha20 nonce stands for
+fn derive(key: &[u8; 32], nonce: &[u8; NONCE_SIZE]) -> ([u8; 32], [u8; 12]) {
+    let subkey = hchacha20(key, nonce[..16].try_into().unwrap());
+    let mut chacha_nonce = [0; 12];
+    chacha_nonce[4..].copy_from_slice(&nonce[16..]);
+    (subkey, chacha_nonce)
+}
+
+fn quarter_round(state: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(16);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(12);
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(8);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(7);
+}
+
+fn rounds(state: &mut [u32; 16]) {
+    for _ in 0..10 {
+        quarter_round(state, 0, 4, 8, 12);
+        quarter_round(state, 1, 5, 9, 13);
+        quarter_round(state, 2, 6, 10, 14);
+        quarter_round(state, 3, 7, 11, 15);
+        quarter_round(state, 0, 5, 10, 15);
+        quarter_round(state, 1, 6, 11, 12);
+        quarter_round(state, 2, 7, 8, 13);
+        quarter_round(state, 3, 4, 9, 14);
+    }
+}
+
+fn read_words(bytes: &[u8], words: &mut [u32]) {
+    for (word, chunk) in words.iter_mut().zip(bytes.chunks_exact(4)) {
+        *word = u32::from_le_bytes(chunk.try_into().unwrap());
+    }
+}
+
+fn hchacha20(key: &[u8; 32], nonce: &[u8; 16]) -> [u8; 32] {
+    let mut state = [0; 16];
+    state[..4].copy_from_slice(&CONSTANTS);
+    read_words(key, &mut state[4..12]);
+    read_words(nonce, &mut state[12..]);
+    rounds(&mut state);
+
+    let mut subkey = [0; 32];
+    for (index, word) in state[..4].iter().chain(&state[12..]).enumerate() {
+        subkey[index * 4..index * 4 + 4].copy_from_slice(&word.to_le_bytes());
+    }
+    subkey
+}
+
+fn chacha20_block(key: &[u8; 32], nonce: &[u8; 12], counter: u32) -> [u8; 64] {
+    let mut initial = [0; 16];
+    initial[..4].copy_from_slice(&CONSTANTS);
+    read_words(key, &mut initial[4..12]);
+    initial[12] = counter;
+    read_words(nonce, &mut initial[13..]);
+
+    let mut state = initial;
+    rounds(&mut state);
+
+    let mut block = [0; 64];
+    for (index, (word, initial)) in state.iter().zip(initial.iter()).enumerate() {
+        block[index * 4..index * 4 + 4].copy_from_slice(&word.wrapping_add(*initial).to_le_bytes());
+    }
+    block
+}
+
+fn apply_keystream(key: &[u8; 32], nonce: &[u8; 12], counter: u32, data: &mut [u8]) {
+    for (index, chunk) in data.chunks_mut(64).enumerate() {
+        let block = chacha20_block(key, nonce, counter.wrapping_add(index as u32));
+        for (byte, key_byte) in chunk.iter_mut().zip(block.iter()) {
+            *byte ^= key_byte;
+        }
+    }
+}
+
+fn tag(
+    key: &[u8; 32],
+    nonce: &[u8; 12],
+    associated_data: &[u8],
+    ciphertext: &[u8],
+) -> [u8; TAG_SIZE] {
+    let block = chacha20_block(key, nonce, 0);
+    let mut poly1305 = Poly1305::new(block[..32].try_into().unwrap());
+    poly1305.padded(associated_data);
+    poly1305.padded(ciphertext);
+    let mut lengths = [0; 16];
+    lengths[..8].copy_from_slice(&(associated_data.len() as u64).to_le_bytes());
+    lengths[8..].copy_from_slice(&(ciphertext.len() as u64).to_le_bytes());
+    poly1305.block(&lengths);
+    poly1305.finish()
+}
+
+// Poly1305 over limbs of 26 bits
+struct Poly1305 {
+    r: [u32; 5],
+    h: [u32; 5],
+    pad: [u32; 4],
+}
+
+impl Poly1305 {
+    fn new(key: &[u8; 32]) -> Self {
+        let load = |index: usize| u32::from_le_bytes(key[index..index + 4].try_into().unwrap());
+        Poly1305 {
+            r: [
+                load(0) & 0x3ff_ffff,
+                (load(3) >> 2) & 0x3ff_ff03,
+                (load(6) >> 4) & 0x3ff_c0ff,
+                (load(9) >> 6) & 0x3f0_3fff,
+                (load(12) >> 8) & 0x00f_ffff,
+            ],
+            h: [0; 5],
+            pad: [load(16), load(20), load(24), load(28)],
+        }
+    }
+
+    // Takes in the data in blocks of 16 bytes, the last padded with zeros
+    fn padded(&mut self, data: &[u8]) {
+        for chunk in data.chunks(16) {
+            let mut block = [0; 16];
+            block[..chunk.len()].copy_from_slice(chunk);
+            self.block(&block);
+        }
+    }
+
+    // Every block is a full one, as the AEAD pads the data it authenticates
+    fn block(&mut self, block: &[u8; 16]) {
+        let load = |index: usize| u32::from_le_bytes(block[index..index + 4].try_into().unwrap());
+        let h = &mut self.h;
+        h[0] += load(0) & 0x3ff_ffff;
+        h[1] += (load(3) >> 2) & 0x3ff_ffff;
+        h[2] += (load(6) >> 4) & 0x3ff_ffff;
+        h[3] += (load(9) >> 6) & 0x3ff_ffff;
+        h[4] += (load(12) >> 8) | (1 << 24);
+
+        let r = self.r.map(u64::from);
+        let s = [0, r[1] * 5, r[2] * 5, r[3] * 5, r[4] * 5];
+        let h64 = h.map(u64::from);
+        let d0 = h64[0] * r[0] + h64[1] * s[4] + h64[2] * s[3] + h64[3] * s[2] + h64[4] * s[1];
+        let mut d1 = h64[0] * r[1] + h64[1] * r[0] + h64[2] * s[4] + h64[3] * s[3] + h64[4] * s[2];
+        let mut d2 = h64[0] * r[2] + h64[1] * r[1] + h64[2] * r[0] + h64[3] * s[4] + h64[4] * s[3];
+        let mut d3 = h64[0] * r[3] + h64[1] * r[2] + h64[2] * r[1] + h64[3] * r[0] + h64[4] * s[4];
+        let mut d4 = h64[0] * r[4] + h64[1] * r[3] + h64[2] * r[2] + h64[3] * r[1] + h64[4] * r[0];
+
+        d1 += d0 >> 26;
+        d2 += d1 >> 26;
+        d3 += d2 >> 26;
+        d4 += d3 >> 26;
+        let mut h0 = (d0 & 0x3ff_ffff) + (d4 >> 26) * 5;
+        let h1 = (d1 & 0x3ff_ffff) + (h0 >> 26);
+        h0 &= 0x3ff_ffff;
+        *h = [
+            h0 as u32,
+            h1 as u32,
+            (d2 & 0x3ff_ffff) as u32,
+            (d3 & 0x3ff_ffff) as u32,
+            (d4 & 0x3ff_ffff) as u32,
+        ];
+    }
+
+    fn finish(self) -> [u8; TAG_SIZE] {
+        let mut h = self.h;
+        // fully carries h
+        let mut carry;
+        carry = h[1] >> 26;
+        h[1] &= 0x3ff_ffff;
+        h[2] += carry;
+        carry = h[2] >> 26;
+        h[2] &= 0x3ff_ffff;
+        h[3] += carry;
+        carry = h[3] >> 26;
+        h[3] &= 0x3ff_ffff;
+        h[4] += carry;
+        carry = h[4] >> 26;
+        h[4] &= 0x3ff_ffff;
+        h[0] += carry * 5;
+        carry = h[0] >> 26;
+        h[0] &= 0x3ff_ffff;
+        h[1] += carry;
+
+        // computes h + -p, & keeps it if h was at least p
+        let mut g = [0u32; 5];
+        g[0] = h[0] + 5;
+        carry = g[0] >> 26;
+        g[0] &= 0x3ff_ffff;
+        for index in 1..4 {
+            g[index] = h[index] + carry;
+            carry = g[index] >> 26;
+            g[index] &= 0x3ff_ffff;
+        }
+        g[4] = (h[4] + carry).wrapping_sub(1 << 26);
+        let mask = (g[4] >> 31).wrapping_sub(1);
+        for index in 0..5 {
+            h[index] = (h[index] & !mask) | (g[index] & mask);
+        }
+
+        let words = [
+            h[0] | (h[1] << 26),
+            (h[1] >> 6) | (h[2] << 20),
+            (h[2] >> 12) | (h[3] << 14),
+            (h[3] >> 18) | (h[4] << 8),
+        ];
+        let mut tag = [0; TAG_SIZE];
+        let mut carry = 0u64;
+        for index in 0..4 {
+            let sum = u64::from(words[index]) + u64::from(self.pad[index]) + carry;
+            tag[index * 4..index * 4 + 4].copy_from_slice(&(sum as u32).to_le_bytes());
+            carry = sum >> 32;
+        }
+        tag
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryInto;
+
+    use super::{apply_keystream, chacha20_block, hchacha20, open, seal, tag};
+    use crate::hex;
+
+    const PLAINTEXT: &[u8] =
+        b"Ladies and Gentlemen of the class of '99: If I could offer you only \
+        one tip for the future, sunscreen would be it.";
+
+    fn bytes(hex: &str) -> Vec<u8> {
+        hex::decode(hex).unwrap()
+    }
+
+    fn key() -> [u8; 32] {
+        bytes("808182838485868788898a8b8c8d8e8f909192939495969798999a9b9c9d9e9f")
+            .try_into()
+            .unwrap()
+    }
+
+    fn counting_key() -> [u8; 32] {
+        bytes("000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f")
+            .try_into()
+            .unwrap()
+    }
+
+    // RFC 8439, section 2.3.2
+    #[test]
+    fn chacha20_block_vector() {
+        let nonce = bytes("000000090000004a00000000").try_into().unwrap();
+        assert_eq!(
+            chacha20_block(&counting_key(), &nonce, 1).to_vec(),
+            bytes(
+                "10f1e7e4d13b5915500fdd1fa32071c4c7d1f4c733c068030422aa9ac3d46c4e\
+                 d2826446079faa0914c2d705d98b02a2b5129cd1de164eb9cbd083e8a2503c4e"
+            ),
+        );
+    }
+
+    // RFC 8439, section 2.8.2
+    #[test]
+    fn chacha20_poly1305_vector() {
+        let nonce = bytes("070000004041424344454647").try_into().unwrap();
+        let associated_data = bytes("50515253c0c1c2c3c4c5c6c7");
+        let mut ciphertext = PLAINTEXT.to_vec();
+        apply_keystream(&key(), &nonce, 1, &mut ciphertext);
+        assert_eq!(
+            ciphertext,
+            bytes(
+                "d31a8d34648e60db7b86afbc53ef7ec2a4aded51296e08fea9e2b5a736ee62d6\
+                 3dbea45e8ca9671282fafb69da92728b1a71de0a9e060b2905d6a5b67ecd3b36\
+                 92ddbd7f2d778b8c9803aee328091b58fab324e4fad675945585808b4831d7bc\
+                 3ff4def08e4b7a9de576d26586cec64b6116"
+            ),
+        );
+        assert_eq!(
+            tag(&key(), &nonce, &associated_data, &ciphertext).to_vec(),
+            bytes("1ae10b594f09e26a7e902ecbd0600691"),
+        );
+    }
+
+    // draft-irtf-cfrg-xchacha-03, section 2.2.1
+    #[test]
+    fn hchacha20_vector() {
+        let nonce = bytes("000000090000004a0000000031415927")
+            .try_into()
+            .unwrap();
+        assert_eq!(
+            hchacha20(&counting_key(), &nonce).to_vec(),
+            bytes("82413b4227b27bfed30e42508a877d73a0f9e4d58a74a853c12ec41326d3ecdc"),
+        );
+    }
+
+    // draft-irtf-cfrg-xchacha-03, appendix A.3.1
+    #[test]
+    fn xchacha20_poly1305_vector() {
+        let nonce = bytes("404142434445464748494a4b4c4d4e4f5051525354555657")
+            .try_into()
+            .unwrap();
+        let associated_data = bytes("50515253c0c1c2c3c4c5c6c7");
+        let sealed = seal(&key(), &nonce, &associated_data, PLAINTEXT);
+        assert_eq!(
+            sealed,
+            bytes(
+                "bd6d179d3e83d43b9576579493c0e939572a1700252bfaccbed2902c21396cbb\
+                 731c7f1b0b4aa6440bf3a82f4eda7e39ae64c6708c54c216cb96b72e1213b452\
+                 2f8c9ba40db5d945b11b69b982c1bb9e3f3fac2bc369488f76b2383565d3fff9\
+                 21f9664c97637da9768812f615c68b13b52e\
+                 c0875924c1c7987947deafd8780acf49"
+            ),
+        );
+        assert_eq!(
+            open(&key(), &nonce, &associated_data, &sealed),
+            Some(PLAINTEXT.to_vec())
+        );
+
+        let mut tampered = sealed;
+        tampered[0] ^= 1;
+        assert_eq!(open(&key(), &nonce, &associated_data, &tampered), None);
+    }
+}
@@ -0,0 +1,156 @@
+//! Properties the link conditioner's statistics must keep, whatever the
+//! conditions it is configured with
+
+use std::{collections::HashMap, convert::TryInto, time::Duration};
+
+use proptest::prelude::*;
+
+use naia_socket_shared::{
+    clock::VirtualClock,
+    link_condition_logic::{self, ConditionedPacket, LinkState},
+    ConditionerStats, Instant, LinkConditionerConfig, TimeQueue,
+};
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+struct TestPacket(Vec<u8>);
+
+impl ConditionedPacket for TestPacket {
+    fn payload(&self) -> &[u8] {
+        &self.0
+    }
+
+    fn with_payload(self, payload: Vec<u8>) -> Self {
+        TestPacket(payload)
+    }
+}
+
+// Numbers each packet by its payload, so copies can be told apart from others
+fn numbered(id: u32) -> TestPacket {
+    TestPacket(id.to_be_bytes().to_vec())
+}
+
+// Runs the given number of packets through the conditioner, one after another
+fn condition(
+    config: &LinkConditionerConfig,
+    packets: u32,
+) -> (ConditionerStats, TimeQueue<TestPacket>) {
+    let mut state = LinkState::new();
+    let mut stats = ConditionerStats::new();
+    let mut time_queue = TimeQueue::new();
+    for id in 0..packets {
+        link_condition_logic::process_packet(
+            config,
+            &mut state,
+            &mut stats,
+            &mut time_queue,
+            numbered(id),
+        );
+    }
+    (stats, time_queue)
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn never_delivered_before_base_latency(
+        latency in 0u32..500,
+        jitter in 0u32..100,
+        reorder in 0.0f32..1.0,
+        reorder_window in 0u32..50,
+        seed in any::<u64>(),
+        packets in 1u32..200,
+    ) {
+        let mut config = LinkConditionerConfig::new(latency, jitter, 0.0, 0.0);
+        config.incoming_reorder = reorder;
+        config.reorder_window = reorder_window;
+        config.seed = Some(seed);
+
+        let before = Instant::now();
+        let (_, time_queue) = condition(&config, packets);
+
+        // uniform jitter takes less than `jitter` off, & nothing else does
+        let mut earliest = before;
+        earliest.add_millis(latency.saturating_sub(jitter));
+        let first = time_queue.peek_entry().unwrap();
+        prop_assert!(first.instant >= earliest);
+    }
+
+    #[test]
+    fn loss_rate_converges_to_configured(loss in 0.0f32..1.0, seed in any::<u64>()) {
+        let packets = 10_000;
+        let mut config = LinkConditionerConfig::new(0, 0, loss, 0.0);
+        config.seed = Some(seed);
+
+        let (stats, _) = condition(&config, packets);
+
+        // six standard deviations, at the widest
+        let measured = stats.dropped as f32 / stats.conditioned as f32;
+        prop_assert_eq!(stats.conditioned, u64::from(packets));
+        prop_assert!((measured - loss).abs() < 0.03, "measured {} for {}", measured, loss);
+    }
+
+    #[test]
+    fn packets_not_dropped_are_all_delivered(
+        loss in 0.0f32..1.0,
+        duplication in 0.0f32..1.0,
+        reorder in 0.0f32..1.0,
+        latency in 0u32..20,
+        jitter in 0u32..10,
+        reorder_window in 0u32..10,
+        seed in any::<u64>(),
+        packets in 1u32..200,
+    ) {
+        let mut config = LinkConditionerConfig::new(latency, jitter, loss, 0.0);
+        config.incoming_duplication = duplication;
+        config.incoming_reorder = reorder;
+        config.reorder_window = reorder_window;
+        config.seed = Some(seed);
+
+        // the packets' delays pass in virtual time, rather than being slept through
+        let clock = VirtualClock::install();
+        let (stats, mut time_queue) = condition(&config, packets);
+        prop_assert_eq!(time_queue.len() as u64, stats.delayed);
+        prop_assert_eq!(stats.delayed, stats.conditioned - stats.dropped + stats.duplicated);
+
+        let mut copies: HashMap<TestPacket, u64> = HashMap::new();
+        while time_queue.len() > 0 {
+            match time_queue.pop_item() {
+                Some(packet) => *copies.entry(packet).or_default() += 1,
+                None => clock.advance(Duration::from_millis(1)),
+            }
+        }
+
+        // every copy delivered is of a packet which was sent, untouched
+        prop_assert!(copies.keys().all(|packet| {
+            packet.0.len() == 4 && u32::from_be_bytes(packet.0[..].try_into().unwrap()) < packets
+        }));
+        let delivered = copies.len() as u64;
+        let duplicated = copies.values().filter(|count| **count == 2).count() as u64;
+        prop_assert!(copies.values().all(|count| *count <= 2));
+        prop_assert_eq!(u64::from(packets) - delivered, stats.dropped);
+        prop_assert_eq!(duplicated, stats.duplicated);
+    }
+
+    #[test]
+    fn throttled_link_keeps_every_copy_it_counts(
+        bandwidth in 1_000u32..100_000,
+        queue_limit in 1usize..32,
+        duplication in 0.0f32..1.0,
+        seed in any::<u64>(),
+        packets in 1u32..200,
+    ) {
+        let mut config = LinkConditionerConfig::new(0, 0, 0.0, 0.0);
+        config.incoming_bandwidth = Some(bandwidth);
+        config.bandwidth_queue_limit = queue_limit;
+        config.incoming_duplication = duplication;
+        config.seed = Some(seed);
+
+        let (stats, time_queue) = condition(&config, packets);
+
+        // copies which don't fit on the link are dropped, but never lost track of
+        prop_assert_eq!(stats.conditioned, u64::from(packets));
+        prop_assert_eq!(time_queue.len() as u64, stats.delayed);
+        prop_assert!(stats.delayed <= 2 * (u64::from(packets) - stats.dropped));
+    }
+}
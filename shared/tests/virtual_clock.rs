@@ -0,0 +1,179 @@
+//! The timers the sockets are built on, run through seconds of virtual time
+//! which pass in no time at all
+
+use std::time::Duration;
+
+use naia_socket_shared::{
+    clock::{self, VirtualClock},
+    fragmentation::{self, Reassembler},
+    link_condition_logic::{self, ConditionedPacket, LinkState},
+    ping::{self, RttEstimator},
+    ConditionerStats, FragmentationConfig, Instant, LinkConditionerConfig, TimeQueue, Timer,
+};
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct TestPacket(Vec<u8>);
+
+impl ConditionedPacket for TestPacket {
+    fn payload(&self) -> &[u8] {
+        &self.0
+    }
+
+    fn with_payload(self, payload: Vec<u8>) -> Self {
+        TestPacket(payload)
+    }
+}
+
+#[test]
+fn time_stands_still_until_advanced() {
+    let clock = VirtualClock::install();
+    let start = Instant::now();
+    std::thread::sleep(Duration::from_millis(5));
+    assert_eq!(start.elapsed(), Duration::from_secs(0));
+
+    clock.advance(Duration::from_secs(3600));
+    assert_eq!(start.elapsed(), Duration::from_secs(3600));
+    assert_eq!(clock::elapsed(start.get_inner()), Duration::from_secs(3600));
+}
+
+#[test]
+fn time_goes_back_to_normal_once_dropped() {
+    let before = std::time::Instant::now();
+    {
+        let clock = VirtualClock::install();
+        clock.advance(Duration::from_secs(3600));
+    }
+    assert!(clock::now() < before + Duration::from_secs(60));
+}
+
+#[test]
+#[should_panic]
+fn only_one_clock_per_thread() {
+    let _clock = VirtualClock::install();
+    let _other = VirtualClock::install();
+}
+
+#[test]
+fn timer_rings_once_its_duration_has_passed() {
+    let clock = VirtualClock::install();
+    let mut timer = Timer::new(Duration::from_secs(30));
+
+    clock.advance(Duration::from_secs(30));
+    assert!(!timer.ringing());
+    clock.advance(Duration::from_millis(1));
+    assert!(timer.ringing());
+
+    timer.reset();
+    assert!(!timer.ringing());
+}
+
+#[test]
+fn run_for_polls_after_every_step() {
+    let clock = VirtualClock::install();
+    let start = Instant::now();
+    let mut polls = Vec::new();
+    clock.run_for(
+        Duration::from_millis(250),
+        Duration::from_millis(100),
+        || {
+            polls.push(start.elapsed());
+            true
+        },
+    );
+    assert_eq!(
+        polls,
+        vec![
+            Duration::from_millis(100),
+            Duration::from_millis(200),
+            Duration::from_millis(250),
+        ]
+    );
+
+    let mut polls = 0;
+    clock.run_for(Duration::from_secs(10), Duration::from_millis(100), || {
+        polls += 1;
+        polls < 3
+    });
+    assert_eq!(polls, 3);
+    assert_eq!(start.elapsed(), Duration::from_millis(550));
+}
+
+#[test]
+fn conditioner_holds_packets_for_its_latency() {
+    let clock = VirtualClock::install();
+    let config = LinkConditionerConfig::new(200, 0, 0.0, 0.0);
+    let mut state = LinkState::new();
+    let mut stats = ConditionerStats::new();
+    let mut time_queue = TimeQueue::new();
+    link_condition_logic::process_packet(
+        &config,
+        &mut state,
+        &mut stats,
+        &mut time_queue,
+        TestPacket(vec![1]),
+    );
+
+    clock.advance(Duration::from_millis(199));
+    assert!(!time_queue.has_item());
+    clock.advance(Duration::from_millis(1));
+    assert!(time_queue.has_item());
+    assert_eq!(time_queue.pop_item(), Some(TestPacket(vec![1])));
+}
+
+#[test]
+fn pings_fall_due_every_interval() {
+    let clock = VirtualClock::install();
+    let interval = Duration::from_secs(1);
+    let mut rtt = RttEstimator::new(interval);
+    assert!(rtt.ping_due());
+
+    let mut pings = 0;
+    clock.run_for(Duration::from_secs(10), Duration::from_millis(50), || {
+        if rtt.ping_due() {
+            rtt.write_ping();
+            pings += 1;
+        }
+        true
+    });
+    assert_eq!(pings, 10);
+    assert!(!rtt.ping_due());
+    assert_eq!(rtt.next_ping_in(), Duration::from_millis(50));
+}
+
+#[test]
+fn round_trips_are_measured_in_virtual_time() {
+    let clock = VirtualClock::install();
+    let mut rtt = RttEstimator::new(Duration::from_secs(1));
+    clock.advance(Duration::from_millis(10));
+
+    let sent = rtt.write_ping();
+    clock.advance(Duration::from_millis(80));
+    let pong = ping::write_pong(&sent).unwrap();
+    assert!(rtt.read_pong(&pong));
+    assert_eq!(rtt.rtt(), Some(Duration::from_millis(80)));
+}
+
+#[test]
+fn fragments_are_given_up_on_after_the_reassembly_timeout() {
+    let clock = VirtualClock::install();
+    let config = FragmentationConfig::default();
+    let timeout = config.reassembly_timeout;
+    let mut reassembler = Reassembler::new(config);
+
+    let payload = [7; 10];
+    let fragments: Vec<Vec<u8>> = fragmentation::split_into_fragments(&payload, 0, 5)
+        .unwrap()
+        .into_iter()
+        .map(|(header, data)| [&header[..], data].concat())
+        .collect();
+
+    // the rest of a packet arriving within the timeout completes it
+    assert_eq!(reassembler.receive(&fragments[0]), None);
+    clock.advance(timeout - Duration::from_millis(1));
+    assert_eq!(reassembler.receive(&fragments[1]), Some(payload.to_vec()));
+
+    // but not once the first fragments have been given up on
+    assert_eq!(reassembler.receive(&fragments[0]), None);
+    clock.advance(timeout);
+    assert_eq!(reassembler.receive(&fragments[1]), None);
+}